@@ -18,12 +18,12 @@ fn main() {
             rsdb_cli.range(iter_mode, page_size, true)
         };
         if let Ok(rs) = rs {
-            if rs.len() == 0 {
+            if rs.is_empty() {
                 break;
             }
             for (k, v) in rs {
                 cnt += 1;
-                let ks = String::from_utf8((&k).to_vec()).unwrap();
+                let ks = String::from_utf8(k.to_vec()).unwrap();
                 let vs = String::from_utf8(v.to_vec()).unwrap();
                 println!("{}: {} => {}", cnt, ks, vs);
                 last_key = k;
@@ -16,24 +16,26 @@ fn main() {
     println!("Counting {}...", &args[1]);
 
     rsdb_cli.use_db(&args[1]).unwrap();
+    // count on a snapshot so pages written meanwhile are not missed or counted twice
+    let snapshot = rsdb_cli.snapshot().unwrap();
 
     let mut cnt: usize = 0;
     let page_size = 1000;
     let mut last_key = vec![];
     loop {
         let rs = if cnt == 0 {
-            rsdb_cli.range(IteratorMode::Start, page_size, true)
+            rsdb_cli.snapshot_range(snapshot, IteratorMode::Start, page_size, true)
         } else {
             let iter_mode = IteratorMode::From(&last_key, Direction::Forward);
-            rsdb_cli.range(iter_mode, page_size, true)
+            rsdb_cli.snapshot_range(snapshot, iter_mode, page_size, true)
         };
         if let Ok(rs) = rs {
-            if rs.len() == 0 {
+            if rs.is_empty() {
                 break;
             }
             for (k, _v) in rs {
                 cnt += 1;
-                // let ks = String::from_utf8((&k).to_vec()).unwrap();
+                // let ks = String::from_utf8(k.to_vec()).unwrap();
                 // let vs = String::from_utf8(v.to_vec()).unwrap();
                 last_key = k;
             }
@@ -42,5 +44,6 @@ fn main() {
             break;
         }
     }
+    rsdb_cli.release_snapshot(snapshot).unwrap();
     println!("pairs {}", cnt);
 }
@@ -1,4 +1,5 @@
-use std::io::{stdout, Write};
+use std::fs::File;
+use std::io::{stdout, BufReader, BufWriter, Write};
 
 use clap::Parser;
 
@@ -6,7 +7,10 @@ extern crate rustyline;
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 
-use rsdbrs::{Direction, IteratorMode, RsDBClient};
+use rsdbrs::{
+    parse_slot_range, Change, ChangeOp, Direction, DumpFormat, IteratorMode, RsDBClient,
+    RsDBResult, Version,
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = "RSDB client utility")]
@@ -37,11 +41,11 @@ fn main() {
     let mut db_name = String::from("(none) ");
 
     loop {
-        if let Some(name) = rsdb_cli.get_db_name() {
+        if let Some(name) = rsdb_cli.get_db_name().clone() {
             db_name = format!("({}) ", name);
         }
 
-        println!("");
+        println!();
         let readline = rl.readline(db_name.as_str());
         match readline {
             Ok(line) => {
@@ -54,7 +58,7 @@ fn main() {
                     break;
                 }
                 let parts: Vec<&str> = valid_part.split_ascii_whitespace().collect();
-                if parts.len() == 0 {
+                if parts.is_empty() {
                     continue;
                 }
 
@@ -63,13 +67,27 @@ fn main() {
                         println!(" Commands currently supported:");
                         println!("                 set - Set key:value pair");
                         println!("                 get - Get value by key");
+                        println!("                mget - Get values of several keys");
+                        println!("              exists - Check which keys exist");
                         println!("              delete - Delete by key");
-                        println!("                 use - Select/Attached a database");
+                        println!("        delete_range - Delete keys in [start, end)");
+                        println!("       delete_prefix - Delete keys by prefix");
+                        println!("                 use - Select/Attached a database, optionally with `key=value;...` options");
+                        println!(
+                            "       use_read_only - Select an existing database for reading only"
+                        );
                         println!("          current_db - Get current database");
                         println!(
                             "             list_db - List all the databases currently attached"
                         );
-                        println!("              detach - Detach a database\n");
+                        println!(
+                            "             catalog - List all the databases on disk and their state"
+                        );
+                        println!("              detach - Detach a database");
+                        println!("                drop - Drop a database and delete its data");
+                        println!("        server_stats - Show open databases and evictions");
+                        println!("           read_only - Refuse writes server-wide with `on`, take them again with `off`");
+                        println!("                info - Show server stats and those of the current database\n");
                         println!("         range_begin - Range pairs from begin");
                         println!("           range_end - Range pairs from a key");
                         println!(
@@ -79,7 +97,43 @@ fn main() {
                         println!(
                             "     range_from_desc - Range pairs from a key (inluding the current key)"
                         );
-                        println!("  range_from_desc_ex - Range pairs from a key");
+                        println!("  range_from_desc_ex - Range pairs from a key\n");
+                        println!("            snapshot - Take a snapshot of the current database");
+                        println!("        snapshot_get - Get value by key from a snapshot");
+                        println!("    snapshot_release - Release a snapshot\n");
+                        println!("              backup - Backup current database to a directory");
+                        println!(
+                            "          checkpoint - Checkpoint current database to a directory"
+                        );
+                        println!("        list_backups - List backups in a directory");
+                        println!(
+                            "       purge_backups - Keep only the newest backups in a directory"
+                        );
+                        println!("             restore - Restore a backup as a new database");
+                        println!("                dump - Dump current database to a local file, as binary or jsonl");
+                        println!("        restore_dump - Load a local dump file into a database\n");
+                        println!("             compact - Compact current database, optionally from/to a key");
+                        println!("               flush - Flush the memtables of current database");
+                        println!("              ingest - Ingest sst files on the server into current database");
+                        println!(
+                            "        wait_compact - Wait for background compactions, optionally up to a timeout\n"
+                        );
+                        println!("            sequence - Show the sequence number of the last write to current database");
+                        println!("             changes - List writes to current database after a sequence number");
+                        println!(
+                            "              get_at - Get the value a key had at a sequence number"
+                        );
+                        println!(
+                            "         get_at_time - Get the value a key had at a time, in ms since the epoch"
+                        );
+                        println!("             history - List the versions kept of a key, newest first\n");
+                        println!(
+                            "            raft_add - Add a node to the raft group, on its leader"
+                        );
+                        println!("         raft_remove - Remove a node from the raft group, on its leader\n");
+                        println!("               slots - Show the hash slots of a sharded server and where moved ones went");
+                        println!("        import_slots - Copy slots `first-last` from another node and serve them");
+                        println!("         purge_slots - Delete the keys of slots `first-last` no longer served");
                         continue;
                     }
                     "set" => {
@@ -112,6 +166,45 @@ fn main() {
                             }
                         }
                     }
+                    "mget" => {
+                        if parts.len() < 2 {
+                            println!("Error: invalid parameter for mget");
+                            continue;
+                        }
+                        let keys: Vec<&[u8]> =
+                            parts[1..].iter().map(|key| key.as_bytes()).collect();
+                        let rs = rsdb_cli.mget(&keys);
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(vals) => {
+                                for (key, val) in parts[1..].iter().zip(vals) {
+                                    match val {
+                                        Some(val) => {
+                                            println!("{key}: {}", String::from_utf8_lossy(&val))
+                                        }
+                                        None => println!("{key}: <none>"),
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    "exists" => {
+                        if parts.len() < 2 {
+                            println!("Error: invalid parameter for exists");
+                            continue;
+                        }
+                        let keys: Vec<&[u8]> =
+                            parts[1..].iter().map(|key| key.as_bytes()).collect();
+                        let rs = rsdb_cli.exists(&keys);
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(found) => {
+                                for (key, found) in parts[1..].iter().zip(found) {
+                                    println!("{key}: {found}");
+                                }
+                            }
+                        }
+                    }
                     "delete" => {
                         if parts.len() != 2 {
                             println!("Error: invalid parameter for delete");
@@ -124,19 +217,72 @@ fn main() {
                             println!("Info: Ok.")
                         }
                     }
-                    "use" => {
+                    "delete_range" => {
+                        if parts.len() != 3 {
+                            println!("Error: invalid parameter for delete_range");
+                            continue;
+                        }
+                        let rs = rsdb_cli.delete_range(parts[1].as_bytes(), parts[2].as_bytes());
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(count) => println!("Deleted: ~{count} keys"),
+                        }
+                    }
+                    "delete_prefix" => {
                         if parts.len() != 2 {
+                            println!("Error: invalid parameter for delete_prefix");
+                            continue;
+                        }
+                        let rs = rsdb_cli.delete_prefix(parts[1].as_bytes());
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(count) => println!("Deleted: ~{count} keys"),
+                        }
+                    }
+                    "use" => {
+                        if parts.len() != 2 && parts.len() != 3 {
                             println!("Error: invalid parameter for use");
                             continue;
                         }
 
-                        let rs = rsdb_cli.use_db(parts[1]);
+                        let rs = match parts.get(2) {
+                            Some(options) => rsdb_cli.use_db_with_options(parts[1], options),
+                            None => rsdb_cli.use_db(parts[1]),
+                        };
                         if let Err(e) = rs {
                             println!("Error: {}", e);
                         } else {
                             println!("Info: Ok.")
                         }
                     }
+                    "use_read_only" => {
+                        if parts.len() != 2 {
+                            println!("Error: invalid parameter for use_read_only");
+                            continue;
+                        }
+
+                        match rsdb_cli.use_db_read_only(parts[1]) {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(()) => println!("Info: Ok."),
+                        }
+                    }
+                    "read_only" => {
+                        let read_only = match parts.get(1) {
+                            Some(&"on") if parts.len() == 2 => true,
+                            Some(&"off") if parts.len() == 2 => false,
+                            _ => {
+                                println!(
+                                    "Error: invalid parameter for read_only, expected on or off"
+                                );
+                                continue;
+                            }
+                        };
+
+                        match rsdb_cli.set_read_only(read_only) {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(()) => println!("Info: Ok."),
+                        }
+                    }
                     "current_db" => {
                         if parts.len() != 1 {
                             println!("Error: invalid parameter for current_db");
@@ -172,6 +318,66 @@ fn main() {
                             }
                         }
                     }
+                    "catalog" => {
+                        if parts.len() != 1 {
+                            println!("Error: invalid parameter for catalog");
+                            continue;
+                        }
+
+                        let rs = rsdb_cli.catalog();
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(val) => {
+                                println!("Databases:");
+                                for (db_name, state) in val {
+                                    println!("  - {db_name} ({state})");
+                                }
+                            }
+                        }
+                    }
+                    "server_stats" => {
+                        if parts.len() != 1 {
+                            println!("Error: invalid parameter for server_stats");
+                            continue;
+                        }
+
+                        let rs = rsdb_cli.server_stats();
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(val) => {
+                                for (name, value) in val {
+                                    println!("  {name}: {value}");
+                                }
+                            }
+                        }
+                    }
+                    "info" => {
+                        if parts.len() != 1 {
+                            println!("Error: invalid parameter for info");
+                            continue;
+                        }
+
+                        match rsdb_cli.server_stats() {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(val) => {
+                                println!("server:");
+                                for (name, value) in val {
+                                    println!("  {name}: {value}");
+                                }
+                            }
+                        }
+                        if let Some(name) = rsdb_cli.get_db_name().clone() {
+                            match rsdb_cli.stats() {
+                                Err(e) => println!("Error: {}", e),
+                                Ok(val) => {
+                                    println!("{name}:");
+                                    for (name, value) in val {
+                                        println!("  {name}: {value}");
+                                    }
+                                }
+                            }
+                        }
+                    }
                     "detach" => {
                         if parts.len() != 2 {
                             println!("Error: invalid parameter for detach");
@@ -185,6 +391,19 @@ fn main() {
                             println!("Info: Ok.")
                         }
                     }
+                    "drop" => {
+                        if parts.len() != 2 {
+                            println!("Error: invalid parameter for drop");
+                            continue;
+                        }
+
+                        let rs = rsdb_cli.drop_db(parts[1]);
+                        if let Err(e) = rs {
+                            println!("Error: {}", e);
+                        } else {
+                            println!("Info: Ok.")
+                        }
+                    }
                     "range_begin" => {
                         if parts.len() != 2 {
                             println!("Error: invalid parameter for range_begin");
@@ -331,6 +550,410 @@ fn main() {
                             }
                         }
                     }
+                    "snapshot" => {
+                        if parts.len() != 1 {
+                            println!("Error: invalid parameter for snapshot");
+                            continue;
+                        }
+
+                        let rs = rsdb_cli.snapshot();
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(handle) => println!("Snapshot: {handle}"),
+                        }
+                    }
+                    "snapshot_get" => {
+                        if parts.len() != 3 {
+                            println!("Error: invalid parameter for snapshot_get");
+                            continue;
+                        }
+
+                        let handle = if let Ok(handle) = parts[1].parse::<u64>() {
+                            handle
+                        } else {
+                            println!("Error: invalid snapshot handle");
+                            continue;
+                        };
+                        let rs = rsdb_cli.snapshot_get(handle, parts[2].as_bytes());
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(val) => {
+                                if let Some(val) = val {
+                                    let m = String::from_utf8(val).unwrap();
+                                    println!("Value: {m}");
+                                } else {
+                                    println!("Value: <none>")
+                                }
+                            }
+                        }
+                    }
+                    "snapshot_release" => {
+                        if parts.len() != 2 {
+                            println!("Error: invalid parameter for snapshot_release");
+                            continue;
+                        }
+
+                        let handle = if let Ok(handle) = parts[1].parse::<u64>() {
+                            handle
+                        } else {
+                            println!("Error: invalid snapshot handle");
+                            continue;
+                        };
+                        let rs = rsdb_cli.release_snapshot(handle);
+                        if let Err(e) = rs {
+                            println!("Error: {}", e);
+                        } else {
+                            println!("Info: Ok.")
+                        }
+                    }
+                    "backup" => {
+                        if parts.len() != 2 {
+                            println!("Error: invalid parameter for backup");
+                            continue;
+                        }
+
+                        let rs = rsdb_cli.backup(parts[1]);
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(backup_id) => println!("Backup: {backup_id}"),
+                        }
+                    }
+                    "checkpoint" => {
+                        if parts.len() != 2 {
+                            println!("Error: invalid parameter for checkpoint");
+                            continue;
+                        }
+
+                        let rs = rsdb_cli.checkpoint(parts[1]);
+                        if let Err(e) = rs {
+                            println!("Error: {}", e);
+                        } else {
+                            println!("Info: Ok.")
+                        }
+                    }
+                    "list_backups" => {
+                        if parts.len() != 2 {
+                            println!("Error: invalid parameter for list_backups");
+                            continue;
+                        }
+
+                        let rs = rsdb_cli.list_backups(parts[1]);
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(val) => {
+                                println!("Backups:");
+                                for info in val {
+                                    println!(
+                                        "  - {}: timestamp {}, {} bytes, {} files",
+                                        info.backup_id, info.timestamp, info.size, info.num_files
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    "purge_backups" => {
+                        if parts.len() != 3 {
+                            println!("Error: invalid parameter for purge_backups");
+                            continue;
+                        }
+
+                        let keep = if let Ok(keep) = parts[2].parse::<u16>() {
+                            keep
+                        } else {
+                            println!("Error: invalid number of backups to keep");
+                            continue;
+                        };
+                        let rs = rsdb_cli.purge_backups(parts[1], keep);
+                        if let Err(e) = rs {
+                            println!("Error: {}", e);
+                        } else {
+                            println!("Info: Ok.")
+                        }
+                    }
+                    "restore" => {
+                        if parts.len() != 3 && parts.len() != 4 {
+                            println!("Error: invalid parameter for restore");
+                            continue;
+                        }
+
+                        let backup_id = match parts.get(3).map(|id| id.parse::<u32>()) {
+                            None => None,
+                            Some(Ok(id)) => Some(id),
+                            Some(Err(_)) => {
+                                println!("Error: invalid backup id");
+                                continue;
+                            }
+                        };
+                        let rs = rsdb_cli.restore_backup(parts[1], backup_id, parts[2]);
+                        if let Err(e) = rs {
+                            println!("Error: {}", e);
+                        } else {
+                            println!("Info: Ok.")
+                        }
+                    }
+                    "dump" => {
+                        if parts.len() != 2 && parts.len() != 3 {
+                            println!("Error: invalid parameter for dump");
+                            continue;
+                        }
+
+                        let format = match parts.get(2).map(|f| f.parse::<DumpFormat>()) {
+                            None => DumpFormat::Binary,
+                            Some(Ok(format)) => format,
+                            Some(Err(e)) => {
+                                println!("Error: {}", e);
+                                continue;
+                            }
+                        };
+                        let rs = File::create(parts[1])
+                            .map_err(|e| e.into())
+                            .and_then(|file| rsdb_cli.dump(BufWriter::new(file), format));
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(count) => println!("Dumped: {count} pairs"),
+                        }
+                    }
+                    "restore_dump" => {
+                        if parts.len() != 3 {
+                            println!("Error: invalid parameter for restore_dump");
+                            continue;
+                        }
+
+                        let rs = File::open(parts[1]).map_err(|e| e.into()).and_then(|file| {
+                            rsdb_cli.restore_dump(parts[2], BufReader::new(file), 1000)
+                        });
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(count) => println!("Restored: {count} pairs"),
+                        }
+                    }
+                    "compact" => {
+                        if parts.len() > 3 {
+                            println!("Error: invalid parameter for compact");
+                            continue;
+                        }
+
+                        let start = parts.get(1).map(|key| key.as_bytes());
+                        let end = parts.get(2).map(|key| key.as_bytes());
+                        print_maintenance(rsdb_cli.compact(start, end));
+                    }
+                    "flush" => {
+                        if parts.len() != 1 {
+                            println!("Error: invalid parameter for flush");
+                            continue;
+                        }
+
+                        print_maintenance(rsdb_cli.flush());
+                    }
+                    "ingest" => {
+                        if parts.len() < 2 {
+                            println!("Error: invalid parameter for ingest");
+                            continue;
+                        }
+
+                        print_maintenance(rsdb_cli.ingest(&parts[1..]));
+                    }
+                    "wait_compact" => {
+                        if parts.len() > 2 {
+                            println!("Error: invalid parameter for wait_compact");
+                            continue;
+                        }
+
+                        let timeout = match parts.get(1).map(|secs| secs.parse::<u16>()) {
+                            None => 0,
+                            Some(Ok(secs)) => secs,
+                            Some(Err(_)) => {
+                                println!("Error: invalid timeout");
+                                continue;
+                            }
+                        };
+                        print_maintenance(rsdb_cli.wait_for_compact(timeout));
+                    }
+                    "sequence" => {
+                        if parts.len() != 1 {
+                            println!("Error: invalid parameter for sequence");
+                            continue;
+                        }
+
+                        match rsdb_cli.latest_sequence() {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(seq) => println!("{seq}"),
+                        }
+                    }
+                    "changes" => {
+                        if parts.len() > 3 {
+                            println!("Error: invalid parameter for changes");
+                            continue;
+                        }
+
+                        let seq = match parts.get(1).map(|seq| seq.parse::<u64>()) {
+                            None => 0,
+                            Some(Ok(seq)) => seq,
+                            Some(Err(_)) => {
+                                println!("Error: invalid sequence number");
+                                continue;
+                            }
+                        };
+                        let limit = match parts.get(2).map(|limit| limit.parse::<u16>()) {
+                            None => 100,
+                            Some(Ok(limit)) => limit,
+                            Some(Err(_)) => {
+                                println!("Error: invalid limit");
+                                continue;
+                            }
+                        };
+                        match rsdb_cli.changes_since(seq, limit) {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(changes) => {
+                                for change in changes {
+                                    print_change(change);
+                                }
+                            }
+                        }
+                    }
+                    "get_at" => {
+                        if parts.len() != 3 {
+                            println!("Error: invalid parameter for get_at");
+                            continue;
+                        }
+
+                        let seq = if let Ok(seq) = parts[2].parse::<u64>() {
+                            seq
+                        } else {
+                            println!("Error: invalid sequence number");
+                            continue;
+                        };
+                        match rsdb_cli.get_at(parts[1].as_bytes(), seq) {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(Some(val)) => println!("Value: {}", String::from_utf8_lossy(&val)),
+                            Ok(None) => println!("Value: <none>"),
+                        }
+                    }
+                    "get_at_time" => {
+                        if parts.len() != 3 {
+                            println!("Error: invalid parameter for get_at_time");
+                            continue;
+                        }
+
+                        let time_ms = if let Ok(time_ms) = parts[2].parse::<u64>() {
+                            time_ms
+                        } else {
+                            println!("Error: invalid time");
+                            continue;
+                        };
+                        match rsdb_cli.get_at_time(parts[1].as_bytes(), time_ms) {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(Some(val)) => println!("Value: {}", String::from_utf8_lossy(&val)),
+                            Ok(None) => println!("Value: <none>"),
+                        }
+                    }
+                    "history" => {
+                        if parts.len() != 2 && parts.len() != 3 {
+                            println!("Error: invalid parameter for history");
+                            continue;
+                        }
+
+                        let limit = match parts.get(2).map(|limit| limit.parse::<u16>()) {
+                            None => 10,
+                            Some(Ok(limit)) => limit,
+                            Some(Err(_)) => {
+                                println!("Error: invalid limit");
+                                continue;
+                            }
+                        };
+                        match rsdb_cli.history(parts[1].as_bytes(), limit) {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(versions) => {
+                                for version in versions {
+                                    print_version(version);
+                                }
+                            }
+                        }
+                    }
+                    "raft_add" => {
+                        if parts.len() != 3 {
+                            println!("Error: invalid parameter for raft_add");
+                            continue;
+                        }
+
+                        let id = if let Ok(id) = parts[1].parse::<u64>() {
+                            id
+                        } else {
+                            println!("Error: invalid node id");
+                            continue;
+                        };
+                        let rs = rsdb_cli.raft_add_node(id, parts[2]);
+                        if let Err(e) = rs {
+                            println!("Error: {}", e);
+                        } else {
+                            println!("Info: Ok.")
+                        }
+                    }
+                    "raft_remove" => {
+                        if parts.len() != 2 {
+                            println!("Error: invalid parameter for raft_remove");
+                            continue;
+                        }
+
+                        let id = if let Ok(id) = parts[1].parse::<u64>() {
+                            id
+                        } else {
+                            println!("Error: invalid node id");
+                            continue;
+                        };
+                        let rs = rsdb_cli.raft_remove_node(id);
+                        if let Err(e) = rs {
+                            println!("Error: {}", e);
+                        } else {
+                            println!("Info: Ok.")
+                        }
+                    }
+                    "slots" => {
+                        if parts.len() != 1 {
+                            println!("Error: invalid parameter for slots");
+                            continue;
+                        }
+
+                        match rsdb_cli.slots() {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(ranges) => {
+                                for (range, moved_to) in ranges {
+                                    if moved_to.is_empty() {
+                                        println!("  {range}: served");
+                                    } else {
+                                        println!("  {range}: moved to {moved_to}");
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    "import_slots" => {
+                        if parts.len() != 4 {
+                            println!("Error: invalid parameter for import_slots, expected `first-last source addr`");
+                            continue;
+                        }
+
+                        match parse_slot_range(parts[1]) {
+                            Err(e) => println!("Error: {}", e),
+                            Ok((first, last)) => print_maintenance(
+                                rsdb_cli.import_slots(first, last, parts[2], parts[3]),
+                            ),
+                        }
+                    }
+                    "purge_slots" => {
+                        if parts.len() != 2 {
+                            println!("Error: invalid parameter for purge_slots");
+                            continue;
+                        }
+
+                        let rs = parse_slot_range(parts[1])
+                            .map_err(rsdbrs::RsDBError::RespError)
+                            .and_then(|(first, last)| rsdb_cli.purge_slots(first, last));
+                        match rs {
+                            Err(e) => println!("Error: {}", e),
+                            Ok(count) => println!("Deleted: {count} keys"),
+                        }
+                    }
                     _ => {
                         println!("Error: unknown command `{}`", parts[0]);
                         continue;
@@ -354,3 +977,42 @@ fn main() {
         }
     }
 }
+
+fn print_maintenance(rs: RsDBResult<Vec<(String, String)>>) {
+    match rs {
+        Err(e) => println!("Error: {}", e),
+        Ok(val) => {
+            for (name, value) in val {
+                println!("  {name}: {value}");
+            }
+        }
+    }
+}
+
+fn print_change(change: Change) {
+    match change.op {
+        ChangeOp::Put(key, value) => println!(
+            "  {}: put {} {}",
+            change.seq,
+            String::from_utf8_lossy(&key),
+            String::from_utf8_lossy(&value)
+        ),
+        ChangeOp::Delete(key) => {
+            println!("  {}: delete {}", change.seq, String::from_utf8_lossy(&key))
+        }
+        ChangeOp::DeleteRange(start, end) => println!(
+            "  {}: delete_range {} {}",
+            change.seq,
+            String::from_utf8_lossy(&start),
+            String::from_utf8_lossy(&end)
+        ),
+    }
+}
+
+fn print_version(version: Version) {
+    let value = match version.value {
+        Some(value) => String::from_utf8_lossy(&value).into_owned(),
+        None => "<deleted>".to_string(),
+    };
+    println!("  {} @{}ms: {}", version.seq, version.time_ms, value);
+}
@@ -0,0 +1,324 @@
+use std::fmt::Display;
+use std::io::{BufRead, Error, ErrorKind, Result, Write};
+use std::str::FromStr;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+/// First bytes of a binary dump, followed by the format version.
+const MAGIC: &[u8] = b"RSDBDUMP";
+const VERSION: u8 = 1;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// File formats for the pairs of a database, readable independently of the
+/// storage engine and its version.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    /// `RSDBDUMP` and a version byte, then every key and value as a
+    /// big-endian u32 length followed by the bytes.
+    Binary,
+    /// One `{"key": "...", "value": "..."}` object per line, with the bytes
+    /// base64 encoded.
+    JsonLines,
+}
+
+impl FromStr for DumpFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "binary" => Ok(DumpFormat::Binary),
+            "jsonl" => Ok(DumpFormat::JsonLines),
+            _ => Err(invalid(format!("unknown dump format `{}`", s))),
+        }
+    }
+}
+
+impl Display for DumpFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DumpFormat::Binary => write!(f, "binary"),
+            DumpFormat::JsonLines => write!(f, "jsonl"),
+        }
+    }
+}
+
+pub struct DumpWriter<W: Write> {
+    inner: W,
+    format: DumpFormat,
+    count: u64,
+}
+
+impl<W: Write> DumpWriter<W> {
+    pub fn new(mut inner: W, format: DumpFormat) -> Result<Self> {
+        if format == DumpFormat::Binary {
+            inner.write_all(MAGIC)?;
+            inner.write_u8(VERSION)?;
+        }
+        Ok(Self {
+            inner,
+            format,
+            count: 0,
+        })
+    }
+
+    pub fn write_pair(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        match self.format {
+            DumpFormat::Binary => {
+                for token in [key, value] {
+                    self.inner.write_u32::<BigEndian>(token.len() as u32)?;
+                    self.inner.write_all(token)?;
+                }
+            }
+            DumpFormat::JsonLines => writeln!(
+                self.inner,
+                r#"{{"key":"{}","value":"{}"}}"#,
+                encode_base64(key),
+                encode_base64(value)
+            )?,
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Pairs written so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Reads the pairs of a dump in either format, told apart by the first bytes.
+pub struct DumpReader<R: BufRead> {
+    inner: R,
+    format: DumpFormat,
+}
+
+impl<R: BufRead> DumpReader<R> {
+    pub fn new(mut inner: R) -> Result<Self> {
+        let format = if inner.fill_buf()?.starts_with(&MAGIC[..1]) {
+            let mut magic = [0; MAGIC.len()];
+            inner.read_exact(&mut magic)?;
+            if magic != MAGIC {
+                return Err(invalid("not a dump file".to_string()));
+            }
+            let version = inner.read_u8()?;
+            if version != VERSION {
+                return Err(invalid(format!("unsupported dump version {}", version)));
+            }
+            DumpFormat::Binary
+        } else {
+            DumpFormat::JsonLines
+        };
+        Ok(Self { inner, format })
+    }
+
+    pub fn format(&self) -> DumpFormat {
+        self.format
+    }
+
+    /// The next pair, `None` at the end of the dump.
+    pub fn read_pair(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        match self.format {
+            DumpFormat::Binary => {
+                if self.inner.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                let key = self.read_token()?;
+                let value = self.read_token()?;
+                Ok(Some((key, value)))
+            }
+            DumpFormat::JsonLines => {
+                let mut line = String::new();
+                while line.trim().is_empty() {
+                    line.clear();
+                    if self.inner.read_line(&mut line)? == 0 {
+                        return Ok(None);
+                    }
+                }
+                parse_line(&line).map(Some)
+            }
+        }
+    }
+
+    fn read_token(&mut self) -> Result<Vec<u8>> {
+        let length = self.inner.read_u32::<BigEndian>()?;
+        let mut token = vec![0; length as usize];
+        self.inner.read_exact(&mut token)?;
+        Ok(token)
+    }
+}
+
+impl<R: BufRead> Iterator for DumpReader<R> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_pair().transpose()
+    }
+}
+
+/// Parse a `{"key": "...", "value": "..."}` line, ignoring other fields.
+fn parse_line(line: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+    let body = line
+        .trim()
+        .strip_prefix('{')
+        .and_then(|body| body.strip_suffix('}'))
+        .ok_or_else(|| invalid(format!("expected a JSON object, got `{}`", line.trim())))?;
+    let (mut key, mut value) = (None, None);
+    let mut rest = body.trim_start();
+    while !rest.is_empty() {
+        let (name, after) = parse_string(rest)?;
+        let after = after
+            .trim_start()
+            .strip_prefix(':')
+            .ok_or_else(|| invalid(format!("expected `:` after `{}`", name)))?;
+        let (field, after) = parse_string(after.trim_start())?;
+        match name.as_str() {
+            "key" => key = Some(decode_base64(&field)?),
+            "value" => value = Some(decode_base64(&field)?),
+            _ => {}
+        }
+        let after = after.trim_start();
+        rest = match after.strip_prefix(',') {
+            Some(after) => after.trim_start(),
+            None if after.is_empty() => after,
+            None => return Err(invalid(format!("unexpected `{}`", after))),
+        };
+    }
+    match (key, value) {
+        (Some(key), Some(value)) => Ok((key, value)),
+        _ => Err(invalid("missing key or value".to_string())),
+    }
+}
+
+/// A JSON string at the start of `text` and what follows it. Only the
+/// escapes a base64 string can contain are supported.
+fn parse_string(text: &str) -> Result<(String, &str)> {
+    let mut chars = text
+        .strip_prefix('"')
+        .ok_or_else(|| invalid(format!("expected a string at `{}`", text)))?
+        .char_indices();
+    let mut string = String::new();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, &text[idx + 2..])),
+            '\\' => match chars.next() {
+                Some((_, c @ ('"' | '\\' | '/'))) => string.push(c),
+                _ => return Err(invalid("unsupported escape in string".to_string())),
+            },
+            c => string.push(c),
+        }
+    }
+    Err(invalid("unterminated string".to_string()))
+}
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (idx, byte)| n | (*byte as u32) << (16 - 8 * idx));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                text.push(BASE64[(n >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+pub fn decode_base64(text: &str) -> Result<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return Err(invalid("base64 length is not a multiple of 4".to_string()));
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for (pos, chunk) in text.chunks(4).enumerate() {
+        let last = pos == text.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return Err(invalid("misplaced base64 padding".to_string()));
+        }
+        let mut n = 0u32;
+        for (idx, c) in chunk[..4 - padding].iter().enumerate() {
+            let digit = BASE64
+                .iter()
+                .position(|b| b == c)
+                .ok_or_else(|| invalid(format!("invalid base64 character `{}`", *c as char)))?;
+            n |= (digit as u32) << (18 - 6 * idx);
+        }
+        bytes.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(bytes)
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs() -> Vec<(Vec<u8>, Vec<u8>)> {
+        vec![
+            (b"key1".to_vec(), b"value1".to_vec()),
+            (vec![0, 0xff, b'"'], vec![]),
+        ]
+    }
+
+    #[test]
+    fn test_dump_round_trip() {
+        for format in [DumpFormat::Binary, DumpFormat::JsonLines] {
+            let mut writer = DumpWriter::new(Vec::new(), format).unwrap();
+            for (key, value) in pairs() {
+                writer.write_pair(&key, &value).unwrap();
+            }
+            assert_eq!(writer.count(), 2);
+            let dump = writer.finish().unwrap();
+
+            let reader = DumpReader::new(&dump[..]).unwrap();
+            assert_eq!(reader.format(), format);
+            let read: Result<Vec<_>> = reader.collect();
+            assert_eq!(read.unwrap(), pairs());
+
+            let truncated = DumpReader::new(&dump[..dump.len() - 2]).unwrap();
+            assert!(truncated.collect::<Result<Vec<_>>>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let dump = b"{ \"key\": \"a2V5\", \"ts\": \"x\", \"value\": \"\" }\n\n";
+        let read: Vec<_> = DumpReader::new(&dump[..]).unwrap().collect();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].as_ref().unwrap(), &(b"key".to_vec(), vec![]));
+
+        let mut reader = DumpReader::new(&b"{\"key\":\"a2V5\"}\n"[..]).unwrap();
+        assert!(reader.read_pair().is_err());
+    }
+
+    #[test]
+    fn test_base64() {
+        for (bytes, text) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode_base64(bytes), text);
+            assert_eq!(decode_base64(text).unwrap(), bytes);
+        }
+        assert!(decode_base64("Zm9").is_err());
+        assert!(decode_base64("Zg==Zm9v").is_err());
+        assert!(decode_base64("Zm9*").is_err());
+    }
+}
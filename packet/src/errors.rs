@@ -7,6 +7,8 @@ use std::string::FromUtf8Error;
 pub enum PacketError {
     IOError(IOErr),
     FromUtf8Error(FromUtf8Error),
+    /// A packet which cannot be encoded, sent as nothing.
+    InvalidPacket(String),
 }
 
 impl Error for PacketError {}
@@ -32,6 +34,9 @@ impl Display for PacketError {
             Self::FromUtf8Error(e) => {
                 write!(f, "{e}")
             }
+            Self::InvalidPacket(msg) => {
+                write!(f, "invalid packet: {msg}")
+            }
         }
     }
 }
@@ -1,23 +1,89 @@
+pub mod dump;
 pub mod errors;
 pub mod packet;
+pub mod slots;
 
 pub use packet::CMD_LENGTH;
 pub use packet::LEN_LENGTH;
 pub use packet::TOKEN_LENGTH;
 
+pub use packet::CMD_BACKUP;
+pub use packet::CMD_CATALOG;
+pub use packet::CMD_CHANGES;
+pub use packet::CMD_CHECKPOINT;
+pub use packet::CMD_COMPACT;
 pub use packet::CMD_CURRENT_DB;
 pub use packet::CMD_DELETE;
+pub use packet::CMD_DELETE_PREFIX;
+pub use packet::CMD_DELETE_RANGE;
+pub use packet::CMD_DELETE_WITH;
+pub use packet::CMD_DROP;
+pub use packet::CMD_EXISTS;
+pub use packet::CMD_FETCH_CHECKPOINT;
+pub use packet::CMD_FLUSH;
+pub use packet::CMD_GET_AT;
+pub use packet::CMD_GET_AT_TIME;
+pub use packet::CMD_HISTORY;
+pub use packet::CMD_IMPORT_SLOTS;
+pub use packet::CMD_INGEST;
+pub use packet::CMD_LATEST_SEQUENCE;
+pub use packet::CMD_LIST_BACKUPS;
+pub use packet::CMD_MOVE_SLOTS;
+pub use packet::CMD_PURGE_BACKUPS;
+pub use packet::CMD_PURGE_SLOTS;
+pub use packet::CMD_RAFT_ADD_NODE;
+pub use packet::CMD_RAFT_APPEND;
+pub use packet::CMD_RAFT_REMOVE_NODE;
+pub use packet::CMD_RAFT_SNAPSHOT;
+pub use packet::CMD_RAFT_VOTE;
 pub use packet::CMD_READ;
+pub use packet::CMD_READ_CHECKPOINT;
+pub use packet::CMD_RELEASE_CHECKPOINT;
+pub use packet::CMD_RESTORE_BACKUP;
+pub use packet::CMD_SERVER_STATS;
+pub use packet::CMD_SET_READ_ONLY;
+pub use packet::CMD_SLOTS;
+pub use packet::CMD_SNAPSHOT;
+pub use packet::CMD_SNAPSHOT_RELEASE;
+pub use packet::CMD_STATS;
 pub use packet::CMD_USE;
+pub use packet::CMD_USE_EXISTING;
+pub use packet::CMD_USE_WITH;
+pub use packet::CMD_USE_WITH_OPTIONS;
+pub use packet::CMD_WAIT_COMPACT;
+pub use packet::CMD_WITH_SNAPSHOT;
 pub use packet::CMD_WRITE;
+pub use packet::CMD_WRITE_WITH;
 
+pub use packet::RESP_CHANGES;
+pub use packet::RESP_CHECKPOINT;
 pub use packet::RESP_ERROR;
+pub use packet::RESP_INVALID_NAME;
+pub use packet::RESP_MOVED;
+pub use packet::RESP_NOT_LEADER;
+pub use packet::RESP_NUMBER;
+pub use packet::RESP_NUMBERS;
 pub use packet::RESP_OK;
 pub use packet::RESP_PAIRS;
+pub use packet::RESP_RAFT;
 pub use packet::RESP_TOKEN;
 pub use packet::RESP_TOKENS;
+pub use packet::RESP_VERSIONS;
 
+pub use packet::WRITE_NO_WAL;
+pub use packet::WRITE_SYNC;
+
+pub use packet::USE_READ_ONLY;
+
+pub use packet::CHANGE_DELETE;
+pub use packet::CHANGE_DELETE_RANGE;
+pub use packet::CHANGE_PUT;
+
+pub use packet::ChangeRecord;
 pub use packet::Packet;
+pub use packet::RaftEntry;
+pub use packet::RaftSnapshotChunk;
+pub use packet::VersionRecord;
 
 pub mod reader;
 pub mod readerwriter;
@@ -26,4 +92,5 @@ pub mod writer;
 // pub use reader::PacketReader;
 pub use errors::{PacketError, PacketResult};
 pub use readerwriter::PacketReaderWriter;
+pub use slots::{key_slot, parse_slot_range, SLOT_COUNT};
 // pub use writer::PacketWriter;
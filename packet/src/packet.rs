@@ -11,6 +11,19 @@ pub const CMD_USE: u8 = 0x04;
 pub const CMD_CURRENT_DB: u8 = 0x05;
 pub const CMD_LIST_DB: u8 = 0x06;
 pub const CMD_DETACH: u8 = 0x07;
+pub const CMD_DELETE_RANGE: u8 = 0x08;
+pub const CMD_DELETE_PREFIX: u8 = 0x09;
+pub const CMD_USE_WITH_OPTIONS: u8 = 0x0a;
+pub const CMD_DROP: u8 = 0x0b;
+pub const CMD_CATALOG: u8 = 0x0c;
+pub const CMD_SERVER_STATS: u8 = 0x0d;
+pub const CMD_EXISTS: u8 = 0x0e;
+pub const CMD_STATS: u8 = 0x0f;
+pub const CMD_WRITE_WITH: u8 = 0x10;
+pub const CMD_DELETE_WITH: u8 = 0x11;
+pub const CMD_USE_EXISTING: u8 = 0x12;
+pub const CMD_USE_WITH: u8 = 0x13;
+pub const CMD_SET_READ_ONLY: u8 = 0x14;
 
 pub const CMD_RANGE_BEGIN: u8 = 0x31;
 pub const CMD_RANGE_END: u8 = 0x32;
@@ -19,14 +32,115 @@ pub const CMD_RANGE_FROM_ASC_EX: u8 = 0x34;
 pub const CMD_RANGE_FROM_DESC: u8 = 0x35;
 pub const CMD_RANGE_FROM_DESC_EX: u8 = 0x36;
 
+pub const CMD_SNAPSHOT: u8 = 0x41;
+pub const CMD_SNAPSHOT_RELEASE: u8 = 0x42;
+pub const CMD_WITH_SNAPSHOT: u8 = 0x43;
+
+pub const CMD_BACKUP: u8 = 0x61;
+pub const CMD_CHECKPOINT: u8 = 0x62;
+pub const CMD_LIST_BACKUPS: u8 = 0x63;
+pub const CMD_PURGE_BACKUPS: u8 = 0x64;
+pub const CMD_RESTORE_BACKUP: u8 = 0x65;
+
+pub const CMD_COMPACT: u8 = 0x71;
+pub const CMD_FLUSH: u8 = 0x72;
+pub const CMD_WAIT_COMPACT: u8 = 0x73;
+pub const CMD_INGEST: u8 = 0x74;
+
+pub const CMD_CHANGES: u8 = 0x81;
+pub const CMD_LATEST_SEQUENCE: u8 = 0x82;
+pub const CMD_GET_AT: u8 = 0x83;
+pub const CMD_HISTORY: u8 = 0x84;
+pub const CMD_FETCH_CHECKPOINT: u8 = 0x85;
+pub const CMD_READ_CHECKPOINT: u8 = 0x86;
+pub const CMD_RELEASE_CHECKPOINT: u8 = 0x87;
+pub const CMD_GET_AT_TIME: u8 = 0x88;
+
+pub const CMD_RAFT_VOTE: u8 = 0x91;
+pub const CMD_RAFT_APPEND: u8 = 0x92;
+pub const CMD_RAFT_SNAPSHOT: u8 = 0x93;
+pub const CMD_RAFT_ADD_NODE: u8 = 0x94;
+pub const CMD_RAFT_REMOVE_NODE: u8 = 0x95;
+
+pub const CMD_SLOTS: u8 = 0xa1;
+pub const CMD_IMPORT_SLOTS: u8 = 0xa2;
+pub const CMD_MOVE_SLOTS: u8 = 0xa3;
+pub const CMD_PURGE_SLOTS: u8 = 0xa4;
+
+// durability flags of CMD_WRITE_WITH and CMD_DELETE_WITH
+pub const WRITE_SYNC: u16 = 0x01;
+pub const WRITE_NO_WAL: u16 = 0x02;
+
+// mode flags of CMD_USE_WITH
+pub const USE_READ_ONLY: u16 = 0x01;
+
+// operations of a ChangeRecord
+pub const CHANGE_PUT: u8 = 0x01;
+pub const CHANGE_DELETE: u8 = 0x02;
+pub const CHANGE_DELETE_RANGE: u8 = 0x03;
+
 // responses
+pub const RESP_CHECKPOINT: u8 = 0x53;
 pub const RESP_OK: u8 = 0x55;
 pub const RESP_ERROR: u8 = 0x56;
 pub const RESP_TOKEN: u8 = 0x57;
 pub const RESP_TOKENS: u8 = 0x58;
 pub const RESP_PAIRS: u8 = 0x59;
+pub const RESP_NUMBER: u8 = 0x5a;
+pub const RESP_NUMBERS: u8 = 0x5b;
+pub const RESP_INVALID_NAME: u8 = 0x5c;
+pub const RESP_CHANGES: u8 = 0x5d;
+pub const RESP_RAFT: u8 = 0x5e;
+pub const RESP_NOT_LEADER: u8 = 0x5f;
+pub const RESP_MOVED: u8 = 0x60;
+// past 0x60 the ids are taken by commands
+pub const RESP_VERSIONS: u8 = 0x54;
 
-#[derive(Debug, PartialEq)]
+/// A committed write in the change feed of a database. `value` is empty for
+/// a delete and is the end key of a range delete.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeRecord {
+    pub seq: u64,
+    pub op: u8,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+/// A value a key held, written with sequence number `seq` at `time_ms`
+/// milliseconds since the Unix epoch. `value` is empty once deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionRecord {
+    pub seq: u64,
+    pub time_ms: u64,
+    pub value: Vec<u8>,
+}
+
+/// An entry of the raft log, opaque to the protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaftEntry {
+    pub term: u64,
+    pub data: Vec<u8>,
+}
+
+/// A piece of a snapshot sent by a raft leader to a lagging node: `data`
+/// goes at `offset` of `file`, a path relative to the snapshot. The last
+/// chunk has `done` set and no file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RaftSnapshotChunk {
+    pub term: u64,
+    pub leader: u64,
+    /// Index and term of the last entry the snapshot covers.
+    pub index: u64,
+    pub last_term: u64,
+    /// Cluster membership as of `index`.
+    pub config: Vec<u8>,
+    pub file: Vec<u8>,
+    pub offset: u64,
+    pub data: Vec<u8>,
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Packet {
     // commands
     CmdWrite(Vec<Vec<u8>>),
@@ -36,6 +150,31 @@ pub enum Packet {
     CmdCurrentDB(),
     CmdListDb(),
     CmdDetach(Vec<u8>),
+    // start key (inclusive), end key (exclusive)
+    CmdDeleteRange(Vec<u8>, Vec<u8>),
+    CmdDeletePrefix(Vec<u8>),
+    // database name, options profile in `key = value` text form
+    CmdUseWithOptions(Vec<u8>, Vec<u8>),
+    CmdDrop(Vec<u8>),
+    // every known database and its state, as pairs
+    CmdCatalog(),
+    // server-wide figures as name and value pairs
+    CmdServerStats(),
+    // keys to check, answered with 1 or 0 per key
+    CmdExists(Vec<Vec<u8>>),
+    // figures of the current database as name and value pairs
+    CmdStats(),
+    // durability flags, then the same as CmdWrite
+    CmdWriteWith(u16, Vec<Vec<u8>>),
+    // durability flags, then the same as CmdDelete
+    CmdDeleteWith(u16, Vec<Vec<u8>>),
+    // like CmdUse, but fails instead of creating a missing database
+    CmdUseExisting(Vec<u8>),
+    // mode flags, then the same as CmdUse
+    CmdUseWith(u16, Vec<u8>),
+    // whether the server refuses the writes of clients from now on, sent by
+    // any client as there is no access control
+    CmdSetReadOnly(bool),
 
     // command-ranges
     CmdRangeBegin(u16),
@@ -45,10 +184,90 @@ pub enum Packet {
     CmdRangeFromDesc(u16, Vec<u8>),
     CmdRangeFromDescEx(u16, Vec<u8>),
 
+    // command-snapshots
+    CmdSnapshot(),
+    CmdSnapshotRelease(u64),
+    // run a read or range command against a snapshot handle
+    CmdWithSnapshot(u64, Box<Packet>),
+
+    // command-backups
+    CmdBackup(Vec<u8>),
+    CmdCheckpoint(Vec<u8>),
+    CmdListBackups(Vec<u8>),
+    CmdPurgeBackups(u16, Vec<u8>),
+    // backup dir, backup id (0 for the latest), new database name
+    CmdRestoreBackup(Vec<u8>, u64, Vec<u8>),
+
+    // command-maintenance
+    // start key, end key (inclusive), an empty key leaves that side open
+    CmdCompact(Vec<u8>, Vec<u8>),
+    CmdFlush(),
+    // timeout in seconds, 0 to wait as long as it takes
+    CmdWaitCompact(u16),
+    // paths of sst files on the server
+    CmdIngest(Vec<Vec<u8>>),
+
+    // command-changes
+    // sequence number to start after, most changes to return
+    CmdChanges(u64, u16),
+    CmdLatestSequence(),
+    // sequence number, key, answered with whether the key then existed, as
+    // a byte 1 or 0, and the value it had
+    CmdGetAt(u64, Vec<u8>),
+    // most versions to return, key
+    CmdHistory(u16, Vec<u8>),
+    // checkpoint the current database for a replica to copy
+    CmdFetchCheckpoint(),
+    // checkpoint handle, path of one of its files, offset to read from
+    CmdReadCheckpoint(u64, Vec<u8>, u64),
+    CmdReleaseCheckpoint(u64),
+    // milliseconds since the Unix epoch, key, answered as CmdGetAt
+    CmdGetAtTime(u64, Vec<u8>),
+
+    // command-raft
+    // term, candidate id, index and term of its last entry
+    CmdRaftVote(u64, u64, u64, u64),
+    // term, leader id, index and term of the entry before the new ones,
+    // commit index of the leader, new entries
+    CmdRaftAppend(u64, u64, u64, u64, u64, Vec<RaftEntry>),
+    CmdRaftSnapshot(RaftSnapshotChunk),
+    // node id, address
+    CmdRaftAddNode(u64, Vec<u8>),
+    CmdRaftRemoveNode(u64),
+
+    // command-slots
+    CmdSlots(),
+    // first and last slot, address of the node to copy them from, address
+    // of the importing node for clients
+    CmdImportSlots(u16, u16, Vec<u8>, Vec<u8>),
+    // first and last slot, address of their new owner
+    CmdMoveSlots(u16, u16, Vec<u8>),
+    // first and last slot
+    CmdPurgeSlots(u16, u16),
+
     // responses
     RespOk(String),
     RespError(String),
     RespToken(Vec<u8>),
     RespTokens(Vec<Vec<u8>>),
     RespPairs(Vec<Vec<u8>>),
+    RespNumber(u64),
+    RespNumbers(Vec<u64>),
+    // a database name was rejected, with the reason
+    RespInvalidName(String),
+    // writes after a sequence number, oldest first
+    RespChanges(Vec<ChangeRecord>),
+    // term of the node, whether the request was granted, and an index:
+    // the last matching entry on success, where to retry from otherwise
+    RespRaft(u64, bool, u64),
+    // address of the raft leader to send writes to, empty when unknown
+    RespNotLeader(String),
+    // slot of a key served by another node, and the address of that node,
+    // empty when unknown
+    RespMoved(u16, String),
+    // versions of a key, newest first
+    RespVersions(Vec<VersionRecord>),
+    // checkpoint handle, sequence number the checkpoint has every write up
+    // to, paths of its files
+    RespCheckpoint(u64, u64, Vec<Vec<u8>>),
 }
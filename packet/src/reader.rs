@@ -56,6 +56,65 @@ impl<T: Read> PacketReader<T> {
                 let token = self.read_token();
                 packet::Packet::CmdDetach(token)
             }
+            packet::CMD_DELETE_RANGE => {
+                let start = self.read_token();
+                let end = self.read_token();
+                packet::Packet::CmdDeleteRange(start, end)
+            }
+            packet::CMD_DELETE_PREFIX => {
+                let prefix = self.read_token();
+                packet::Packet::CmdDeletePrefix(prefix)
+            }
+            packet::CMD_USE_WITH_OPTIONS => {
+                let name = self.read_token();
+                let options = self.read_token();
+                packet::Packet::CmdUseWithOptions(name, options)
+            }
+            packet::CMD_DROP => {
+                let token = self.read_token();
+                packet::Packet::CmdDrop(token)
+            }
+            packet::CMD_CATALOG => packet::Packet::CmdCatalog(),
+            packet::CMD_SERVER_STATS => packet::Packet::CmdServerStats(),
+            packet::CMD_EXISTS => {
+                let key_count = self.read_size();
+                let mut keys = Vec::new();
+                for _ in 0..key_count {
+                    let key = self.read_token();
+                    keys.push(key);
+                }
+                packet::Packet::CmdExists(keys)
+            }
+            packet::CMD_STATS => packet::Packet::CmdStats(),
+            packet::CMD_WRITE_WITH => {
+                let flags = self.read_size();
+                let pairs = self.read_size();
+                let mut tokens = Vec::new();
+                for _ in 0..pairs {
+                    tokens.push(self.read_token());
+                    tokens.push(self.read_token());
+                }
+                packet::Packet::CmdWriteWith(flags, tokens)
+            }
+            packet::CMD_DELETE_WITH => {
+                let flags = self.read_size();
+                let key_count = self.read_size();
+                let mut keys = Vec::new();
+                for _ in 0..key_count {
+                    keys.push(self.read_token());
+                }
+                packet::Packet::CmdDeleteWith(flags, keys)
+            }
+            packet::CMD_USE_EXISTING => {
+                let token = self.read_token();
+                packet::Packet::CmdUseExisting(token)
+            }
+            packet::CMD_USE_WITH => {
+                let flags = self.read_size();
+                let token = self.read_token();
+                packet::Packet::CmdUseWith(flags, token)
+            }
+            packet::CMD_SET_READ_ONLY => packet::Packet::CmdSetReadOnly(self.read_size() != 0),
 
             packet::CMD_RANGE_BEGIN => {
                 let page_size = self.read_size();
@@ -86,6 +145,157 @@ impl<T: Read> PacketReader<T> {
                 packet::Packet::CmdRangeFromDescEx(page_size, token)
             }
 
+            packet::CMD_SNAPSHOT => packet::Packet::CmdSnapshot(),
+            packet::CMD_SNAPSHOT_RELEASE => {
+                let handle = self.read_number();
+                packet::Packet::CmdSnapshotRelease(handle)
+            }
+            packet::CMD_WITH_SNAPSHOT => {
+                let handle = self.read_number();
+                let inner = self.read_packet();
+                packet::Packet::CmdWithSnapshot(handle, Box::new(inner))
+            }
+
+            packet::CMD_BACKUP => {
+                let token = self.read_token();
+                packet::Packet::CmdBackup(token)
+            }
+            packet::CMD_CHECKPOINT => {
+                let token = self.read_token();
+                packet::Packet::CmdCheckpoint(token)
+            }
+            packet::CMD_LIST_BACKUPS => {
+                let token = self.read_token();
+                packet::Packet::CmdListBackups(token)
+            }
+            packet::CMD_PURGE_BACKUPS => {
+                let keep = self.read_size();
+                let token = self.read_token();
+                packet::Packet::CmdPurgeBackups(keep, token)
+            }
+            packet::CMD_RESTORE_BACKUP => {
+                let backup_dir = self.read_token();
+                let backup_id = self.read_number();
+                let name = self.read_token();
+                packet::Packet::CmdRestoreBackup(backup_dir, backup_id, name)
+            }
+            packet::CMD_COMPACT => {
+                let start = self.read_token();
+                let end = self.read_token();
+                packet::Packet::CmdCompact(start, end)
+            }
+            packet::CMD_FLUSH => packet::Packet::CmdFlush(),
+            packet::CMD_WAIT_COMPACT => {
+                let timeout = self.read_size();
+                packet::Packet::CmdWaitCompact(timeout)
+            }
+            packet::CMD_INGEST => {
+                let file_count = self.read_size();
+                let mut files = Vec::new();
+                for _ in 0..file_count {
+                    files.push(self.read_token());
+                }
+                packet::Packet::CmdIngest(files)
+            }
+
+            packet::CMD_CHANGES => {
+                let since = self.read_number();
+                let limit = self.read_size();
+                packet::Packet::CmdChanges(since, limit)
+            }
+            packet::CMD_LATEST_SEQUENCE => packet::Packet::CmdLatestSequence(),
+            packet::CMD_GET_AT => {
+                let seq = self.read_number();
+                let key = self.read_token();
+                packet::Packet::CmdGetAt(seq, key)
+            }
+            packet::CMD_HISTORY => {
+                let limit = self.read_size();
+                let key = self.read_token();
+                packet::Packet::CmdHistory(limit, key)
+            }
+            packet::CMD_FETCH_CHECKPOINT => packet::Packet::CmdFetchCheckpoint(),
+            packet::CMD_READ_CHECKPOINT => {
+                let handle = self.read_number();
+                let file = self.read_token();
+                let offset = self.read_number();
+                packet::Packet::CmdReadCheckpoint(handle, file, offset)
+            }
+            packet::CMD_GET_AT_TIME => {
+                let time_ms = self.read_number();
+                let key = self.read_token();
+                packet::Packet::CmdGetAtTime(time_ms, key)
+            }
+            packet::CMD_RELEASE_CHECKPOINT => {
+                packet::Packet::CmdReleaseCheckpoint(self.read_number())
+            }
+
+            packet::CMD_RAFT_VOTE => {
+                let term = self.read_number();
+                let candidate = self.read_number();
+                let last_index = self.read_number();
+                let last_term = self.read_number();
+                packet::Packet::CmdRaftVote(term, candidate, last_index, last_term)
+            }
+            packet::CMD_RAFT_APPEND => {
+                let term = self.read_number();
+                let leader = self.read_number();
+                let prev_index = self.read_number();
+                let prev_term = self.read_number();
+                let commit = self.read_number();
+                let count = self.read_size();
+                let mut entries = Vec::new();
+                for _ in 0..count {
+                    let term = self.read_number();
+                    let data = self.read_token();
+                    entries.push(packet::RaftEntry { term, data });
+                }
+                packet::Packet::CmdRaftAppend(term, leader, prev_index, prev_term, commit, entries)
+            }
+            packet::CMD_RAFT_SNAPSHOT => {
+                let chunk = packet::RaftSnapshotChunk {
+                    term: self.read_number(),
+                    leader: self.read_number(),
+                    index: self.read_number(),
+                    last_term: self.read_number(),
+                    config: self.read_token(),
+                    file: self.read_token(),
+                    offset: self.read_number(),
+                    data: self.read_token(),
+                    done: self.read_size() != 0,
+                };
+                packet::Packet::CmdRaftSnapshot(chunk)
+            }
+            packet::CMD_RAFT_ADD_NODE => {
+                let id = self.read_number();
+                let addr = self.read_token();
+                packet::Packet::CmdRaftAddNode(id, addr)
+            }
+            packet::CMD_RAFT_REMOVE_NODE => {
+                let id = self.read_number();
+                packet::Packet::CmdRaftRemoveNode(id)
+            }
+
+            packet::CMD_SLOTS => packet::Packet::CmdSlots(),
+            packet::CMD_IMPORT_SLOTS => {
+                let first = self.read_size();
+                let last = self.read_size();
+                let source = self.read_token();
+                let addr = self.read_token();
+                packet::Packet::CmdImportSlots(first, last, source, addr)
+            }
+            packet::CMD_MOVE_SLOTS => {
+                let first = self.read_size();
+                let last = self.read_size();
+                let addr = self.read_token();
+                packet::Packet::CmdMoveSlots(first, last, addr)
+            }
+            packet::CMD_PURGE_SLOTS => {
+                let first = self.read_size();
+                let last = self.read_size();
+                packet::Packet::CmdPurgeSlots(first, last)
+            }
+
             packet::RESP_OK => {
                 let message = self.read_token();
                 let message = String::from_utf8(message).unwrap();
@@ -96,6 +306,11 @@ impl<T: Read> PacketReader<T> {
                 let message = String::from_utf8(message).unwrap();
                 packet::Packet::RespError(message)
             }
+            packet::RESP_INVALID_NAME => {
+                let message = self.read_token();
+                let message = String::from_utf8(message).unwrap();
+                packet::Packet::RespInvalidName(message)
+            }
             packet::RESP_TOKEN => {
                 let token = self.read_token();
                 packet::Packet::RespToken(token)
@@ -120,6 +335,78 @@ impl<T: Read> PacketReader<T> {
                 }
                 packet::Packet::RespPairs(pairs)
             }
+            packet::RESP_NUMBER => {
+                let number = self.read_number();
+                packet::Packet::RespNumber(number)
+            }
+            packet::RESP_NUMBERS => {
+                let count = self.read_size();
+                let mut numbers = Vec::new();
+                for _ in 0..count {
+                    let number = self.read_number();
+                    numbers.push(number);
+                }
+                packet::Packet::RespNumbers(numbers)
+            }
+            packet::RESP_CHANGES => {
+                let count = self.read_size();
+                let mut changes = Vec::new();
+                for _ in 0..count {
+                    let seq = self.read_number();
+                    let op = self.read_header();
+                    let key = self.read_token();
+                    let value = self.read_token();
+                    changes.push(packet::ChangeRecord {
+                        seq,
+                        op,
+                        key,
+                        value,
+                    });
+                }
+                packet::Packet::RespChanges(changes)
+            }
+            packet::RESP_RAFT => {
+                let term = self.read_number();
+                let success = self.read_size() != 0;
+                let index = self.read_number();
+                packet::Packet::RespRaft(term, success, index)
+            }
+            packet::RESP_NOT_LEADER => {
+                let leader = self.read_token();
+                let leader = String::from_utf8(leader).unwrap();
+                packet::Packet::RespNotLeader(leader)
+            }
+            packet::RESP_MOVED => {
+                let slot = self.read_size();
+                let addr = self.read_token();
+                let addr = String::from_utf8(addr).unwrap();
+                packet::Packet::RespMoved(slot, addr)
+            }
+            packet::RESP_VERSIONS => {
+                let count = self.read_size();
+                let mut versions = Vec::new();
+                for _ in 0..count {
+                    let seq = self.read_number();
+                    let time_ms = self.read_number();
+                    let value = self.read_token();
+                    versions.push(packet::VersionRecord {
+                        seq,
+                        time_ms,
+                        value,
+                    });
+                }
+                packet::Packet::RespVersions(versions)
+            }
+            packet::RESP_CHECKPOINT => {
+                let handle = self.read_number();
+                let seq = self.read_number();
+                let count = self.read_size();
+                let mut files = Vec::new();
+                for _ in 0..count {
+                    files.push(self.read_token());
+                }
+                packet::Packet::RespCheckpoint(handle, seq, files)
+            }
 
             _ => {
                 panic!("Unknown packet");
@@ -135,6 +422,10 @@ impl<T: Read> PacketReader<T> {
         self.reader.read_u16::<BigEndian>().unwrap()
     }
 
+    fn read_number(&mut self) -> u64 {
+        self.reader.read_u64::<BigEndian>().unwrap()
+    }
+
     fn read_token(&mut self) -> Vec<u8> {
         let length = self.reader.read_u32::<BigEndian>().unwrap();
         if length == 0 {
@@ -283,9 +574,46 @@ mod test_packet_reader {
         assert_eq!(packet, packet::Packet::CmdUse(b"world".to_vec()),);
     }
 
+    #[test]
+    fn test_cmd_use_existing() {
+        let bytes = [
+            packet::CMD_USE_EXISTING, // packet type id
+            0,
+            0,
+            0,
+            2,
+            b'd',
+            b'b', // token
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdUseExisting(b"db".to_vec()));
+    }
+
+    #[test]
+    fn test_cmd_use_with() {
+        let bytes = [
+            packet::CMD_USE_WITH, // packet type id
+            0,
+            1, // flags
+            0,
+            0,
+            0,
+            2,
+            b'd',
+            b'b', // token
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdUseWith(packet::USE_READ_ONLY, b"db".to_vec())
+        );
+    }
+
     #[test]
     fn test_cmd_current_db() {
-        let bytes = vec![packet::CMD_CURRENT_DB];
+        let bytes = [packet::CMD_CURRENT_DB];
         let mut packer = PacketReader::new(&bytes[..]);
         let p = packer.read_packet();
         assert_eq!(p, packet::Packet::CmdCurrentDB());
@@ -293,7 +621,7 @@ mod test_packet_reader {
 
     #[test]
     fn test_cmd_list_db() {
-        let bytes = vec![packet::CMD_LIST_DB];
+        let bytes = [packet::CMD_LIST_DB];
         let mut packer = PacketReader::new(&bytes[..]);
         let p = packer.read_packet();
         assert_eq!(p, packet::Packet::CmdListDb());
@@ -318,9 +646,172 @@ mod test_packet_reader {
         assert_eq!(packet, packet::Packet::CmdDetach(b"world".to_vec()),);
     }
 
+    #[test]
+    fn test_cmd_drop() {
+        let bytes = [
+            packet::CMD_DROP, // packet type id
+            0,
+            0,
+            0,
+            5,
+            b'w',
+            b'o',
+            b'r',
+            b'l',
+            b'd', // token
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdDrop(b"world".to_vec()));
+    }
+
+    #[test]
+    fn test_cmd_catalog() {
+        let bytes = [packet::CMD_CATALOG];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let p = packer.read_packet();
+        assert_eq!(p, packet::Packet::CmdCatalog());
+    }
+
+    #[test]
+    fn test_cmd_server_stats() {
+        let bytes = [packet::CMD_SERVER_STATS];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let p = packer.read_packet();
+        assert_eq!(p, packet::Packet::CmdServerStats());
+    }
+
+    #[test]
+    fn test_cmd_write_with() {
+        let bytes = [
+            packet::CMD_WRITE_WITH, // packet type id
+            0,
+            1, // flags
+            0,
+            1, // pair count
+            0,
+            0,
+            0,
+            1,
+            b'k', // key
+            0,
+            0,
+            0,
+            1,
+            b'v', // value
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let p = packer.read_packet();
+        assert_eq!(
+            p,
+            packet::Packet::CmdWriteWith(packet::WRITE_SYNC, vec![b"k".to_vec(), b"v".to_vec()])
+        );
+    }
+
+    #[test]
+    fn test_cmd_stats() {
+        let bytes = [packet::CMD_STATS];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let p = packer.read_packet();
+        assert_eq!(p, packet::Packet::CmdStats());
+    }
+
+    #[test]
+    fn test_cmd_exists() {
+        let bytes = [
+            packet::CMD_EXISTS, // packet type id
+            0,
+            2, // key count
+            0,
+            0,
+            0,
+            1,
+            b'a', // key 1
+            0,
+            0,
+            0,
+            2,
+            b'b',
+            b'c', // key 2
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let p = packer.read_packet();
+        assert_eq!(
+            p,
+            packet::Packet::CmdExists(vec![b"a".to_vec(), b"bc".to_vec()])
+        );
+    }
+
+    #[test]
+    fn test_cmd_delete_range() {
+        let bytes = [
+            packet::CMD_DELETE_RANGE, // packet type id
+            0,
+            0,
+            0,
+            2,
+            b'a',
+            b'a', // start
+            0,
+            0,
+            0,
+            2,
+            b'a',
+            b'b', // end
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdDeleteRange(b"aa".to_vec(), b"ab".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_cmd_delete_prefix() {
+        let bytes = [
+            packet::CMD_DELETE_PREFIX, // packet type id
+            0,
+            0,
+            0,
+            2,
+            b't',
+            b'1', // prefix
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdDeletePrefix(b"t1".to_vec()));
+    }
+
+    #[test]
+    fn test_cmd_use_with_options() {
+        let bytes = [
+            packet::CMD_USE_WITH_OPTIONS, // packet type id
+            0,
+            0,
+            0,
+            2,
+            b'd',
+            b'b', // name
+            0,
+            0,
+            0,
+            3,
+            b'a',
+            b'=',
+            b'1', // options
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdUseWithOptions(b"db".to_vec(), b"a=1".to_vec())
+        );
+    }
+
     #[test]
     fn test_cmd_range_begin() {
-        let bytes = vec![
+        let bytes = [
             packet::CMD_RANGE_BEGIN, // packet type id
             2,
             16,
@@ -332,94 +823,391 @@ mod test_packet_reader {
 
     #[test]
     fn test_cmd_range_end() {
-        let bytes = vec![
+        let bytes = [
             packet::CMD_RANGE_END, // packet type id
             2,
             16,
         ];
         let mut packer = PacketReader::new(&bytes[..]);
         let packet = packer.read_packet();
-        assert_eq!(packet, packet::Packet::CmdRangeEnd(0x0210));
+        assert_eq!(packet, packet::Packet::CmdRangeEnd(0x0210));
+    }
+
+    #[test]
+    fn test_cmd_range_from_asc() {
+        let bytes = vec![
+            packet::CMD_RANGE_FROM_ASC, // packet type id
+            2,
+            16, // size
+            0,
+            0,
+            0,
+            5,
+            b'w',
+            b'o',
+            b'r',
+            b'l',
+            b'd', // token
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdRangeFromAsc(0x0210, b"world".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_cmd_range_from_asc_ex() {
+        let bytes = vec![
+            packet::CMD_RANGE_FROM_ASC_EX, // packet type id
+            2,
+            16, // size
+            0,
+            0,
+            0,
+            5,
+            b'w',
+            b'o',
+            b'r',
+            b'l',
+            b'd', // token
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdRangeFromAscEx(0x0210, b"world".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_cmd_range_from_desc() {
+        let bytes = vec![
+            packet::CMD_RANGE_FROM_DESC, // packet type id
+            2,
+            16, // size
+            0,
+            0,
+            0,
+            5,
+            b'w',
+            b'o',
+            b'r',
+            b'l',
+            b'd', // token
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdRangeFromDesc(0x0210, b"world".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_cmd_range_from_desc_ex() {
+        let bytes = vec![
+            packet::CMD_RANGE_FROM_DESC_EX, // packet type id
+            2,
+            16, // size
+            0,
+            0,
+            0,
+            5,
+            b'w',
+            b'o',
+            b'r',
+            b'l',
+            b'd', // token
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdRangeFromDescEx(0x0210, b"world".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_cmd_snapshot() {
+        let bytes = [packet::CMD_SNAPSHOT];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let p = packer.read_packet();
+        assert_eq!(p, packet::Packet::CmdSnapshot());
+    }
+
+    #[test]
+    fn test_cmd_snapshot_release() {
+        let bytes = vec![
+            packet::CMD_SNAPSHOT_RELEASE, // packet type id
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            1,
+            2, // handle
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdSnapshotRelease(0x0102));
+    }
+
+    #[test]
+    fn test_cmd_with_snapshot() {
+        let bytes = vec![
+            packet::CMD_WITH_SNAPSHOT, // packet type id
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            7, // handle
+            packet::CMD_RANGE_FROM_ASC_EX,
+            2,
+            16, // size
+            0,
+            0,
+            0,
+            5,
+            b'w',
+            b'o',
+            b'r',
+            b'l',
+            b'd', // token
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdWithSnapshot(
+                7,
+                Box::new(packet::Packet::CmdRangeFromAscEx(0x0210, b"world".to_vec()))
+            )
+        );
+    }
+
+    #[test]
+    fn test_cmd_backup() {
+        let bytes = [
+            packet::CMD_BACKUP, // packet type id
+            0,
+            0,
+            0,
+            4,
+            b'/',
+            b'b',
+            b'a',
+            b'k', // backup dir
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdBackup(b"/bak".to_vec()));
+    }
+
+    #[test]
+    fn test_cmd_checkpoint() {
+        let bytes = [
+            packet::CMD_CHECKPOINT, // packet type id
+            0,
+            0,
+            0,
+            4,
+            b'/',
+            b'c',
+            b'k',
+            b'p', // target dir
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdCheckpoint(b"/ckp".to_vec()));
+    }
+
+    #[test]
+    fn test_cmd_list_backups() {
+        let bytes = [
+            packet::CMD_LIST_BACKUPS, // packet type id
+            0,
+            0,
+            0,
+            4,
+            b'/',
+            b'b',
+            b'a',
+            b'k', // backup dir
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdListBackups(b"/bak".to_vec()));
+    }
+
+    #[test]
+    fn test_cmd_purge_backups() {
+        let bytes = [
+            packet::CMD_PURGE_BACKUPS, // packet type id
+            0,
+            3, // backups to keep
+            0,
+            0,
+            0,
+            4,
+            b'/',
+            b'b',
+            b'a',
+            b'k', // backup dir
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdPurgeBackups(3, b"/bak".to_vec()));
+    }
+
+    #[test]
+    fn test_cmd_compact() {
+        let bytes = [
+            packet::CMD_COMPACT, // packet type id
+            0,
+            0,
+            0,
+            1,
+            b'a', // start key
+            0,
+            0,
+            0,
+            0, // open end
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdCompact(b"a".to_vec(), vec![]));
+    }
+
+    #[test]
+    fn test_cmd_ingest() {
+        let bytes = [
+            packet::CMD_INGEST, // packet type id
+            0,
+            1, // file count
+            0,
+            0,
+            0,
+            5,
+            b'1',
+            b'.',
+            b's',
+            b's',
+            b't', // file
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdIngest(vec![b"1.sst".to_vec()]));
     }
 
     #[test]
-    fn test_cmd_range_from_asc() {
-        let bytes = vec![
-            packet::CMD_RANGE_FROM_ASC, // packet type id
-            2,
-            16, // size
+    fn test_cmd_changes() {
+        let bytes = [
+            packet::CMD_CHANGES, // packet type id
             0,
             0,
             0,
-            5,
-            b'w',
-            b'o',
-            b'r',
-            b'l',
-            b'd', // token
+            0,
+            0,
+            0,
+            1,
+            2, // since
+            0,
+            100, // limit
         ];
         let mut packer = PacketReader::new(&bytes[..]);
         let packet = packer.read_packet();
-        assert_eq!(
-            packet,
-            packet::Packet::CmdRangeFromAsc(0x0210, b"world".to_vec())
-        );
+        assert_eq!(packet, packet::Packet::CmdChanges(0x0102, 100));
     }
 
     #[test]
-    fn test_cmd_range_from_asc_ex() {
-        let bytes = vec![
-            packet::CMD_RANGE_FROM_ASC_EX, // packet type id
-            2,
-            16, // size
+    fn test_cmd_get_at() {
+        let bytes = [
+            packet::CMD_GET_AT, // packet type id
             0,
             0,
             0,
-            5,
-            b'w',
-            b'o',
-            b'r',
-            b'l',
-            b'd', // token
+            0,
+            0,
+            0,
+            0,
+            9, // seq
+            0,
+            0,
+            0,
+            1,
+            b'k', // key
         ];
         let mut packer = PacketReader::new(&bytes[..]);
         let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdGetAt(9, b"k".to_vec()));
+    }
+
+    #[test]
+    fn test_cmd_get_at_time() {
+        let mut bytes = vec![packet::CMD_GET_AT_TIME];
+        bytes.extend_from_slice(&1_700_000_000_000u64.to_be_bytes()); // time
+        bytes.extend_from_slice(&[0, 0, 0, 1, b'k']); // key
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
         assert_eq!(
             packet,
-            packet::Packet::CmdRangeFromAscEx(0x0210, b"world".to_vec())
+            packet::Packet::CmdGetAtTime(1_700_000_000_000, b"k".to_vec())
         );
     }
 
     #[test]
-    fn test_cmd_range_from_desc() {
-        let bytes = vec![
-            packet::CMD_RANGE_FROM_DESC, // packet type id
-            2,
-            16, // size
-            0,
-            0,
-            0,
-            5,
-            b'w',
-            b'o',
-            b'r',
-            b'l',
-            b'd', // token
-        ];
+    fn test_cmd_read_checkpoint() {
+        let mut bytes = vec![packet::CMD_READ_CHECKPOINT];
+        bytes.extend_from_slice(&3u64.to_be_bytes()); // handle
+        bytes.extend_from_slice(&[0, 0, 0, 7]);
+        bytes.extend_from_slice(b"CURRENT"); // file
+        bytes.extend_from_slice(&4096u64.to_be_bytes()); // offset
         let mut packer = PacketReader::new(&bytes[..]);
         let packet = packer.read_packet();
         assert_eq!(
             packet,
-            packet::Packet::CmdRangeFromDesc(0x0210, b"world".to_vec())
+            packet::Packet::CmdReadCheckpoint(3, b"CURRENT".to_vec(), 4096)
         );
     }
 
     #[test]
-    fn test_cmd_range_from_desc_ex() {
-        let bytes = vec![
-            packet::CMD_RANGE_FROM_DESC_EX, // packet type id
-            2,
-            16, // size
+    fn test_cmd_wait_compact() {
+        let bytes = [
+            packet::CMD_WAIT_COMPACT, // packet type id
+            0,
+            30, // timeout
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdWaitCompact(30));
+    }
+
+    #[test]
+    fn test_cmd_restore_backup() {
+        let bytes = [
+            packet::CMD_RESTORE_BACKUP, // packet type id
+            0,
+            0,
+            0,
+            4,
+            b'/',
+            b'b',
+            b'a',
+            b'k', // backup dir
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            2, // backup id
             0,
             0,
             0,
@@ -428,13 +1216,13 @@ mod test_packet_reader {
             b'o',
             b'r',
             b'l',
-            b'd', // token
+            b'd', // name
         ];
         let mut packer = PacketReader::new(&bytes[..]);
         let packet = packer.read_packet();
         assert_eq!(
             packet,
-            packet::Packet::CmdRangeFromDescEx(0x0210, b"world".to_vec())
+            packet::Packet::CmdRestoreBackup(b"/bak".to_vec(), 2, b"world".to_vec())
         );
     }
 
@@ -885,4 +1673,283 @@ mod test_packet_reader {
             ]),
         );
     }
+
+    #[test]
+    fn test_resp_number() {
+        let bytes = vec![
+            packet::RESP_NUMBER, // packet type id
+            0,
+            0,
+            0,
+            0,
+            0,
+            1,
+            0,
+            3, // number
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::RespNumber(0x010003));
+    }
+
+    #[test]
+    fn test_resp_numbers() {
+        let bytes = [
+            packet::RESP_NUMBERS, // packet type id
+            0,
+            2, // number count
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            1, // number 1
+            0,
+            0,
+            0,
+            0,
+            0,
+            1,
+            0,
+            3, // number 2
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::RespNumbers(vec![1, 0x010003]));
+    }
+
+    #[test]
+    fn test_resp_invalid_name() {
+        let bytes = [
+            packet::RESP_INVALID_NAME, // packet type id
+            0,
+            0,
+            0,
+            2,
+            b'.',
+            b'.', // message
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::RespInvalidName("..".to_string()));
+    }
+
+    #[test]
+    fn test_resp_changes() {
+        let bytes = [
+            packet::RESP_CHANGES, // packet type id
+            0,
+            2, // change count
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            7, // seq
+            packet::CHANGE_PUT,
+            0,
+            0,
+            0,
+            1,
+            b'k', // key
+            0,
+            0,
+            0,
+            1,
+            b'v', // value
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            8, // seq
+            packet::CHANGE_DELETE,
+            0,
+            0,
+            0,
+            1,
+            b'k', // key
+            0,
+            0,
+            0,
+            0, // value
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::RespChanges(vec![
+                packet::ChangeRecord {
+                    seq: 7,
+                    op: packet::CHANGE_PUT,
+                    key: b"k".to_vec(),
+                    value: b"v".to_vec(),
+                },
+                packet::ChangeRecord {
+                    seq: 8,
+                    op: packet::CHANGE_DELETE,
+                    key: b"k".to_vec(),
+                    value: vec![],
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_cmd_raft_vote() {
+        let bytes = [
+            packet::CMD_RAFT_VOTE, // packet type id
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            3, // term
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            2, // candidate
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            9, // last index
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            1, // last term
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::CmdRaftVote(3, 2, 9, 1));
+    }
+
+    #[test]
+    fn test_resp_not_leader() {
+        let bytes = [
+            packet::RESP_NOT_LEADER, // packet type id
+            0,
+            0,
+            0,
+            3,
+            b'h',
+            b':',
+            b'1', // leader
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::RespNotLeader("h:1".to_string()));
+    }
+
+    #[test]
+    fn test_cmd_move_slots() {
+        let bytes = [
+            packet::CMD_MOVE_SLOTS, // packet type id
+            0,
+            10, // first slot
+            0x01,
+            0x00, // last slot
+            0,
+            0,
+            0,
+            3,
+            b'h',
+            b':',
+            b'2', // address
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::CmdMoveSlots(10, 256, b"h:2".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_resp_moved() {
+        let bytes = [
+            packet::RESP_MOVED, // packet type id
+            0x2f,
+            0x96, // slot
+            0,
+            0,
+            0,
+            3,
+            b'h',
+            b':',
+            b'2', // owner
+        ];
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(packet, packet::Packet::RespMoved(12182, "h:2".to_string()));
+    }
+
+    #[test]
+    fn test_resp_versions() {
+        let mut bytes = vec![packet::RESP_VERSIONS, 0, 2]; // version count
+        bytes.extend_from_slice(&8u64.to_be_bytes()); // seq
+        bytes.extend_from_slice(&1000u64.to_be_bytes()); // time
+        bytes.extend_from_slice(&[0, 0, 0, 0]); // value
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+        bytes.extend_from_slice(&0u64.to_be_bytes());
+        bytes.extend_from_slice(&[0, 0, 0, 1, b'v']);
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::RespVersions(vec![
+                packet::VersionRecord {
+                    seq: 8,
+                    time_ms: 1000,
+                    value: vec![],
+                },
+                packet::VersionRecord {
+                    seq: 0,
+                    time_ms: 0,
+                    value: b"v".to_vec(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_resp_checkpoint() {
+        let mut bytes = vec![packet::RESP_CHECKPOINT];
+        bytes.extend_from_slice(&1u64.to_be_bytes()); // handle
+        bytes.extend_from_slice(&42u64.to_be_bytes()); // seq
+        bytes.extend_from_slice(&[0, 2]); // file count
+        bytes.extend_from_slice(&[0, 0, 0, 7]);
+        bytes.extend_from_slice(b"CURRENT");
+        bytes.extend_from_slice(&[0, 0, 0, 10]);
+        bytes.extend_from_slice(b"000009.sst");
+        let mut packer = PacketReader::new(&bytes[..]);
+        let packet = packer.read_packet();
+        assert_eq!(
+            packet,
+            packet::Packet::RespCheckpoint(
+                1,
+                42,
+                vec![b"CURRENT".to_vec(), b"000009.sst".to_vec()]
+            )
+        );
+    }
 }
@@ -2,7 +2,7 @@ use std::io::{Read, Write};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use crate::errors::PacketResult;
+use crate::errors::{PacketError, PacketResult};
 use crate::packet;
 
 pub struct PacketReaderWriter<T: Read + Write> {
@@ -57,6 +57,71 @@ impl<T: Read + Write> PacketReaderWriter<T> {
                 let token = self.read_token()?;
                 Ok(packet::Packet::CmdDetach(token))
             }
+            packet::CMD_DELETE_RANGE => {
+                let start = self.read_token()?;
+                let end = self.read_token()?;
+                Ok(packet::Packet::CmdDeleteRange(start, end))
+            }
+            packet::CMD_DELETE_PREFIX => {
+                let prefix = self.read_token()?;
+                Ok(packet::Packet::CmdDeletePrefix(prefix))
+            }
+            packet::CMD_USE_WITH_OPTIONS => {
+                let name = self.read_token()?;
+                let options = self.read_token()?;
+                Ok(packet::Packet::CmdUseWithOptions(name, options))
+            }
+            packet::CMD_DROP => {
+                let token = self.read_token()?;
+                Ok(packet::Packet::CmdDrop(token))
+            }
+            packet::CMD_CATALOG => Ok(packet::Packet::CmdCatalog()),
+            packet::CMD_SERVER_STATS => Ok(packet::Packet::CmdServerStats()),
+            packet::CMD_EXISTS => {
+                let key_count = self.read_size()?;
+                let mut keys = Vec::new();
+                for _ in 0..key_count {
+                    let key = self.read_token()?;
+                    keys.push(key);
+                }
+                Ok(packet::Packet::CmdExists(keys))
+            }
+            packet::CMD_STATS => Ok(packet::Packet::CmdStats()),
+            packet::CMD_WRITE_WITH => {
+                let flags = self.read_size()?;
+                let pairs = self.read_size()?;
+                let mut tokens = Vec::new();
+                for _ in 0..pairs {
+                    let token = self.read_token()?;
+                    tokens.push(token);
+                    let token = self.read_token()?;
+                    tokens.push(token);
+                }
+                Ok(packet::Packet::CmdWriteWith(flags, tokens))
+            }
+            packet::CMD_DELETE_WITH => {
+                let flags = self.read_size()?;
+                let key_count = self.read_size()?;
+                let mut keys = Vec::new();
+                for _ in 0..key_count {
+                    let key = self.read_token()?;
+                    keys.push(key);
+                }
+                Ok(packet::Packet::CmdDeleteWith(flags, keys))
+            }
+            packet::CMD_USE_EXISTING => {
+                let token = self.read_token()?;
+                Ok(packet::Packet::CmdUseExisting(token))
+            }
+            packet::CMD_USE_WITH => {
+                let flags = self.read_size()?;
+                let token = self.read_token()?;
+                Ok(packet::Packet::CmdUseWith(flags, token))
+            }
+            packet::CMD_SET_READ_ONLY => {
+                let read_only = self.read_size()? != 0;
+                Ok(packet::Packet::CmdSetReadOnly(read_only))
+            }
 
             packet::CMD_RANGE_BEGIN => {
                 let page_size = self.read_size()?;
@@ -87,6 +152,164 @@ impl<T: Read + Write> PacketReaderWriter<T> {
                 Ok(packet::Packet::CmdRangeFromDescEx(page_size, token))
             }
 
+            packet::CMD_SNAPSHOT => Ok(packet::Packet::CmdSnapshot()),
+            packet::CMD_SNAPSHOT_RELEASE => {
+                let handle = self.read_number()?;
+                Ok(packet::Packet::CmdSnapshotRelease(handle))
+            }
+            packet::CMD_WITH_SNAPSHOT => {
+                let handle = self.read_number()?;
+                let inner = self.read_packet()?;
+                Ok(packet::Packet::CmdWithSnapshot(handle, Box::new(inner)))
+            }
+
+            packet::CMD_BACKUP => {
+                let token = self.read_token()?;
+                Ok(packet::Packet::CmdBackup(token))
+            }
+            packet::CMD_CHECKPOINT => {
+                let token = self.read_token()?;
+                Ok(packet::Packet::CmdCheckpoint(token))
+            }
+            packet::CMD_LIST_BACKUPS => {
+                let token = self.read_token()?;
+                Ok(packet::Packet::CmdListBackups(token))
+            }
+            packet::CMD_PURGE_BACKUPS => {
+                let keep = self.read_size()?;
+                let token = self.read_token()?;
+                Ok(packet::Packet::CmdPurgeBackups(keep, token))
+            }
+            packet::CMD_RESTORE_BACKUP => {
+                let backup_dir = self.read_token()?;
+                let backup_id = self.read_number()?;
+                let name = self.read_token()?;
+                Ok(packet::Packet::CmdRestoreBackup(
+                    backup_dir, backup_id, name,
+                ))
+            }
+            packet::CMD_COMPACT => {
+                let start = self.read_token()?;
+                let end = self.read_token()?;
+                Ok(packet::Packet::CmdCompact(start, end))
+            }
+            packet::CMD_FLUSH => Ok(packet::Packet::CmdFlush()),
+            packet::CMD_WAIT_COMPACT => {
+                let timeout = self.read_size()?;
+                Ok(packet::Packet::CmdWaitCompact(timeout))
+            }
+            packet::CMD_INGEST => {
+                let file_count = self.read_size()?;
+                let mut files = Vec::new();
+                for _ in 0..file_count {
+                    let file = self.read_token()?;
+                    files.push(file);
+                }
+                Ok(packet::Packet::CmdIngest(files))
+            }
+
+            packet::CMD_CHANGES => {
+                let since = self.read_number()?;
+                let limit = self.read_size()?;
+                Ok(packet::Packet::CmdChanges(since, limit))
+            }
+            packet::CMD_LATEST_SEQUENCE => Ok(packet::Packet::CmdLatestSequence()),
+            packet::CMD_GET_AT => {
+                let seq = self.read_number()?;
+                let key = self.read_token()?;
+                Ok(packet::Packet::CmdGetAt(seq, key))
+            }
+            packet::CMD_HISTORY => {
+                let limit = self.read_size()?;
+                let key = self.read_token()?;
+                Ok(packet::Packet::CmdHistory(limit, key))
+            }
+            packet::CMD_FETCH_CHECKPOINT => Ok(packet::Packet::CmdFetchCheckpoint()),
+            packet::CMD_READ_CHECKPOINT => {
+                let handle = self.read_number()?;
+                let file = self.read_token()?;
+                let offset = self.read_number()?;
+                Ok(packet::Packet::CmdReadCheckpoint(handle, file, offset))
+            }
+            packet::CMD_GET_AT_TIME => {
+                let time_ms = self.read_number()?;
+                let key = self.read_token()?;
+                Ok(packet::Packet::CmdGetAtTime(time_ms, key))
+            }
+            packet::CMD_RELEASE_CHECKPOINT => {
+                Ok(packet::Packet::CmdReleaseCheckpoint(self.read_number()?))
+            }
+
+            packet::CMD_RAFT_VOTE => {
+                let term = self.read_number()?;
+                let candidate = self.read_number()?;
+                let last_index = self.read_number()?;
+                let last_term = self.read_number()?;
+                Ok(packet::Packet::CmdRaftVote(
+                    term, candidate, last_index, last_term,
+                ))
+            }
+            packet::CMD_RAFT_APPEND => {
+                let term = self.read_number()?;
+                let leader = self.read_number()?;
+                let prev_index = self.read_number()?;
+                let prev_term = self.read_number()?;
+                let commit = self.read_number()?;
+                let count = self.read_size()?;
+                let mut entries = Vec::new();
+                for _ in 0..count {
+                    let term = self.read_number()?;
+                    let data = self.read_token()?;
+                    entries.push(packet::RaftEntry { term, data });
+                }
+                Ok(packet::Packet::CmdRaftAppend(
+                    term, leader, prev_index, prev_term, commit, entries,
+                ))
+            }
+            packet::CMD_RAFT_SNAPSHOT => {
+                let chunk = packet::RaftSnapshotChunk {
+                    term: self.read_number()?,
+                    leader: self.read_number()?,
+                    index: self.read_number()?,
+                    last_term: self.read_number()?,
+                    config: self.read_token()?,
+                    file: self.read_token()?,
+                    offset: self.read_number()?,
+                    data: self.read_token()?,
+                    done: self.read_size()? != 0,
+                };
+                Ok(packet::Packet::CmdRaftSnapshot(chunk))
+            }
+            packet::CMD_RAFT_ADD_NODE => {
+                let id = self.read_number()?;
+                let addr = self.read_token()?;
+                Ok(packet::Packet::CmdRaftAddNode(id, addr))
+            }
+            packet::CMD_RAFT_REMOVE_NODE => {
+                let id = self.read_number()?;
+                Ok(packet::Packet::CmdRaftRemoveNode(id))
+            }
+
+            packet::CMD_SLOTS => Ok(packet::Packet::CmdSlots()),
+            packet::CMD_IMPORT_SLOTS => {
+                let first = self.read_size()?;
+                let last = self.read_size()?;
+                let source = self.read_token()?;
+                let addr = self.read_token()?;
+                Ok(packet::Packet::CmdImportSlots(first, last, source, addr))
+            }
+            packet::CMD_MOVE_SLOTS => {
+                let first = self.read_size()?;
+                let last = self.read_size()?;
+                let addr = self.read_token()?;
+                Ok(packet::Packet::CmdMoveSlots(first, last, addr))
+            }
+            packet::CMD_PURGE_SLOTS => {
+                let first = self.read_size()?;
+                let last = self.read_size()?;
+                Ok(packet::Packet::CmdPurgeSlots(first, last))
+            }
+
             packet::RESP_OK => {
                 let message = self.read_token()?;
                 let message = String::from_utf8(message)?;
@@ -97,6 +320,11 @@ impl<T: Read + Write> PacketReaderWriter<T> {
                 let message = String::from_utf8(message)?;
                 Ok(packet::Packet::RespError(message))
             }
+            packet::RESP_INVALID_NAME => {
+                let message = self.read_token()?;
+                let message = String::from_utf8(message)?;
+                Ok(packet::Packet::RespInvalidName(message))
+            }
             packet::RESP_TOKEN => {
                 let token = self.read_token()?;
                 Ok(packet::Packet::RespToken(token))
@@ -121,6 +349,78 @@ impl<T: Read + Write> PacketReaderWriter<T> {
                 }
                 Ok(packet::Packet::RespPairs(pairs))
             }
+            packet::RESP_NUMBER => {
+                let number = self.read_number()?;
+                Ok(packet::Packet::RespNumber(number))
+            }
+            packet::RESP_NUMBERS => {
+                let count = self.read_size()?;
+                let mut numbers = Vec::new();
+                for _ in 0..count {
+                    let number = self.read_number()?;
+                    numbers.push(number);
+                }
+                Ok(packet::Packet::RespNumbers(numbers))
+            }
+            packet::RESP_CHANGES => {
+                let count = self.read_size()?;
+                let mut changes = Vec::new();
+                for _ in 0..count {
+                    let seq = self.read_number()?;
+                    let op = self.read_header()?;
+                    let key = self.read_token()?;
+                    let value = self.read_token()?;
+                    changes.push(packet::ChangeRecord {
+                        seq,
+                        op,
+                        key,
+                        value,
+                    });
+                }
+                Ok(packet::Packet::RespChanges(changes))
+            }
+            packet::RESP_RAFT => {
+                let term = self.read_number()?;
+                let success = self.read_size()? != 0;
+                let index = self.read_number()?;
+                Ok(packet::Packet::RespRaft(term, success, index))
+            }
+            packet::RESP_NOT_LEADER => {
+                let leader = self.read_token()?;
+                let leader = String::from_utf8(leader)?;
+                Ok(packet::Packet::RespNotLeader(leader))
+            }
+            packet::RESP_MOVED => {
+                let slot = self.read_size()?;
+                let addr = self.read_token()?;
+                let addr = String::from_utf8(addr)?;
+                Ok(packet::Packet::RespMoved(slot, addr))
+            }
+            packet::RESP_VERSIONS => {
+                let count = self.read_size()?;
+                let mut versions = Vec::new();
+                for _ in 0..count {
+                    let seq = self.read_number()?;
+                    let time_ms = self.read_number()?;
+                    let value = self.read_token()?;
+                    versions.push(packet::VersionRecord {
+                        seq,
+                        time_ms,
+                        value,
+                    });
+                }
+                Ok(packet::Packet::RespVersions(versions))
+            }
+            packet::RESP_CHECKPOINT => {
+                let handle = self.read_number()?;
+                let seq = self.read_number()?;
+                let count = self.read_size()?;
+                let mut files = Vec::new();
+                for _ in 0..count {
+                    files.push(self.read_token()?);
+                }
+                Ok(packet::Packet::RespCheckpoint(handle, seq, files))
+            }
 
             _ => {
                 panic!("Unknown packet");
@@ -129,8 +429,16 @@ impl<T: Read + Write> PacketReaderWriter<T> {
     }
 
     pub fn write_packet(&mut self, packet: &packet::Packet) -> PacketResult<()> {
+        self.write_body(packet)?;
+        self.flush()?;
+
+        Ok(())
+    }
+
+    fn write_body(&mut self, packet: &packet::Packet) -> PacketResult<()> {
         match packet {
             packet::Packet::CmdWrite(pairs) => {
+                check_pairs(pairs)?;
                 self.write_header(packet::CMD_WRITE)?;
                 self.write_size((pairs.len() / 2) as u16)?;
                 for token in pairs {
@@ -165,6 +473,70 @@ impl<T: Read + Write> PacketReaderWriter<T> {
                 self.write_header(packet::CMD_DETACH)?;
                 self.write_token(name)?;
             }
+            packet::Packet::CmdDeleteRange(start, end) => {
+                self.write_header(packet::CMD_DELETE_RANGE)?;
+                self.write_token(start)?;
+                self.write_token(end)?;
+            }
+            packet::Packet::CmdDeletePrefix(prefix) => {
+                self.write_header(packet::CMD_DELETE_PREFIX)?;
+                self.write_token(prefix)?;
+            }
+            packet::Packet::CmdUseWithOptions(name, options) => {
+                self.write_header(packet::CMD_USE_WITH_OPTIONS)?;
+                self.write_token(name)?;
+                self.write_token(options)?;
+            }
+            packet::Packet::CmdDrop(name) => {
+                self.write_header(packet::CMD_DROP)?;
+                self.write_token(name)?;
+            }
+            packet::Packet::CmdCatalog() => {
+                self.write_header(packet::CMD_CATALOG)?;
+            }
+            packet::Packet::CmdServerStats() => {
+                self.write_header(packet::CMD_SERVER_STATS)?;
+            }
+            packet::Packet::CmdExists(keys) => {
+                self.write_header(packet::CMD_EXISTS)?;
+                self.write_size(keys.len() as u16)?;
+                for token in keys {
+                    self.write_token(token)?;
+                }
+            }
+            packet::Packet::CmdStats() => {
+                self.write_header(packet::CMD_STATS)?;
+            }
+            packet::Packet::CmdWriteWith(flags, pairs) => {
+                check_pairs(pairs)?;
+                self.write_header(packet::CMD_WRITE_WITH)?;
+                self.write_size(flags.to_owned())?;
+                self.write_size((pairs.len() / 2) as u16)?;
+                for token in pairs {
+                    self.write_token(token)?;
+                }
+            }
+            packet::Packet::CmdDeleteWith(flags, keys) => {
+                self.write_header(packet::CMD_DELETE_WITH)?;
+                self.write_size(flags.to_owned())?;
+                self.write_size(keys.len() as u16)?;
+                for token in keys {
+                    self.write_token(token)?;
+                }
+            }
+            packet::Packet::CmdUseExisting(name) => {
+                self.write_header(packet::CMD_USE_EXISTING)?;
+                self.write_token(name)?;
+            }
+            packet::Packet::CmdUseWith(flags, name) => {
+                self.write_header(packet::CMD_USE_WITH)?;
+                self.write_size(flags.to_owned())?;
+                self.write_token(name)?;
+            }
+            packet::Packet::CmdSetReadOnly(read_only) => {
+                self.write_header(packet::CMD_SET_READ_ONLY)?;
+                self.write_size(*read_only as u16)?;
+            }
 
             packet::Packet::CmdRangeBegin(page_size) => {
                 self.write_header(packet::CMD_RANGE_BEGIN)?;
@@ -195,6 +567,163 @@ impl<T: Read + Write> PacketReaderWriter<T> {
                 self.write_token(data)?;
             }
 
+            packet::Packet::CmdSnapshot() => {
+                self.write_header(packet::CMD_SNAPSHOT)?;
+            }
+            packet::Packet::CmdSnapshotRelease(handle) => {
+                self.write_header(packet::CMD_SNAPSHOT_RELEASE)?;
+                self.write_number(handle.to_owned())?;
+            }
+            packet::Packet::CmdWithSnapshot(handle, inner) => {
+                self.write_header(packet::CMD_WITH_SNAPSHOT)?;
+                self.write_number(handle.to_owned())?;
+                self.write_body(inner)?;
+            }
+
+            packet::Packet::CmdBackup(backup_dir) => {
+                self.write_header(packet::CMD_BACKUP)?;
+                self.write_token(backup_dir)?;
+            }
+            packet::Packet::CmdCheckpoint(target_dir) => {
+                self.write_header(packet::CMD_CHECKPOINT)?;
+                self.write_token(target_dir)?;
+            }
+            packet::Packet::CmdListBackups(backup_dir) => {
+                self.write_header(packet::CMD_LIST_BACKUPS)?;
+                self.write_token(backup_dir)?;
+            }
+            packet::Packet::CmdPurgeBackups(keep, backup_dir) => {
+                self.write_header(packet::CMD_PURGE_BACKUPS)?;
+                self.write_size(keep.to_owned())?;
+                self.write_token(backup_dir)?;
+            }
+            packet::Packet::CmdRestoreBackup(backup_dir, backup_id, name) => {
+                self.write_header(packet::CMD_RESTORE_BACKUP)?;
+                self.write_token(backup_dir)?;
+                self.write_number(backup_id.to_owned())?;
+                self.write_token(name)?;
+            }
+            packet::Packet::CmdCompact(start, end) => {
+                self.write_header(packet::CMD_COMPACT)?;
+                self.write_token(start)?;
+                self.write_token(end)?;
+            }
+            packet::Packet::CmdFlush() => {
+                self.write_header(packet::CMD_FLUSH)?;
+            }
+            packet::Packet::CmdWaitCompact(timeout) => {
+                self.write_header(packet::CMD_WAIT_COMPACT)?;
+                self.write_size(timeout.to_owned())?;
+            }
+            packet::Packet::CmdIngest(files) => {
+                self.write_header(packet::CMD_INGEST)?;
+                self.write_size(files.len() as u16)?;
+                for token in files {
+                    self.write_token(token)?;
+                }
+            }
+
+            packet::Packet::CmdChanges(since, limit) => {
+                self.write_header(packet::CMD_CHANGES)?;
+                self.write_number(since.to_owned())?;
+                self.write_size(limit.to_owned())?;
+            }
+            packet::Packet::CmdLatestSequence() => {
+                self.write_header(packet::CMD_LATEST_SEQUENCE)?;
+            }
+            packet::Packet::CmdGetAt(seq, key) => {
+                self.write_header(packet::CMD_GET_AT)?;
+                self.write_number(seq.to_owned())?;
+                self.write_token(key)?;
+            }
+            packet::Packet::CmdHistory(limit, key) => {
+                self.write_header(packet::CMD_HISTORY)?;
+                self.write_size(limit.to_owned())?;
+                self.write_token(key)?;
+            }
+            packet::Packet::CmdFetchCheckpoint() => {
+                self.write_header(packet::CMD_FETCH_CHECKPOINT)?;
+            }
+            packet::Packet::CmdReadCheckpoint(handle, file, offset) => {
+                self.write_header(packet::CMD_READ_CHECKPOINT)?;
+                self.write_number(handle.to_owned())?;
+                self.write_token(file)?;
+                self.write_number(offset.to_owned())?;
+            }
+            packet::Packet::CmdGetAtTime(time_ms, key) => {
+                self.write_header(packet::CMD_GET_AT_TIME)?;
+                self.write_number(time_ms.to_owned())?;
+                self.write_token(key)?;
+            }
+            packet::Packet::CmdReleaseCheckpoint(handle) => {
+                self.write_header(packet::CMD_RELEASE_CHECKPOINT)?;
+                self.write_number(handle.to_owned())?;
+            }
+
+            packet::Packet::CmdRaftVote(term, candidate, last_index, last_term) => {
+                self.write_header(packet::CMD_RAFT_VOTE)?;
+                self.write_number(term.to_owned())?;
+                self.write_number(candidate.to_owned())?;
+                self.write_number(last_index.to_owned())?;
+                self.write_number(last_term.to_owned())?;
+            }
+            packet::Packet::CmdRaftAppend(term, leader, prev_index, prev_term, commit, entries) => {
+                self.write_header(packet::CMD_RAFT_APPEND)?;
+                self.write_number(term.to_owned())?;
+                self.write_number(leader.to_owned())?;
+                self.write_number(prev_index.to_owned())?;
+                self.write_number(prev_term.to_owned())?;
+                self.write_number(commit.to_owned())?;
+                self.write_size(entries.len() as u16)?;
+                for entry in entries {
+                    self.write_number(entry.term)?;
+                    self.write_token(&entry.data)?;
+                }
+            }
+            packet::Packet::CmdRaftSnapshot(chunk) => {
+                self.write_header(packet::CMD_RAFT_SNAPSHOT)?;
+                self.write_number(chunk.term)?;
+                self.write_number(chunk.leader)?;
+                self.write_number(chunk.index)?;
+                self.write_number(chunk.last_term)?;
+                self.write_token(&chunk.config)?;
+                self.write_token(&chunk.file)?;
+                self.write_number(chunk.offset)?;
+                self.write_token(&chunk.data)?;
+                self.write_size(chunk.done as u16)?;
+            }
+            packet::Packet::CmdRaftAddNode(id, addr) => {
+                self.write_header(packet::CMD_RAFT_ADD_NODE)?;
+                self.write_number(id.to_owned())?;
+                self.write_token(addr)?;
+            }
+            packet::Packet::CmdRaftRemoveNode(id) => {
+                self.write_header(packet::CMD_RAFT_REMOVE_NODE)?;
+                self.write_number(id.to_owned())?;
+            }
+
+            packet::Packet::CmdSlots() => {
+                self.write_header(packet::CMD_SLOTS)?;
+            }
+            packet::Packet::CmdImportSlots(first, last, source, addr) => {
+                self.write_header(packet::CMD_IMPORT_SLOTS)?;
+                self.write_size(first.to_owned())?;
+                self.write_size(last.to_owned())?;
+                self.write_token(source)?;
+                self.write_token(addr)?;
+            }
+            packet::Packet::CmdMoveSlots(first, last, addr) => {
+                self.write_header(packet::CMD_MOVE_SLOTS)?;
+                self.write_size(first.to_owned())?;
+                self.write_size(last.to_owned())?;
+                self.write_token(addr)?;
+            }
+            packet::Packet::CmdPurgeSlots(first, last) => {
+                self.write_header(packet::CMD_PURGE_SLOTS)?;
+                self.write_size(first.to_owned())?;
+                self.write_size(last.to_owned())?;
+            }
+
             packet::Packet::RespOk(message) => {
                 self.write_header(packet::RESP_OK)?;
                 self.write_token(message.as_bytes())?;
@@ -203,6 +732,10 @@ impl<T: Read + Write> PacketReaderWriter<T> {
                 self.write_header(packet::RESP_ERROR)?;
                 self.write_token(message.as_bytes())?;
             }
+            packet::Packet::RespInvalidName(message) => {
+                self.write_header(packet::RESP_INVALID_NAME)?;
+                self.write_token(message.as_bytes())?;
+            }
             packet::Packet::RespToken(token) => {
                 self.write_header(packet::RESP_TOKEN)?;
                 self.write_token(token)?;
@@ -221,10 +754,62 @@ impl<T: Read + Write> PacketReaderWriter<T> {
                     self.write_token(token)?;
                 }
             }
+            packet::Packet::RespNumber(number) => {
+                self.write_header(packet::RESP_NUMBER)?;
+                self.write_number(number.to_owned())?;
+            }
+            packet::Packet::RespNumbers(numbers) => {
+                self.write_header(packet::RESP_NUMBERS)?;
+                self.write_size(numbers.len() as u16)?;
+                for number in numbers {
+                    self.write_number(number.to_owned())?;
+                }
+            }
+            packet::Packet::RespChanges(changes) => {
+                self.write_header(packet::RESP_CHANGES)?;
+                self.write_size(changes.len() as u16)?;
+                for change in changes {
+                    self.write_number(change.seq)?;
+                    self.write_header(change.op)?;
+                    self.write_token(&change.key)?;
+                    self.write_token(&change.value)?;
+                }
+            }
+            packet::Packet::RespRaft(term, success, index) => {
+                self.write_header(packet::RESP_RAFT)?;
+                self.write_number(term.to_owned())?;
+                self.write_size(*success as u16)?;
+                self.write_number(index.to_owned())?;
+            }
+            packet::Packet::RespNotLeader(leader) => {
+                self.write_header(packet::RESP_NOT_LEADER)?;
+                self.write_token(leader.as_bytes())?;
+            }
+            packet::Packet::RespMoved(slot, addr) => {
+                self.write_header(packet::RESP_MOVED)?;
+                self.write_size(slot.to_owned())?;
+                self.write_token(addr.as_bytes())?;
+            }
+            packet::Packet::RespVersions(versions) => {
+                self.write_header(packet::RESP_VERSIONS)?;
+                self.write_size(versions.len() as u16)?;
+                for version in versions {
+                    self.write_number(version.seq)?;
+                    self.write_number(version.time_ms)?;
+                    self.write_token(&version.value)?;
+                }
+            }
+            packet::Packet::RespCheckpoint(handle, seq, files) => {
+                self.write_header(packet::RESP_CHECKPOINT)?;
+                self.write_number(handle.to_owned())?;
+                self.write_number(seq.to_owned())?;
+                self.write_size(files.len() as u16)?;
+                for file in files {
+                    self.write_token(file)?;
+                }
+            }
         }
 
-        self.flush()?;
-
         Ok(())
     }
 
@@ -246,6 +831,15 @@ impl<T: Read + Write> PacketReaderWriter<T> {
         Ok(())
     }
 
+    fn read_number(&mut self) -> PacketResult<u64> {
+        Ok(self.rw.read_u64::<BigEndian>()?)
+    }
+
+    fn write_number(&mut self, number: u64) -> PacketResult<()> {
+        self.rw.write_u64::<BigEndian>(number)?;
+        Ok(())
+    }
+
     fn read_token(&mut self) -> PacketResult<Vec<u8>> {
         let length = self.rw.read_u32::<BigEndian>()?;
         if length == 0 {
@@ -258,7 +852,7 @@ impl<T: Read + Write> PacketReaderWriter<T> {
 
     fn write_token(&mut self, token: &[u8]) -> PacketResult<()> {
         self.rw.write_u32::<BigEndian>(token.len() as u32)?;
-        if token.len() == 0 {
+        if token.is_empty() {
             // TODO logging warn
             return Ok(());
         }
@@ -271,3 +865,64 @@ impl<T: Read + Write> PacketReaderWriter<T> {
         Ok(())
     }
 }
+
+/// The count of pairs sent would leave the last token of an odd number
+/// behind on the wire.
+fn check_pairs(pairs: &[Vec<u8>]) -> PacketResult<()> {
+    if pairs.len().is_multiple_of(2) {
+        Ok(())
+    } else {
+        Err(PacketError::InvalidPacket(
+            "a write needs a value for every key".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn round_trip(packet: &packet::Packet) -> PacketResult<packet::Packet> {
+        let mut rw = PacketReaderWriter::new(Cursor::new(vec![]));
+        rw.write_packet(packet)?;
+        rw.rw.set_position(0);
+        rw.read_packet()
+    }
+
+    #[test]
+    fn test_cmd_write() {
+        let packet = packet::Packet::CmdWrite(vec![b"k".to_vec(), b"v".to_vec()]);
+        assert_eq!(round_trip(&packet).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_cmd_write_odd_pairs() {
+        let mut rw = PacketReaderWriter::new(Cursor::new(vec![]));
+        let packet = packet::Packet::CmdWrite(vec![b"k".to_vec(), b"v".to_vec(), b"k2".to_vec()]);
+        assert!(matches!(
+            rw.write_packet(&packet),
+            Err(PacketError::InvalidPacket(_))
+        ));
+        // nothing is sent, so the connection stays usable
+        assert!(rw.rw.get_ref().is_empty());
+    }
+
+    #[test]
+    fn test_cmd_write_with_odd_pairs() {
+        let pairs = vec![b"k".to_vec(), b"v".to_vec()];
+        let packet = packet::Packet::CmdWriteWith(packet::WRITE_SYNC, pairs.clone());
+        assert_eq!(round_trip(&packet).unwrap(), packet);
+
+        let mut rw = PacketReaderWriter::new(Cursor::new(vec![]));
+        let mut odd = pairs;
+        odd.push(b"k2".to_vec());
+        let packet = packet::Packet::CmdWriteWith(packet::WRITE_SYNC, odd);
+        assert!(matches!(
+            rw.write_packet(&packet),
+            Err(PacketError::InvalidPacket(_))
+        ));
+        assert!(rw.rw.get_ref().is_empty());
+    }
+}
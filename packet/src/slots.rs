@@ -0,0 +1,77 @@
+/// Number of hash slots the keyspace of a sharded deployment is split into.
+pub const SLOT_COUNT: u16 = 16384;
+
+/// The hash slot of `key`: the CRC16 (XMODEM) of the key modulo
+/// `SLOT_COUNT`. When the key holds a non-empty `{tag}`, only the tag is
+/// hashed, so that keys sharing it land in the same slot.
+pub fn key_slot(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) % SLOT_COUNT
+}
+
+/// Parse a `first-last` range of slots, or a single slot.
+pub fn parse_slot_range(range: &str) -> Result<(u16, u16), String> {
+    let (first, last) = range.split_once('-').unwrap_or((range, range));
+    let parse = |slot: &str| match slot.trim().parse::<u16>() {
+        Ok(slot) if slot < SLOT_COUNT => Ok(slot),
+        _ => Err(format!("invalid slot `{}`", slot)),
+    };
+    let (first, last) = (parse(first)?, parse(last)?);
+    if first > last {
+        return Err(format!("invalid slot range `{}`", range));
+    }
+    Ok((first, last))
+}
+
+fn hash_tag(key: &[u8]) -> &[u8] {
+    if let Some(open) = key.iter().position(|&b| b == b'{') {
+        if let Some(len) = key[open + 1..].iter().position(|&b| b == b'}') {
+            if len > 0 {
+                return &key[open + 1..open + 1 + len];
+            }
+        }
+    }
+    key
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_slot() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_slot(b"foo"), 12182);
+        assert_eq!(key_slot(b"somekey"), 11058);
+        assert_eq!(key_slot(b""), 0);
+
+        assert_eq!(key_slot(b"{user1}.name"), key_slot(b"user1"));
+        assert_eq!(key_slot(b"{user1}.name"), key_slot(b"x{user1}"));
+        // empty or unclosed tags hash the whole key
+        assert_eq!(key_slot(b"{}user1"), crc16(b"{}user1") % SLOT_COUNT);
+        assert_eq!(key_slot(b"{user1"), crc16(b"{user1") % SLOT_COUNT);
+    }
+
+    #[test]
+    fn test_parse_slot_range() {
+        assert_eq!(parse_slot_range("0-8191"), Ok((0, 8191)));
+        assert_eq!(parse_slot_range("42"), Ok((42, 42)));
+        assert!(parse_slot_range("9-3").is_err());
+        assert!(parse_slot_range("0-16384").is_err());
+        assert!(parse_slot_range("a-b").is_err());
+    }
+}
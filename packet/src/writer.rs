@@ -48,6 +48,63 @@ impl<T: Write> PacketWriter<T> {
                 self.write_header(packet::CMD_DETACH);
                 self.write_token(name);
             }
+            packet::Packet::CmdDeleteRange(start, end) => {
+                self.write_header(packet::CMD_DELETE_RANGE);
+                self.write_token(start);
+                self.write_token(end);
+            }
+            packet::Packet::CmdDeletePrefix(prefix) => {
+                self.write_header(packet::CMD_DELETE_PREFIX);
+                self.write_token(prefix);
+            }
+            packet::Packet::CmdUseWithOptions(name, options) => {
+                self.write_header(packet::CMD_USE_WITH_OPTIONS);
+                self.write_token(name);
+                self.write_token(options);
+            }
+            packet::Packet::CmdDrop(name) => {
+                self.write_header(packet::CMD_DROP);
+                self.write_token(name);
+            }
+            packet::Packet::CmdCatalog() => self.write_header(packet::CMD_CATALOG),
+            packet::Packet::CmdServerStats() => self.write_header(packet::CMD_SERVER_STATS),
+            packet::Packet::CmdExists(keys) => {
+                self.write_header(packet::CMD_EXISTS);
+                self.write_size(keys.len() as u16);
+                for token in keys {
+                    self.write_token(token);
+                }
+            }
+            packet::Packet::CmdStats() => self.write_header(packet::CMD_STATS),
+            packet::Packet::CmdWriteWith(flags, pairs) => {
+                self.write_header(packet::CMD_WRITE_WITH);
+                self.write_size(flags.to_owned());
+                self.write_size((pairs.len() / 2) as u16);
+                for token in pairs {
+                    self.write_token(token);
+                }
+            }
+            packet::Packet::CmdDeleteWith(flags, keys) => {
+                self.write_header(packet::CMD_DELETE_WITH);
+                self.write_size(flags.to_owned());
+                self.write_size(keys.len() as u16);
+                for token in keys {
+                    self.write_token(token);
+                }
+            }
+            packet::Packet::CmdUseExisting(name) => {
+                self.write_header(packet::CMD_USE_EXISTING);
+                self.write_token(name);
+            }
+            packet::Packet::CmdUseWith(flags, name) => {
+                self.write_header(packet::CMD_USE_WITH);
+                self.write_size(flags.to_owned());
+                self.write_token(name);
+            }
+            packet::Packet::CmdSetReadOnly(read_only) => {
+                self.write_header(packet::CMD_SET_READ_ONLY);
+                self.write_size(*read_only as u16);
+            }
 
             packet::Packet::CmdRangeBegin(page_size) => {
                 self.write_header(packet::CMD_RANGE_BEGIN);
@@ -78,6 +135,153 @@ impl<T: Write> PacketWriter<T> {
                 self.write_token(data);
             }
 
+            packet::Packet::CmdSnapshot() => self.write_header(packet::CMD_SNAPSHOT),
+            packet::Packet::CmdSnapshotRelease(handle) => {
+                self.write_header(packet::CMD_SNAPSHOT_RELEASE);
+                self.write_number(handle.to_owned());
+            }
+            packet::Packet::CmdWithSnapshot(handle, inner) => {
+                self.write_header(packet::CMD_WITH_SNAPSHOT);
+                self.write_number(handle.to_owned());
+                self.write_packet(inner);
+            }
+
+            packet::Packet::CmdBackup(backup_dir) => {
+                self.write_header(packet::CMD_BACKUP);
+                self.write_token(backup_dir);
+            }
+            packet::Packet::CmdCheckpoint(target_dir) => {
+                self.write_header(packet::CMD_CHECKPOINT);
+                self.write_token(target_dir);
+            }
+            packet::Packet::CmdListBackups(backup_dir) => {
+                self.write_header(packet::CMD_LIST_BACKUPS);
+                self.write_token(backup_dir);
+            }
+            packet::Packet::CmdPurgeBackups(keep, backup_dir) => {
+                self.write_header(packet::CMD_PURGE_BACKUPS);
+                self.write_size(keep.to_owned());
+                self.write_token(backup_dir);
+            }
+            packet::Packet::CmdRestoreBackup(backup_dir, backup_id, name) => {
+                self.write_header(packet::CMD_RESTORE_BACKUP);
+                self.write_token(backup_dir);
+                self.write_number(backup_id.to_owned());
+                self.write_token(name);
+            }
+            packet::Packet::CmdCompact(start, end) => {
+                self.write_header(packet::CMD_COMPACT);
+                self.write_token(start);
+                self.write_token(end);
+            }
+            packet::Packet::CmdFlush() => self.write_header(packet::CMD_FLUSH),
+            packet::Packet::CmdWaitCompact(timeout) => {
+                self.write_header(packet::CMD_WAIT_COMPACT);
+                self.write_size(timeout.to_owned());
+            }
+            packet::Packet::CmdIngest(files) => {
+                self.write_header(packet::CMD_INGEST);
+                self.write_size(files.len() as u16);
+                for token in files {
+                    self.write_token(token);
+                }
+            }
+
+            packet::Packet::CmdChanges(since, limit) => {
+                self.write_header(packet::CMD_CHANGES);
+                self.write_number(since.to_owned());
+                self.write_size(limit.to_owned());
+            }
+            packet::Packet::CmdLatestSequence() => self.write_header(packet::CMD_LATEST_SEQUENCE),
+            packet::Packet::CmdGetAt(seq, key) => {
+                self.write_header(packet::CMD_GET_AT);
+                self.write_number(seq.to_owned());
+                self.write_token(key);
+            }
+            packet::Packet::CmdHistory(limit, key) => {
+                self.write_header(packet::CMD_HISTORY);
+                self.write_size(limit.to_owned());
+                self.write_token(key);
+            }
+            packet::Packet::CmdFetchCheckpoint() => self.write_header(packet::CMD_FETCH_CHECKPOINT),
+            packet::Packet::CmdReadCheckpoint(handle, file, offset) => {
+                self.write_header(packet::CMD_READ_CHECKPOINT);
+                self.write_number(handle.to_owned());
+                self.write_token(file);
+                self.write_number(offset.to_owned());
+            }
+            packet::Packet::CmdGetAtTime(time_ms, key) => {
+                self.write_header(packet::CMD_GET_AT_TIME);
+                self.write_number(time_ms.to_owned());
+                self.write_token(key);
+            }
+            packet::Packet::CmdReleaseCheckpoint(handle) => {
+                self.write_header(packet::CMD_RELEASE_CHECKPOINT);
+                self.write_number(handle.to_owned());
+            }
+
+            packet::Packet::CmdRaftVote(term, candidate, last_index, last_term) => {
+                self.write_header(packet::CMD_RAFT_VOTE);
+                self.write_number(term.to_owned());
+                self.write_number(candidate.to_owned());
+                self.write_number(last_index.to_owned());
+                self.write_number(last_term.to_owned());
+            }
+            packet::Packet::CmdRaftAppend(term, leader, prev_index, prev_term, commit, entries) => {
+                self.write_header(packet::CMD_RAFT_APPEND);
+                self.write_number(term.to_owned());
+                self.write_number(leader.to_owned());
+                self.write_number(prev_index.to_owned());
+                self.write_number(prev_term.to_owned());
+                self.write_number(commit.to_owned());
+                self.write_size(entries.len() as u16);
+                for entry in entries {
+                    self.write_number(entry.term);
+                    self.write_token(&entry.data);
+                }
+            }
+            packet::Packet::CmdRaftSnapshot(chunk) => {
+                self.write_header(packet::CMD_RAFT_SNAPSHOT);
+                self.write_number(chunk.term);
+                self.write_number(chunk.leader);
+                self.write_number(chunk.index);
+                self.write_number(chunk.last_term);
+                self.write_token(&chunk.config);
+                self.write_token(&chunk.file);
+                self.write_number(chunk.offset);
+                self.write_token(&chunk.data);
+                self.write_size(chunk.done as u16);
+            }
+            packet::Packet::CmdRaftAddNode(id, addr) => {
+                self.write_header(packet::CMD_RAFT_ADD_NODE);
+                self.write_number(id.to_owned());
+                self.write_token(addr);
+            }
+            packet::Packet::CmdRaftRemoveNode(id) => {
+                self.write_header(packet::CMD_RAFT_REMOVE_NODE);
+                self.write_number(id.to_owned());
+            }
+
+            packet::Packet::CmdSlots() => self.write_header(packet::CMD_SLOTS),
+            packet::Packet::CmdImportSlots(first, last, source, addr) => {
+                self.write_header(packet::CMD_IMPORT_SLOTS);
+                self.write_size(first.to_owned());
+                self.write_size(last.to_owned());
+                self.write_token(source);
+                self.write_token(addr);
+            }
+            packet::Packet::CmdMoveSlots(first, last, addr) => {
+                self.write_header(packet::CMD_MOVE_SLOTS);
+                self.write_size(first.to_owned());
+                self.write_size(last.to_owned());
+                self.write_token(addr);
+            }
+            packet::Packet::CmdPurgeSlots(first, last) => {
+                self.write_header(packet::CMD_PURGE_SLOTS);
+                self.write_size(first.to_owned());
+                self.write_size(last.to_owned());
+            }
+
             packet::Packet::RespOk(message) => {
                 self.write_header(packet::RESP_OK);
                 self.write_token(message.as_bytes());
@@ -86,6 +290,10 @@ impl<T: Write> PacketWriter<T> {
                 self.write_header(packet::RESP_ERROR);
                 self.write_token(message.as_bytes());
             }
+            packet::Packet::RespInvalidName(message) => {
+                self.write_header(packet::RESP_INVALID_NAME);
+                self.write_token(message.as_bytes());
+            }
             packet::Packet::RespToken(token) => {
                 self.write_header(packet::RESP_TOKEN);
                 self.write_token(token);
@@ -104,6 +312,60 @@ impl<T: Write> PacketWriter<T> {
                     self.write_token(token);
                 }
             }
+            packet::Packet::RespNumber(number) => {
+                self.write_header(packet::RESP_NUMBER);
+                self.write_number(number.to_owned());
+            }
+            packet::Packet::RespNumbers(numbers) => {
+                self.write_header(packet::RESP_NUMBERS);
+                self.write_size(numbers.len() as u16);
+                for number in numbers {
+                    self.write_number(number.to_owned());
+                }
+            }
+            packet::Packet::RespChanges(changes) => {
+                self.write_header(packet::RESP_CHANGES);
+                self.write_size(changes.len() as u16);
+                for change in changes {
+                    self.write_number(change.seq);
+                    self.write_header(change.op);
+                    self.write_token(&change.key);
+                    self.write_token(&change.value);
+                }
+            }
+            packet::Packet::RespRaft(term, success, index) => {
+                self.write_header(packet::RESP_RAFT);
+                self.write_number(term.to_owned());
+                self.write_size(*success as u16);
+                self.write_number(index.to_owned());
+            }
+            packet::Packet::RespNotLeader(leader) => {
+                self.write_header(packet::RESP_NOT_LEADER);
+                self.write_token(leader.as_bytes());
+            }
+            packet::Packet::RespMoved(slot, addr) => {
+                self.write_header(packet::RESP_MOVED);
+                self.write_size(slot.to_owned());
+                self.write_token(addr.as_bytes());
+            }
+            packet::Packet::RespVersions(versions) => {
+                self.write_header(packet::RESP_VERSIONS);
+                self.write_size(versions.len() as u16);
+                for version in versions {
+                    self.write_number(version.seq);
+                    self.write_number(version.time_ms);
+                    self.write_token(&version.value);
+                }
+            }
+            packet::Packet::RespCheckpoint(handle, seq, files) => {
+                self.write_header(packet::RESP_CHECKPOINT);
+                self.write_number(handle.to_owned());
+                self.write_number(seq.to_owned());
+                self.write_size(files.len() as u16);
+                for file in files {
+                    self.write_token(file);
+                }
+            }
         }
     }
 
@@ -115,11 +377,15 @@ impl<T: Write> PacketWriter<T> {
         self.writer.write_u16::<BigEndian>(size).unwrap();
     }
 
+    fn write_number(&mut self, number: u64) {
+        self.writer.write_u64::<BigEndian>(number).unwrap();
+    }
+
     fn write_token(&mut self, token: &[u8]) {
         self.writer
             .write_u32::<BigEndian>(token.len() as u32)
             .unwrap();
-        if token.len() == 0 {
+        if token.is_empty() {
             return;
         }
         self.writer.write_all(token).unwrap();
@@ -169,7 +435,7 @@ mod test_packet_writer {
     fn test_cmd_read() {
         let mut writer = Vec::new();
         let mut packer = PacketWriter::new(&mut writer);
-        packer.write_packet(&&packet::Packet::CmdRead(vec![
+        packer.write_packet(&packet::Packet::CmdRead(vec![
             b"key".to_vec(),
             b"val".to_vec(),
         ]));
@@ -201,7 +467,7 @@ mod test_packet_writer {
     fn test_cmd_delete() {
         let mut writer = Vec::new();
         let mut packer = PacketWriter::new(&mut writer);
-        packer.write_packet(&&packet::Packet::CmdDelete(vec![
+        packer.write_packet(&packet::Packet::CmdDelete(vec![
             b"key".to_vec(),
             b"val".to_vec(),
         ]));
@@ -240,6 +506,23 @@ mod test_packet_writer {
             [packet::CMD_USE, 0, 0, 0, 5, b'w', b'o', b'r', b'l', b'd'],
         );
     }
+
+    #[test]
+    fn test_cmd_use_existing() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdUseExisting(b"db".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_USE_EXISTING, 0, 0, 0, 2, b'd', b'b']);
+    }
+
+    #[test]
+    fn test_cmd_set_read_only() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        packer.write_packet(&packet::Packet::CmdSetReadOnly(true));
+        assert_eq!(writer, [packet::CMD_SET_READ_ONLY, 0, 1]);
+    }
     #[test]
     fn test_cmd_current_db() {
         let mut writer = Vec::new();
@@ -267,6 +550,142 @@ mod test_packet_writer {
             [packet::CMD_DETACH, 0, 0, 0, 5, b'w', b'o', b'r', b'l', b'd'],
         );
     }
+    #[test]
+    fn test_cmd_drop() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdDrop(b"world".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [packet::CMD_DROP, 0, 0, 0, 5, b'w', b'o', b'r', b'l', b'd'],
+        );
+    }
+
+    #[test]
+    fn test_cmd_catalog() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdCatalog();
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_CATALOG]);
+    }
+
+    #[test]
+    fn test_cmd_server_stats() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdServerStats();
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_SERVER_STATS]);
+    }
+
+    #[test]
+    fn test_cmd_delete_with() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdDeleteWith(packet::WRITE_NO_WAL, vec![b"k".to_vec()]);
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [packet::CMD_DELETE_WITH, 0, 2, 0, 1, 0, 0, 0, 1, b'k']
+        );
+    }
+
+    #[test]
+    fn test_cmd_stats() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdStats();
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_STATS]);
+    }
+
+    #[test]
+    fn test_cmd_exists() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdExists(vec![b"a".to_vec(), b"bc".to_vec()]);
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::CMD_EXISTS,
+                0,
+                2,
+                0,
+                0,
+                0,
+                1,
+                b'a',
+                0,
+                0,
+                0,
+                2,
+                b'b',
+                b'c'
+            ],
+        );
+    }
+    #[test]
+    fn test_cmd_delete_range() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdDeleteRange(b"aa".to_vec(), b"ab".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::CMD_DELETE_RANGE,
+                0,
+                0,
+                0,
+                2,
+                b'a',
+                b'a',
+                0,
+                0,
+                0,
+                2,
+                b'a',
+                b'b'
+            ],
+        );
+    }
+    #[test]
+    fn test_cmd_delete_prefix() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdDeletePrefix(b"t1".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_DELETE_PREFIX, 0, 0, 0, 2, b't', b'1'],);
+    }
+    #[test]
+    fn test_cmd_use_with_options() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdUseWithOptions(b"db".to_vec(), b"a=1".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::CMD_USE_WITH_OPTIONS,
+                0,
+                0,
+                0,
+                2,
+                b'd',
+                b'b',
+                0,
+                0,
+                0,
+                3,
+                b'a',
+                b'=',
+                b'1'
+            ],
+        );
+    }
 
     #[test]
     fn test_cmd_range_begin() {
@@ -386,6 +805,249 @@ mod test_packet_writer {
         );
     }
 
+    #[test]
+    fn test_cmd_snapshot() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdSnapshot();
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_SNAPSHOT],);
+    }
+
+    #[test]
+    fn test_cmd_snapshot_release() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdSnapshotRelease(0x0102);
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [packet::CMD_SNAPSHOT_RELEASE, 0, 0, 0, 0, 0, 0, 1, 2],
+        );
+    }
+
+    #[test]
+    fn test_cmd_with_snapshot() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet =
+            packet::Packet::CmdWithSnapshot(7, Box::new(packet::Packet::CmdRangeBegin(0x0210)));
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::CMD_WITH_SNAPSHOT,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                7,
+                packet::CMD_RANGE_BEGIN,
+                2,
+                16
+            ],
+        );
+    }
+
+    #[test]
+    fn test_cmd_backup() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdBackup(b"/bak".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [packet::CMD_BACKUP, 0, 0, 0, 4, b'/', b'b', b'a', b'k'],
+        );
+    }
+
+    #[test]
+    fn test_cmd_checkpoint() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdCheckpoint(b"/ckp".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [packet::CMD_CHECKPOINT, 0, 0, 0, 4, b'/', b'c', b'k', b'p'],
+        );
+    }
+
+    #[test]
+    fn test_cmd_list_backups() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdListBackups(b"/bak".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [packet::CMD_LIST_BACKUPS, 0, 0, 0, 4, b'/', b'b', b'a', b'k'],
+        );
+    }
+
+    #[test]
+    fn test_cmd_purge_backups() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdPurgeBackups(3, b"/bak".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::CMD_PURGE_BACKUPS,
+                0,
+                3,
+                0,
+                0,
+                0,
+                4,
+                b'/',
+                b'b',
+                b'a',
+                b'k'
+            ],
+        );
+    }
+
+    #[test]
+    fn test_cmd_compact() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdCompact(vec![], b"z".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_COMPACT, 0, 0, 0, 0, 0, 0, 0, 1, b'z']);
+    }
+
+    #[test]
+    fn test_cmd_ingest() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdIngest(vec![b"1.sst".to_vec()]);
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::CMD_INGEST,
+                0,
+                1,
+                0,
+                0,
+                0,
+                5,
+                b'1',
+                b'.',
+                b's',
+                b's',
+                b't'
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cmd_latest_sequence() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdLatestSequence();
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_LATEST_SEQUENCE]);
+    }
+
+    #[test]
+    fn test_cmd_history() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdHistory(10, b"k".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_HISTORY, 0, 10, 0, 0, 0, 1, b'k']);
+    }
+
+    #[test]
+    fn test_cmd_get_at_time() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        packer.write_packet(&packet::Packet::CmdGetAtTime(7, b"k".to_vec()));
+        let mut expected = vec![packet::CMD_GET_AT_TIME];
+        expected.extend_from_slice(&7u64.to_be_bytes());
+        expected.extend_from_slice(&[0, 0, 0, 1, b'k']);
+        assert_eq!(writer, expected);
+    }
+
+    #[test]
+    fn test_cmd_release_checkpoint() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        packer.write_packet(&packet::Packet::CmdReleaseCheckpoint(2));
+        assert_eq!(
+            writer,
+            [packet::CMD_RELEASE_CHECKPOINT, 0, 0, 0, 0, 0, 0, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_resp_checkpoint() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::RespCheckpoint(1, 42, vec![b"CURRENT".to_vec()]);
+        packer.write_packet(&packet);
+        let mut expected = vec![packet::RESP_CHECKPOINT];
+        expected.extend_from_slice(&1u64.to_be_bytes());
+        expected.extend_from_slice(&42u64.to_be_bytes());
+        expected.extend_from_slice(&[0, 1, 0, 0, 0, 7]);
+        expected.extend_from_slice(b"CURRENT");
+        assert_eq!(writer, expected);
+    }
+
+    #[test]
+    fn test_cmd_flush() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdFlush();
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::CMD_FLUSH]);
+    }
+
+    #[test]
+    fn test_cmd_restore_backup() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdRestoreBackup(b"/bak".to_vec(), 2, b"world".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::CMD_RESTORE_BACKUP,
+                0,
+                0,
+                0,
+                4,
+                b'/',
+                b'b',
+                b'a',
+                b'k',
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                2,
+                0,
+                0,
+                0,
+                5,
+                b'w',
+                b'o',
+                b'r',
+                b'l',
+                b'd'
+            ],
+        );
+    }
+
     #[test]
     fn test_resp_ok() {
         let mut writer = Vec::new();
@@ -509,4 +1171,195 @@ mod test_packet_writer {
             ],
         );
     }
+
+    #[test]
+    fn test_resp_number() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::RespNumber(0x010003);
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::RESP_NUMBER, 0, 0, 0, 0, 0, 1, 0, 3],);
+    }
+
+    #[test]
+    fn test_resp_numbers() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::RespNumbers(vec![1, 0x010003]);
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::RESP_NUMBERS,
+                0,
+                2,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                1,
+                0,
+                0,
+                0,
+                0,
+                0,
+                1,
+                0,
+                3
+            ],
+        );
+    }
+
+    #[test]
+    fn test_resp_invalid_name() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::RespInvalidName("..".to_string());
+        packer.write_packet(&packet);
+        assert_eq!(writer, [packet::RESP_INVALID_NAME, 0, 0, 0, 2, b'.', b'.']);
+    }
+
+    #[test]
+    fn test_resp_changes() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::RespChanges(vec![packet::ChangeRecord {
+            seq: 7,
+            op: packet::CHANGE_DELETE_RANGE,
+            key: b"a".to_vec(),
+            value: b"z".to_vec(),
+        }]);
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::RESP_CHANGES,
+                0,
+                1,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                7,
+                packet::CHANGE_DELETE_RANGE,
+                0,
+                0,
+                0,
+                1,
+                b'a',
+                0,
+                0,
+                0,
+                1,
+                b'z'
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resp_versions() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::RespVersions(vec![packet::VersionRecord {
+            seq: 7,
+            time_ms: 1000,
+            value: b"v".to_vec(),
+        }]);
+        packer.write_packet(&packet);
+        let mut expected = vec![packet::RESP_VERSIONS, 0, 1]; // version count
+        expected.extend_from_slice(&7u64.to_be_bytes());
+        expected.extend_from_slice(&1000u64.to_be_bytes());
+        expected.extend_from_slice(&[0, 0, 0, 1, b'v']);
+        assert_eq!(writer, expected);
+    }
+
+    #[test]
+    fn test_cmd_raft_append() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let entries = vec![packet::RaftEntry {
+            term: 2,
+            data: vec![0],
+        }];
+        let packet = packet::Packet::CmdRaftAppend(2, 1, 4, 1, 3, entries);
+        packer.write_packet(&packet);
+        let mut expected = vec![packet::CMD_RAFT_APPEND];
+        for number in [2u64, 1, 4, 1, 3] {
+            expected.extend_from_slice(&number.to_be_bytes());
+        }
+        expected.extend_from_slice(&[0, 1]); // entry count
+        expected.extend_from_slice(&2u64.to_be_bytes());
+        expected.extend_from_slice(&[0, 0, 0, 1, 0]);
+        assert_eq!(writer, expected);
+    }
+
+    #[test]
+    fn test_resp_raft() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::RespRaft(5, true, 7);
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::RESP_RAFT,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                5,
+                0,
+                1,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                7
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cmd_import_slots() {
+        let mut writer = Vec::new();
+        let mut packer = PacketWriter::new(&mut writer);
+        let packet = packet::Packet::CmdImportSlots(0, 99, b"a:1".to_vec(), b"b:2".to_vec());
+        packer.write_packet(&packet);
+        assert_eq!(
+            writer,
+            [
+                packet::CMD_IMPORT_SLOTS,
+                0,
+                0,
+                0,
+                99,
+                0,
+                0,
+                0,
+                3,
+                b'a',
+                b':',
+                b'1',
+                0,
+                0,
+                0,
+                3,
+                b'b',
+                b':',
+                b'2'
+            ]
+        );
+    }
 }
@@ -0,0 +1,61 @@
+use std::net::TcpStream;
+
+use packet::{Packet, PacketReaderWriter};
+
+use crate::errors::{ProxyError, ProxyResult};
+
+/// The connection of one client session to one node, opened on first use
+/// and again after a failure.
+pub struct Backend {
+    addr: String,
+    rw: Option<PacketReaderWriter<TcpStream>>,
+}
+
+impl Backend {
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            rw: None,
+        }
+    }
+
+    /// Send `request` and read the response. A failure to reach the node is
+    /// answered as an error from it.
+    pub fn request(&mut self, request: &Packet, select: Option<&Packet>) -> Packet {
+        self.call(request, select).unwrap_or_else(|e| {
+            self.reset();
+            Packet::RespError(format!("node {}: {}", self.addr, e))
+        })
+    }
+
+    /// Drop the connection, so the next request starts a new one.
+    pub fn reset(&mut self) {
+        self.rw = None;
+    }
+
+    /// Send `request`, connecting first if needed. `select` is sent ahead
+    /// on a new connection, to pick the database of the session.
+    fn call(&mut self, request: &Packet, select: Option<&Packet>) -> ProxyResult<Packet> {
+        let rw = match self.rw.as_mut() {
+            Some(rw) => rw,
+            None => {
+                let stream = TcpStream::connect(&self.addr)?;
+                stream.set_nodelay(true)?;
+                let mut rw = PacketReaderWriter::new(stream);
+                if let Some(select) = select {
+                    rw.write_packet(select)?;
+                    match rw.read_packet()? {
+                        Packet::RespOk(_) => {}
+                        Packet::RespError(msg) | Packet::RespInvalidName(msg) => {
+                            return Err(ProxyError::Select(msg))
+                        }
+                        resp => return Err(ProxyError::Select(format!("{:?}", resp))),
+                    }
+                }
+                self.rw.insert(rw)
+            }
+        };
+        rw.write_packet(request)?;
+        Ok(rw.read_packet()?)
+    }
+}
@@ -0,0 +1,192 @@
+extern crate packet;
+extern crate rsdbrs;
+
+use std::iter;
+use std::process;
+
+use clap::Parser;
+use packet::{parse_slot_range, SLOT_COUNT};
+use rsdbrs::{RsDBClient, RsDBResult};
+
+/// Move hash slots between sharded rsdb-server nodes until each serves
+/// about as many, a range at a time, while they keep serving clients.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = "RSDB slot rebalancing")]
+struct Args {
+    /// Nodes to share the slots between, as clients reach them. New nodes
+    /// are started with an empty `--slots`
+    #[arg(long, value_delimiter = ',', required = true)]
+    nodes: Vec<String>,
+
+    /// Print the moves without making them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Most slots moved by one import
+    #[arg(long, default_value_t = 1024)]
+    batch: u16,
+}
+
+/// Slots `first` to `last` to move from node `from` to node `to`.
+struct Move {
+    first: u16,
+    last: u16,
+    from: usize,
+    to: usize,
+}
+
+/// The ranges of slots the node at `addr` serves.
+fn served(addr: &str) -> RsDBResult<Vec<(u16, u16)>> {
+    let mut client = RsDBClient::new();
+    client.connect(addr)?;
+    let mut ranges = vec![];
+    for (range, moved_to) in client.slots()? {
+        if moved_to.is_empty() {
+            let range = parse_slot_range(&range).map_err(rsdbrs::RsDBError::RespError)?;
+            ranges.push(range);
+        }
+    }
+    Ok(ranges)
+}
+
+/// Moves leaving each node with its share of the slots served, give or
+/// take one. Nodes over their share give away their highest slots.
+fn plan(served: &[Vec<(u16, u16)>], batch: u16) -> Vec<Move> {
+    let counts: Vec<usize> = served
+        .iter()
+        .map(|ranges| ranges.iter().map(|(f, l)| (l - f) as usize + 1).sum())
+        .collect();
+    let total: usize = counts.iter().sum();
+    let nodes = served.len();
+    let share = |node: usize| total / nodes + (node < total % nodes) as usize;
+
+    let mut given = vec![];
+    for (node, ranges) in served.iter().enumerate() {
+        let excess = counts[node].saturating_sub(share(node));
+        let slots = ranges.iter().rev().flat_map(|&(f, l)| (f..=l).rev());
+        given.extend(slots.take(excess).map(|slot| (slot, node)));
+    }
+    given.sort();
+    let mut takers =
+        (0..nodes).flat_map(|node| iter::repeat_n(node, share(node).saturating_sub(counts[node])));
+
+    let mut moves: Vec<Move> = vec![];
+    for (slot, from) in given {
+        let Some(to) = takers.next() else {
+            break;
+        };
+        match moves.last_mut() {
+            Some(m)
+                if m.from == from
+                    && m.to == to
+                    && m.last + 1 == slot
+                    && m.last - m.first + 1 < batch =>
+            {
+                m.last = slot
+            }
+            _ => moves.push(Move {
+                first: slot,
+                last: slot,
+                from,
+                to,
+            }),
+        }
+    }
+    moves
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let mut ranges = vec![];
+    for addr in &args.nodes {
+        match served(addr) {
+            Ok(served) => ranges.push(served),
+            Err(e) => {
+                eprintln!("Error: {}: {}", addr, e);
+                process::exit(1);
+            }
+        }
+    }
+    let total: usize = ranges
+        .iter()
+        .flatten()
+        .map(|(f, l)| (l - f) as usize + 1)
+        .sum();
+    if total < SLOT_COUNT as usize {
+        eprintln!("Warning: the nodes serve {} of {} slots", total, SLOT_COUNT);
+    }
+
+    let moves = plan(&ranges, args.batch.max(1));
+    if moves.is_empty() {
+        println!("Info: Balanced.");
+    }
+    for m in moves {
+        let (from, to) = (&args.nodes[m.from], &args.nodes[m.to]);
+        println!("Slots {}-{}: {} -> {}", m.first, m.last, from, to);
+        if args.dry_run {
+            continue;
+        }
+        let mut client = RsDBClient::new();
+        let imported = client
+            .connect(to)
+            .and_then(|()| client.import_slots(m.first, m.last, from, to));
+        match imported {
+            Ok(figures) => {
+                let figures: Vec<String> =
+                    figures.iter().map(|(k, v)| format!("{k}={v}")).collect();
+                println!("    {}", figures.join(" "));
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(served: &[Vec<(u16, u16)>], batch: u16) -> Vec<(u16, u16, usize, usize)> {
+        let moves = plan(served, batch).into_iter();
+        moves.map(|m| (m.first, m.last, m.from, m.to)).collect()
+    }
+
+    #[test]
+    fn test_plan_new_node() {
+        let served = [vec![(0, 16383)], vec![]];
+        assert_eq!(moves(&served, 16384), vec![(8192, 16383, 0, 1)]);
+        assert_eq!(
+            moves(&served, 4096),
+            vec![(8192, 12287, 0, 1), (12288, 16383, 0, 1)]
+        );
+    }
+
+    #[test]
+    fn test_plan_balanced() {
+        let served = [vec![(0, 5461)], vec![(5462, 10922)], vec![(10923, 16383)]];
+        assert!(moves(&served, 1024).is_empty());
+        // off by one slot at most
+        let served = [vec![(0, 8191)], vec![(8192, 16383)]];
+        assert!(moves(&served, 1024).is_empty());
+    }
+
+    #[test]
+    fn test_plan_highest_slots_given_away() {
+        let served = [vec![(0, 99), (10000, 16383)], vec![(100, 9999)], vec![]];
+        let total = 16384 / 3;
+        let mut counts = [6484, 9900, 0];
+        for (first, last, from, to) in moves(&served, 16384) {
+            let count = (last - first + 1) as usize;
+            counts[from] -= count;
+            counts[to] += count;
+            assert!(served[from].iter().any(|&(f, l)| f <= first && last <= l));
+        }
+        assert!(counts
+            .iter()
+            .all(|&count| count == total || count == total + 1));
+        assert_eq!(counts.iter().sum::<usize>(), 16384);
+    }
+}
@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::Error as IOErr;
+
+use packet::errors::PacketError;
+
+#[derive(Debug)]
+pub enum ProxyError {
+    IOError(IOErr),
+    PacketError(PacketError),
+    /// A node refused to select the database of the connection.
+    Select(String),
+}
+
+impl Error for ProxyError {}
+
+impl From<IOErr> for ProxyError {
+    fn from(e: IOErr) -> Self {
+        ProxyError::IOError(e)
+    }
+}
+
+impl From<PacketError> for ProxyError {
+    fn from(e: PacketError) -> Self {
+        ProxyError::PacketError(e)
+    }
+}
+
+impl Display for ProxyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IOError(e) => {
+                write!(f, "IOError - {e}")
+            }
+            Self::PacketError(e) => {
+                write!(f, "PacketError - {e}")
+            }
+            Self::Select(msg) => {
+                write!(f, "Select - {msg}")
+            }
+        }
+    }
+}
+
+pub type ProxyResult<T> = Result<T, ProxyError>;
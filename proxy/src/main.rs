@@ -0,0 +1,143 @@
+extern crate packet;
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::os::unix::net::UnixListener;
+use std::process;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use clap::Parser;
+use packet::PacketReaderWriter;
+
+mod backend;
+mod errors;
+mod session;
+mod slotmap;
+
+use errors::ProxyResult;
+use session::Session;
+use slotmap::SlotMap;
+
+/// How often the slot map is refreshed from the slot tables of the nodes.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Route the commands of clients to the rsdb-server nodes owning the hash
+/// slots of their keys.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = "RSDB routing proxy")]
+struct Args {
+    #[arg(short, long)]
+    addr: Option<String>,
+
+    #[arg(short, long)]
+    unix_addr: Option<String>,
+
+    /// Nodes to split the hash slots evenly between, in slot order
+    #[arg(
+        long,
+        value_delimiter = ',',
+        required_unless_present = "shard",
+        conflicts_with = "shard"
+    )]
+    nodes: Vec<String>,
+
+    /// Slots of a node as `start-end=addr`, repeated to cover all 16384
+    #[arg(long)]
+    shard: Vec<String>,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let pkg_name = env!("CARGO_PKG_NAME");
+    let pkg_version = env!("CARGO_PKG_VERSION");
+
+    println!("\n\t{pkg_name} {pkg_version}\n");
+
+    let slots = if args.shard.is_empty() {
+        SlotMap::even(args.nodes)
+    } else {
+        SlotMap::from_shards(&args.shard).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        })
+    };
+    let slots = Arc::new(RwLock::new(Arc::new(slots)));
+    slotmap::refresh(&slots);
+    println!("    > Listening at tcp  address {:?}", &args.addr);
+    println!("    > Listening at unix address {:?}", &args.unix_addr);
+    let map = slots.read().unwrap().clone();
+    for (node, addr) in map.nodes().iter().enumerate() {
+        println!("    > Slots {} at {}", map.ranges(node), addr);
+    }
+    println!();
+
+    let refreshed = slots.clone();
+    thread::spawn(move || loop {
+        thread::sleep(REFRESH_INTERVAL);
+        slotmap::refresh(&refreshed);
+    });
+
+    if let Some(addr) = args.unix_addr {
+        let unix_sock = UnixListener::bind(addr).unwrap();
+        let slots = slots.clone();
+        thread::spawn(move || {
+            for stream in unix_sock.incoming() {
+                match stream {
+                    Err(e) => eprintln!("error: {}", e),
+                    Ok(stream) => {
+                        let slots = slots.clone();
+                        thread::spawn(move || {
+                            handler(stream, "<local unix client>", slots).unwrap_or_else(|error| {
+                                eprintln!("{:?}", error);
+                            });
+                        });
+                    }
+                }
+            }
+        });
+    }
+
+    if let Some(addr) = args.addr {
+        let listener = TcpListener::bind(addr).unwrap();
+        for stream in listener.incoming() {
+            match stream {
+                Err(e) => eprintln!("error: {}", e),
+                Ok(stream) => {
+                    let peer_name = format!("{}", stream.peer_addr().unwrap());
+                    stream.set_nodelay(true).unwrap();
+                    let slots = slots.clone();
+                    thread::spawn(move || {
+                        handler(stream, &peer_name, slots).unwrap_or_else(|error| {
+                            eprintln!("{:?}", error);
+                        });
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn handler<T: Read + Write>(
+    stream: T,
+    peer_name: &str,
+    slots: Arc<RwLock<Arc<SlotMap>>>,
+) -> ProxyResult<()> {
+    println!("Connection from {}", peer_name);
+
+    let mut rw = PacketReaderWriter::new(stream);
+    let mut session = Session::new(slots);
+    loop {
+        let packet = rw.read_packet();
+        if packet.is_err() {
+            println!("Connection closed by client: <{peer_name}>");
+            break;
+        }
+        let resp = session.handle(packet?);
+        rw.write_packet(&resp)?;
+    }
+
+    Ok(())
+}
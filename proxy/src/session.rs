@@ -0,0 +1,854 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use packet::Packet;
+
+use crate::backend::Backend;
+use crate::slotmap::{self, SlotMap};
+
+/// Times a command on keys is sent again after a node answers that a slot
+/// moved, each after refreshing the slot map.
+const MOVED_RETRIES: usize = 3;
+
+/// The state of one client connection to the proxy: a connection to every
+/// node, the selected database and the snapshots taken.
+///
+/// Commands on keys go to the nodes owning them, split by node and sent to
+/// the nodes in parallel when a batch spans several. Commands on whole
+/// databases go to every node. A batch is not atomic across nodes: when one
+/// node fails, the others may have applied their part.
+///
+/// While slots move between nodes, a node may hold keys of slots it does
+/// not own; range scans leave them out.
+///
+/// Nodes are reached at the addresses in the slot map only. A node of a
+/// raft group which is not its leader answers writes with `RespNotLeader`,
+/// which is passed on to the client as it is rather than followed, so the
+/// map should list the leaders.
+pub struct Session {
+    slots: Arc<RwLock<Arc<SlotMap>>>,
+    backends: Vec<Backend>,
+    /// Command which selected the current database, sent again on new
+    /// connections to nodes.
+    select: Option<Packet>,
+    /// Handle of the snapshot on each node, and the slot map when it was
+    /// taken, by proxy handle.
+    snapshots: HashMap<u64, (Vec<u64>, Arc<SlotMap>)>,
+    next_handle: u64,
+}
+
+impl Session {
+    pub fn new(slots: Arc<RwLock<Arc<SlotMap>>>) -> Self {
+        Self {
+            slots,
+            backends: vec![],
+            select: None,
+            snapshots: HashMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    pub fn handle(&mut self, packet: Packet) -> Packet {
+        let mut retries = if on_keys(&packet) { MOVED_RETRIES } else { 0 };
+        loop {
+            self.sync_backends();
+            if retries == 0 {
+                return self.dispatch(packet);
+            }
+            match self.dispatch(packet.clone()) {
+                Packet::RespMoved(..) => {
+                    retries -= 1;
+                    slotmap::refresh(&self.slots);
+                }
+                resp => return resp,
+            }
+        }
+    }
+
+    fn map(&self) -> Arc<SlotMap> {
+        self.slots.read().unwrap().clone()
+    }
+
+    /// Add a connection for each node the slot map has gained.
+    fn sync_backends(&mut self) {
+        let map = self.map();
+        for addr in &map.nodes()[self.backends.len()..] {
+            self.backends.push(Backend::new(addr));
+        }
+    }
+
+    fn dispatch(&mut self, packet: Packet) -> Packet {
+        match packet {
+            Packet::CmdUse(_)
+            | Packet::CmdUseExisting(_)
+            | Packet::CmdUseWith(..)
+            | Packet::CmdUseWithOptions(..) => self.select_db(packet),
+            // every node has the same database selected
+            Packet::CmdCurrentDB() => self.fan_out(vec![(0, packet)]).remove(0).1,
+            Packet::CmdListDb() => {
+                let mut names = BTreeSet::new();
+                for (_, resp) in self.broadcast(&packet) {
+                    match resp {
+                        Packet::RespTokens(tokens) => names.extend(tokens),
+                        resp => return unexpected(resp),
+                    }
+                }
+                Packet::RespTokens(names.into_iter().collect())
+            }
+            Packet::CmdCatalog() => {
+                let mut entries = BTreeMap::new();
+                for (_, resp) in self.broadcast(&packet) {
+                    match resp {
+                        Packet::RespPairs(tokens) => {
+                            for (name, state) in into_pairs(tokens) {
+                                entries.entry(name).or_insert(state);
+                            }
+                        }
+                        resp => return unexpected(resp),
+                    }
+                }
+                Packet::RespPairs(entries.into_iter().flat_map(|(k, v)| [k, v]).collect())
+            }
+            Packet::CmdSetReadOnly(_) => all_ok(self.broadcast(&packet)),
+            Packet::CmdDetach(ref name) | Packet::CmdDrop(ref name) => {
+                let resp = all_ok(self.broadcast(&packet));
+                if matches!(resp, Packet::RespOk(_)) && self.selected() == Some(name) {
+                    self.select = None;
+                }
+                resp
+            }
+            Packet::CmdWrite(pairs) => self.write(pairs, Packet::CmdWrite),
+            Packet::CmdWriteWith(flags, pairs) => {
+                self.write(pairs, |pairs| Packet::CmdWriteWith(flags, pairs))
+            }
+            Packet::CmdDelete(keys) => self.delete(keys, Packet::CmdDelete),
+            Packet::CmdDeleteWith(flags, keys) => {
+                self.delete(keys, |keys| Packet::CmdDeleteWith(flags, keys))
+            }
+            Packet::CmdRead(keys) => self.read(keys, None),
+            Packet::CmdExists(keys) => {
+                let build = |_, keys| Packet::CmdExists(keys);
+                self.gather(&self.map(), &keys, build, |resp| match resp {
+                    Packet::RespNumbers(found) => Ok(found),
+                    resp => Err(resp),
+                })
+                .map_or_else(|resp| resp, Packet::RespNumbers)
+            }
+            Packet::CmdGetAt(_, ref key)
+            | Packet::CmdGetAtTime(_, ref key)
+            | Packet::CmdHistory(_, ref key) => {
+                let node = self.map().node_of(key);
+                self.backends[node].request(&packet, self.select.as_ref())
+            }
+            Packet::CmdDeleteRange(..) | Packet::CmdDeletePrefix(_) => {
+                let mut deleted = 0;
+                for (_, resp) in self.broadcast(&packet) {
+                    match resp {
+                        Packet::RespNumber(count) => deleted += count,
+                        resp => return unexpected(resp),
+                    }
+                }
+                Packet::RespNumber(deleted)
+            }
+            Packet::CmdRangeBegin(_)
+            | Packet::CmdRangeEnd(_)
+            | Packet::CmdRangeFromAsc(..)
+            | Packet::CmdRangeFromAscEx(..)
+            | Packet::CmdRangeFromDesc(..)
+            | Packet::CmdRangeFromDescEx(..) => self.range(&packet, None),
+            Packet::CmdSnapshot() => self.snapshot(),
+            Packet::CmdSnapshotRelease(handle) => match self.snapshots.remove(&handle) {
+                Some((handles, _)) => {
+                    let requests = handles
+                        .into_iter()
+                        .enumerate()
+                        .map(|(node, handle)| (node, Packet::CmdSnapshotRelease(handle)));
+                    all_ok(self.fan_out(requests.collect()))
+                }
+                None => Packet::RespError("invalid snapshot handle".to_string()),
+            },
+            Packet::CmdWithSnapshot(handle, cmd) => match *cmd {
+                Packet::CmdRead(keys) => self.read(keys, Some(handle)),
+                Packet::CmdRangeBegin(_)
+                | Packet::CmdRangeEnd(_)
+                | Packet::CmdRangeFromAsc(..)
+                | Packet::CmdRangeFromAscEx(..)
+                | Packet::CmdRangeFromDesc(..)
+                | Packet::CmdRangeFromDescEx(..) => self.range(&cmd, Some(handle)),
+                _ => Packet::RespError("command not supported on snapshot".to_string()),
+            },
+            Packet::CmdStats()
+            | Packet::CmdCompact(..)
+            | Packet::CmdFlush()
+            | Packet::CmdWaitCompact(_) => self.node_pairs(&packet),
+            Packet::CmdServerStats() => {
+                let mut tokens = vec![
+                    b"proxy_nodes".to_vec(),
+                    self.backends.len().to_string().into_bytes(),
+                ];
+                let map = self.map();
+                for (node, addr) in map.nodes().iter().enumerate() {
+                    tokens.push(format!("slots.{}", addr).into_bytes());
+                    tokens.push(map.ranges(node).into_bytes());
+                }
+                match self.node_pairs(&packet) {
+                    Packet::RespPairs(pairs) => tokens.extend(pairs),
+                    resp => return resp,
+                }
+                Packet::RespPairs(tokens)
+            }
+            _ => Packet::RespError("command not supported through the proxy".to_string()),
+        }
+    }
+
+    fn selected(&self) -> Option<&Vec<u8>> {
+        match self.select.as_ref()? {
+            Packet::CmdUse(name) | Packet::CmdUseExisting(name) => Some(name),
+            Packet::CmdUseWith(_, name) | Packet::CmdUseWithOptions(name, _) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Select a database on every node. If any of them refuses, the
+    /// connections are dropped so that all nodes keep the previous one.
+    fn select_db(&mut self, packet: Packet) -> Packet {
+        let resp = all_ok(self.broadcast(&packet));
+        if matches!(resp, Packet::RespOk(_)) {
+            self.select = Some(packet);
+        } else {
+            self.backends.iter_mut().for_each(Backend::reset);
+        }
+        resp
+    }
+
+    /// Send each request to its node, all nodes at once, and return the
+    /// responses by node.
+    fn fan_out(&mut self, requests: Vec<(usize, Packet)>) -> Vec<(usize, Packet)> {
+        let select = self.select.as_ref();
+        if let [(node, request)] = requests.as_slice() {
+            return vec![(*node, self.backends[*node].request(request, select))];
+        }
+        let mut requests: HashMap<usize, Packet> = requests.into_iter().collect();
+        thread::scope(|scope| {
+            let calls: Vec<_> = self
+                .backends
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(node, backend)| {
+                    let request = requests.remove(&node)?;
+                    Some((node, scope.spawn(move || backend.request(&request, select))))
+                })
+                .collect();
+            calls
+                .into_iter()
+                .map(|(node, call)| {
+                    let resp = call.join().unwrap_or_else(|_| {
+                        Packet::RespError(format!("request to node {} failed", node))
+                    });
+                    (node, resp)
+                })
+                .collect()
+        })
+    }
+
+    fn broadcast(&mut self, packet: &Packet) -> Vec<(usize, Packet)> {
+        let requests = (0..self.backends.len()).map(|node| (node, packet.clone()));
+        self.fan_out(requests.collect())
+    }
+
+    /// Send the keys owned by each node to it, built into a command with
+    /// `build`, and put the items answered back in the order of `keys`.
+    fn gather<T, B, I>(
+        &mut self,
+        map: &SlotMap,
+        keys: &[Vec<u8>],
+        build: B,
+        items: I,
+    ) -> Result<Vec<T>, Packet>
+    where
+        T: Clone + Default,
+        B: Fn(usize, Vec<Vec<u8>>) -> Packet,
+        I: Fn(Packet) -> Result<Vec<T>, Packet>,
+    {
+        let groups = group(map, keys.iter());
+        let requests = groups.iter().map(|(&node, positions)| {
+            let keys = positions.iter().map(|&pos| keys[pos].clone());
+            (node, build(node, keys.collect()))
+        });
+        let mut gathered = vec![T::default(); keys.len()];
+        for (node, resp) in self.fan_out(requests.collect()) {
+            let found = items(resp).map_err(unexpected)?;
+            if found.len() != groups[&node].len() {
+                return Err(unexpected(Packet::RespTokens(vec![])));
+            }
+            for (&pos, item) in groups[&node].iter().zip(found) {
+                gathered[pos] = item;
+            }
+        }
+        Ok(gathered)
+    }
+
+    /// The node handles of snapshot `handle` and the slot map it was taken
+    /// with, or none and the current map when reading live.
+    fn view(&self, snapshot: Option<u64>) -> Result<(Option<Vec<u64>>, Arc<SlotMap>), Packet> {
+        match snapshot {
+            Some(handle) => match self.snapshots.get(&handle) {
+                Some((handles, map)) => Ok((Some(handles.clone()), map.clone())),
+                None => Err(Packet::RespError("invalid snapshot handle".to_string())),
+            },
+            None => Ok((None, self.map())),
+        }
+    }
+
+    fn read(&mut self, keys: Vec<Vec<u8>>, snapshot: Option<u64>) -> Packet {
+        let (handles, map) = match self.view(snapshot) {
+            Ok(view) => view,
+            Err(resp) => return resp,
+        };
+        let build = |node, keys| match handles.as_ref() {
+            Some(handles) => {
+                Packet::CmdWithSnapshot(handles[node], Box::new(Packet::CmdRead(keys)))
+            }
+            None => Packet::CmdRead(keys),
+        };
+        self.gather(&map, &keys, build, |resp| match resp {
+            Packet::RespTokens(values) => Ok(values),
+            resp => Err(resp),
+        })
+        .map_or_else(|resp| resp, Packet::RespTokens)
+    }
+
+    fn write<F: Fn(Vec<Vec<u8>>) -> Packet>(&mut self, pairs: Vec<Vec<u8>>, build: F) -> Packet {
+        // packets read off the wire always come in pairs, as clients refuse
+        // to encode others, so this only guards packets built in process
+        if !pairs.len().is_multiple_of(2) {
+            return Packet::RespError("a write needs a value for every key".to_string());
+        }
+        let groups = group(&self.map(), pairs.iter().step_by(2));
+        let requests = groups.into_iter().map(|(node, positions)| {
+            let pairs = positions
+                .into_iter()
+                .flat_map(|pos| pairs[pos * 2..pos * 2 + 2].iter().cloned());
+            (node, build(pairs.collect()))
+        });
+        all_ok(self.fan_out(requests.collect()))
+    }
+
+    fn delete<F: Fn(Vec<Vec<u8>>) -> Packet>(&mut self, keys: Vec<Vec<u8>>, build: F) -> Packet {
+        let groups = group(&self.map(), keys.iter());
+        let requests = groups.into_iter().map(|(node, positions)| {
+            let keys = positions.into_iter().map(|pos| keys[pos].clone());
+            (node, build(keys.collect()))
+        });
+        all_ok(self.fan_out(requests.collect()))
+    }
+
+    /// Ask every node for a page of the range and keep the first page of
+    /// the pairs merged in key order. A node is asked again, from where its
+    /// page ended, until it has a page of pairs in the slots it owns or
+    /// has no more.
+    fn range(&mut self, cmd: &Packet, snapshot: Option<u64>) -> Packet {
+        let (page_size, reverse) = match cmd {
+            Packet::CmdRangeBegin(page_size) => (*page_size, false),
+            Packet::CmdRangeEnd(page_size) => (*page_size, true),
+            Packet::CmdRangeFromAsc(page_size, _) | Packet::CmdRangeFromAscEx(page_size, _) => {
+                (*page_size, false)
+            }
+            Packet::CmdRangeFromDesc(page_size, _) | Packet::CmdRangeFromDescEx(page_size, _) => {
+                (*page_size, true)
+            }
+            _ => return Packet::RespError("unknown command".to_string()),
+        };
+        let (handles, map) = match self.view(snapshot) {
+            Ok(view) => view,
+            Err(resp) => return resp,
+        };
+        let nodes = handles.as_ref().map_or(self.backends.len(), Vec::len);
+
+        let mut owned = vec![vec![]; nodes];
+        let mut pending: Vec<(usize, Packet)> =
+            (0..nodes).map(|node| (node, cmd.clone())).collect();
+        while !pending.is_empty() {
+            let requests = pending.drain(..).map(|(node, cmd)| match handles.as_ref() {
+                Some(handles) => (node, Packet::CmdWithSnapshot(handles[node], Box::new(cmd))),
+                None => (node, cmd),
+            });
+            for (node, resp) in self.fan_out(requests.collect()) {
+                let pairs = match resp {
+                    Packet::RespPairs(tokens) => into_pairs(tokens),
+                    resp => return unexpected(resp),
+                };
+                let next = match pairs.last() {
+                    Some((key, _)) if pairs.len() >= page_size as usize => Some(key.clone()),
+                    _ => None,
+                };
+                let pairs = pairs
+                    .into_iter()
+                    .filter(|(key, _)| map.node_of(key) == node);
+                owned[node].extend(pairs);
+                if let (Some(key), true) = (next, owned[node].len() < page_size as usize) {
+                    let cmd = if reverse {
+                        Packet::CmdRangeFromDescEx(page_size, key)
+                    } else {
+                        Packet::CmdRangeFromAscEx(page_size, key)
+                    };
+                    pending.push((node, cmd));
+                }
+            }
+        }
+
+        let mut pairs: Vec<(Vec<u8>, Vec<u8>)> = owned.into_iter().flatten().collect();
+        pairs.sort_by(|a, b| a.0.cmp(&b.0));
+        if reverse {
+            pairs.reverse();
+        }
+        pairs.truncate(page_size as usize);
+        Packet::RespPairs(pairs.into_iter().flat_map(|(k, v)| [k, v]).collect())
+    }
+
+    /// Take a snapshot on every node, released again if any node fails.
+    fn snapshot(&mut self) -> Packet {
+        let map = self.map();
+        let mut handles = vec![0; self.backends.len()];
+        let mut failed = None;
+        for (node, resp) in self.broadcast(&Packet::CmdSnapshot()) {
+            match resp {
+                Packet::RespNumber(handle) => handles[node] = handle,
+                resp => failed = Some(unexpected(resp)),
+            }
+        }
+        if let Some(resp) = failed {
+            let taken = handles.into_iter().enumerate().filter(|(_, h)| *h != 0);
+            let releases = taken.map(|(node, handle)| (node, Packet::CmdSnapshotRelease(handle)));
+            self.fan_out(releases.collect());
+            return resp;
+        }
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.snapshots.insert(handle, (handles, map));
+        Packet::RespNumber(handle)
+    }
+
+    /// Pairs answered by every node, named after the node.
+    fn node_pairs(&mut self, packet: &Packet) -> Packet {
+        let map = self.map();
+        let mut tokens = vec![];
+        for (node, resp) in self.broadcast(packet) {
+            let addr = &map.nodes()[node];
+            match resp {
+                Packet::RespPairs(pairs) => {
+                    for (name, value) in into_pairs(pairs) {
+                        tokens.push([addr.as_bytes(), b".", &name].concat());
+                        tokens.push(value);
+                    }
+                }
+                resp => return unexpected(resp),
+            }
+        }
+        Packet::RespPairs(tokens)
+    }
+}
+
+/// Positions of `keys`, grouped by the node owning them.
+fn group<'a, I: Iterator<Item = &'a Vec<u8>>>(
+    map: &SlotMap,
+    keys: I,
+) -> BTreeMap<usize, Vec<usize>> {
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (pos, key) in keys.enumerate() {
+        groups.entry(map.node_of(key)).or_default().push(pos);
+    }
+    groups
+}
+
+/// Commands on keys, which nodes refuse for keys in slots they do not own.
+fn on_keys(packet: &Packet) -> bool {
+    matches!(
+        packet,
+        Packet::CmdRead(_)
+            | Packet::CmdExists(_)
+            | Packet::CmdWrite(_)
+            | Packet::CmdWriteWith(..)
+            | Packet::CmdDelete(_)
+            | Packet::CmdDeleteWith(..)
+            | Packet::CmdGetAt(..)
+            | Packet::CmdGetAtTime(..)
+            | Packet::CmdHistory(..)
+    )
+}
+
+fn into_pairs(tokens: Vec<Vec<u8>>) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut pairs = vec![];
+    let mut tokens = tokens.into_iter();
+    while let (Some(key), Some(value)) = (tokens.next(), tokens.next()) {
+        pairs.push((key, value));
+    }
+    pairs
+}
+
+/// The first response which is not `RespOk`, or `RespOk`.
+fn all_ok(resps: Vec<(usize, Packet)>) -> Packet {
+    for (_, resp) in resps {
+        if !matches!(resp, Packet::RespOk(_)) {
+            return unexpected(resp);
+        }
+    }
+    Packet::RespOk("Ok.".to_string())
+}
+
+/// Errors from a node are passed on as they are.
+fn unexpected(resp: Packet) -> Packet {
+    match resp {
+        Packet::RespError(_)
+        | Packet::RespInvalidName(_)
+        | Packet::RespNotLeader(_)
+        | Packet::RespMoved(..) => resp,
+        _ => Packet::RespError("unexpected response from a node".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::ops::Bound;
+    use std::sync::Mutex;
+
+    use packet::PacketReaderWriter;
+
+    use super::*;
+
+    type Answer = Box<dyn Fn(&Packet) -> Option<Packet> + Send + Sync>;
+
+    /// A node keeping its pairs in memory, recording the requests it gets.
+    /// `answer` takes precedence over the usual answers.
+    #[derive(Default)]
+    struct Stub {
+        data: Mutex<BTreeMap<Vec<u8>, Vec<u8>>>,
+        requests: Mutex<Vec<Packet>>,
+        answer: Option<Answer>,
+    }
+
+    impl Stub {
+        fn with_answer<F>(answer: F) -> Self
+        where
+            F: Fn(&Packet) -> Option<Packet> + Send + Sync + 'static,
+        {
+            Self {
+                answer: Some(Box::new(answer)),
+                ..Default::default()
+            }
+        }
+
+        fn put(&self, key: &[u8], value: &[u8]) {
+            self.data
+                .lock()
+                .unwrap()
+                .insert(key.to_vec(), value.to_vec());
+        }
+
+        fn requests(&self) -> Vec<Packet> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        fn respond(&self, packet: &Packet) -> Packet {
+            if let Some(resp) = self.answer.as_ref().and_then(|answer| answer(packet)) {
+                return resp;
+            }
+            let mut data = self.data.lock().unwrap();
+            match packet {
+                Packet::CmdUse(_) | Packet::CmdSnapshotRelease(_) => {
+                    Packet::RespOk("Ok.".to_string())
+                }
+                Packet::CmdWrite(pairs) => {
+                    for pair in pairs.chunks_exact(2) {
+                        data.insert(pair[0].clone(), pair[1].clone());
+                    }
+                    Packet::RespOk("Ok.".to_string())
+                }
+                Packet::CmdRead(keys) => Packet::RespTokens(
+                    keys.iter()
+                        .map(|key| data.get(key).cloned().unwrap_or_default())
+                        .collect(),
+                ),
+                Packet::CmdSnapshot() => Packet::RespNumber(7),
+                Packet::CmdWithSnapshot(_, cmd) => {
+                    drop(data);
+                    self.respond(cmd)
+                }
+                cmd => range(&data, cmd),
+            }
+        }
+    }
+
+    fn range(data: &BTreeMap<Vec<u8>, Vec<u8>>, cmd: &Packet) -> Packet {
+        let (page_size, pairs): (u16, Vec<_>) = match cmd {
+            Packet::CmdRangeBegin(page_size) => (*page_size, data.iter().collect()),
+            Packet::CmdRangeEnd(page_size) => (*page_size, data.iter().rev().collect()),
+            Packet::CmdRangeFromAscEx(page_size, key) => {
+                let bounds = (Bound::Excluded(key.clone()), Bound::Unbounded);
+                (*page_size, data.range(bounds).collect())
+            }
+            Packet::CmdRangeFromDescEx(page_size, key) => {
+                (*page_size, data.range(..key.clone()).rev().collect())
+            }
+            _ => return Packet::RespError("unknown command".to_string()),
+        };
+        let pairs = pairs.into_iter().take(page_size as usize);
+        Packet::RespPairs(pairs.flat_map(|(k, v)| [k.clone(), v.clone()]).collect())
+    }
+
+    /// Serve `stub` on a free port, returning its address.
+    fn serve(stub: Arc<Stub>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stub = stub.clone();
+                thread::spawn(move || {
+                    let mut rw = PacketReaderWriter::new(stream.unwrap());
+                    while let Ok(packet) = rw.read_packet() {
+                        stub.requests.lock().unwrap().push(packet.clone());
+                        if rw.write_packet(&stub.respond(&packet)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    /// A session over `stubs` sharing the slots evenly, with a database
+    /// selected.
+    fn session(stubs: &[Arc<Stub>]) -> (Session, Arc<SlotMap>) {
+        let nodes = stubs.iter().map(|stub| serve(stub.clone())).collect();
+        let map = Arc::new(SlotMap::even(nodes));
+        let mut session = Session::new(Arc::new(RwLock::new(map.clone())));
+        let resp = session.handle(Packet::CmdUse(b"db".to_vec()));
+        assert_eq!(resp, Packet::RespOk("Ok.".to_string()));
+        (session, map)
+    }
+
+    /// The first `count` keys `key-N` owned by `node`.
+    fn keys_of(map: &SlotMap, node: usize, count: usize) -> Vec<Vec<u8>> {
+        (0..)
+            .map(|i| format!("key-{:04}", i).into_bytes())
+            .filter(|key| map.node_of(key) == node)
+            .take(count)
+            .collect()
+    }
+
+    fn flatten(pairs: &[(Vec<u8>, Vec<u8>)]) -> Vec<Vec<u8>> {
+        pairs
+            .iter()
+            .flat_map(|(k, v)| [k.clone(), v.clone()])
+            .collect()
+    }
+
+    #[test]
+    fn test_group() {
+        let map = SlotMap::even(vec!["a".to_string(), "b".to_string()]);
+        let mut keys = keys_of(&map, 1, 2);
+        keys.insert(1, keys_of(&map, 0, 1).remove(0));
+        let groups = group(&map, keys.iter());
+        assert_eq!(groups, BTreeMap::from([(0, vec![1]), (1, vec![0, 2])]));
+        assert_eq!(group(&map, [].iter()), BTreeMap::new());
+    }
+
+    #[test]
+    fn test_into_pairs() {
+        let tokens = vec![b"a".to_vec(), b"1".to_vec(), b"b".to_vec(), b"2".to_vec()];
+        let pairs = vec![
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"2".to_vec()),
+        ];
+        assert_eq!(into_pairs(tokens.clone()), pairs);
+        // a trailing key without a value is left out
+        let mut odd = tokens;
+        odd.push(b"c".to_vec());
+        assert_eq!(into_pairs(odd), pairs);
+        assert_eq!(into_pairs(vec![]), vec![]);
+    }
+
+    #[test]
+    fn test_write_split_by_node() {
+        let stubs = [Arc::new(Stub::default()), Arc::new(Stub::default())];
+        let (mut session, map) = session(&stubs);
+        let (on_0, on_1) = (keys_of(&map, 0, 2), keys_of(&map, 1, 1));
+        let pairs = vec![
+            on_0[0].clone(),
+            b"v0".to_vec(),
+            on_1[0].clone(),
+            b"v1".to_vec(),
+            on_0[1].clone(),
+            b"v2".to_vec(),
+        ];
+        let resp = session.handle(Packet::CmdWrite(pairs));
+        assert_eq!(resp, Packet::RespOk("Ok.".to_string()));
+        let written = |node: usize| {
+            let requests = stubs[node].requests();
+            requests
+                .into_iter()
+                .find(|p| matches!(p, Packet::CmdWrite(_)))
+        };
+        let to_0 = vec![
+            on_0[0].clone(),
+            b"v0".to_vec(),
+            on_0[1].clone(),
+            b"v2".to_vec(),
+        ];
+        assert_eq!(written(0), Some(Packet::CmdWrite(to_0)));
+        let to_1 = vec![on_1[0].clone(), b"v1".to_vec()];
+        assert_eq!(written(1), Some(Packet::CmdWrite(to_1)));
+
+        let resp = session.handle(Packet::CmdWrite(vec![on_0[0].clone()]));
+        assert!(matches!(resp, Packet::RespError(_)));
+    }
+
+    #[test]
+    fn test_read_gathered_in_order() {
+        let stubs = [Arc::new(Stub::default()), Arc::new(Stub::default())];
+        let (mut session, map) = session(&stubs);
+        let (on_0, on_1) = (keys_of(&map, 0, 2), keys_of(&map, 1, 2));
+        stubs[0].put(&on_0[0], b"a");
+        stubs[0].put(&on_0[1], b"b");
+        stubs[1].put(&on_1[0], b"c");
+        let keys = vec![
+            on_1[0].clone(),
+            on_0[1].clone(),
+            on_1[1].clone(),
+            on_0[0].clone(),
+        ];
+        let resp = session.handle(Packet::CmdRead(keys));
+        let values = [b"c".to_vec(), b"b".to_vec(), vec![], b"a".to_vec()];
+        assert_eq!(resp, Packet::RespTokens(values.to_vec()));
+    }
+
+    #[test]
+    fn test_range_merged_and_owned_only() {
+        let stubs = [Arc::new(Stub::default()), Arc::new(Stub::default())];
+        let (mut session, map) = session(&stubs);
+        let mut expected = vec![];
+        for (node, stub) in stubs.iter().enumerate() {
+            for key in keys_of(&map, node, 5) {
+                stub.put(&key, b"owned");
+                expected.push((key, b"owned".to_vec()));
+            }
+        }
+        // left behind on node 0 by a move of their slots to node 1, and
+        // more than a page of them
+        for key in keys_of(&map, 1, 12) {
+            stubs[0].put(&key, b"stale");
+        }
+        expected.sort();
+
+        let resp = session.handle(Packet::CmdRangeBegin(4));
+        assert_eq!(resp, Packet::RespPairs(flatten(&expected[..4])));
+        let after = expected[3].0.clone();
+        let resp = session.handle(Packet::CmdRangeFromAscEx(100, after));
+        assert_eq!(resp, Packet::RespPairs(flatten(&expected[4..])));
+
+        let mut descending = expected.clone();
+        descending.reverse();
+        let resp = session.handle(Packet::CmdRangeEnd(3));
+        assert_eq!(resp, Packet::RespPairs(flatten(&descending[..3])));
+        let before = descending[2].0.clone();
+        let resp = session.handle(Packet::CmdRangeFromDescEx(100, before));
+        assert_eq!(resp, Packet::RespPairs(flatten(&descending[3..])));
+    }
+
+    #[test]
+    fn test_snapshot_released_on_partial_failure() {
+        let failing = Stub::with_answer(|packet| match packet {
+            Packet::CmdSnapshot() => Some(Packet::RespError("no snapshot".to_string())),
+            _ => None,
+        });
+        let stubs = [Arc::new(Stub::default()), Arc::new(failing)];
+        let (mut session, _) = session(&stubs);
+        let resp = session.handle(Packet::CmdSnapshot());
+        assert_eq!(resp, Packet::RespError("no snapshot".to_string()));
+        assert!(stubs[0].requests().contains(&Packet::CmdSnapshotRelease(7)));
+        assert!(!stubs[1]
+            .requests()
+            .iter()
+            .any(|p| matches!(p, Packet::CmdSnapshotRelease(_))));
+    }
+
+    #[test]
+    fn test_snapshot_fan_out() {
+        let stubs = [Arc::new(Stub::default()), Arc::new(Stub::default())];
+        let (mut session, map) = session(&stubs);
+        let key = keys_of(&map, 1, 1).remove(0);
+        stubs[1].put(&key, b"value");
+        let handle = match session.handle(Packet::CmdSnapshot()) {
+            Packet::RespNumber(handle) => handle,
+            resp => panic!("unexpected {:?}", resp),
+        };
+        let read = Packet::CmdWithSnapshot(handle, Box::new(Packet::CmdRead(vec![key])));
+        assert_eq!(
+            session.handle(read),
+            Packet::RespTokens(vec![b"value".to_vec()])
+        );
+        let resp = session.handle(Packet::CmdSnapshotRelease(handle));
+        assert_eq!(resp, Packet::RespOk("Ok.".to_string()));
+        for stub in &stubs {
+            assert!(stub.requests().contains(&Packet::CmdSnapshotRelease(7)));
+        }
+        // only the node owning the key is read from
+        let reads = |node: usize| {
+            let requests = stubs[node].requests().into_iter();
+            requests
+                .filter(|p| matches!(p, Packet::CmdWithSnapshot(7, _)))
+                .count()
+        };
+        assert_eq!((reads(0), reads(1)), (0, 1));
+        let resp = session.handle(Packet::CmdSnapshotRelease(handle));
+        assert!(matches!(resp, Packet::RespError(_)));
+    }
+
+    #[test]
+    fn test_moved_retried_after_refresh() {
+        let target = Arc::new(Stub::with_answer(|packet| match packet {
+            Packet::CmdSlots() => Some(Packet::RespPairs(vec![b"0-16383".to_vec(), vec![]])),
+            _ => None,
+        }));
+        let moved_to = serve(target.clone());
+        let source = Stub::with_answer(move |packet| match packet {
+            Packet::CmdWrite(_) => Some(Packet::RespMoved(0, moved_to.clone())),
+            Packet::CmdSlots() => Some(Packet::RespPairs(vec![
+                b"0-16383".to_vec(),
+                moved_to.clone().into_bytes(),
+            ])),
+            _ => None,
+        });
+        let (mut session, _) = session(&[Arc::new(source)]);
+        let pairs = vec![b"key".to_vec(), b"value".to_vec()];
+        let resp = session.handle(Packet::CmdWrite(pairs.clone()));
+        assert_eq!(resp, Packet::RespOk("Ok.".to_string()));
+        // the database of the session is selected on the new node first
+        let requests = target.requests();
+        let select = requests
+            .iter()
+            .position(|p| *p == Packet::CmdUse(b"db".to_vec()));
+        let write = requests
+            .iter()
+            .position(|p| *p == Packet::CmdWrite(pairs.clone()));
+        assert!(select.is_some() && select < write);
+        assert_eq!(session.map().ranges(1), "0-16383");
+    }
+
+    #[test]
+    fn test_moved_given_up_after_retries() {
+        let source = Arc::new(Stub::with_answer(|packet| match packet {
+            Packet::CmdWrite(_) => Some(Packet::RespMoved(0, "127.0.0.1:1".to_string())),
+            _ => None,
+        }));
+        let (mut session, _) = session(std::slice::from_ref(&source));
+        let resp = session.handle(Packet::CmdWrite(vec![b"key".to_vec(), b"value".to_vec()]));
+        assert!(matches!(resp, Packet::RespMoved(..)));
+        let writes = source.requests().into_iter();
+        let writes = writes.filter(|p| matches!(p, Packet::CmdWrite(_)));
+        assert_eq!(writes.count(), MOVED_RETRIES + 1);
+    }
+}
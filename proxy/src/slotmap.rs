@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+use packet::{key_slot, parse_slot_range, Packet, SLOT_COUNT};
+
+use crate::backend::Backend;
+
+/// Which node owns each hash slot.
+#[derive(Clone)]
+pub struct SlotMap {
+    nodes: Vec<String>,
+    /// Index in `nodes` of the owner of each slot.
+    owners: Vec<usize>,
+}
+
+impl SlotMap {
+    /// Split the slots into as many contiguous ranges of about the same
+    /// size as there are nodes.
+    pub fn even(nodes: Vec<String>) -> Self {
+        let count = nodes.len().max(1);
+        let owners = (0..SLOT_COUNT as usize)
+            .map(|slot| slot * count / SLOT_COUNT as usize)
+            .collect();
+        Self { nodes, owners }
+    }
+
+    /// Build the map from `start-end=addr` specs, which must cover every
+    /// slot exactly once.
+    pub fn from_shards(shards: &[String]) -> Result<Self, String> {
+        let mut nodes: Vec<String> = vec![];
+        let mut owners = vec![None; SLOT_COUNT as usize];
+        for shard in shards {
+            let (range, addr) = shard
+                .split_once('=')
+                .ok_or_else(|| format!("expected `start-end=addr`, got `{}`", shard))?;
+            let (start, end) = parse_slot_range(range)?;
+            let (start, end) = (start as usize, end as usize);
+            let node = node_index(&mut nodes, addr);
+            for (slot, owner) in owners.iter_mut().enumerate().take(end + 1).skip(start) {
+                if owner.replace(node).is_some() {
+                    return Err(format!("slot {} is assigned twice", slot));
+                }
+            }
+        }
+        let owners = owners
+            .into_iter()
+            .enumerate()
+            .map(|(slot, owner)| owner.ok_or_else(|| format!("slot {} is not assigned", slot)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { nodes, owners })
+    }
+
+    pub fn nodes(&self) -> &[String] {
+        &self.nodes
+    }
+
+    /// Give slots `first` to `last` to the node at `addr`, adding it.
+    pub fn assign(&mut self, first: u16, last: u16, addr: &str) {
+        let node = node_index(&mut self.nodes, addr);
+        self.owners[first as usize..=last as usize].fill(node);
+    }
+
+    /// Index of the node owning `key`.
+    pub fn node_of(&self, key: &[u8]) -> usize {
+        self.owners[key_slot(key) as usize]
+    }
+
+    /// The slots of `node` as `start-end` ranges separated by commas.
+    pub fn ranges(&self, node: usize) -> String {
+        let mut ranges: Vec<String> = vec![];
+        let mut start = None;
+        for slot in 0..=self.owners.len() {
+            let owned = self.owners.get(slot) == Some(&node);
+            match (owned, start) {
+                (true, None) => start = Some(slot),
+                (false, Some(first)) => {
+                    ranges.push(format!("{}-{}", first, slot - 1));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        ranges.join(",")
+    }
+}
+
+fn node_index(nodes: &mut Vec<String>, addr: &str) -> usize {
+    match nodes.iter().position(|node| node == addr) {
+        Some(node) => node,
+        None => {
+            nodes.push(addr.to_string());
+            nodes.len() - 1
+        }
+    }
+}
+
+/// Replace the map in `slots` with one updated from the slot tables of the
+/// nodes, following the addresses slots were moved to. Nodes which are not
+/// sharded, or cannot be reached, leave their slots as they are.
+pub fn refresh(slots: &RwLock<Arc<SlotMap>>) {
+    let mut map = SlotMap::clone(&slots.read().unwrap());
+    let mut pending: VecDeque<String> = map.nodes().to_vec().into();
+    let mut asked = vec![];
+    let mut served = vec![];
+    while let Some(addr) = pending.pop_front() {
+        if asked.contains(&addr) {
+            continue;
+        }
+        let table = Backend::new(&addr).request(&Packet::CmdSlots(), None);
+        if let Packet::RespPairs(tokens) = table {
+            for pair in tokens.chunks_exact(2) {
+                let range = String::from_utf8_lossy(&pair[0]);
+                let Ok((first, last)) = parse_slot_range(&range) else {
+                    continue;
+                };
+                match String::from_utf8_lossy(&pair[1]).into_owned() {
+                    moved_to if !moved_to.is_empty() => pending.push_back(moved_to),
+                    _ => served.push((first, last, addr.clone())),
+                }
+            }
+        }
+        asked.push(addr);
+    }
+    for (first, last, addr) in served {
+        map.assign(first, last, &addr);
+    }
+    *slots.write().unwrap() = Arc::new(map);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shards(specs: &[&str]) -> Vec<String> {
+        specs.iter().map(|spec| spec.to_string()).collect()
+    }
+
+    #[test]
+    fn test_even() {
+        let map = SlotMap::even(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(map.ranges(0), "0-5461");
+        assert_eq!(map.ranges(1), "5462-10922");
+        assert_eq!(map.ranges(2), "10923-16383");
+    }
+
+    #[test]
+    fn test_from_shards() {
+        let map =
+            SlotMap::from_shards(&shards(&["0-99=a", "100-16283=b", "16284-16383=a"])).unwrap();
+        assert_eq!(map.nodes(), ["a", "b"]);
+        assert_eq!(map.ranges(0), "0-99,16284-16383");
+        assert_eq!(map.ranges(1), "100-16283");
+        assert_eq!(map.node_of(b"{tag}key"), map.node_of(b"tag"));
+    }
+
+    #[test]
+    fn test_from_shards_overlap() {
+        let rs = SlotMap::from_shards(&shards(&["0-100=a", "100-16383=b"]));
+        assert_eq!(rs.err().unwrap(), "slot 100 is assigned twice");
+    }
+
+    #[test]
+    fn test_from_shards_gap() {
+        let rs = SlotMap::from_shards(&shards(&["0-99=a", "101-16383=b"]));
+        assert_eq!(rs.err().unwrap(), "slot 100 is not assigned");
+        let rs = SlotMap::from_shards(&shards(&["0-16382=a"]));
+        assert_eq!(rs.err().unwrap(), "slot 16383 is not assigned");
+    }
+
+    #[test]
+    fn test_from_shards_invalid() {
+        assert!(SlotMap::from_shards(&shards(&["0-16383"])).is_err());
+        assert!(SlotMap::from_shards(&shards(&["0-16384=a"])).is_err());
+        assert!(SlotMap::from_shards(&shards(&["10-5=a"])).is_err());
+    }
+
+    #[test]
+    fn test_assign() {
+        let mut map = SlotMap::even(vec!["a".to_string(), "b".to_string()]);
+        map.assign(0, 9, "c");
+        map.assign(8191, 8192, "a");
+        assert_eq!(map.nodes(), ["a", "b", "c"]);
+        assert_eq!(map.ranges(0), "10-8192");
+        assert_eq!(map.ranges(1), "8193-16383");
+        assert_eq!(map.ranges(2), "0-9");
+        map.assign(0, 16383, "b");
+        assert_eq!(map.ranges(0), "");
+    }
+}
@@ -114,6 +114,16 @@ class RsDbClient:
         if val == const.RESP_ERROR:
             msg = self._read_token().decode()
             raise errors.OpError(msg)
+        if val == const.RESP_INVALID_NAME:
+            msg = self._read_token().decode()
+            raise errors.InvalidNameError(msg)
+        if val == const.RESP_NOT_LEADER:
+            leader = self._read_token().decode()
+            raise errors.NotLeaderError(leader)
+        if val == const.RESP_MOVED:
+            slot = self._read_size()
+            addr = (self._read_token() or b'').decode()
+            raise errors.MovedError(slot, addr)
         if val == const.RESP_TOKEN:
             byt = self._read_token()
             return Response(const.RESP_TOKEN, token=byt)
@@ -26,6 +26,9 @@ RESP_ERROR = 0x56
 RESP_TOKEN = 0x57
 RESP_TOKENS = 0x58
 RESP_PAIRS = 0x59
+RESP_INVALID_NAME = 0x5c
+RESP_NOT_LEADER = 0x5f
+RESP_MOVED = 0x60
 
 RESP_TYPES = (
     RESP_OK,
@@ -33,6 +36,9 @@ RESP_TYPES = (
     RESP_TOKEN,
     RESP_TOKENS,
     RESP_PAIRS,
+    RESP_INVALID_NAME,
+    RESP_NOT_LEADER,
+    RESP_MOVED,
 )
 
 
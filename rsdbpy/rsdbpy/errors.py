@@ -29,3 +29,26 @@ class OpError(BaseError):
     """raised when an operation fails"""
     def __init__(self, msg):
         super().__init__(f"Operation failed: {msg}")
+
+
+class InvalidNameError(BaseError):
+    """raised when the server rejects a database name"""
+    def __init__(self, msg):
+        super().__init__(f"Invalid name: {msg}")
+
+
+class NotLeaderError(BaseError):
+    """raised when a write is sent to a raft follower, `leader` being the
+    address to send it to instead, empty while there is none"""
+    def __init__(self, leader):
+        self.leader = leader
+        super().__init__(f"Not the raft leader, leader at: {leader or '<unknown>'}")
+
+
+class MovedError(BaseError):
+    """raised when a key is in a hash slot the server does not serve, `addr`
+    being the node serving it, empty while unknown"""
+    def __init__(self, slot, addr):
+        self.slot = slot
+        self.addr = addr
+        super().__init__(f"Slot {slot} is served at: {addr or '<unknown>'}")
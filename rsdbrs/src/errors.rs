@@ -10,6 +10,13 @@ use packet::errors::PacketError;
 pub enum RsDBError {
     IOError(IOErr),
     RespError(String),
+    InvalidName(String),
+    /// A write sent to a raft follower, with the address of the leader to
+    /// send it to instead, empty while there is none.
+    NotLeader(String),
+    /// A key in a hash slot the server does not serve, with the slot and
+    /// the address of the node serving it, empty when unknown.
+    Moved(u16, String),
     FromUtf8Error(FromUtf8Error),
     NotConnect,
     NoDbSelected,
@@ -46,6 +53,21 @@ impl Display for RsDBError {
             Self::RespError(ref msg) => {
                 write!(f, "RespError - {msg}")
             }
+            Self::InvalidName(ref msg) => {
+                write!(f, "InvalidName - {msg}")
+            }
+            Self::NotLeader(ref leader) if leader.is_empty() => {
+                write!(f, "NotLeader - no leader elected")
+            }
+            Self::NotLeader(ref leader) => {
+                write!(f, "NotLeader - leader at {leader}")
+            }
+            Self::Moved(slot, ref addr) if addr.is_empty() => {
+                write!(f, "Moved - slot {slot} is not served here")
+            }
+            Self::Moved(slot, ref addr) => {
+                write!(f, "Moved - slot {slot} is served at {addr}")
+            }
             Self::NotConnect => {
                 write!(f, "Not connect to server")
             }
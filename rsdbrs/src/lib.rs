@@ -1,6 +1,11 @@
+use std::io::{BufRead, Write};
 use std::net::TcpStream;
 use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
 
+pub use packet::dump::{DumpFormat, DumpReader, DumpWriter};
+pub use packet::slots::{key_slot, parse_slot_range, SLOT_COUNT};
 use packet::{Packet, PacketReaderWriter};
 
 // extern crate storage;
@@ -11,6 +16,15 @@ pub use errors::{RsDBError, RsDBResult};
 
 extern crate packet;
 
+/// Pairs fetched per request while dumping.
+const DUMP_PAGE_SIZE: u16 = 1000;
+
+/// Changes fetched per request by `Changes`.
+const CHANGES_PAGE_SIZE: u16 = 1000;
+
+/// How long `Changes` waits before asking again once it has caught up.
+const CHANGES_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 #[derive(Copy, Clone)]
 pub enum Direction {
     Forward,
@@ -24,6 +38,111 @@ pub enum IteratorMode<'a> {
     From(&'a [u8], Direction),
 }
 
+/// Durability of a single write, on top of the defaults of the database
+/// (`sync_writes` and `disable_wal` in its options).
+#[derive(Copy, Clone, Default)]
+pub struct WriteFlags {
+    /// Acknowledge the write only once the WAL is fsynced.
+    pub sync: bool,
+    /// Skip the WAL, so the write is lost if the server crashes before the
+    /// memtable is flushed.
+    pub disable_wal: bool,
+}
+
+impl WriteFlags {
+    fn bits(&self) -> u16 {
+        let mut bits = 0;
+        if self.sync {
+            bits |= packet::WRITE_SYNC;
+        }
+        if self.disable_wal {
+            bits |= packet::WRITE_NO_WAL;
+        }
+        bits
+    }
+}
+
+/// A committed write from the change feed of a database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Sequence number of the write, to resume the feed from.
+    pub seq: u64,
+    pub op: ChangeOp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    /// Every key in `[start, end)`.
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+/// A value a key held, kept by a database with `history_ttl_seconds`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    /// Sequence number of the write, 0 for the value held when the history
+    /// of the key started to be kept.
+    pub seq: u64,
+    /// Time of the write in milliseconds since the Unix epoch, 0 if unknown.
+    pub time_ms: u64,
+    /// `None` once deleted.
+    pub value: Option<Vec<u8>>,
+}
+
+/// Endless iterator over the changes of the current database, see
+/// `RsDBClient::subscribe`.
+pub struct Changes<'a> {
+    client: &'a mut RsDBClient,
+    seq: u64,
+    pending: std::vec::IntoIter<Change>,
+}
+
+impl Changes<'_> {
+    /// Sequence number of the last change returned, to subscribe again from.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+impl Iterator for Changes<'_> {
+    type Item = RsDBResult<Change>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(change) = self.pending.next() {
+                self.seq = change.seq;
+                return Some(Ok(change));
+            }
+            match self.client.changes_since(self.seq, CHANGES_PAGE_SIZE) {
+                Ok(changes) if changes.is_empty() => thread::sleep(CHANGES_POLL_INTERVAL),
+                Ok(changes) => self.pending = changes.into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// A checkpoint of a database taken by the server, see
+/// `RsDBClient::fetch_checkpoint`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteCheckpoint {
+    pub handle: u64,
+    /// Sequence number the checkpoint has every write up to, to follow the
+    /// changes of the database from once it is copied.
+    pub seq: u64,
+    /// Names of its files, all in one directory.
+    pub files: Vec<String>,
+}
+
+pub struct BackupInfo {
+    pub backup_id: u32,
+    pub timestamp: i64,
+    pub size: u64,
+    pub num_files: u32,
+}
+
+#[derive(Default)]
 pub struct RsDBClient {
     db_name: Option<String>,
     rw: (
@@ -58,17 +177,10 @@ impl RsDBClient {
 
     pub fn set(&mut self, key: &[u8], value: &[u8]) -> RsDBResult<()> {
         self.check_db()?;
-        let mut bytes_parts = vec![];
-        bytes_parts.push(key.to_vec());
-        bytes_parts.push(value.to_vec());
+        let bytes_parts = vec![key.to_vec(), value.to_vec()];
         let packet = Packet::CmdWrite(bytes_parts);
         self.send_request(&packet)?;
-        let res = self.read_resp()?;
-        if let Packet::RespError(ref msg) = res {
-            return Err(RsDBError::RespError(msg.to_string()));
-        }
-
-        Ok(())
+        self.read_ok()
     }
 
     pub fn mset(&mut self, items: &Vec<Vec<u8>>) -> RsDBResult<()> {
@@ -80,12 +192,20 @@ impl RsDBClient {
         }
         let packet = Packet::CmdWrite(bytes_parts);
         self.send_request(&packet)?;
-        let res = self.read_resp()?;
-        if let Packet::RespError(ref msg) = res {
-            return Err(RsDBError::RespError(msg.to_string()));
-        }
+        self.read_ok()
+    }
 
-        Ok(())
+    /// Like `mset`, with the durability given by `flags`.
+    pub fn mset_with(&mut self, items: &[Vec<u8>], flags: WriteFlags) -> RsDBResult<()> {
+        self.check_db()?;
+        assert_eq!(items.len() % 2, 0);
+        let packet = Packet::CmdWriteWith(flags.bits(), items.to_vec());
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    pub fn set_with(&mut self, key: &[u8], value: &[u8], flags: WriteFlags) -> RsDBResult<()> {
+        self.mset_with(&[key.to_vec(), value.to_vec()], flags)
     }
 
     pub fn get(&mut self, key: &[u8]) -> RsDBResult<Option<Vec<u8>>> {
@@ -94,61 +214,145 @@ impl RsDBClient {
         self.send_request(&packet)?;
         let resp = self.read_resp()?;
         match resp {
-            Packet::RespTokens(vals) => {
+            Packet::RespTokens(mut vals) => {
                 if vals.len() != 1 {
                     return Err(RsDBError::RespError("invalid response".to_string()));
                 }
-                if vals[0].len() == 0 {
-                    return Ok(None);
+                match vals.pop() {
+                    Some(val) if !val.is_empty() => Ok(Some(val)),
+                    _ => Ok(None),
                 }
-                for val in vals {
-                    return Ok(Some(val));
-                }
-                panic!("Should be here")
             }
+            Packet::RespMoved(slot, addr) => Err(RsDBError::Moved(slot, addr)),
             _ => Err(RsDBError::RespError("invalid response".to_string())),
         }
     }
 
-    pub fn delete(&mut self, key: &[u8]) -> RsDBResult<()> {
+    /// Get several values in one request, `None` for missing keys.
+    pub fn mget(&mut self, keys: &[&[u8]]) -> RsDBResult<Vec<Option<Vec<u8>>>> {
         self.check_db()?;
-        let packet = Packet::CmdDelete(vec![key.to_owned()]);
+        let packet = Packet::CmdRead(keys.iter().map(|key| key.to_vec()).collect());
         self.send_request(&packet)?;
         let resp = self.read_resp()?;
         match resp {
-            Packet::RespOk(_msg) => Ok(()),
+            Packet::RespTokens(vals) if vals.len() == keys.len() => Ok(vals
+                .into_iter()
+                .map(|val| if val.is_empty() { None } else { Some(val) })
+                .collect()),
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            Packet::RespMoved(slot, addr) => Err(RsDBError::Moved(slot, addr)),
             _ => Err(RsDBError::RespError("invalid response".to_string())),
         }
     }
 
-    pub fn use_db(&mut self, name: &str) -> RsDBResult<()> {
-        let packet = Packet::CmdUse(name.as_bytes().to_owned());
+    /// Check which keys exist without fetching their values.
+    pub fn exists(&mut self, keys: &[&[u8]]) -> RsDBResult<Vec<bool>> {
+        self.check_db()?;
+        let packet = Packet::CmdExists(keys.iter().map(|key| key.to_vec()).collect());
         self.send_request(&packet)?;
         let resp = self.read_resp()?;
         match resp {
-            Packet::RespOk(_msg) => {
-                self.db_name = Some(name.to_string());
-                Ok(())
+            Packet::RespNumbers(found) if found.len() == keys.len() => {
+                Ok(found.into_iter().map(|found| found != 0).collect())
             }
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            Packet::RespMoved(slot, addr) => Err(RsDBError::Moved(slot, addr)),
             _ => Err(RsDBError::RespError("invalid response".to_string())),
         }
     }
 
+    pub fn delete(&mut self, key: &[u8]) -> RsDBResult<()> {
+        self.check_db()?;
+        let packet = Packet::CmdDelete(vec![key.to_owned()]);
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    pub fn delete_with(&mut self, key: &[u8], flags: WriteFlags) -> RsDBResult<()> {
+        self.check_db()?;
+        let packet = Packet::CmdDeleteWith(flags.bits(), vec![key.to_owned()]);
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    /// Delete all keys in `[start, end)`, returning the approximate number
+    /// of keys removed.
+    pub fn delete_range(&mut self, start: &[u8], end: &[u8]) -> RsDBResult<u64> {
+        self.check_db()?;
+        let packet = Packet::CmdDeleteRange(start.to_owned(), end.to_owned());
+        self.send_request(&packet)?;
+        self.read_number()
+    }
+
+    /// Delete all keys starting with `prefix`, returning the approximate
+    /// number of keys removed.
+    pub fn delete_prefix(&mut self, prefix: &[u8]) -> RsDBResult<u64> {
+        self.check_db()?;
+        let packet = Packet::CmdDeletePrefix(prefix.to_owned());
+        self.send_request(&packet)?;
+        self.read_number()
+    }
+
+    pub fn use_db(&mut self, name: &str) -> RsDBResult<()> {
+        let packet = Packet::CmdUse(name.as_bytes().to_owned());
+        self.send_request(&packet)?;
+        self.read_ok()?;
+        self.db_name = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Select a database like `use_db`, failing if it does not exist yet.
+    pub fn use_existing_db(&mut self, name: &str) -> RsDBResult<()> {
+        let packet = Packet::CmdUseExisting(name.as_bytes().to_owned());
+        self.send_request(&packet)?;
+        self.read_ok()?;
+        self.db_name = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Select an existing database for reading only, opening it so unless
+    /// it is already open read-only. Writes to it are refused until it is
+    /// detached.
+    pub fn use_db_read_only(&mut self, name: &str) -> RsDBResult<()> {
+        let packet = Packet::CmdUseWith(packet::USE_READ_ONLY, name.as_bytes().to_owned());
+        self.send_request(&packet)?;
+        self.read_ok()?;
+        self.db_name = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Select a database, opening it with `options` (`key = value` pairs
+    /// separated by `;` or newlines) which are persisted with the database.
+    pub fn use_db_with_options(&mut self, name: &str, options: &str) -> RsDBResult<()> {
+        let packet =
+            Packet::CmdUseWithOptions(name.as_bytes().to_owned(), options.as_bytes().to_owned());
+        self.send_request(&packet)?;
+        self.read_ok()?;
+        self.db_name = Some(name.to_string());
+        Ok(())
+    }
+
     pub fn detach_db(&mut self, name: &str) -> RsDBResult<()> {
         let packet = Packet::CmdDetach(name.as_bytes().to_owned());
         self.send_request(&packet)?;
-        let resp = self.read_resp()?;
-        match resp {
-            Packet::RespOk(_msg) => {
-                if let Some(ref db_name) = self.db_name {
-                    if db_name == name {
-                        self.db_name = None;
-                    }
-                }
-                Ok(())
+        self.read_ok()?;
+        if let Some(ref db_name) = self.db_name {
+            if db_name == name {
+                self.db_name = None;
             }
-            _ => Err(RsDBError::RespError("invalid response".to_string())),
         }
+        Ok(())
+    }
+
+    /// Detach the database `name` on the server and delete its data.
+    pub fn drop_db(&mut self, name: &str) -> RsDBResult<()> {
+        let packet = Packet::CmdDrop(name.as_bytes().to_owned());
+        self.send_request(&packet)?;
+        self.read_ok()?;
+        if self.db_name.as_deref() == Some(name) {
+            self.db_name = None;
+        }
+        Ok(())
     }
 
     pub fn get_db_name(&self) -> &Option<String> {
@@ -189,6 +393,95 @@ impl RsDBClient {
         }
     }
 
+    /// List every database known to the server, attached or not, with its
+    /// state (`attached` or `detached`).
+    pub fn catalog(&mut self) -> RsDBResult<Vec<(String, String)>> {
+        let packet = Packet::CmdCatalog();
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
+    /// Make the server refuse the writes of all clients, or take them again,
+    /// as for maintenance. Reads and scans are still served, but no database
+    /// is created. The server has no access control: any client connected
+    /// to it can switch this, so keep its port to trusted clients.
+    pub fn set_read_only(&mut self, read_only: bool) -> RsDBResult<()> {
+        let packet = Packet::CmdSetReadOnly(read_only);
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    /// Server-wide figures, such as open databases and evictions, as name
+    /// and value pairs.
+    pub fn server_stats(&mut self) -> RsDBResult<Vec<(String, String)>> {
+        let packet = Packet::CmdServerStats();
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
+    /// Figures of the current database, such as its estimated number of keys
+    /// and RocksDB statistics when enabled, as name and value pairs.
+    pub fn stats(&mut self) -> RsDBResult<Vec<(String, String)>> {
+        self.check_db()?;
+        let packet = Packet::CmdStats();
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
+    /// Compact the keys in `[start, end]` of the current database, the whole
+    /// database when both are `None`, to reclaim the space of deleted data.
+    ///
+    /// Blocks until done and returns its `duration_ms` and, for RocksDB, the
+    /// size of the sst files as `live_sst_size_before` and `live_sst_size_after`.
+    pub fn compact(
+        &mut self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+    ) -> RsDBResult<Vec<(String, String)>> {
+        self.check_db()?;
+        let start = start.unwrap_or_default().to_owned();
+        let end = end.unwrap_or_default().to_owned();
+        let packet = Packet::CmdCompact(start, end);
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
+    /// Write the memtables of the current database to disk, reported like
+    /// `compact`.
+    pub fn flush(&mut self) -> RsDBResult<Vec<(String, String)>> {
+        self.check_db()?;
+        let packet = Packet::CmdFlush();
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
+    /// Atomically add the pairs of sst files built by `rsdb-sst` to the
+    /// current database. `files` are paths on the server, which takes the
+    /// files over. Reported like `compact`.
+    pub fn ingest(&mut self, files: &[&str]) -> RsDBResult<Vec<(String, String)>> {
+        self.check_db()?;
+        let files = files.iter().map(|file| file.as_bytes().to_vec()).collect();
+        let packet = Packet::CmdIngest(files);
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
+    /// Wait for the background flushes and compactions of the current
+    /// database to finish, for at most `timeout` seconds (0 for no limit).
+    pub fn wait_for_compact(&mut self, timeout: u16) -> RsDBResult<Vec<(String, String)>> {
+        self.check_db()?;
+        let packet = Packet::CmdWaitCompact(timeout);
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
     pub fn range(
         &mut self,
         iter_mode: IteratorMode,
@@ -197,31 +490,383 @@ impl RsDBClient {
     ) -> RsDBResult<Vec<(Vec<u8>, Vec<u8>)>> {
         self.check_db()?;
 
-        let packet = match iter_mode {
-            IteratorMode::Start => Packet::CmdRangeBegin(page_size),
-            IteratorMode::End => Packet::CmdRangeEnd(page_size),
-            IteratorMode::From(key, direction) => match (direction, exclude_current) {
-                (Direction::Forward, false) => Packet::CmdRangeFromAsc(page_size, key.to_vec()),
-                (Direction::Forward, true) => Packet::CmdRangeFromAscEx(page_size, key.to_vec()),
-                (Direction::Reverse, false) => Packet::CmdRangeFromDesc(page_size, key.to_vec()),
-                (Direction::Reverse, true) => Packet::CmdRangeFromDescEx(page_size, key.to_vec()),
+        let packet = range_packet(iter_mode, page_size, exclude_current);
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_pairs(resp)
+    }
+
+    /// Take a snapshot of the current database, returning its handle.
+    ///
+    /// Reads through the handle see the database as it was at this point,
+    /// until the handle is released or unused for the server timeout.
+    pub fn snapshot(&mut self) -> RsDBResult<u64> {
+        self.check_db()?;
+        let packet = Packet::CmdSnapshot();
+        self.send_request(&packet)?;
+        self.read_number()
+    }
+
+    pub fn release_snapshot(&mut self, handle: u64) -> RsDBResult<()> {
+        let packet = Packet::CmdSnapshotRelease(handle);
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    pub fn snapshot_get(&mut self, handle: u64, key: &[u8]) -> RsDBResult<Option<Vec<u8>>> {
+        let packet = Packet::CmdWithSnapshot(handle, Box::new(Packet::CmdRead(vec![key.to_vec()])));
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        match resp {
+            Packet::RespTokens(mut vals) if vals.len() == 1 => match vals.pop() {
+                Some(val) if !val.is_empty() => Ok(Some(val)),
+                _ => Ok(None),
             },
-        };
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            _ => Err(RsDBError::RespError("invalid response".to_string())),
+        }
+    }
+
+    pub fn snapshot_range(
+        &mut self,
+        handle: u64,
+        iter_mode: IteratorMode,
+        page_size: u16,
+        exclude_current: bool,
+    ) -> RsDBResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let packet = range_packet(iter_mode, page_size, exclude_current);
+        let packet = Packet::CmdWithSnapshot(handle, Box::new(packet));
         self.send_request(&packet)?;
         let resp = self.read_resp()?;
+        into_pairs(resp)
+    }
 
+    /// Sequence number of the last write to the current database. Passing it
+    /// to `changes_since` or `subscribe` follows the writes from now on.
+    pub fn latest_sequence(&mut self) -> RsDBResult<u64> {
+        self.check_db()?;
+        let packet = Packet::CmdLatestSequence();
+        self.send_request(&packet)?;
+        self.read_number()
+    }
+
+    /// Up to `limit` committed writes to the current database after sequence
+    /// number `seq`, oldest first. Writes that skipped the WAL are missing.
+    pub fn changes_since(&mut self, seq: u64, limit: u16) -> RsDBResult<Vec<Change>> {
+        self.check_db()?;
+        let packet = Packet::CmdChanges(seq, limit);
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
         match resp {
-            Packet::RespPairs(mut tokens) => {
-                let mut pairs = vec![];
-                let pair_count = tokens.len() / 2;
-                for _idx in 0..pair_count {
-                    let v = tokens.pop().ok_or(RsDBError::EmptyToken)?;
-                    let k = tokens.pop().ok_or(RsDBError::EmptyToken)?;
-                    pairs.push((k, v))
+            Packet::RespChanges(records) => records.into_iter().map(into_change).collect(),
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            _ => Err(RsDBError::RespError("invalid response".to_string())),
+        }
+    }
+
+    /// Checkpoint the current database on the server for it to be copied
+    /// file by file with `read_checkpoint`, until released or unused for the
+    /// server snapshot timeout.
+    pub fn fetch_checkpoint(&mut self) -> RsDBResult<RemoteCheckpoint> {
+        self.check_db()?;
+        let packet = Packet::CmdFetchCheckpoint();
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        match resp {
+            Packet::RespCheckpoint(handle, seq, files) => Ok(RemoteCheckpoint {
+                handle,
+                seq,
+                files: files
+                    .into_iter()
+                    .map(|file| String::from_utf8_lossy(&file).to_string())
+                    .collect(),
+            }),
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            _ => Err(RsDBError::RespError("invalid response".to_string())),
+        }
+    }
+
+    /// A chunk of `file` of a checkpoint from `offset` on, empty past its end.
+    pub fn read_checkpoint(&mut self, handle: u64, file: &str, offset: u64) -> RsDBResult<Vec<u8>> {
+        let packet = Packet::CmdReadCheckpoint(handle, file.as_bytes().to_vec(), offset);
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        match resp {
+            Packet::RespToken(data) => Ok(data),
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            _ => Err(RsDBError::RespError("invalid response".to_string())),
+        }
+    }
+
+    pub fn release_checkpoint(&mut self, handle: u64) -> RsDBResult<()> {
+        let packet = Packet::CmdReleaseCheckpoint(handle);
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    /// The value `key` had once the writes up to sequence number `seq` were
+    /// made, from the history the current database keeps.
+    pub fn get_at(&mut self, key: &[u8], seq: u64) -> RsDBResult<Option<Vec<u8>>> {
+        self.check_db()?;
+        let packet = Packet::CmdGetAt(seq, key.to_vec());
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_value_at(resp)
+    }
+
+    /// The value `key` had at `time_ms` milliseconds since the Unix epoch,
+    /// going by when each of its versions was written.
+    pub fn get_at_time(&mut self, key: &[u8], time_ms: u64) -> RsDBResult<Option<Vec<u8>>> {
+        self.check_db()?;
+        let packet = Packet::CmdGetAtTime(time_ms, key.to_vec());
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_value_at(resp)
+    }
+
+    /// Up to `limit` of the versions of `key` kept by the current database,
+    /// newest first.
+    pub fn history(&mut self, key: &[u8], limit: u16) -> RsDBResult<Vec<Version>> {
+        self.check_db()?;
+        let packet = Packet::CmdHistory(limit, key.to_vec());
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        match resp {
+            Packet::RespVersions(records) => Ok(records
+                .into_iter()
+                .map(|record| Version {
+                    seq: record.seq,
+                    time_ms: record.time_ms,
+                    value: Some(record.value).filter(|value| !value.is_empty()),
+                })
+                .collect()),
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            Packet::RespMoved(slot, addr) => Err(RsDBError::Moved(slot, addr)),
+            _ => Err(RsDBError::RespError("invalid response".to_string())),
+        }
+    }
+
+    /// Follow the writes to the current database after sequence number
+    /// `seq`, polling the server once caught up. The iterator never ends;
+    /// after a disconnect, subscribe again from `Changes::seq`.
+    pub fn subscribe(&mut self, seq: u64) -> Changes<'_> {
+        Changes {
+            client: self,
+            seq,
+            pending: Vec::new().into_iter(),
+        }
+    }
+
+    /// Add node `id`, serving clients at `addr`, to the raft group of the
+    /// server, which must be its leader. Returns once the change commits.
+    pub fn raft_add_node(&mut self, id: u64, addr: &str) -> RsDBResult<()> {
+        let packet = Packet::CmdRaftAddNode(id, addr.as_bytes().to_vec());
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    /// Remove node `id` from the raft group of the server, which must be
+    /// its leader. A leader removing itself steps down afterwards.
+    pub fn raft_remove_node(&mut self, id: u64) -> RsDBResult<()> {
+        let packet = Packet::CmdRaftRemoveNode(id);
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    /// The hash slots known to a sharded server as `first-last` ranges, with
+    /// the address they were moved to, or an empty one for those it serves.
+    pub fn slots(&mut self) -> RsDBResult<Vec<(String, String)>> {
+        let packet = Packet::CmdSlots();
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
+    /// Have the server copy slots `first` to `last` from the node at
+    /// `source` and take them over, announcing itself at `addr` to the
+    /// clients of the source. Returns once the slots are served, with
+    /// figures about the copy.
+    pub fn import_slots(
+        &mut self,
+        first: u16,
+        last: u16,
+        source: &str,
+        addr: &str,
+    ) -> RsDBResult<Vec<(String, String)>> {
+        let packet = Packet::CmdImportSlots(
+            first,
+            last,
+            source.as_bytes().to_vec(),
+            addr.as_bytes().to_vec(),
+        );
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        into_text_pairs(resp)
+    }
+
+    /// Stop serving slots `first` to `last`, sending their clients to
+    /// `addr` from now on. Used by the importing node.
+    pub fn move_slots(&mut self, first: u16, last: u16, addr: &str) -> RsDBResult<()> {
+        let packet = Packet::CmdMoveSlots(first, last, addr.as_bytes().to_vec());
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    /// Delete the keys of slots `first` to `last`, which the server must no
+    /// longer serve, from all its databases. Returns how many were deleted.
+    pub fn purge_slots(&mut self, first: u16, last: u16) -> RsDBResult<u64> {
+        let packet = Packet::CmdPurgeSlots(first, last);
+        self.send_request(&packet)?;
+        self.read_number()
+    }
+
+    /// Write every pair of the current database to `writer`, reading them
+    /// page by page from a snapshot so that the dump is consistent. Returns
+    /// the number of pairs written.
+    pub fn dump<W: Write>(&mut self, writer: W, format: DumpFormat) -> RsDBResult<u64> {
+        let handle = self.snapshot()?;
+        let rs = self.dump_snapshot(handle, DumpWriter::new(writer, format)?);
+        let released = self.release_snapshot(handle);
+        let count = rs?;
+        released?;
+        Ok(count)
+    }
+
+    fn dump_snapshot<W: Write>(&mut self, handle: u64, mut dump: DumpWriter<W>) -> RsDBResult<u64> {
+        let mut last_key: Option<Vec<u8>> = None;
+        loop {
+            let pairs = match last_key.as_ref() {
+                Some(key) => {
+                    let iter_mode = IteratorMode::From(key, Direction::Forward);
+                    self.snapshot_range(handle, iter_mode, DUMP_PAGE_SIZE, true)?
                 }
-                pairs.reverse();
-                Ok(pairs)
+                None => self.snapshot_range(handle, IteratorMode::Start, DUMP_PAGE_SIZE, false)?,
+            };
+            if pairs.is_empty() {
+                break;
+            }
+            for (key, value) in &pairs {
+                dump.write_pair(key, value)?;
             }
+            last_key = pairs.into_iter().last().map(|(key, _)| key);
+        }
+        let count = dump.count();
+        dump.finish()?;
+        Ok(count)
+    }
+
+    /// Load a dump in either format into the database `name`, which is
+    /// selected, writing `batch_size` pairs per request. Returns the number
+    /// of pairs loaded.
+    pub fn restore_dump<R: BufRead>(
+        &mut self,
+        name: &str,
+        reader: R,
+        batch_size: u16,
+    ) -> RsDBResult<u64> {
+        self.use_db(name)?;
+        let mut count = 0;
+        let mut items = vec![];
+        for pair in DumpReader::new(reader)? {
+            let (key, value) = pair?;
+            items.push(key);
+            items.push(value);
+            if items.len() / 2 >= batch_size.max(1) as usize {
+                self.mset(&items)?;
+                count += (items.len() / 2) as u64;
+                items.clear();
+            }
+        }
+        if !items.is_empty() {
+            self.mset(&items)?;
+            count += (items.len() / 2) as u64;
+        }
+        Ok(count)
+    }
+
+    /// Add an incremental backup of the current database to `backup_dir`
+    /// on the server, returning the new backup id.
+    pub fn backup(&mut self, backup_dir: &str) -> RsDBResult<u32> {
+        self.check_db()?;
+        let packet = Packet::CmdBackup(backup_dir.as_bytes().to_vec());
+        self.send_request(&packet)?;
+        Ok(self.read_number()? as u32)
+    }
+
+    /// Write a checkpoint of the current database into `target_dir` on the server.
+    pub fn checkpoint(&mut self, target_dir: &str) -> RsDBResult<()> {
+        self.check_db()?;
+        let packet = Packet::CmdCheckpoint(target_dir.as_bytes().to_vec());
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    pub fn list_backups(&mut self, backup_dir: &str) -> RsDBResult<Vec<BackupInfo>> {
+        let packet = Packet::CmdListBackups(backup_dir.as_bytes().to_vec());
+        self.send_request(&packet)?;
+        let resp = self.read_resp()?;
+        match resp {
+            Packet::RespNumbers(numbers) => {
+                let backups = numbers
+                    .chunks_exact(4)
+                    .map(|info| BackupInfo {
+                        backup_id: info[0] as u32,
+                        timestamp: info[1] as i64,
+                        size: info[2],
+                        num_files: info[3] as u32,
+                    })
+                    .collect();
+                Ok(backups)
+            }
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            _ => Err(RsDBError::RespError("invalid response".to_string())),
+        }
+    }
+
+    /// Delete all but the newest `keep` backups in `backup_dir`.
+    pub fn purge_backups(&mut self, backup_dir: &str, keep: u16) -> RsDBResult<()> {
+        let packet = Packet::CmdPurgeBackups(keep, backup_dir.as_bytes().to_vec());
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    /// Restore a backup (the latest one if `backup_id` is `None`) as the new
+    /// database `name`.
+    pub fn restore_backup(
+        &mut self,
+        backup_dir: &str,
+        backup_id: Option<u32>,
+        name: &str,
+    ) -> RsDBResult<()> {
+        let packet = Packet::CmdRestoreBackup(
+            backup_dir.as_bytes().to_vec(),
+            backup_id.unwrap_or_default() as u64,
+            name.as_bytes().to_vec(),
+        );
+        self.send_request(&packet)?;
+        self.read_ok()
+    }
+
+    fn read_number(&mut self) -> RsDBResult<u64> {
+        let resp = self.read_resp()?;
+        match resp {
+            Packet::RespNumber(number) => Ok(number),
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            Packet::RespInvalidName(msg) => Err(RsDBError::InvalidName(msg)),
+            Packet::RespNotLeader(leader) => Err(RsDBError::NotLeader(leader)),
+            Packet::RespMoved(slot, addr) => Err(RsDBError::Moved(slot, addr)),
+            _ => Err(RsDBError::RespError("invalid response".to_string())),
+        }
+    }
+
+    fn read_ok(&mut self) -> RsDBResult<()> {
+        let resp = self.read_resp()?;
+        match resp {
+            Packet::RespOk(_msg) => Ok(()),
+            Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+            Packet::RespInvalidName(msg) => Err(RsDBError::InvalidName(msg)),
+            Packet::RespNotLeader(leader) => Err(RsDBError::NotLeader(leader)),
+            Packet::RespMoved(slot, addr) => Err(RsDBError::Moved(slot, addr)),
             _ => Err(RsDBError::RespError("invalid response".to_string())),
         }
     }
@@ -259,9 +904,75 @@ impl RsDBClient {
     }
 
     fn check_db(&self) -> RsDBResult<()> {
-        if let None = self.db_name {
+        if self.db_name.is_none() {
             return Err(RsDBError::NoDbSelected);
         }
-        return Ok(());
+        Ok(())
+    }
+}
+
+fn range_packet(iter_mode: IteratorMode, page_size: u16, exclude_current: bool) -> Packet {
+    match iter_mode {
+        IteratorMode::Start => Packet::CmdRangeBegin(page_size),
+        IteratorMode::End => Packet::CmdRangeEnd(page_size),
+        IteratorMode::From(key, direction) => match (direction, exclude_current) {
+            (Direction::Forward, false) => Packet::CmdRangeFromAsc(page_size, key.to_vec()),
+            (Direction::Forward, true) => Packet::CmdRangeFromAscEx(page_size, key.to_vec()),
+            (Direction::Reverse, false) => Packet::CmdRangeFromDesc(page_size, key.to_vec()),
+            (Direction::Reverse, true) => Packet::CmdRangeFromDescEx(page_size, key.to_vec()),
+        },
+    }
+}
+
+fn into_change(record: packet::ChangeRecord) -> RsDBResult<Change> {
+    let op = match record.op {
+        packet::CHANGE_PUT => ChangeOp::Put(record.key, record.value),
+        packet::CHANGE_DELETE => ChangeOp::Delete(record.key),
+        packet::CHANGE_DELETE_RANGE => ChangeOp::DeleteRange(record.key, record.value),
+        op => return Err(RsDBError::RespError(format!("unknown change type {}", op))),
+    };
+    Ok(Change {
+        seq: record.seq,
+        op,
+    })
+}
+
+/// A value read from history, after a flag telling whether it was found.
+fn into_value_at(resp: Packet) -> RsDBResult<Option<Vec<u8>>> {
+    match resp {
+        Packet::RespTokens(tokens) => match <[Vec<u8>; 2]>::try_from(tokens) {
+            Ok([found, value]) if found == [1] => Ok(Some(value)),
+            Ok([found, _]) if found == [0] => Ok(None),
+            _ => Err(RsDBError::RespError("invalid response".to_string())),
+        },
+        Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+        Packet::RespMoved(slot, addr) => Err(RsDBError::Moved(slot, addr)),
+        _ => Err(RsDBError::RespError("invalid response".to_string())),
+    }
+}
+
+fn into_text_pairs(resp: Packet) -> RsDBResult<Vec<(String, String)>> {
+    let mut pairs = vec![];
+    for (name, value) in into_pairs(resp)? {
+        pairs.push((String::from_utf8(name)?, String::from_utf8(value)?));
+    }
+    Ok(pairs)
+}
+
+fn into_pairs(resp: Packet) -> RsDBResult<Vec<(Vec<u8>, Vec<u8>)>> {
+    match resp {
+        Packet::RespPairs(mut tokens) => {
+            let mut pairs = vec![];
+            let pair_count = tokens.len() / 2;
+            for _idx in 0..pair_count {
+                let v = tokens.pop().ok_or(RsDBError::EmptyToken)?;
+                let k = tokens.pop().ok_or(RsDBError::EmptyToken)?;
+                pairs.push((k, v))
+            }
+            pairs.reverse();
+            Ok(pairs)
+        }
+        Packet::RespError(msg) => Err(RsDBError::RespError(msg)),
+        _ => Err(RsDBError::RespError("invalid response".to_string())),
     }
 }
@@ -0,0 +1,103 @@
+extern crate packet;
+extern crate storage;
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::process;
+
+use clap::{Parser, Subcommand};
+use packet::dump::{DumpFormat, DumpReader, DumpWriter};
+use storage::{BatchOp, IteratorMode, KvEngine, Storage, StorageError, StorageResult};
+
+/// Dump a database directory to a portable file or load one into it, with
+/// no server running on the directory.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = "RSDB offline dump and restore")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write every pair of a database to a file
+    Dump {
+        /// Directory of the database, e.g. `<root>/<name>`
+        #[arg(long)]
+        db: String,
+
+        #[arg(short, long)]
+        output: String,
+
+        /// `binary` or `jsonl`
+        #[arg(long, default_value = "binary")]
+        format: DumpFormat,
+    },
+    /// Load a dump in either format into a database, created if missing
+    Restore {
+        /// Directory of the database, e.g. `<root>/<name>`
+        #[arg(long)]
+        db: String,
+
+        #[arg(short, long)]
+        input: String,
+
+        /// Pairs written per batch
+        #[arg(long, default_value_t = 1000)]
+        batch_size: usize,
+    },
+}
+
+fn main() {
+    let args = Args::parse();
+    let rs = match args.command {
+        Command::Dump { db, output, format } => dump(&db, &output, format),
+        Command::Restore {
+            db,
+            input,
+            batch_size,
+        } => restore(&db, &input, batch_size),
+    };
+    match rs {
+        Ok(count) => eprintln!("{} pairs", count),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn dump(db: &str, output: &str, format: DumpFormat) -> StorageResult<u64> {
+    if !Path::new(db).is_dir() {
+        return Err(StorageError::DbNotFound(db.to_string()));
+    }
+    // left untouched, so that a db on a read-only mount can be dumped
+    let storage = Storage::open_read_only(db)?;
+    let mut writer = DumpWriter::new(BufWriter::new(File::create(output)?), format)?;
+    for pair in storage.iterator(IteratorMode::Start)? {
+        let (key, value) = pair?;
+        writer.write_pair(&key, &value)?;
+    }
+    let count = writer.count();
+    writer.finish()?;
+    Ok(count)
+}
+
+fn restore(db: &str, input: &str, batch_size: usize) -> StorageResult<u64> {
+    let storage = Storage::new(db)?;
+    let reader = DumpReader::new(BufReader::new(File::open(input)?))?;
+    let mut count = 0;
+    let mut ops = vec![];
+    for pair in reader {
+        let (key, value) = pair?;
+        ops.push(BatchOp::Put(key, value));
+        if ops.len() >= batch_size.max(1) {
+            count += ops.len() as u64;
+            storage.write_batch(std::mem::take(&mut ops))?;
+        }
+    }
+    count += ops.len() as u64;
+    storage.write_batch(ops)?;
+    Ok(count)
+}
@@ -0,0 +1,79 @@
+extern crate storage;
+
+use std::fs::File;
+use std::io::{stdin, BufRead, BufReader};
+use std::process;
+
+use clap::Parser;
+use storage::{write_sst_files, StorageError, StorageOptions};
+
+/// Build sst files for the `ingest` command from `key<TAB>value` lines
+/// sorted by key.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = "RSDB sst file builder")]
+struct Args {
+    /// Directory for the sst files, created if missing
+    #[arg(short, long)]
+    output: String,
+
+    /// Bytes after which a new sst file is started
+    #[arg(long, default_value_t = 256 << 20)]
+    file_size: u64,
+
+    /// Options of the target database as `key = value; ...`, e.g. its compression
+    #[arg(long, default_value = "")]
+    options: String,
+
+    /// Input file, standard input when missing
+    input: Option<String>,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let options = match StorageOptions::parse(&args.options) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    };
+    let reader: Box<dyn BufRead> = match args.input.as_ref() {
+        Some(path) => match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        },
+        None => Box::new(stdin().lock()),
+    };
+
+    let mut count = 0u64;
+    let pairs = reader.split(b'\n').map(|line| {
+        let mut line = line?;
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        let tab = line
+            .iter()
+            .position(|byte| *byte == b'\t')
+            .ok_or_else(|| StorageError::InvalidData(format!("no tab on line {}", count + 1)))?;
+        let value = line.split_off(tab + 1);
+        line.truncate(tab);
+        count += 1;
+        Ok((line, value))
+    });
+    match write_sst_files(&args.output, &options, args.file_size, pairs) {
+        Ok(files) => {
+            for file in &files {
+                println!("{}", file);
+            }
+            eprintln!("{} pairs in {} files", count, files.len());
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
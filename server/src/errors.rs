@@ -4,6 +4,7 @@ use std::io::Error as IOErr;
 use std::string::FromUtf8Error;
 
 use packet::errors::PacketError;
+use rsdbrs::RsDBError;
 use storage::StorageError;
 
 #[derive(Debug)]
@@ -13,6 +14,9 @@ pub enum ServerError {
     StorageError(StorageError),
     InvalidData,
     PacketError(PacketError),
+    PrimaryError(RsDBError),
+    /// A slot table or a slot migration was refused.
+    SlotError(String),
 }
 
 impl Error for ServerError {}
@@ -41,6 +45,12 @@ impl From<PacketError> for ServerError {
     }
 }
 
+impl From<RsDBError> for ServerError {
+    fn from(e: RsDBError) -> Self {
+        ServerError::PrimaryError(e)
+    }
+}
+
 impl Display for ServerError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
@@ -59,6 +69,12 @@ impl Display for ServerError {
             Self::PacketError(e) => {
                 write!(f, "PacketError - {e}")
             }
+            Self::PrimaryError(e) => {
+                write!(f, "PrimaryError - {e}")
+            }
+            Self::SlotError(msg) => {
+                write!(f, "SlotError - {msg}")
+            }
         }
     }
 }
@@ -1,2 +1,8 @@
 pub mod errors;
 pub mod logic;
+pub mod migration;
+pub mod raft;
+pub mod raft_log;
+pub mod replica;
+pub mod session;
+pub mod slots;
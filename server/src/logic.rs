@@ -1,58 +1,212 @@
+use std::fs;
 use std::io::{Read, Result, Write};
 use std::net::TcpListener;
 use std::os::unix::net::UnixListener;
-use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
 extern crate packet;
 extern crate storage;
 
-use packet::{Packet, PacketReaderWriter};
-use storage::{Direction, IteratorMode, MultiDB};
+use packet::{ChangeRecord, Packet, PacketReaderWriter, VersionRecord};
+use storage::{
+    BatchOp, Change, ChangeOp, Direction, Durability, EngineKind, IteratorMode, KvEngine,
+    KvIterator, MultiDB, StorageError, StorageOptions, StorageResult, Version,
+};
 
 use crate::errors::{ServerError, ServerResult};
+use crate::migration;
+use crate::raft::{Members, Raft};
+use crate::replica::Replication;
+use crate::session::{Checkpoints, Snapshots, CHECKPOINTS_DIR};
+use crate::slots::{Owner, SlotTable};
+
+/// How long a drop waits for other connections to finish with the database.
+pub(crate) const DROP_WAIT: Duration = Duration::from_secs(3);
+
+/// What a server is started with.
+pub struct ServerConfig {
+    pub addr: Option<String>,
+    pub unix_addr: Option<String>,
+    /// Root directory of the databases.
+    pub root: String,
+    /// How long an unused snapshot of a connection is kept.
+    pub snapshot_timeout: Duration,
+    /// File of the option profiles of new databases.
+    pub db_config: Option<String>,
+    pub engine: EngineKind,
+    /// Most databases kept open at once, the others being evicted.
+    pub max_open: Option<usize>,
+    /// Root directory of another server on this host, whose databases are
+    /// followed as secondary instances.
+    pub primary: Option<String>,
+}
 
 pub struct Server {
-    storage: Arc<Mutex<MultiDB>>,
+    storage: Arc<MultiDB>,
     address: Option<String>,
     unix_address: Option<String>,
     storage_dir: String,
+    snapshot_timeout: Duration,
+    replication: Option<Arc<Replication>>,
+    raft: Option<Arc<Raft>>,
+    slots: Option<Arc<SlotTable>>,
 }
 
 impl Server {
-    pub fn new(addr: Option<String>, root: &str, unix_addr: Option<String>) -> ServerResult<Self> {
+    /// With a `primary`, the databases are followed as secondary instances
+    /// of those under that root, written by another server on the same host.
+    pub fn new(config: ServerConfig) -> ServerResult<Self> {
+        let root = config.root.as_str();
+        let mut mdb = MultiDB::with_engine(root, config.engine);
+        if let Some(path) = config.db_config {
+            mdb.set_profiles(storage::load_profiles(&path)?);
+        }
+        mdb.set_max_open(config.max_open);
+        if let Some(primary_root) = config.primary {
+            mdb.follow(&primary_root)?;
+        }
+        for (name, e) in mdb.open_catalog()? {
+            eprintln!("    > Failed to reattach database `{}`: {}", name, e);
+        }
+        // left behind by connections of a previous run
+        let checkpoints = Path::new(root).join(CHECKPOINTS_DIR);
+        if checkpoints.exists() {
+            fs::remove_dir_all(&checkpoints)?;
+        }
         let server = Server {
-            storage: Arc::new(Mutex::new(MultiDB::new(root))),
-            address: addr,
-            unix_address: unix_addr,
+            storage: Arc::new(mdb),
+            address: config.addr,
+            unix_address: config.unix_addr,
             storage_dir: root.to_string(),
+            snapshot_timeout: config.snapshot_timeout,
+            replication: None,
+            raft: None,
+            slots: None,
         };
 
         Ok(server)
     }
 
+    /// Follow the databases of the server at `primary`, refusing writes
+    /// from clients.
+    pub fn replicate_from(&mut self, primary: &str) {
+        self.replication = Some(Replication::start(
+            primary,
+            &self.storage_dir,
+            self.storage.clone(),
+        ));
+    }
+
+    /// Catch the databases followed up with their primary every `interval`,
+    /// which bounds how stale the reads of a secondary server are.
+    pub fn catch_up_every(&self, interval: Duration) {
+        let mdb = self.storage.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            for (name, e) in mdb.catch_up() {
+                eprintln!("    > Failed to catch up database `{}`: {}", name, e);
+            }
+        });
+    }
+
+    /// Drop the versions of values kept past their history ttl every
+    /// `interval`, for the keys which are not written again.
+    pub fn prune_history_every(&self, interval: Duration) {
+        let mdb = self.storage.clone();
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            for (name, e) in mdb.prune_history() {
+                eprintln!("    > Failed to prune the history of `{}`: {}", name, e);
+            }
+        });
+    }
+
+    /// Join a raft group as node `id`, founding it with `members` unless
+    /// this node already has a log.
+    pub fn enable_raft(
+        &mut self,
+        id: u64,
+        members: Members,
+        snapshot_entries: u64,
+    ) -> ServerResult<()> {
+        let raft = Raft::start(
+            id,
+            members,
+            &self.storage_dir,
+            self.storage.clone(),
+            snapshot_entries,
+        )?;
+        self.raft = Some(raft);
+        Ok(())
+    }
+
+    /// Serve only the keys of some hash slots, `ranges` at first and those
+    /// saved under the root once slots have been moved.
+    pub fn enable_slots(&mut self, ranges: &str) -> ServerResult<()> {
+        self.slots = Some(Arc::new(SlotTable::open(&self.storage_dir, ranges)?));
+        Ok(())
+    }
+
+    /// Refuse the writes of clients until switched back at runtime.
+    pub fn set_read_only(&self) {
+        self.storage.set_read_only(true);
+    }
+
     pub fn listen_and_serve(&self) -> Result<()> {
         // Build a server
         println!("    > Listening at tcp  address {:?}", &self.address);
         println!("    > Listening at unix address {:?}", &self.unix_address);
-        println!("    > Storage: {}\n", &self.storage_dir);
+        println!("    > Storage: {}", &self.storage_dir);
+        if let Some(replication) = &self.replication {
+            println!("    > Read-only replica of {}", replication.primary());
+        }
+        if let Some(primary) = self.storage.primary() {
+            println!("    > Read-only secondary of {}", primary);
+        }
+        if let Some(raft) = &self.raft {
+            println!("    > Raft node {}", raft.id());
+        }
+        if let Some(slots) = &self.slots {
+            println!("    > Serving {} hash slots", slots.read().served());
+        }
+        if self.storage.is_read_only() {
+            println!("    > Read-only, writes are refused");
+        }
+        println!();
 
         // create a new thread to handle unix domain socket
         if let Some(addr) = &self.unix_address {
             let unix_sock = UnixListener::bind(addr)?;
             let storage = self.storage.clone();
+            let snapshot_timeout = self.snapshot_timeout;
+            let replication = self.replication.clone();
+            let raft = self.raft.clone();
+            let slots = self.slots.clone();
             thread::spawn(move || {
                 for stream in unix_sock.incoming() {
                     match stream {
                         Err(e) => eprintln!("error: {}", e),
                         Ok(stream) => {
                             let db_copy = storage.clone();
+                            let replication = replication.clone();
+                            let raft = raft.clone();
+                            let slots = slots.clone();
                             thread::spawn(move || {
-                                handler(stream, "<local unix client>", db_copy).unwrap_or_else(
-                                    |error| {
-                                        eprintln!("{:?}", error);
-                                    },
-                                );
+                                handler(
+                                    stream,
+                                    "<local unix client>",
+                                    db_copy,
+                                    snapshot_timeout,
+                                    replication,
+                                    raft,
+                                    slots,
+                                )
+                                .unwrap_or_else(|error| {
+                                    eprintln!("{:?}", error);
+                                });
                             });
                         }
                     }
@@ -72,8 +226,21 @@ impl Server {
                         let peer_name = format!("{}", stream.peer_addr().unwrap());
                         stream.set_nodelay(true).unwrap();
                         let db_copy = self.storage.clone();
+                        let snapshot_timeout = self.snapshot_timeout;
+                        let replication = self.replication.clone();
+                        let raft = self.raft.clone();
+                        let slots = self.slots.clone();
                         thread::spawn(move || {
-                            handler(stream, &peer_name, db_copy).unwrap_or_else(|error| {
+                            handler(
+                                stream,
+                                &peer_name,
+                                db_copy,
+                                snapshot_timeout,
+                                replication,
+                                raft,
+                                slots,
+                            )
+                            .unwrap_or_else(|error| {
                                 eprintln!("{:?}", error);
                             });
                         });
@@ -86,14 +253,26 @@ impl Server {
     }
 }
 
-fn handler<T>(stream: T, peer_name: &str, mdb: Arc<Mutex<MultiDB>>) -> ServerResult<()>
+fn handler<T>(
+    stream: T,
+    peer_name: &str,
+    mdb: Arc<MultiDB>,
+    snapshot_timeout: Duration,
+    replication: Option<Arc<Replication>>,
+    raft: Option<Arc<Raft>>,
+    slots: Option<Arc<SlotTable>>,
+) -> ServerResult<()>
 where
     T: Read + Write,
 {
     println!("Connection from {}", peer_name);
 
     let mut rw = PacketReaderWriter::new(stream);
-    let mut db: Option<Arc<storage::Storage>> = None;
+    let mut db: Option<Arc<dyn KvEngine>> = None;
+    // name of `db`, which raft log entries refer to
+    let mut db_name: Option<String> = None;
+    let mut snapshots = Snapshots::new(snapshot_timeout);
+    let mut checkpoints = Checkpoints::new(mdb.root_path(), snapshot_timeout);
     loop {
         // let packet = rw.read_packet();
         let packet = rw.read_packet();
@@ -101,226 +280,786 @@ where
             println!("Connection closed by client: <{peer_name}>");
             break;
         }
-        let resp = match packet? {
+        let packet = packet?;
+        snapshots.expire();
+        checkpoints.expire();
+        // checked ahead of raft and replication, which apply writes pairwise
+        if let Packet::CmdWrite(pairs) | Packet::CmdWriteWith(_, pairs) = &packet {
+            if !pairs.len().is_multiple_of(2) {
+                let msg = "a write needs a value for every key".to_string();
+                rw.write_packet(&Packet::RespError(msg))?;
+                continue;
+            }
+        }
+        if let (Some(replication), true) = (replication.as_ref(), is_write(&packet)) {
+            let msg = format!("read-only replica of {}", replication.primary());
+            rw.write_packet(&Packet::RespError(msg))?;
+            continue;
+        }
+        if let (Some(primary), true) = (mdb.primary(), is_write(&packet)) {
+            let msg = format!("read-only secondary of {}", primary);
+            rw.write_packet(&Packet::RespError(msg))?;
+            continue;
+        }
+        if mdb.is_read_only() && is_write(&packet) {
+            let msg = "server is read-only for maintenance".to_string();
+            rw.write_packet(&Packet::RespError(msg))?;
+            continue;
+        }
+        if changes_db(&packet) && db.as_ref().is_some_and(|sdb| sdb.read_only()) {
+            let msg = format!(
+                "database `{}` is read-only",
+                db_name.as_deref().unwrap_or("")
+            );
+            rw.write_packet(&Packet::RespError(msg))?;
+            continue;
+        }
+        if let (Some(raft), true) = (raft.as_ref(), is_write(&packet) || is_raft(&packet)) {
+            rw.write_packet(&raft_request(raft, packet, db_name.as_deref()))?;
+            continue;
+        }
+        // the table stays read locked until the command is done, so that
+        // slots change hands between commands only
+        let owners = match (slots.as_ref(), command_keys(&packet)) {
+            (Some(table), Some(keys)) => {
+                let owners = table.read();
+                if let Some(resp) = owners.moved(&keys) {
+                    rw.write_packet(&resp)?;
+                    continue;
+                }
+                Some(owners)
+            }
+            _ => None,
+        };
+        // neither read-only servers nor replicas create databases for clients
+        let may_create = !mdb.is_read_only() && replication.is_none();
+        let resp = match packet {
             Packet::CmdDelete(ref cmd) => match db.as_ref() {
                 Some(sdb) => {
-                    for key in cmd {
-                        sdb.delete(key)?
-                    }
+                    let ops = cmd.iter().map(|key| BatchOp::Delete(key.clone()));
+                    sdb.write_batch(ops.collect())?;
                     Packet::RespOk("Ok.".to_string())
                 }
                 None => Packet::RespError("no db selected".to_string()),
             },
+            Packet::CmdDeleteRange(ref start, ref end) => match db.as_ref() {
+                Some(sdb) => Packet::RespNumber(sdb.delete_range(start, end)?),
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdDeletePrefix(ref prefix) => match db.as_ref() {
+                Some(sdb) => Packet::RespNumber(sdb.delete_prefix(prefix)?),
+                None => Packet::RespError("no db selected".to_string()),
+            },
             Packet::CmdRead(ref cmd) => match db.as_ref() {
+                Some(sdb) => read_values(sdb.multi_get(cmd)?),
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdExists(ref keys) => match db.as_ref() {
                 Some(sdb) => {
-                    let mut values = Vec::new();
-                    for key in cmd {
-                        let db_rs = sdb.get(key)?;
-                        let value = match db_rs {
-                            Some(value) => value,
-                            None => Vec::new(),
-                        };
-                        values.push(value);
+                    let mut found = Vec::new();
+                    for key in keys {
+                        found.push(sdb.exists(key)? as u64);
                     }
-                    Packet::RespTokens(values)
+                    Packet::RespNumbers(found)
                 }
                 None => Packet::RespError("no db selected".to_string()),
             },
+            Packet::CmdStats() => match db.as_ref() {
+                Some(sdb) => match sdb.stats() {
+                    Ok(stats) => stats_packet(stats),
+                    Err(e) => Packet::RespError(e.to_string()),
+                },
+                None => Packet::RespError("no db selected".to_string()),
+            },
             Packet::CmdWrite(ref cmd) => match db.as_ref() {
                 Some(sdb) => {
-                    let pairs = cmd.len() / 2;
-                    for idx in 0..pairs {
-                        let begin = idx * 2;
-                        sdb.set(
-                            cmd.get(begin).ok_or(ServerError::InvalidData)?,
-                            cmd.get(begin + 1).ok_or(ServerError::InvalidData)?,
-                        )?
-                    }
+                    let ops = cmd
+                        .chunks_exact(2)
+                        .map(|pair| BatchOp::Put(pair[0].clone(), pair[1].clone()));
+                    sdb.write_batch(ops.collect())?;
                     Packet::RespOk("Ok.".to_string())
                 }
                 None => Packet::RespError("no db selected".to_string()),
             },
+            Packet::CmdWriteWith(flags, ref cmd) => match db.as_ref() {
+                Some(sdb) => {
+                    let ops = cmd
+                        .chunks_exact(2)
+                        .map(|pair| BatchOp::Put(pair[0].clone(), pair[1].clone()));
+                    match sdb.write_batch_with(ops.collect(), durability(flags)) {
+                        Ok(()) => Packet::RespOk("Ok.".to_string()),
+                        Err(e) => Packet::RespError(e.to_string()),
+                    }
+                }
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdDeleteWith(flags, ref cmd) => match db.as_ref() {
+                Some(sdb) => {
+                    let ops = cmd.iter().map(|key| BatchOp::Delete(key.clone()));
+                    match sdb.write_batch_with(ops.collect(), durability(flags)) {
+                        Ok(()) => Packet::RespOk("Ok.".to_string()),
+                        Err(e) => Packet::RespError(e.to_string()),
+                    }
+                }
+                None => Packet::RespError("no db selected".to_string()),
+            },
             Packet::CmdUse(cmd) => {
                 // println!("Received use command");
                 let current_db_name = String::from_utf8(cmd)?;
 
-                let unlocked_db = mdb.lock();
-                match unlocked_db {
-                    Ok(mut msdb) => {
-                        msdb.attach(&current_db_name)?;
-                        db = msdb.get_db(&current_db_name);
+                match attach(&mdb, &current_db_name, false, may_create) {
+                    Ok(sdb) => {
+                        db = Some(sdb);
+                        db_name = Some(current_db_name);
+                        Packet::RespOk("Ok.".to_string())
+                    }
+                    Err(e) => error_packet(e),
+                }
+            }
+            Packet::CmdUseExisting(cmd) => {
+                let current_db_name = String::from_utf8(cmd)?;
+
+                match mdb.attach_existing(&current_db_name) {
+                    Ok(sdb) => {
+                        db = Some(sdb);
+                        db_name = Some(current_db_name);
+                        Packet::RespOk("Ok.".to_string())
+                    }
+                    Err(e) => error_packet(e),
+                }
+            }
+            Packet::CmdUseWith(flags, name) => {
+                let current_db_name = String::from_utf8(name)?;
+                let read_only = flags & packet::USE_READ_ONLY != 0;
+
+                match attach(&mdb, &current_db_name, read_only, may_create) {
+                    Ok(sdb) => {
+                        db = Some(sdb);
+                        db_name = Some(current_db_name);
                         Packet::RespOk("Ok.".to_string())
                     }
-                    Err(_) => Packet::RespError("get lock failed".to_string()),
+                    Err(e) => error_packet(e),
+                }
+            }
+            Packet::CmdSetReadOnly(read_only) => {
+                // any client may switch it, there is no access control, so
+                // who did is logged
+                mdb.set_read_only(read_only);
+                let mode = if read_only { "on" } else { "off" };
+                println!("    > Read-only mode {} by <{}>", mode, peer_name);
+                Packet::RespOk("Ok.".to_string())
+            }
+            Packet::CmdUseWithOptions(name, options) => {
+                let current_db_name = String::from_utf8(name)?;
+                let options = String::from_utf8(options)?;
+
+                match StorageOptions::parse(&options).and_then(|options| {
+                    if !may_create && !db_exists(&mdb, &current_db_name) {
+                        return Err(StorageError::DbNotFound(current_db_name.clone()));
+                    }
+                    mdb.attach_with_options(&current_db_name, options)
+                }) {
+                    Ok(sdb) => {
+                        db = Some(sdb);
+                        db_name = Some(current_db_name);
+                        Packet::RespOk("Ok.".to_string())
+                    }
+                    Err(e) => error_packet(e),
                 }
             }
             Packet::CmdCurrentDB() => match db.as_ref() {
                 Some(sdb) => {
                     let tmpname = "<temp path>".to_string();
-                    let dbname = sdb.path.as_ref().unwrap_or(&tmpname);
+                    let dbname = sdb.path().unwrap_or(&tmpname);
                     Packet::RespToken(dbname.as_bytes().to_vec())
                 }
                 None => Packet::RespError("no db selected".to_string()),
             },
             Packet::CmdListDb() => {
-                let db = mdb.lock();
-                match db {
-                    Ok(msdb) => {
-                        let mut db_names = vec![];
-                        for name in msdb.list_db() {
-                            db_names.push(name.to_owned().to_vec())
-                        }
-                        Packet::RespTokens(db_names)
-                    }
-                    Err(_) => Packet::RespError("get lock failed".to_string()),
+                let mut db_names = vec![];
+                for name in mdb.list_db() {
+                    db_names.push(name.into_bytes())
                 }
+                Packet::RespTokens(db_names)
             }
             Packet::CmdDetach(cmd) => {
                 let detach_db = String::from_utf8(cmd)?;
                 if let Some(sdb) = db.as_ref() {
-                    if let Some(path) = sdb.path.as_ref() {
-                        if path == &detach_db {
+                    if let Some(path) = sdb.path() {
+                        if path == detach_db {
                             let _abd = db;
                             db = None;
+                            db_name = None;
                         }
                     }
                 }
 
-                {
-                    let db = mdb.lock();
-                    match db {
-                        Ok(mut msdb) => {
-                            msdb.detach(&detach_db);
-                            Packet::RespOk("Ok.".to_string())
-                        }
-                        Err(_) => Packet::RespError("get lock failed".to_string()),
-                    }
+                match mdb.detach(&detach_db) {
+                    Ok(()) => Packet::RespOk("Ok.".to_string()),
+                    Err(e) => error_packet(e),
                 }
             }
-            Packet::CmdRangeBegin(page_size) => {
-                match db.as_ref() {
-                    Some(sdb) => {
-                        let mut tokens = vec![];
-                        let it = sdb.this_db().iterator(IteratorMode::Start);
-                        for rs in it.take(page_size as usize) {
-                            if let Ok((k, v)) = rs {
-                                tokens.push(k.to_vec());
-                                tokens.push(v.to_vec());
-                            } else {
-                                // TODO add warning log
-                            }
-                        }
-                        Packet::RespPairs(tokens)
-                    }
-                    None => Packet::RespError("no db selected".to_string()),
+            Packet::CmdCatalog() => {
+                let mut tokens = vec![];
+                for entry in mdb.catalog() {
+                    tokens.push(entry.name.into_bytes());
+                    tokens.push(entry.state.to_string().into_bytes());
                 }
+                Packet::RespPairs(tokens)
             }
-            Packet::CmdRangeEnd(page_size) => {
-                match db.as_ref() {
-                    Some(sdb) => {
-                        let mut tokens = vec![];
-                        let it = sdb.this_db().iterator(IteratorMode::End);
-                        for rs in it.take(page_size as usize) {
-                            if let Ok((k, v)) = rs {
-                                tokens.push(k.to_vec());
-                                tokens.push(v.to_vec());
-                            } else {
-                                // TODO add warning log
-                            }
-                        }
-                        Packet::RespPairs(tokens)
-                    }
-                    None => Packet::RespError("no db selected".to_string()),
+            Packet::CmdServerStats() => {
+                let stats = mdb.pool_stats();
+                let max_open = match stats.max_open {
+                    Some(max_open) => max_open.to_string(),
+                    None => "unlimited".to_string(),
+                };
+                let figures = [
+                    ("open_databases", stats.open.to_string()),
+                    ("max_open_databases", max_open),
+                    ("evictions", stats.evictions.to_string()),
+                    ("reopens", stats.reopens.to_string()),
+                    ("read_only", mdb.is_read_only().to_string()),
+                ];
+                let mut tokens = vec![];
+                for (name, value) in figures {
+                    tokens.push(name.as_bytes().to_vec());
+                    tokens.push(value.into_bytes());
+                }
+                let raft_stats = raft.iter().flat_map(|r| r.stats());
+                for (name, value) in replication.iter().flat_map(|r| r.stats()).chain(raft_stats) {
+                    tokens.push(name.into_bytes());
+                    tokens.push(value.into_bytes());
+                }
+                if let Some(table) = slots.as_ref() {
+                    tokens.push(b"slots_served".to_vec());
+                    tokens.push(table.read().served().to_string().into_bytes());
+                }
+                if let Some(primary) = mdb.primary() {
+                    tokens.push(b"secondary_of".to_vec());
+                    tokens.push(primary.as_bytes().to_vec());
                 }
+                Packet::RespPairs(tokens)
             }
-            Packet::CmdRangeFromAsc(page_size, key) => {
-                match db.as_ref() {
-                    Some(sdb) => {
-                        let mut tokens = vec![];
-                        let iter_mode = IteratorMode::From(key.as_slice(), Direction::Forward);
-                        let it = sdb.this_db().iterator(iter_mode);
-                        for rs in it.take(page_size as usize) {
-                            if let Ok((k, v)) = rs {
-                                tokens.push(k.to_vec());
-                                tokens.push(v.to_vec());
-                            } else {
-                                // TODO add warning log
-                            }
+            Packet::CmdDrop(cmd) => {
+                let drop_db = String::from_utf8(cmd)?;
+                let mut selected = false;
+                if let (Some(sdb), Some(target)) = (db.as_ref(), mdb.get_db(&drop_db)) {
+                    selected = Arc::ptr_eq(sdb, &target);
+                }
+                if selected {
+                    db = None;
+                }
+                match mdb.drop_db(&drop_db, DROP_WAIT) {
+                    Ok(()) => {
+                        if selected {
+                            db_name = None;
                         }
-                        Packet::RespPairs(tokens)
+                        Packet::RespOk("Ok.".to_string())
                     }
-                    None => Packet::RespError("no db selected".to_string()),
-                }
-            }
-            Packet::CmdRangeFromAscEx(page_size, key) => {
-                match db.as_ref() {
-                    Some(sdb) => {
-                        let mut tokens = vec![];
-                        let iter_mode = IteratorMode::From(key.as_slice(), Direction::Forward);
-                        let it = sdb.this_db().iterator(iter_mode);
-                        for (idx, rs) in it.take(page_size as usize + 1).enumerate() {
-                            if let Ok((k, v)) = rs {
-                                let k_vec = k.to_vec();
-                                if idx == 0 && k_vec == key {
-                                    continue;
-                                }
-                                tokens.push(k_vec);
-                                tokens.push(v.to_vec());
-                            } else {
-                                // TODO add warning log
-                            }
+                    Err(e) => {
+                        if selected {
+                            db = mdb.get_db(&drop_db);
                         }
-                        Packet::RespPairs(tokens)
+                        error_packet(e)
                     }
-                    None => Packet::RespError("no db selected".to_string()),
                 }
             }
-            Packet::CmdRangeFromDesc(page_size, key) => {
-                match db.as_ref() {
-                    Some(sdb) => {
-                        let mut tokens = vec![];
-                        let iter_mode = IteratorMode::From(key.as_slice(), Direction::Reverse);
-                        let it = sdb.this_db().iterator(iter_mode);
-                        for rs in it.take(page_size as usize) {
-                            if let Ok((k, v)) = rs {
-                                tokens.push(k.to_vec());
-                                tokens.push(v.to_vec());
-                            } else {
-                                // TODO add warning log
-                            }
+            Packet::CmdRangeBegin(_)
+            | Packet::CmdRangeEnd(_)
+            | Packet::CmdRangeFromAsc(_, _)
+            | Packet::CmdRangeFromAscEx(_, _)
+            | Packet::CmdRangeFromDesc(_, _)
+            | Packet::CmdRangeFromDescEx(_, _) => match db.as_ref() {
+                Some(sdb) => read_range(&packet, |mode| sdb.iterator(mode)),
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdSnapshot() => match db.as_ref() {
+                Some(sdb) => Packet::RespNumber(snapshots.create(sdb)),
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdSnapshotRelease(handle) => {
+                if snapshots.release(handle) {
+                    Packet::RespOk("Ok.".to_string())
+                } else {
+                    Packet::RespError("invalid snapshot handle".to_string())
+                }
+            }
+            Packet::CmdWithSnapshot(handle, ref cmd) => match snapshots.get(handle) {
+                Some(snapshot) => match **cmd {
+                    Packet::CmdRead(ref keys) => read_values(snapshot.multi_get(keys)?),
+                    Packet::CmdRangeBegin(_)
+                    | Packet::CmdRangeEnd(_)
+                    | Packet::CmdRangeFromAsc(_, _)
+                    | Packet::CmdRangeFromAscEx(_, _)
+                    | Packet::CmdRangeFromDesc(_, _)
+                    | Packet::CmdRangeFromDescEx(_, _) => {
+                        read_range(cmd, |mode| snapshot.iterator(mode))
+                    }
+                    _ => Packet::RespError("command not supported on snapshot".to_string()),
+                },
+                None => Packet::RespError("invalid snapshot handle".to_string()),
+            },
+            Packet::CmdBackup(cmd) => match db.as_ref() {
+                Some(sdb) => {
+                    let backup_dir = String::from_utf8(cmd)?;
+                    match sdb.backup(&backup_dir) {
+                        Ok(backup_id) => Packet::RespNumber(backup_id as u64),
+                        Err(e) => Packet::RespError(e.to_string()),
+                    }
+                }
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdCheckpoint(cmd) => match db.as_ref() {
+                Some(sdb) => {
+                    let target_dir = String::from_utf8(cmd)?;
+                    match sdb.checkpoint(&target_dir) {
+                        Ok(()) => Packet::RespOk("Ok.".to_string()),
+                        Err(e) => Packet::RespError(e.to_string()),
+                    }
+                }
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdListBackups(cmd) => {
+                let backup_dir = String::from_utf8(cmd)?;
+                match storage::list_backups(&backup_dir) {
+                    Ok(backups) => {
+                        let mut numbers = vec![];
+                        for info in backups {
+                            numbers.push(info.backup_id as u64);
+                            numbers.push(info.timestamp as u64);
+                            numbers.push(info.size);
+                            numbers.push(info.num_files as u64);
                         }
-                        Packet::RespPairs(tokens)
+                        Packet::RespNumbers(numbers)
                     }
-                    None => Packet::RespError("no db selected".to_string()),
+                    Err(e) => Packet::RespError(e.to_string()),
                 }
             }
-            Packet::CmdRangeFromDescEx(page_size, key) => {
-                match db.as_ref() {
-                    Some(sdb) => {
-                        let mut tokens = vec![];
-                        let iter_mode = IteratorMode::From(key.as_slice(), Direction::Reverse);
-                        let it = sdb.this_db().iterator(iter_mode);
-                        for (idx, rs) in it.take(page_size as usize + 1).enumerate() {
-                            if let Ok((k, v)) = rs {
-                                let k_vec = k.to_vec();
-                                if idx == 0 && k_vec == key {
-                                    continue;
-                                }
-                                tokens.push(k_vec);
-                                tokens.push(v.to_vec());
-                            } else {
-                                // TODO add warning log
-                            }
-                        }
-                        Packet::RespPairs(tokens)
+            Packet::CmdPurgeBackups(keep, cmd) => {
+                let backup_dir = String::from_utf8(cmd)?;
+                match storage::purge_backups(&backup_dir, keep as usize) {
+                    Ok(()) => Packet::RespOk("Ok.".to_string()),
+                    Err(e) => Packet::RespError(e.to_string()),
+                }
+            }
+            Packet::CmdRestoreBackup(backup_dir, backup_id, name) => {
+                let backup_dir = String::from_utf8(backup_dir)?;
+                let name = String::from_utf8(name)?;
+                let backup_id = match backup_id {
+                    0 => None,
+                    id => Some(u32::try_from(id).map_err(|_| ServerError::InvalidData)?),
+                };
+
+                match mdb.restore(&backup_dir, backup_id, &name) {
+                    Ok(()) => Packet::RespOk("Ok.".to_string()),
+                    Err(e) => error_packet(e),
+                }
+            }
+            Packet::CmdCompact(ref start, ref end) => match db.as_ref() {
+                Some(sdb) => {
+                    let start = (!start.is_empty()).then_some(start.as_slice());
+                    let end = (!end.is_empty()).then_some(end.as_slice());
+                    maintenance(sdb.as_ref(), || sdb.compact(start, end))
+                }
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdFlush() => match db.as_ref() {
+                Some(sdb) => maintenance(sdb.as_ref(), || sdb.flush()),
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdIngest(cmd) => match db.as_ref() {
+                Some(sdb) => {
+                    let mut files = vec![];
+                    for file in cmd {
+                        files.push(String::from_utf8(file)?);
                     }
-                    None => Packet::RespError("no db selected".to_string()),
+                    maintenance(sdb.as_ref(), || sdb.ingest(&files))
+                }
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdWaitCompact(timeout) => match db.as_ref() {
+                Some(sdb) => {
+                    let timeout = (timeout > 0).then(|| Duration::from_secs(timeout as u64));
+                    maintenance(sdb.as_ref(), || sdb.wait_for_compact(timeout))
+                }
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdChanges(since, limit) => match db.as_ref() {
+                Some(sdb) => match sdb.changes_since(since, limit as usize) {
+                    Ok(changes) => {
+                        Packet::RespChanges(changes.into_iter().map(change_record).collect())
+                    }
+                    Err(e) => Packet::RespError(e.to_string()),
+                },
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdLatestSequence() => match db.as_ref() {
+                Some(sdb) => match sdb.latest_sequence() {
+                    Ok(seq) => Packet::RespNumber(seq),
+                    Err(e) => Packet::RespError(e.to_string()),
+                },
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdFetchCheckpoint() => match db.as_ref() {
+                Some(sdb) => match checkpoints.create(sdb) {
+                    Ok((handle, seq, files)) => Packet::RespCheckpoint(
+                        handle,
+                        seq,
+                        files.into_iter().map(String::into_bytes).collect(),
+                    ),
+                    Err(e) => Packet::RespError(e.to_string()),
+                },
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdReadCheckpoint(handle, ref file, offset) => {
+                let file = String::from_utf8(file.clone())?;
+                match checkpoints.read(handle, &file, offset)? {
+                    Some(data) => Packet::RespToken(data),
+                    None => Packet::RespError("invalid checkpoint handle or file".to_string()),
+                }
+            }
+            Packet::CmdReleaseCheckpoint(handle) => {
+                if checkpoints.release(handle) {
+                    Packet::RespOk("Ok.".to_string())
+                } else {
+                    Packet::RespError("invalid checkpoint handle".to_string())
                 }
             }
+            Packet::CmdGetAt(seq, ref key) => match db.as_ref() {
+                Some(sdb) => match sdb.get_at(key, seq) {
+                    Ok(value) => value_at(value),
+                    Err(e) => Packet::RespError(e.to_string()),
+                },
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdGetAtTime(time_ms, ref key) => match db.as_ref() {
+                Some(sdb) => match sdb.get_at_time(key, time_ms) {
+                    Ok(value) => value_at(value),
+                    Err(e) => Packet::RespError(e.to_string()),
+                },
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdHistory(limit, ref key) => match db.as_ref() {
+                Some(sdb) => match sdb.history(key, limit as usize) {
+                    Ok(versions) => {
+                        Packet::RespVersions(versions.into_iter().map(version_record).collect())
+                    }
+                    Err(e) => Packet::RespError(e.to_string()),
+                },
+                None => Packet::RespError("no db selected".to_string()),
+            },
+            Packet::CmdRaftVote(..)
+            | Packet::CmdRaftAppend(..)
+            | Packet::CmdRaftSnapshot(_)
+            | Packet::CmdRaftAddNode(..)
+            | Packet::CmdRaftRemoveNode(_) => Packet::RespError("raft is not enabled".to_string()),
+            Packet::CmdSlots()
+            | Packet::CmdImportSlots(..)
+            | Packet::CmdMoveSlots(..)
+            | Packet::CmdPurgeSlots(..) => match slots.as_ref() {
+                Some(table) => slots_request(table, &mdb, packet),
+                None => Packet::RespError("slots are not enabled".to_string()),
+            },
             _ => Packet::RespError("unknown command".to_string()),
         };
+        drop(owners);
         rw.write_packet(&resp)?;
     }
 
     Ok(())
 }
+
+/// Attach `name` for a client, for reading only with `read_only`, which
+/// never creates it. Otherwise it is created when missing only with
+/// `create`, unset while the server is read-only or a replica.
+fn attach(
+    mdb: &MultiDB,
+    name: &str,
+    read_only: bool,
+    create: bool,
+) -> StorageResult<Arc<dyn KvEngine>> {
+    if read_only {
+        mdb.attach_read_only(name)
+    } else if !create {
+        mdb.attach_existing(name)
+    } else {
+        mdb.attach(name)
+    }
+}
+
+/// Whether attaching `name` would find a database rather than create one.
+fn db_exists(mdb: &MultiDB, name: &str) -> bool {
+    mdb.get_db(name).is_some() || mdb.catalog().iter().any(|entry| entry.name == name)
+}
+
+/// The response for a failed database command, telling rejected names apart.
+fn error_packet(e: StorageError) -> Packet {
+    match e {
+        StorageError::InvalidName(_) => Packet::RespInvalidName(e.to_string()),
+        e => Packet::RespError(e.to_string()),
+    }
+}
+
+/// Answer a request from another member of the raft group, or send a
+/// write through the log. Writes that replace whole databases are refused.
+fn raft_request(raft: &Arc<Raft>, packet: Packet, db_name: Option<&str>) -> Packet {
+    let rs = match packet {
+        Packet::CmdRaftVote(term, candidate, last_index, last_term) => {
+            raft.vote(term, candidate, last_index, last_term)
+        }
+        Packet::CmdRaftAppend(term, leader, prev_index, prev_term, commit, entries) => {
+            raft.append(term, leader, prev_index, prev_term, commit, entries)
+        }
+        Packet::CmdRaftSnapshot(chunk) => raft.install_snapshot(chunk),
+        Packet::CmdRaftAddNode(id, addr) => match String::from_utf8(addr) {
+            Ok(addr) => raft.add_node(id, addr),
+            Err(e) => Err(e.into()),
+        },
+        Packet::CmdRaftRemoveNode(id) => raft.remove_node(id),
+        Packet::CmdDrop(_)
+        | Packet::CmdRestoreBackup(..)
+        | Packet::CmdIngest(_)
+        | Packet::CmdImportSlots(..)
+        | Packet::CmdPurgeSlots(..) => {
+            return Packet::RespError("not supported by a raft node".to_string())
+        }
+        packet => match db_name {
+            Some(name) => raft.propose(name, &packet),
+            None => return Packet::RespError("no db selected".to_string()),
+        },
+    };
+    rs.unwrap_or_else(|e| Packet::RespError(e.to_string()))
+}
+
+/// Answer a command reading or changing the slot table.
+fn slots_request(table: &SlotTable, mdb: &MultiDB, packet: Packet) -> Packet {
+    let rs = match packet {
+        Packet::CmdSlots() => {
+            let mut tokens = vec![];
+            for (first, last, owner) in table.read().ranges() {
+                tokens.push(format!("{}-{}", first, last).into_bytes());
+                tokens.push(match owner {
+                    Owner::Moved(addr) => addr.clone().into_bytes(),
+                    _ => vec![],
+                });
+            }
+            Ok(Packet::RespPairs(tokens))
+        }
+        Packet::CmdImportSlots(first, last, source, addr) => {
+            match (String::from_utf8(source), String::from_utf8(addr)) {
+                (Ok(source), Ok(addr)) => {
+                    migration::import_slots(table, mdb, first, last, &source, &addr)
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e.into()),
+            }
+        }
+        Packet::CmdMoveSlots(first, last, addr) => String::from_utf8(addr)
+            .map_err(ServerError::from)
+            .and_then(|addr| move_slots(table, first, last, addr)),
+        Packet::CmdPurgeSlots(first, last) => {
+            migration::purge_slots(table, mdb, first, last).map(Packet::RespNumber)
+        }
+        _ => return Packet::RespError("unknown command".to_string()),
+    };
+    rs.unwrap_or_else(|e| Packet::RespError(e.to_string()))
+}
+
+/// Hand slots served here over to the node at `addr`. Asking again for the
+/// same node succeeds, so that an import can be retried.
+fn move_slots(table: &SlotTable, first: u16, last: u16, addr: String) -> ServerResult<Packet> {
+    if first > last || last >= packet::SLOT_COUNT || addr.is_empty() {
+        return Err(ServerError::InvalidData);
+    }
+    let mut owners = table.write();
+    let moved = Owner::Moved(addr);
+    if let Some(slot) = owners.find_not(first, last, |owner| {
+        *owner == Owner::Local || *owner == moved
+    }) {
+        return Err(ServerError::SlotError(format!(
+            "slot {} is not served here",
+            slot
+        )));
+    }
+    owners.assign(first, last, moved);
+    table.save(&owners)?;
+    Ok(Packet::RespOk("Ok.".to_string()))
+}
+
+/// Keys a command reads or writes, which must be in the slots served here.
+fn command_keys(packet: &Packet) -> Option<Vec<&[u8]>> {
+    match packet {
+        Packet::CmdRead(keys)
+        | Packet::CmdExists(keys)
+        | Packet::CmdDelete(keys)
+        | Packet::CmdDeleteWith(_, keys) => Some(keys.iter().map(Vec::as_slice).collect()),
+        Packet::CmdWrite(pairs) | Packet::CmdWriteWith(_, pairs) => {
+            Some(pairs.iter().step_by(2).map(Vec::as_slice).collect())
+        }
+        Packet::CmdGetAt(_, key) | Packet::CmdGetAtTime(_, key) | Packet::CmdHistory(_, key) => {
+            Some(vec![key.as_slice()])
+        }
+        _ => None,
+    }
+}
+
+/// Requests between the members of a raft group, and to change them.
+fn is_raft(packet: &Packet) -> bool {
+    matches!(
+        packet,
+        Packet::CmdRaftVote(..)
+            | Packet::CmdRaftAppend(..)
+            | Packet::CmdRaftSnapshot(_)
+            | Packet::CmdRaftAddNode(..)
+            | Packet::CmdRaftRemoveNode(_)
+    )
+}
+
+/// Commands changing the data of a database, which replicas refuse.
+fn is_write(packet: &Packet) -> bool {
+    changes_db(packet)
+        || matches!(
+            packet,
+            Packet::CmdDrop(_)
+                | Packet::CmdRestoreBackup(..)
+                | Packet::CmdImportSlots(..)
+                | Packet::CmdPurgeSlots(..)
+        )
+}
+
+/// Commands changing the data of the selected database.
+fn changes_db(packet: &Packet) -> bool {
+    matches!(
+        packet,
+        Packet::CmdWrite(_)
+            | Packet::CmdDelete(_)
+            | Packet::CmdWriteWith(..)
+            | Packet::CmdDeleteWith(..)
+            | Packet::CmdDeleteRange(..)
+            | Packet::CmdDeletePrefix(_)
+            | Packet::CmdIngest(_)
+    )
+}
+
+pub(crate) fn durability(flags: u16) -> Durability {
+    Durability {
+        sync: flags & packet::WRITE_SYNC != 0,
+        disable_wal: flags & packet::WRITE_NO_WAL != 0,
+    }
+}
+
+fn stats_packet(stats: Vec<(String, String)>) -> Packet {
+    let mut tokens = vec![];
+    for (name, value) in stats {
+        tokens.push(name.into_bytes());
+        tokens.push(value.into_bytes());
+    }
+    Packet::RespPairs(tokens)
+}
+
+/// Run a blocking maintenance `task` and report how long it took, and the
+/// size of the sst files before and after when the engine has any.
+fn maintenance<F: FnOnce() -> StorageResult<()>>(sdb: &dyn KvEngine, task: F) -> Packet {
+    let live_size = || {
+        let stats = sdb.stats().ok()?;
+        let (_, size) = stats
+            .into_iter()
+            .find(|(name, _)| name == "live_sst_size")?;
+        Some(size)
+    };
+    let before = live_size();
+    let started = Instant::now();
+    if let Err(e) = task() {
+        return Packet::RespError(e.to_string());
+    }
+    let mut stats = vec![(
+        "duration_ms".to_string(),
+        started.elapsed().as_millis().to_string(),
+    )];
+    if let (Some(before), Some(after)) = (before, live_size()) {
+        stats.push(("live_sst_size_before".to_string(), before));
+        stats.push(("live_sst_size_after".to_string(), after));
+    }
+    stats_packet(stats)
+}
+
+fn change_record(change: Change) -> ChangeRecord {
+    let (op, key, value) = match change.op {
+        ChangeOp::Put(key, value) => (packet::CHANGE_PUT, key, value),
+        ChangeOp::Delete(key) => (packet::CHANGE_DELETE, key, vec![]),
+        ChangeOp::DeleteRange(start, end) => (packet::CHANGE_DELETE_RANGE, start, end),
+    };
+    ChangeRecord {
+        seq: change.seq,
+        op,
+        key,
+        value,
+    }
+}
+
+fn version_record(version: Version) -> VersionRecord {
+    VersionRecord {
+        seq: version.seq,
+        time_ms: version.time_ms,
+        value: version.value.unwrap_or_default(),
+    }
+}
+
+/// A value read from history, flagged as an empty value is not a missing
+/// one.
+fn value_at(value: Option<Vec<u8>>) -> Packet {
+    let found = vec![value.is_some() as u8];
+    Packet::RespTokens(vec![found, value.unwrap_or_default()])
+}
+
+/// Missing keys are sent as empty values.
+fn read_values(values: Vec<Option<Vec<u8>>>) -> Packet {
+    Packet::RespTokens(values.into_iter().map(Option::unwrap_or_default).collect())
+}
+
+fn read_range<'a, F>(cmd: &Packet, iterator: F) -> Packet
+where
+    F: Fn(IteratorMode) -> StorageResult<KvIterator<'a>>,
+{
+    let (it, page_size, exclude) = match cmd {
+        Packet::CmdRangeBegin(page_size) => (iterator(IteratorMode::Start), *page_size, None),
+        Packet::CmdRangeEnd(page_size) => (iterator(IteratorMode::End), *page_size, None),
+        Packet::CmdRangeFromAsc(page_size, key) => {
+            let iter_mode = IteratorMode::From(key.as_slice(), Direction::Forward);
+            (iterator(iter_mode), *page_size, None)
+        }
+        Packet::CmdRangeFromAscEx(page_size, key) => {
+            let iter_mode = IteratorMode::From(key.as_slice(), Direction::Forward);
+            (iterator(iter_mode), *page_size, Some(key))
+        }
+        Packet::CmdRangeFromDesc(page_size, key) => {
+            let iter_mode = IteratorMode::From(key.as_slice(), Direction::Reverse);
+            (iterator(iter_mode), *page_size, None)
+        }
+        Packet::CmdRangeFromDescEx(page_size, key) => {
+            let iter_mode = IteratorMode::From(key.as_slice(), Direction::Reverse);
+            (iterator(iter_mode), *page_size, Some(key))
+        }
+        _ => return Packet::RespError("unknown command".to_string()),
+    };
+    let it = match it {
+        Ok(it) => it,
+        Err(e) => return Packet::RespError(e.to_string()),
+    };
+
+    let mut tokens = vec![];
+    for (idx, rs) in it.enumerate() {
+        if tokens.len() / 2 >= page_size as usize {
+            break;
+        }
+        if let Ok((k, v)) = rs {
+            if idx == 0 && exclude.is_some_and(|key| key.as_slice() == k.as_ref()) {
+                continue;
+            }
+            tokens.push(k.to_vec());
+            tokens.push(v.to_vec());
+        } else {
+            // TODO add warning log
+        }
+    }
+    Packet::RespPairs(tokens)
+}
@@ -1,10 +1,19 @@
 extern crate packet;
 extern crate storage;
 
+use std::time::Duration;
+
 use clap::Parser;
+use storage::EngineKind;
 
 mod errors;
 mod logic;
+mod migration;
+mod raft;
+mod raft_log;
+mod replica;
+mod session;
+mod slots;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = "RSDB server")]
@@ -17,6 +26,93 @@ struct Args {
 
     #[arg(short, long)]
     unix_addr: Option<String>,
+
+    /// Seconds an unused snapshot is kept for a connection
+    #[arg(long, default_value_t = 300)]
+    snapshot_timeout: u64,
+
+    /// Config file with `[name]` sections of RocksDB options for new databases
+    #[arg(long)]
+    db_config: Option<String>,
+
+    /// Keep all databases as column families of one RocksDB instance
+    #[arg(long)]
+    column_families: bool,
+
+    /// Keep all databases in memory, for tests
+    #[arg(long, conflicts_with = "column_families")]
+    in_memory: bool,
+
+    /// Most databases kept open, idle ones beyond it are closed until used
+    #[arg(long)]
+    max_open_dbs: Option<usize>,
+
+    /// Address of a primary server to replicate from, serving reads only
+    #[arg(long, conflicts_with_all = ["in_memory", "column_families"])]
+    replica_of: Option<String>,
+
+    /// Id of this node in a raft group replicating writes, needs `--addr`
+    #[arg(long, requires = "addr", conflicts_with_all = ["replica_of", "in_memory", "column_families"])]
+    raft_id: Option<u64>,
+
+    /// Founding members of the raft group as `id=addr,...`, the same on each
+    /// of them. Left out when joining a running group
+    #[arg(long, requires = "raft_id", value_delimiter = ',')]
+    raft_peers: Vec<String>,
+
+    /// Applied raft entries after which the databases are checkpointed and
+    /// the log truncated
+    #[arg(long, default_value_t = 10000)]
+    raft_snapshot_entries: u64,
+
+    /// Serve only the keys of these hash slots, as `first-last,...`, empty
+    /// for none yet. The slots saved under the root take precedence once
+    /// any have been moved
+    #[arg(long, conflicts_with_all = ["replica_of", "raft_id"])]
+    slots: Option<String>,
+
+    /// Root directory of another server on this host, whose databases are
+    /// followed as RocksDB secondary instances serving reads only
+    #[arg(long, conflicts_with_all = ["replica_of", "raft_id", "slots", "in_memory", "column_families"])]
+    secondary_of: Option<String>,
+
+    /// Milliseconds between catching up with the primary, the most a
+    /// secondary lags behind it, save for the time to catch up
+    #[arg(long, requires = "secondary_of", default_value_t = 1000)]
+    catch_up_interval_ms: u64,
+
+    /// Seconds between dropping the versions kept past `history_ttl_seconds`
+    /// of keys not written since
+    #[arg(long, default_value_t = 60)]
+    history_prune_interval: u64,
+
+    /// Start refusing the writes of clients and the creation of databases,
+    /// as for maintenance, until switched off with `read_only off`, which
+    /// any client can send
+    #[arg(long)]
+    read_only: bool,
+}
+
+/// Parse `id=addr` members, which must include `id` when any are given.
+fn parse_members(id: u64, peers: &[String]) -> Result<raft::Members, String> {
+    let mut members = vec![];
+    for peer in peers {
+        let (member, addr) = peer
+            .split_once('=')
+            .ok_or_else(|| format!("expected `id=addr`, got `{}`", peer))?;
+        let member: u64 = member
+            .parse()
+            .map_err(|_| format!("invalid node id `{}`", member))?;
+        if member == 0 || members.iter().any(|(other, _)| *other == member) {
+            return Err(format!("invalid or repeated node id {}", member));
+        }
+        members.push((member, addr.to_string()));
+    }
+    if !members.is_empty() && !members.iter().any(|(member, _)| *member == id) {
+        return Err(format!("node {} is not among the raft peers", id));
+    }
+    members.sort();
+    Ok(members)
 }
 
 fn main() {
@@ -27,9 +123,54 @@ fn main() {
 
     println!("\n\t{pkg_name} {pkg_version}\n");
 
-    let s = logic::Server::new(args.addr, &args.root, args.unix_addr);
+    let engine = if args.in_memory {
+        EngineKind::Memory
+    } else if args.column_families {
+        EngineKind::ColumnFamilies
+    } else {
+        EngineKind::RocksDB
+    };
+    let s = logic::Server::new(logic::ServerConfig {
+        addr: args.addr,
+        unix_addr: args.unix_addr,
+        root: args.root,
+        snapshot_timeout: Duration::from_secs(args.snapshot_timeout),
+        db_config: args.db_config,
+        engine,
+        max_open: args.max_open_dbs,
+        primary: args.secondary_of.clone(),
+    });
     match s {
         Err(e) => eprintln!("Error: {}", e),
-        Ok(s) => s.listen_and_serve().unwrap(),
+        Ok(mut s) => {
+            if let Some(primary) = args.replica_of {
+                s.replicate_from(&primary);
+            }
+            if args.secondary_of.is_some() {
+                s.catch_up_every(Duration::from_millis(args.catch_up_interval_ms));
+            } else {
+                s.prune_history_every(Duration::from_secs(args.history_prune_interval));
+            }
+            if let Some(id) = args.raft_id {
+                let started = parse_members(id, &args.raft_peers).and_then(|members| {
+                    s.enable_raft(id, members, args.raft_snapshot_entries)
+                        .map_err(|e| e.to_string())
+                });
+                if let Err(e) = started {
+                    eprintln!("Error: {}", e);
+                    return;
+                }
+            }
+            if let Some(ranges) = args.slots {
+                if let Err(e) = s.enable_slots(&ranges) {
+                    eprintln!("Error: {}", e);
+                    return;
+                }
+            }
+            if args.read_only {
+                s.set_read_only();
+            }
+            s.listen_and_serve().unwrap()
+        }
     }
 }
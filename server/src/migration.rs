@@ -0,0 +1,297 @@
+use std::ops::RangeInclusive;
+use std::time::Instant;
+
+use packet::{key_slot, Packet};
+use rsdbrs::{ChangeOp, IteratorMode as ClientMode, RsDBClient};
+use storage::{BatchOp, DbState, Direction, IteratorMode, KvEngine, MultiDB};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::slots::{Owner, SlotTable};
+
+/// Pairs copied, changes applied or keys deleted per batch.
+const PAGE_SIZE: u16 = 1000;
+
+#[derive(Default)]
+struct Progress {
+    copied: u64,
+    applied: u64,
+}
+
+/// Take slots `first` to `last` over from the node at `source`, which
+/// clients are told to send them to `addr` afterwards.
+///
+/// The keys of the slots are copied from a snapshot of each database of the
+/// source, then its change feed is followed until close. The slot table is
+/// then held for writing, which holds back the commands on keys here, while
+/// the source hands the slots over and the last changes are applied. The
+/// source deletes its copy once the slots are served here.
+///
+/// Writes to the slots that skip the WAL on the source during the copy are
+/// lost. The source must have no detached databases, whose keys could be
+/// neither copied nor purged without attaching them. A failed import can be
+/// run again: keys left over from it are deleted first, and the source
+/// still has all of them until the end.
+pub fn import_slots(
+    table: &SlotTable,
+    mdb: &MultiDB,
+    first: u16,
+    last: u16,
+    source: &str,
+    addr: &str,
+) -> ServerResult<Packet> {
+    let _importing = match table.try_import() {
+        Some(guard) => guard,
+        None => return Err(ServerError::SlotError("already importing".to_string())),
+    };
+    if source == addr {
+        return Err(ServerError::SlotError(
+            "cannot import from itself".to_string(),
+        ));
+    }
+    let local = table
+        .read()
+        .find_not(first, last, |owner| *owner != Owner::Local);
+    if let Some(slot) = local {
+        let msg = format!("slot {} is already served here", slot);
+        return Err(ServerError::SlotError(msg));
+    }
+
+    let started = Instant::now();
+    let slots = first..=last;
+    let mut client = RsDBClient::new();
+    client.connect(source)?;
+    let mut progress = Progress::default();
+    let mut dbs: Vec<(String, u64)> = vec![];
+    for name in source_dbs(&mut client)? {
+        let seq = copy_db(&mut client, mdb, &name, &slots, &mut progress)?;
+        dbs.push((name, seq));
+    }
+    // catch up while the source still takes writes to the slots
+    loop {
+        let mut caught_up = true;
+        for (name, applied) in dbs.iter_mut() {
+            caught_up &= catch_up(&mut client, mdb, name, applied, &slots, &mut progress)?;
+        }
+        if caught_up {
+            break;
+        }
+    }
+
+    let mut owners = table.write();
+    // checked again right before the move, which is not undone on failure
+    source_dbs(&mut client)?;
+    client.move_slots(first, last, addr)?;
+    for (name, applied) in dbs.iter_mut() {
+        client.use_existing_db(name)?;
+        let latest = client.latest_sequence()?;
+        while *applied < latest {
+            if catch_up(&mut client, mdb, name, applied, &slots, &mut progress)? {
+                break;
+            }
+        }
+    }
+    // databases created on the source since the copy started, including
+    // any detached meanwhile, which are attached again to be copied
+    for (name, _) in client.catalog()? {
+        if !dbs.iter().any(|(copied, _)| *copied == name) {
+            copy_db(&mut client, mdb, &name, &slots, &mut progress)?;
+        }
+    }
+    owners.assign(first, last, Owner::Local);
+    table.save(&owners)?;
+    drop(owners);
+    eprintln!(
+        "    > Serving slots {}-{} imported from {}",
+        first, last, source
+    );
+
+    let purged = client.purge_slots(first, last)?;
+    let figures = [
+        ("copied", progress.copied),
+        ("applied_changes", progress.applied),
+        ("purged_on_source", purged),
+        ("duration_ms", started.elapsed().as_millis() as u64),
+    ];
+    let mut tokens = vec![];
+    for (name, value) in figures {
+        tokens.push(name.as_bytes().to_vec());
+        tokens.push(value.to_string().into_bytes());
+    }
+    Ok(Packet::RespPairs(tokens))
+}
+
+/// Delete the keys of slots `first` to `last` from every database, once they
+/// are no longer served here. Detached databases are opened for it and
+/// detached again. Returns how many were deleted.
+pub fn purge_slots(table: &SlotTable, mdb: &MultiDB, first: u16, last: u16) -> ServerResult<u64> {
+    let local = table
+        .read()
+        .find_not(first, last, |owner| *owner != Owner::Local);
+    if let Some(slot) = local {
+        return Err(ServerError::SlotError(format!(
+            "slot {} is served here",
+            slot
+        )));
+    }
+    let slots = first..=last;
+    let mut purged = 0;
+    for entry in mdb.catalog() {
+        let sdb = mdb.attach_existing(&entry.name)?;
+        purged += delete_slots(sdb.as_ref(), b"", None, &slots)?;
+        if entry.state == DbState::Detached {
+            drop(sdb);
+            mdb.detach(&entry.name)?;
+        }
+    }
+    Ok(purged)
+}
+
+/// The databases of the source, which must all be attached.
+fn source_dbs(client: &mut RsDBClient) -> ServerResult<Vec<String>> {
+    let mut names = vec![];
+    for (name, state) in client.catalog()? {
+        if state != "attached" {
+            let msg = format!(
+                "database `{}` is {} on the source, attach or drop it first",
+                name, state
+            );
+            return Err(ServerError::SlotError(msg));
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Copy the keys of `slots` in database `name` of the source from a
+/// snapshot, returning the sequence number to follow its changes from.
+fn copy_db(
+    client: &mut RsDBClient,
+    mdb: &MultiDB,
+    name: &str,
+    slots: &RangeInclusive<u16>,
+    progress: &mut Progress,
+) -> ServerResult<u64> {
+    let local = mdb.attach(name)?;
+    client.use_existing_db(name)?;
+    delete_slots(local.as_ref(), b"", None, slots)?;
+    let seq = client.latest_sequence()?;
+    let handle = client.snapshot()?;
+    let rs = copy_snapshot(client, handle, local.as_ref(), slots, progress);
+    let released = client.release_snapshot(handle);
+    rs?;
+    released?;
+    Ok(seq)
+}
+
+fn copy_snapshot(
+    client: &mut RsDBClient,
+    handle: u64,
+    local: &dyn KvEngine,
+    slots: &RangeInclusive<u16>,
+    progress: &mut Progress,
+) -> ServerResult<()> {
+    let mut last_key: Option<Vec<u8>> = None;
+    loop {
+        let pairs = match last_key.as_ref() {
+            Some(key) => {
+                let iter_mode = ClientMode::From(key, rsdbrs::Direction::Forward);
+                client.snapshot_range(handle, iter_mode, PAGE_SIZE, true)?
+            }
+            None => client.snapshot_range(handle, ClientMode::Start, PAGE_SIZE, false)?,
+        };
+        last_key = match pairs.last() {
+            Some((key, _)) => Some(key.clone()),
+            None => return Ok(()),
+        };
+        let ops: Vec<BatchOp> = pairs
+            .into_iter()
+            .filter(|(key, _)| slots.contains(&key_slot(key)))
+            .map(|(key, value)| BatchOp::Put(key, value))
+            .collect();
+        progress.copied += ops.len() as u64;
+        if !ops.is_empty() {
+            local.write_batch(ops)?;
+        }
+    }
+}
+
+/// Apply a page of the changes to database `name` after `applied` which
+/// touch `slots`, returning whether it was the last one.
+fn catch_up(
+    client: &mut RsDBClient,
+    mdb: &MultiDB,
+    name: &str,
+    applied: &mut u64,
+    slots: &RangeInclusive<u16>,
+    progress: &mut Progress,
+) -> ServerResult<bool> {
+    let local = mdb.attach(name)?;
+    client.use_existing_db(name)?;
+    let changes = client.changes_since(*applied, PAGE_SIZE)?;
+    let caught_up = changes.len() < PAGE_SIZE as usize;
+    let mut ops = vec![];
+    for change in changes {
+        *applied = change.seq;
+        match change.op {
+            ChangeOp::Put(key, value) if slots.contains(&key_slot(&key)) => {
+                ops.push(BatchOp::Put(key, value))
+            }
+            ChangeOp::Delete(key) if slots.contains(&key_slot(&key)) => {
+                ops.push(BatchOp::Delete(key))
+            }
+            ChangeOp::DeleteRange(start, end) => {
+                if !ops.is_empty() {
+                    progress.applied += ops.len() as u64;
+                    local.write_batch(std::mem::take(&mut ops))?;
+                }
+                // only the keys of the slots, others here are not the source's
+                progress.applied += delete_slots(local.as_ref(), &start, Some(&end), slots)?;
+            }
+            _ => {}
+        }
+    }
+    if !ops.is_empty() {
+        progress.applied += ops.len() as u64;
+        local.write_batch(ops)?;
+    }
+    Ok(caught_up)
+}
+
+/// Delete the keys from `start` up to `end` which are in `slots`, returning
+/// how many. The iterator is dropped before each batch is written.
+fn delete_slots(
+    local: &dyn KvEngine,
+    start: &[u8],
+    end: Option<&[u8]>,
+    slots: &RangeInclusive<u16>,
+) -> ServerResult<u64> {
+    let mut deleted = 0;
+    let mut from = start.to_vec();
+    loop {
+        let mut keys = vec![];
+        let mut done = true;
+        for pair in local.iterator(IteratorMode::From(&from, Direction::Forward))? {
+            let (key, _) = pair?;
+            if end.is_some_and(|end| key.as_ref() >= end) {
+                break;
+            }
+            if slots.contains(&key_slot(&key)) {
+                keys.push(key.to_vec());
+                if keys.len() == PAGE_SIZE as usize {
+                    done = false;
+                    break;
+                }
+            }
+        }
+        if let Some(key) = keys.last() {
+            from = key.clone();
+        }
+        deleted += keys.len() as u64;
+        if !keys.is_empty() {
+            local.write_batch(keys.into_iter().map(BatchOp::Delete).collect())?;
+        }
+        if done {
+            return Ok(deleted);
+        }
+    }
+}
@@ -0,0 +1,1326 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Component, Path};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use packet::{Packet, PacketReaderWriter, RaftEntry, RaftSnapshotChunk};
+use storage::{BatchOp, DbState, KvEngine, MultiDB, StorageResult};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::logic::{durability, DROP_WAIT};
+use crate::raft_log::RaftLog;
+
+/// Directory under the root holding the raft state of the node.
+const RAFT_DIR: &str = ".raft";
+
+/// File of a snapshot listing the databases detached on the node that took
+/// it, which are left alone on installing it. Named like no database can be.
+const DETACHED_FILE: &str = ".detached";
+
+// kinds of log entries, the first byte of their data
+const ENTRY_NOOP: u8 = 0;
+const ENTRY_MEMBERS: u8 = 1;
+const ENTRY_WRITE: u8 = 2;
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shortest election timeout, the longest being twice as long.
+const ELECTION_TIMEOUT: Duration = Duration::from_millis(500);
+
+const TICK_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long a node waits for a peer to connect or answer.
+const RPC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client write waits to be committed and applied.
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most entries sent per append request or applied at once.
+const MAX_ENTRIES: usize = 256;
+
+const SNAPSHOT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Node ids and client addresses of the members of the group.
+pub type Members = Vec<(u64, String)>;
+
+/// The databases written by the entries being applied, and whether any of
+/// those writes skipped the WAL.
+type Touched = HashMap<String, (Arc<dyn KvEngine>, bool)>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Role {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// What a leader or candidate knows of another member.
+struct Peer {
+    /// Index of the next entry to send.
+    next: u64,
+    /// Highest index known to be in the log of the peer.
+    matched: u64,
+    /// Whether the vote of the peer was asked for in this term.
+    vote_asked: bool,
+    sent_at: Option<Instant>,
+}
+
+/// A request for a peer, made under the state lock and sent without it.
+enum Request {
+    Rpc(Packet),
+    Snapshot(RaftSnapshotChunk),
+}
+
+struct State {
+    log: RaftLog,
+    role: Role,
+    /// Id of the known leader, 0 for none.
+    leader: u64,
+    commit: u64,
+    applied: u64,
+    /// Latest membership in the log, in effect as soon as appended.
+    members: Members,
+    /// Index of the entry holding `members`.
+    members_index: u64,
+    /// `members` as encoded in the log.
+    members_data: Vec<u8>,
+    election_at: Instant,
+    /// Last time a leader was heard from.
+    heard_at: Option<Instant>,
+    peers: HashMap<u64, Peer>,
+    /// Peers with a replication thread running.
+    threads: HashSet<u64>,
+    votes: HashSet<u64>,
+    /// Results of the entries proposed through this node, once applied.
+    waiting: HashMap<u64, Option<Packet>>,
+    /// Index of the snapshot being received, 0 for none.
+    incoming: u64,
+}
+
+impl State {
+    fn is_member(&self, id: u64) -> bool {
+        self.members.iter().any(|(member, _)| *member == id)
+    }
+
+    fn address(&self, id: u64) -> Option<&str> {
+        let member = self.members.iter().find(|(member, _)| *member == id);
+        member.map(|(_, addr)| addr.as_str())
+    }
+
+    /// The encoded membership in effect at `index` and where it was set.
+    fn members_at(&self, index: u64) -> (u64, Vec<u8>) {
+        if index >= self.members_index {
+            return (self.members_index, self.members_data.clone());
+        }
+        self.scan_members(index)
+    }
+
+    /// Like `members_at`, reading the log rather than the latest membership.
+    fn scan_members(&self, index: u64) -> (u64, Vec<u8>) {
+        let mut entries = self.log.entries_since(0).rev();
+        let found =
+            entries.find(|(at, entry)| *at <= index && entry.data.first() == Some(&ENTRY_MEMBERS));
+        match found {
+            Some((at, entry)) => (at, entry.data[1..].to_vec()),
+            None => (
+                self.log.snapshot_index(),
+                self.log.snapshot_config().to_vec(),
+            ),
+        }
+    }
+
+    fn not_leader(&self) -> Packet {
+        Packet::RespNotLeader(self.address(self.leader).unwrap_or_default().to_string())
+    }
+}
+
+/// A member of a raft group replicating writes to the databases of the
+/// server, which needs the RocksDB engine.
+///
+/// Writes to a database go into the log of the leader, and are applied to
+/// the databases of every member once a majority has them. Other members
+/// answer writes with `RespNotLeader` and the address of the leader. Reads
+/// are served from the local databases, which may lag on followers.
+///
+/// Membership changes go one at a time through the log. Every
+/// `snapshot_entries` applied entries, the databases are checkpointed under
+/// `<root>/.raft` and the log before them is dropped; members too far behind
+/// are sent the checkpoints instead, which leave the databases detached on
+/// the sender alone.
+pub struct Raft {
+    id: u64,
+    dir: String,
+    mdb: Arc<MultiDB>,
+    snapshot_entries: u64,
+    state: Mutex<State>,
+    changed: Condvar,
+    /// Held while entries or a snapshot go into the databases. Taken before
+    /// `state` when both are needed.
+    applying: Mutex<()>,
+}
+
+impl Raft {
+    /// Start node `id`, keeping its state under `root`. A new group is
+    /// founded with `members`, which is ignored once the node has a log; a
+    /// node started without is added later by the leader.
+    pub fn start(
+        id: u64,
+        members: Members,
+        root: &str,
+        mdb: Arc<MultiDB>,
+        snapshot_entries: u64,
+    ) -> ServerResult<Arc<Self>> {
+        let dir = format!("{}/{}", root, RAFT_DIR);
+        fs::create_dir_all(&dir)?;
+        let mut log = RaftLog::open(&format!("{}/log", dir))?;
+        if log.last_index() == 0 && !members.is_empty() {
+            let entry = RaftEntry {
+                term: 0,
+                data: members_entry(&members),
+            };
+            log.append(vec![entry])?;
+        }
+        let applied = log.applied().max(log.snapshot_index());
+        let state = State {
+            log,
+            role: Role::Follower,
+            leader: 0,
+            commit: applied,
+            applied,
+            members: vec![],
+            members_index: 0,
+            members_data: vec![],
+            election_at: election_deadline(),
+            heard_at: None,
+            peers: HashMap::new(),
+            threads: HashSet::new(),
+            votes: HashSet::new(),
+            waiting: HashMap::new(),
+            incoming: 0,
+        };
+        let raft = Arc::new(Self {
+            id,
+            dir,
+            mdb,
+            snapshot_entries: snapshot_entries.max(1),
+            state: Mutex::new(state),
+            changed: Condvar::new(),
+            applying: Mutex::new(()),
+        });
+        raft.refresh_members(&mut raft.state())?;
+
+        let ticker = raft.clone();
+        thread::spawn(move || ticker.tick());
+        let applier = raft.clone();
+        thread::spawn(move || applier.apply());
+        Ok(raft)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Figures for `CmdServerStats`.
+    pub fn stats(&self) -> Vec<(String, String)> {
+        let state = self.state();
+        let members: Vec<String> = state
+            .members
+            .iter()
+            .map(|(id, addr)| format!("{}={}", id, addr))
+            .collect();
+        let role = match state.role {
+            Role::Follower => "follower",
+            Role::Candidate => "candidate",
+            Role::Leader => "leader",
+        };
+        let figures = [
+            ("raft_id", self.id.to_string()),
+            ("raft_role", role.to_string()),
+            ("raft_term", state.log.term().to_string()),
+            (
+                "raft_leader",
+                state.address(state.leader).unwrap_or_default().to_string(),
+            ),
+            ("raft_last_index", state.log.last_index().to_string()),
+            ("raft_commit_index", state.commit.to_string()),
+            ("raft_applied_index", state.applied.to_string()),
+            (
+                "raft_snapshot_index",
+                state.log.snapshot_index().to_string(),
+            ),
+            ("raft_members", members.join(",")),
+        ];
+        let figures = figures.into_iter();
+        figures
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    /// Commit a write command to the database `name` through the log, and
+    /// answer with the result of applying it.
+    pub fn propose(self: &Arc<Self>, name: &str, cmd: &Packet) -> ServerResult<Packet> {
+        let mut data = vec![ENTRY_WRITE];
+        data.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        data.extend_from_slice(name.as_bytes());
+        let mut body = vec![];
+        PacketReaderWriter::new(Cursor::new(&mut body)).write_packet(cmd)?;
+        data.extend(body);
+        let state = self.state();
+        self.commit_entry(state, data)
+    }
+
+    pub fn add_node(self: &Arc<Self>, id: u64, addr: String) -> ServerResult<Packet> {
+        self.change_members(|members| {
+            if id == 0 {
+                return Err("node id 0 is reserved".to_string());
+            }
+            if members.iter().any(|(member, _)| *member == id) {
+                return Err(format!("node {} is already a member", id));
+            }
+            members.push((id, addr));
+            members.sort();
+            Ok(())
+        })
+    }
+
+    pub fn remove_node(self: &Arc<Self>, id: u64) -> ServerResult<Packet> {
+        self.change_members(|members| {
+            let before = members.len();
+            members.retain(|(member, _)| *member != id);
+            match members.len() {
+                len if len == before => Err(format!("node {} is not a member", id)),
+                0 => Err("cannot remove the last member".to_string()),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    /// Answer a candidate asking for the vote of this node.
+    pub fn vote(
+        self: &Arc<Self>,
+        term: u64,
+        candidate: u64,
+        last_index: u64,
+        last_term: u64,
+    ) -> ServerResult<Packet> {
+        let mut state = self.state();
+        let current = state.log.term();
+        // while a leader is heard from, nodes that lost touch with it (or
+        // were removed) cannot depose it
+        let leader_alive = state.role == Role::Leader
+            || state
+                .heard_at
+                .is_some_and(|at| at.elapsed() < ELECTION_TIMEOUT);
+        if term < current || (term > current && leader_alive) {
+            return Ok(Packet::RespRaft(current, false, 0));
+        }
+        self.follow_term(&mut state, term)?;
+
+        let up_to_date = (last_term, last_index) >= (state.log.last_term(), state.log.last_index());
+        let vote = state.log.vote();
+        if up_to_date && (vote == 0 || vote == candidate) {
+            if vote == 0 {
+                state.log.set_term(term, candidate)?;
+            }
+            state.election_at = election_deadline();
+            return Ok(Packet::RespRaft(term, true, 0));
+        }
+        Ok(Packet::RespRaft(term, false, 0))
+    }
+
+    /// Take entries from the leader.
+    pub fn append(
+        self: &Arc<Self>,
+        term: u64,
+        leader: u64,
+        mut prev_index: u64,
+        mut prev_term: u64,
+        commit: u64,
+        mut entries: Vec<RaftEntry>,
+    ) -> ServerResult<Packet> {
+        let mut state = self.state();
+        if !self.heard_leader(&mut state, term, leader)? {
+            return Ok(Packet::RespRaft(state.log.term(), false, 0));
+        }
+
+        // entries up to the snapshot are already in the databases
+        let snapshot_index = state.log.snapshot_index();
+        if prev_index < snapshot_index {
+            let skip = (snapshot_index - prev_index) as usize;
+            if skip >= entries.len() {
+                return Ok(Packet::RespRaft(term, true, snapshot_index));
+            }
+            entries.drain(..skip);
+            prev_index = snapshot_index;
+            prev_term = state.log.snapshot_term();
+        }
+        if prev_index > state.log.last_index() {
+            let next = state.log.last_index() + 1;
+            return Ok(Packet::RespRaft(term, false, next));
+        }
+        if let Some(conflict) = state.log.term_at(prev_index).filter(|t| *t != prev_term) {
+            // skip back over the whole conflicting term at once
+            let mut next = prev_index;
+            while next > snapshot_index + 1 && state.log.term_at(next - 1) == Some(conflict) {
+                next -= 1;
+            }
+            return Ok(Packet::RespRaft(term, false, next));
+        }
+
+        let last_new = prev_index + entries.len() as u64;
+        let mut present = 0;
+        let mut truncated = false;
+        for (pos, entry) in entries.iter().enumerate() {
+            let index = prev_index + 1 + pos as u64;
+            match state.log.term_at(index) {
+                Some(t) if t == entry.term => present = pos + 1,
+                Some(_) => {
+                    state.log.truncate(index)?;
+                    truncated = true;
+                    break;
+                }
+                None => break,
+            }
+        }
+        let new = entries.split_off(present);
+        let new_members = new.iter().any(|e| e.data.first() == Some(&ENTRY_MEMBERS));
+        state.log.append(new)?;
+        if truncated || new_members {
+            self.refresh_members(&mut state)?;
+        }
+
+        let commit = commit.min(last_new);
+        if commit > state.commit {
+            state.commit = commit;
+            self.changed.notify_all();
+        }
+        Ok(Packet::RespRaft(term, true, last_new))
+    }
+
+    /// Take a piece of a snapshot from the leader, and install the whole
+    /// snapshot with the last piece.
+    pub fn install_snapshot(self: &Arc<Self>, chunk: RaftSnapshotChunk) -> ServerResult<Packet> {
+        let _applying = if chunk.done {
+            Some(lock(&self.applying))
+        } else {
+            None
+        };
+        let mut state = self.state();
+        let term = chunk.term;
+        if !self.heard_leader(&mut state, term, chunk.leader)? {
+            return Ok(Packet::RespRaft(state.log.term(), false, 0));
+        }
+        if chunk.index <= state.commit {
+            return Ok(Packet::RespRaft(term, true, chunk.index));
+        }
+
+        let incoming = format!("{}/incoming-{}", self.dir, chunk.index);
+        if !chunk.done {
+            if state.incoming != chunk.index {
+                if chunk.offset != 0 {
+                    return Ok(Packet::RespRaft(term, false, 0));
+                }
+                self.discard_incoming(&mut state);
+                fs::create_dir_all(&incoming)?;
+                state.incoming = chunk.index;
+            }
+            let file = String::from_utf8(chunk.file)?;
+            let path = Path::new(&file);
+            if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+                return Err(ServerError::InvalidData);
+            }
+            let path = Path::new(&incoming).join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(false)
+                .open(path)?;
+            file.seek(SeekFrom::Start(chunk.offset))?;
+            file.write_all(&chunk.data)?;
+            return Ok(Packet::RespRaft(term, true, chunk.index));
+        }
+        if state.incoming != chunk.index {
+            return Ok(Packet::RespRaft(term, false, 0));
+        }
+
+        let installed = self.install_dbs(&incoming);
+        self.discard_incoming(&mut state);
+        installed?;
+        let keep_later = state.log.term_at(chunk.index) == Some(chunk.last_term);
+        state
+            .log
+            .compact(chunk.index, chunk.last_term, &chunk.config, keep_later)?;
+        state.log.set_applied(chunk.index)?;
+        state.applied = chunk.index;
+        state.commit = chunk.index;
+        self.refresh_members(&mut state)?;
+        eprintln!("    > Raft: installed snapshot at index {}", chunk.index);
+        Ok(Packet::RespRaft(term, true, chunk.index))
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+
+    fn wait<'a>(&self, state: MutexGuard<'a, State>, timeout: Duration) -> MutexGuard<'a, State> {
+        match self.changed.wait_timeout(state, timeout) {
+            Ok((state, _)) => state,
+            Err(e) => e.into_inner().0,
+        }
+    }
+
+    /// Check the term of a request from a leader and follow it, returning
+    /// false when the request is stale.
+    fn heard_leader(&self, state: &mut State, term: u64, leader: u64) -> ServerResult<bool> {
+        if term < state.log.term() {
+            return Ok(false);
+        }
+        self.follow_term(state, term)?;
+        state.role = Role::Follower;
+        state.leader = leader;
+        state.heard_at = Some(Instant::now());
+        state.election_at = election_deadline();
+        Ok(true)
+    }
+
+    /// Step down on learning of a later term.
+    fn follow_term(&self, state: &mut State, term: u64) -> ServerResult<()> {
+        if term > state.log.term() {
+            state.log.set_term(term, 0)?;
+            if state.role != Role::Follower {
+                state.role = Role::Follower;
+                state.election_at = election_deadline();
+            }
+            state.leader = 0;
+            self.changed.notify_all();
+        }
+        Ok(())
+    }
+
+    /// Pick up the latest membership in the log, and start replicating to
+    /// new members.
+    fn refresh_members(self: &Arc<Self>, state: &mut State) -> ServerResult<()> {
+        let (index, members) = state.scan_members(u64::MAX);
+        state.members = decode_members(&members)?;
+        state.members_index = index;
+        state.members_data = members;
+
+        let last = state.log.last_index();
+        let ids: Vec<u64> = state
+            .members
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| *id != self.id)
+            .collect();
+        state.peers.retain(|id, _| ids.contains(id));
+        for id in ids {
+            state.peers.entry(id).or_insert(Peer {
+                next: last + 1,
+                matched: 0,
+                vote_asked: true,
+                sent_at: None,
+            });
+            if state.threads.insert(id) {
+                let raft = self.clone();
+                thread::spawn(move || raft.replicate(id));
+            }
+        }
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Start elections when no leader is heard from.
+    fn tick(self: Arc<Self>) {
+        loop {
+            thread::sleep(TICK_INTERVAL);
+            let mut state = self.state();
+            if state.role == Role::Leader
+                || Instant::now() < state.election_at
+                || !state.is_member(self.id)
+            {
+                continue;
+            }
+            if let Err(e) = self.campaign(&mut state) {
+                eprintln!("    > Raft: election failed: {}", e);
+                state.election_at = election_deadline();
+            }
+        }
+    }
+
+    fn campaign(self: &Arc<Self>, state: &mut State) -> ServerResult<()> {
+        let term = state.log.term() + 1;
+        state.log.set_term(term, self.id)?;
+        state.role = Role::Candidate;
+        state.leader = 0;
+        state.election_at = election_deadline();
+        state.votes = HashSet::from([self.id]);
+        for peer in state.peers.values_mut() {
+            peer.vote_asked = false;
+        }
+        eprintln!("    > Raft: node {} campaigning in term {}", self.id, term);
+        self.count_votes(state)?;
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    fn count_votes(&self, state: &mut State) -> ServerResult<()> {
+        let granted = state
+            .members
+            .iter()
+            .filter(|(id, _)| state.votes.contains(id))
+            .count();
+        if state.role == Role::Candidate && granted > state.members.len() / 2 {
+            self.lead(state)?;
+        }
+        Ok(())
+    }
+
+    fn lead(&self, state: &mut State) -> ServerResult<()> {
+        state.role = Role::Leader;
+        state.leader = self.id;
+        let next = state.log.last_index() + 1;
+        for peer in state.peers.values_mut() {
+            peer.next = next;
+            peer.matched = 0;
+            peer.sent_at = None;
+        }
+        // entries of earlier terms only commit along with one of this term
+        let term = state.log.term();
+        let noop = RaftEntry {
+            term,
+            data: vec![ENTRY_NOOP],
+        };
+        state.log.append(vec![noop])?;
+        eprintln!("    > Raft: node {} leading in term {}", self.id, term);
+        self.advance_commit(state);
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Commit up to the highest entry of this term a majority has.
+    fn advance_commit(&self, state: &mut State) {
+        let mut matched: Vec<u64> = state
+            .members
+            .iter()
+            .map(|(id, _)| match state.peers.get(id) {
+                Some(peer) => peer.matched,
+                None => state.log.last_index(),
+            })
+            .collect();
+        matched.sort_unstable_by(|a, b| b.cmp(a));
+        if let Some(&index) = matched.get(matched.len() / 2) {
+            if index > state.commit && state.log.term_at(index) == Some(state.log.term()) {
+                state.commit = index;
+                self.changed.notify_all();
+            }
+        }
+        // a leader removed from the group leads until that is committed
+        if !state.is_member(self.id) && state.commit >= state.members_index {
+            state.role = Role::Follower;
+            state.leader = 0;
+            self.changed.notify_all();
+        }
+    }
+
+    /// Append an entry as the leader and wait for it to be applied.
+    fn commit_entry(
+        self: &Arc<Self>,
+        mut state: MutexGuard<'_, State>,
+        data: Vec<u8>,
+    ) -> ServerResult<Packet> {
+        if state.role != Role::Leader {
+            return Ok(state.not_leader());
+        }
+        let term = state.log.term();
+        let members = data.first() == Some(&ENTRY_MEMBERS);
+        state.log.append(vec![RaftEntry { term, data }])?;
+        let index = state.log.last_index();
+        state.waiting.insert(index, None);
+        if members {
+            self.refresh_members(&mut state)?;
+        }
+        self.advance_commit(&mut state);
+        self.changed.notify_all();
+
+        let deadline = Instant::now() + COMMIT_TIMEOUT;
+        loop {
+            if let Some(Some(_)) = state.waiting.get(&index) {
+                return Ok(state.waiting.remove(&index).flatten().unwrap_or_else(|| {
+                    Packet::RespError("lost the result of the write".to_string())
+                }));
+            }
+            if state.role != Role::Leader || state.log.term() != term {
+                state.waiting.remove(&index);
+                return Ok(state.not_leader());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                state.waiting.remove(&index);
+                let msg = "timed out waiting for a majority of the raft group";
+                return Ok(Packet::RespError(msg.to_string()));
+            }
+            state = self.wait(state, deadline - now);
+        }
+    }
+
+    fn change_members<F>(self: &Arc<Self>, change: F) -> ServerResult<Packet>
+    where
+        F: FnOnce(&mut Members) -> Result<(), String>,
+    {
+        let state = self.state();
+        if state.role != Role::Leader {
+            return Ok(state.not_leader());
+        }
+        if state.members_index > state.commit {
+            let msg = "a membership change is in progress";
+            return Ok(Packet::RespError(msg.to_string()));
+        }
+        let mut members = state.members.clone();
+        if let Err(msg) = change(&mut members) {
+            return Ok(Packet::RespError(msg));
+        }
+        self.commit_entry(state, members_entry(&members))
+    }
+
+    /// Send votes requests or entries to `peer` until it leaves the group.
+    fn replicate(self: Arc<Self>, peer: u64) {
+        let mut conn = None;
+        let mut failing = false;
+        loop {
+            let (request, addr, term) = {
+                let mut state = self.state();
+                loop {
+                    if !state.peers.contains_key(&peer) {
+                        state.threads.remove(&peer);
+                        return;
+                    }
+                    if let Some(request) = self.next_request(&mut state, peer) {
+                        let addr = state.address(peer).unwrap_or_default().to_string();
+                        break (request, addr, state.log.term());
+                    }
+                    state = self.wait(state, HEARTBEAT_INTERVAL);
+                }
+            };
+            let sent = match request {
+                Request::Rpc(packet) => call(&mut conn, &addr, &packet)
+                    .and_then(|resp| self.handle_response(peer, term, &packet, resp)),
+                Request::Snapshot(chunk) => self.send_snapshot(&mut conn, &addr, peer, chunk),
+            };
+            match sent {
+                Ok(()) => failing = false,
+                Err(e) => {
+                    if !failing {
+                        eprintln!("    > Raft: lost node {} at {}: {}", peer, addr, e);
+                    }
+                    failing = true;
+                    conn = None;
+                    thread::sleep(HEARTBEAT_INTERVAL);
+                }
+            }
+        }
+    }
+
+    fn next_request(&self, state: &mut State, id: u64) -> Option<Request> {
+        let term = state.log.term();
+        let (last_index, last_term) = (state.log.last_index(), state.log.last_term());
+        let snapshot_index = state.log.snapshot_index();
+        let peer = state.peers.get_mut(&id)?;
+        match state.role {
+            Role::Candidate if !peer.vote_asked => {
+                peer.vote_asked = true;
+                let vote = Packet::CmdRaftVote(term, self.id, last_index, last_term);
+                Some(Request::Rpc(vote))
+            }
+            Role::Leader if peer.next <= snapshot_index => {
+                let (_, config) = state.members_at(snapshot_index);
+                Some(Request::Snapshot(RaftSnapshotChunk {
+                    term,
+                    leader: self.id,
+                    index: snapshot_index,
+                    last_term: state.log.snapshot_term(),
+                    config,
+                    file: vec![],
+                    offset: 0,
+                    data: vec![],
+                    done: false,
+                }))
+            }
+            Role::Leader => {
+                let heartbeat = peer
+                    .sent_at
+                    .is_none_or(|at| at.elapsed() >= HEARTBEAT_INTERVAL);
+                if peer.next > last_index && !heartbeat {
+                    return None;
+                }
+                peer.sent_at = Some(Instant::now());
+                let prev_index = peer.next - 1;
+                let prev_term = state.log.term_at(prev_index).unwrap_or_default();
+                let entries = state.log.entries_from(peer.next, MAX_ENTRIES);
+                Some(Request::Rpc(Packet::CmdRaftAppend(
+                    term,
+                    self.id,
+                    prev_index,
+                    prev_term,
+                    state.commit,
+                    entries,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_response(
+        &self,
+        id: u64,
+        term: u64,
+        request: &Packet,
+        resp: Packet,
+    ) -> ServerResult<()> {
+        let (resp_term, granted, index) = match resp {
+            Packet::RespRaft(resp_term, granted, index) => (resp_term, granted, index),
+            Packet::RespError(msg) => {
+                eprintln!("    > Raft: node {} refused: {}", id, msg);
+                return Ok(());
+            }
+            _ => return Err(ServerError::InvalidData),
+        };
+        let mut state = self.state();
+        if resp_term > state.log.term() {
+            return self.follow_term(&mut state, resp_term);
+        }
+        if state.log.term() != term {
+            return Ok(());
+        }
+        match (request, state.role) {
+            (Packet::CmdRaftVote(..), Role::Candidate) if granted => {
+                state.votes.insert(id);
+                self.count_votes(&mut state)?;
+            }
+            (Packet::CmdRaftAppend(..), Role::Leader) => {
+                let Some(peer) = state.peers.get_mut(&id) else {
+                    return Ok(());
+                };
+                if granted {
+                    peer.matched = peer.matched.max(index);
+                    peer.next = index + 1;
+                    self.advance_commit(&mut state);
+                } else {
+                    peer.next = index.min(peer.next - 1).max(1);
+                }
+                self.changed.notify_all();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Send the files of the latest snapshot, described by `chunk`, to a
+    /// peer too far behind for the log.
+    fn send_snapshot(
+        &self,
+        conn: &mut Option<PacketReaderWriter<TcpStream>>,
+        addr: &str,
+        id: u64,
+        mut chunk: RaftSnapshotChunk,
+    ) -> ServerResult<()> {
+        let dir = format!("{}/snapshot-{}", self.dir, chunk.index);
+        eprintln!(
+            "    > Raft: sending snapshot {} to node {}",
+            chunk.index, id
+        );
+        let mut files = vec![];
+        list_files(Path::new(&dir), "", &mut files)?;
+        for file in files {
+            let mut reader = fs::File::open(Path::new(&dir).join(&file))?;
+            let mut offset = 0;
+            loop {
+                let mut data = vec![0; SNAPSHOT_CHUNK_SIZE];
+                let len = reader.read(&mut data)?;
+                data.truncate(len);
+                if len == 0 && offset > 0 {
+                    break;
+                }
+                chunk.file = file.clone().into_bytes();
+                chunk.offset = offset;
+                chunk.data = data;
+                if !self.send_chunk(conn, addr, id, &chunk)? {
+                    return Ok(());
+                }
+                if len == 0 {
+                    break;
+                }
+                offset += len as u64;
+            }
+        }
+        chunk.file = vec![];
+        chunk.offset = 0;
+        chunk.data = vec![];
+        chunk.done = true;
+        if self.send_chunk(conn, addr, id, &chunk)? {
+            let mut state = self.state();
+            if let Some(peer) = state.peers.get_mut(&id) {
+                peer.matched = peer.matched.max(chunk.index);
+                peer.next = peer.next.max(chunk.index + 1);
+            }
+            self.advance_commit(&mut state);
+        }
+        Ok(())
+    }
+
+    /// Send one piece of a snapshot, returning whether it was taken.
+    fn send_chunk(
+        &self,
+        conn: &mut Option<PacketReaderWriter<TcpStream>>,
+        addr: &str,
+        id: u64,
+        chunk: &RaftSnapshotChunk,
+    ) -> ServerResult<bool> {
+        let resp = call(conn, addr, &Packet::CmdRaftSnapshot(chunk.clone()))?;
+        match resp {
+            Packet::RespRaft(_, true, _) => Ok(true),
+            Packet::RespRaft(term, false, _) => {
+                let mut state = self.state();
+                self.follow_term(&mut state, term)?;
+                Ok(false)
+            }
+            Packet::RespError(msg) => {
+                eprintln!("    > Raft: node {} refused the snapshot: {}", id, msg);
+                Ok(false)
+            }
+            _ => Err(ServerError::InvalidData),
+        }
+    }
+
+    fn discard_incoming(&self, state: &mut State) {
+        if state.incoming != 0 {
+            let dir = format!("{}/incoming-{}", self.dir, state.incoming);
+            if let Err(e) = fs::remove_dir_all(&dir) {
+                eprintln!("    > Raft: failed to remove {}: {}", dir, e);
+            }
+            state.incoming = 0;
+        }
+    }
+
+    /// Replace the databases with the checkpoints under `dir`. Those
+    /// detached where the snapshot was taken are left alone, and the other
+    /// attached ones are dropped.
+    fn install_dbs(&self, dir: &str) -> ServerResult<()> {
+        let mut names = HashSet::new();
+        let detached = Path::new(dir).join(DETACHED_FILE);
+        if detached.exists() {
+            let text = fs::read_to_string(&detached)?;
+            names.extend(text.lines().map(str::to_string));
+        }
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name == DETACHED_FILE {
+                continue;
+            }
+            let path = entry.path().to_string_lossy().to_string();
+            self.mdb.install(&name, &path, DROP_WAIT)?;
+            names.insert(name);
+        }
+        for entry in self.mdb.catalog() {
+            if entry.state == DbState::Attached && !names.contains(&entry.name) {
+                self.mdb.drop_db(&entry.name, DROP_WAIT)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply committed entries to the databases.
+    fn apply(self: Arc<Self>) {
+        loop {
+            {
+                let mut state = self.state();
+                while state.applied >= state.commit {
+                    state = self.wait(state, ELECTION_TIMEOUT);
+                }
+            }
+            let _applying = lock(&self.applying);
+            let (first, entries) = {
+                let state = self.state();
+                let count = (state.commit - state.applied) as usize;
+                let first = state.applied + 1;
+                (first, state.log.entries_from(first, count.min(MAX_ENTRIES)))
+            };
+            let mut results = vec![];
+            let mut touched = Touched::new();
+            for (pos, entry) in entries.iter().enumerate() {
+                let result = self.apply_entry(&entry.data, &mut touched);
+                results.push((first + pos as u64, result));
+            }
+            // the applied index is saved once the data is durable, or a
+            // crash could lose writes it claims to have applied
+            let synced = touched.into_values().try_for_each(|(sdb, skipped_wal)| {
+                if skipped_wal {
+                    sdb.flush()
+                } else {
+                    sdb.sync_wal()
+                }
+            });
+
+            let mut state = self.state();
+            let applied = first + entries.len() as u64 - 1;
+            if applied >= first {
+                let saved = match synced {
+                    Ok(()) => state.log.set_applied(applied),
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = saved {
+                    eprintln!("    > Raft: failed to save the applied index: {}", e);
+                }
+                state.applied = applied;
+            }
+            for (index, result) in results {
+                if let Some(slot) = state.waiting.get_mut(&index) {
+                    *slot = Some(result);
+                }
+            }
+            self.changed.notify_all();
+
+            let due =
+                state.applied.saturating_sub(state.log.snapshot_index()) >= self.snapshot_entries;
+            drop(state);
+            if due {
+                if let Err(e) = self.snapshot() {
+                    eprintln!("    > Raft: snapshot failed: {}", e);
+                }
+            }
+        }
+    }
+
+    fn apply_entry(&self, data: &[u8], touched: &mut Touched) -> Packet {
+        if data.first() != Some(&ENTRY_WRITE) {
+            return Packet::RespOk("Ok.".to_string());
+        }
+        let (name, cmd) = match decode_write(&data[1..]) {
+            Ok(write) => write,
+            Err(e) => return Packet::RespError(e.to_string()),
+        };
+        let sdb = match self.mdb.attach(&name) {
+            Ok(sdb) => sdb,
+            Err(e) => return Packet::RespError(e.to_string()),
+        };
+        let skips_wal = sdb.options().disable_wal.unwrap_or(false)
+            || matches!(cmd, Packet::CmdWriteWith(flags, _) | Packet::CmdDeleteWith(flags, _)
+                if durability(flags).disable_wal);
+        let rs = apply_write(sdb.as_ref(), cmd);
+        touched.entry(name).or_insert((sdb, false)).1 |= skips_wal;
+        rs.unwrap_or_else(|e| Packet::RespError(e.to_string()))
+    }
+
+    /// Checkpoint the databases as of the applied index and drop the log up
+    /// to it. Runs with `applying` held, so nothing is written meanwhile.
+    fn snapshot(&self) -> ServerResult<()> {
+        let (index, term, config) = {
+            let state = self.state();
+            let index = state.applied;
+            let (_, config) = state.members_at(index);
+            (index, state.log.term_at(index).unwrap_or_default(), config)
+        };
+        let target = format!("{}/snapshot-{}", self.dir, index);
+        let tmp = format!("{}.tmp", target);
+        if Path::new(&tmp).exists() {
+            fs::remove_dir_all(&tmp)?;
+        }
+        fs::create_dir_all(&tmp)?;
+        let mut detached = String::new();
+        for entry in self.mdb.catalog() {
+            if entry.state == DbState::Attached {
+                let sdb = self.mdb.attach_existing(&entry.name)?;
+                sdb.checkpoint(&format!("{}/{}", tmp, entry.name))?;
+            } else {
+                detached.push_str(&entry.name);
+                detached.push('\n');
+            }
+        }
+        fs::write(Path::new(&tmp).join(DETACHED_FILE), detached)?;
+        fs::rename(&tmp, &target)?;
+        self.state().log.compact(index, term, &config, true)?;
+
+        let current = format!("snapshot-{}", index);
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if name.starts_with("snapshot-") && name != current {
+                fs::remove_dir_all(format!("{}/{}", self.dir, name))?;
+            }
+        }
+        eprintln!("    > Raft: snapshot at index {}", index);
+        Ok(())
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A random point in the coming election timeout.
+fn election_deadline() -> Instant {
+    let random = RandomState::new().build_hasher().finish();
+    let jitter = random % ELECTION_TIMEOUT.as_millis() as u64;
+    Instant::now() + ELECTION_TIMEOUT + Duration::from_millis(jitter)
+}
+
+/// Send a request to a peer over `conn`, connecting first if needed.
+fn call(
+    conn: &mut Option<PacketReaderWriter<TcpStream>>,
+    addr: &str,
+    request: &Packet,
+) -> ServerResult<Packet> {
+    let rw = match conn {
+        Some(rw) => rw,
+        None => conn.insert(PacketReaderWriter::new(connect(addr)?)),
+    };
+    let resp = rw.write_packet(request).and_then(|()| rw.read_packet());
+    if resp.is_err() {
+        *conn = None;
+    }
+    Ok(resp?)
+}
+
+fn connect(addr: &str) -> ServerResult<TcpStream> {
+    let sock_addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or(ServerError::InvalidData)?;
+    let stream = TcpStream::connect_timeout(&sock_addr, RPC_TIMEOUT)?;
+    stream.set_read_timeout(Some(RPC_TIMEOUT))?;
+    stream.set_write_timeout(Some(RPC_TIMEOUT))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+/// The paths of the files under `dir`, relative to it.
+fn list_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> ServerResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{}/", name), files)?;
+        } else {
+            files.push(name);
+        }
+    }
+    Ok(())
+}
+
+fn members_entry(members: &Members) -> Vec<u8> {
+    let mut data = vec![ENTRY_MEMBERS];
+    data.extend_from_slice(&(members.len() as u16).to_be_bytes());
+    for (id, addr) in members {
+        data.extend_from_slice(&id.to_be_bytes());
+        data.extend_from_slice(&(addr.len() as u16).to_be_bytes());
+        data.extend_from_slice(addr.as_bytes());
+    }
+    data
+}
+
+fn decode_members(data: &[u8]) -> ServerResult<Members> {
+    let mut members = vec![];
+    if data.is_empty() {
+        return Ok(members);
+    }
+    let mut reader = Cursor::new(data);
+    for _ in 0..read_u16(&mut reader)? {
+        let mut id = [0; 8];
+        reader.read_exact(&mut id)?;
+        let mut addr = vec![0; read_u16(&mut reader)? as usize];
+        reader.read_exact(&mut addr)?;
+        members.push((u64::from_be_bytes(id), String::from_utf8(addr)?));
+    }
+    Ok(members)
+}
+
+fn decode_write(data: &[u8]) -> ServerResult<(String, Packet)> {
+    let mut reader = Cursor::new(data);
+    let mut name = vec![0; read_u16(&mut reader)? as usize];
+    reader.read_exact(&mut name)?;
+    let rest = data[reader.position() as usize..].to_vec();
+    let cmd = PacketReaderWriter::new(Cursor::new(rest)).read_packet()?;
+    Ok((String::from_utf8(name)?, cmd))
+}
+
+fn read_u16(reader: &mut Cursor<&[u8]>) -> ServerResult<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_be_bytes(bytes))
+}
+
+fn apply_write(sdb: &dyn KvEngine, cmd: Packet) -> StorageResult<Packet> {
+    let puts = |pairs: Vec<Vec<u8>>| -> Vec<BatchOp> {
+        let pairs = pairs.chunks_exact(2);
+        pairs
+            .map(|pair| BatchOp::Put(pair[0].clone(), pair[1].clone()))
+            .collect()
+    };
+    let deletes =
+        |keys: Vec<Vec<u8>>| -> Vec<BatchOp> { keys.into_iter().map(BatchOp::Delete).collect() };
+    match cmd {
+        Packet::CmdWrite(pairs) => sdb.write_batch(puts(pairs))?,
+        Packet::CmdWriteWith(flags, pairs) => {
+            sdb.write_batch_with(puts(pairs), durability(flags))?
+        }
+        Packet::CmdDelete(keys) => sdb.write_batch(deletes(keys))?,
+        Packet::CmdDeleteWith(flags, keys) => {
+            sdb.write_batch_with(deletes(keys), durability(flags))?
+        }
+        Packet::CmdDeleteRange(start, end) => {
+            return Ok(Packet::RespNumber(sdb.delete_range(&start, &end)?))
+        }
+        Packet::CmdDeletePrefix(prefix) => {
+            return Ok(Packet::RespNumber(sdb.delete_prefix(&prefix)?))
+        }
+        _ => return Ok(Packet::RespError("unknown command".to_string())),
+    }
+    Ok(Packet::RespOk("Ok.".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Node 1 of a group whose other members are never reached.
+    fn start(root: &Path, members: &[u64]) -> (Arc<Raft>, Arc<MultiDB>) {
+        let root = root.to_string_lossy().to_string();
+        let mdb = Arc::new(MultiDB::new(&root));
+        let members = members
+            .iter()
+            .map(|id| (*id, format!("127.0.0.1:{}", id)))
+            .collect();
+        let raft = Raft::start(1, members, &root, mdb.clone(), 100).unwrap();
+        (raft, mdb)
+    }
+
+    #[test]
+    fn test_one_vote_per_term() {
+        let dir = tempfile::tempdir().unwrap();
+        let (raft, _) = start(dir.path(), &[1, 2, 3]);
+        assert_eq!(raft.vote(1, 2, 1, 0).unwrap(), Packet::RespRaft(1, true, 0));
+        // two candidates splitting the vote of the term
+        assert_eq!(
+            raft.vote(1, 3, 1, 0).unwrap(),
+            Packet::RespRaft(1, false, 0)
+        );
+        assert_eq!(raft.vote(1, 2, 1, 0).unwrap(), Packet::RespRaft(1, true, 0));
+        // nor for a candidate whose log is behind
+        assert_eq!(
+            raft.vote(2, 3, 0, 0).unwrap(),
+            Packet::RespRaft(2, false, 0)
+        );
+        assert_eq!(
+            raft.vote(1, 3, 1, 0).unwrap(),
+            Packet::RespRaft(2, false, 0)
+        );
+    }
+
+    #[test]
+    fn test_split_vote_elects_nobody() {
+        let dir = tempfile::tempdir().unwrap();
+        let (raft, _) = start(dir.path(), &[1, 2, 3, 4]);
+        let mut state = raft.state();
+        raft.campaign(&mut state).unwrap();
+        let term = state.log.term();
+        state.votes.insert(2);
+        raft.count_votes(&mut state).unwrap();
+        // half of the group is no majority
+        assert_eq!(state.role, Role::Candidate);
+
+        // the next campaign starts over in a later term
+        raft.campaign(&mut state).unwrap();
+        assert_eq!(state.log.term(), term + 1);
+        assert_eq!(state.votes, HashSet::from([1]));
+        state.votes.extend([2, 3]);
+        raft.count_votes(&mut state).unwrap();
+        assert_eq!(state.role, Role::Leader);
+        assert_eq!(state.leader, 1);
+    }
+
+    #[test]
+    fn test_members_at() {
+        let dir = tempfile::tempdir().unwrap();
+        let (raft, _) = start(dir.path(), &[1, 2]);
+        let mut state = raft.state();
+        let founders = members_entry(&vec![
+            (1, "127.0.0.1:1".to_string()),
+            (2, "127.0.0.1:2".to_string()),
+        ]);
+        assert_eq!(state.members_at(5), (1, founders[1..].to_vec()));
+
+        let members = vec![(1, "127.0.0.1:1".to_string())];
+        let term = state.log.term();
+        let entries = vec![
+            RaftEntry {
+                term,
+                data: vec![ENTRY_NOOP],
+            },
+            RaftEntry {
+                term,
+                data: members_entry(&members),
+            },
+        ];
+        state.log.append(entries).unwrap();
+        raft.refresh_members(&mut state).unwrap();
+        assert_eq!(state.members, members);
+        assert_eq!(
+            state.members_at(3),
+            (3, members_entry(&members)[1..].to_vec())
+        );
+        assert_eq!(state.members_at(2), (1, founders[1..].to_vec()));
+    }
+
+    #[test]
+    fn test_install_dbs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (raft, mdb) = start(&dir.path().join("node"), &[1]);
+        mdb.attach("replaced").unwrap().set(b"key", b"old").unwrap();
+        mdb.attach("gone").unwrap().set(b"key", b"old").unwrap();
+        mdb.attach("detached").unwrap().set(b"key", b"old").unwrap();
+        mdb.detach("detached").unwrap();
+        mdb.attach("detached_there")
+            .unwrap()
+            .set(b"key", b"old")
+            .unwrap();
+
+        // the snapshot of a node where `detached_there` is detached
+        let leader = MultiDB::new(&dir.path().join("leader").to_string_lossy());
+        leader
+            .attach("replaced")
+            .unwrap()
+            .set(b"key", b"new")
+            .unwrap();
+        leader.attach("added").unwrap().set(b"key", b"new").unwrap();
+        let snapshot = dir.path().join("snapshot");
+        fs::create_dir_all(&snapshot).unwrap();
+        for name in ["replaced", "added"] {
+            let target = snapshot.join(name).to_string_lossy().to_string();
+            leader.attach(name).unwrap().checkpoint(&target).unwrap();
+        }
+        fs::write(snapshot.join(DETACHED_FILE), "detached_there\n").unwrap();
+
+        raft.install_dbs(&snapshot.to_string_lossy()).unwrap();
+        let value = |name: &str| mdb.attach_existing(name).unwrap().get(b"key").unwrap();
+        assert_eq!(value("replaced"), Some(b"new".to_vec()));
+        assert_eq!(value("added"), Some(b"new".to_vec()));
+        assert_eq!(value("detached_there"), Some(b"old".to_vec()));
+        let state = |name: &str| {
+            let catalog = mdb.catalog();
+            catalog
+                .into_iter()
+                .find(|entry| entry.name == name)
+                .map(|entry| entry.state)
+        };
+        assert_eq!(state("gone"), None);
+        assert_eq!(state("detached"), Some(DbState::Detached));
+    }
+}
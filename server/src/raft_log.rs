@@ -0,0 +1,327 @@
+use packet::RaftEntry;
+use storage::{BatchOp, Direction, Durability, IteratorMode, KvEngine, Storage};
+
+use crate::errors::{ServerError, ServerResult};
+
+const KEY_TERM: &[u8] = b"term";
+const KEY_VOTE: &[u8] = b"vote";
+const KEY_APPLIED: &[u8] = b"applied";
+/// Index, term and membership of the last snapshot.
+const KEY_SNAPSHOT: &[u8] = b"snapshot";
+/// Followed by the big-endian index of each entry.
+const LOG_PREFIX: &[u8] = b"log/";
+
+/// The state of a raft node that must survive restarts: its term, its vote
+/// and the log after the last snapshot, kept in a RocksDB instance of its
+/// own and mirrored in memory.
+pub struct RaftLog {
+    db: Storage,
+    term: u64,
+    /// Node voted for in `term`, 0 for none.
+    vote: u64,
+    applied: u64,
+    snapshot_index: u64,
+    snapshot_term: u64,
+    snapshot_config: Vec<u8>,
+    /// The entry at `snapshot_index + 1` first.
+    entries: Vec<RaftEntry>,
+}
+
+impl RaftLog {
+    pub fn open(dir: &str) -> ServerResult<Self> {
+        let db = Storage::new(dir)?;
+        let number = |key: &[u8]| -> ServerResult<u64> {
+            match db.get(key)? {
+                Some(value) => decode_number(&value),
+                None => Ok(0),
+            }
+        };
+        let mut log = Self {
+            term: number(KEY_TERM)?,
+            vote: number(KEY_VOTE)?,
+            applied: number(KEY_APPLIED)?,
+            snapshot_index: 0,
+            snapshot_term: 0,
+            snapshot_config: vec![],
+            entries: vec![],
+            db,
+        };
+        if let Some(value) = log.db.get(KEY_SNAPSHOT)? {
+            if value.len() < 16 {
+                return Err(ServerError::InvalidData);
+            }
+            log.snapshot_index = decode_number(&value[..8])?;
+            log.snapshot_term = decode_number(&value[8..16])?;
+            log.snapshot_config = value[16..].to_vec();
+        }
+        let mode = IteratorMode::From(LOG_PREFIX, Direction::Forward);
+        for item in log.db.iterator(mode)? {
+            let (key, value) = item?;
+            if !key.starts_with(LOG_PREFIX) {
+                break;
+            }
+            if value.len() < 8 {
+                return Err(ServerError::InvalidData);
+            }
+            log.entries.push(RaftEntry {
+                term: decode_number(&value[..8])?,
+                data: value[8..].to_vec(),
+            });
+        }
+        Ok(log)
+    }
+
+    pub fn term(&self) -> u64 {
+        self.term
+    }
+
+    pub fn vote(&self) -> u64 {
+        self.vote
+    }
+
+    /// Persist a new term and the vote cast in it before answering anyone.
+    pub fn set_term(&mut self, term: u64, vote: u64) -> ServerResult<()> {
+        let ops = vec![
+            BatchOp::Put(KEY_TERM.to_vec(), term.to_be_bytes().to_vec()),
+            BatchOp::Put(KEY_VOTE.to_vec(), vote.to_be_bytes().to_vec()),
+        ];
+        self.write(ops)?;
+        self.term = term;
+        self.vote = vote;
+        Ok(())
+    }
+
+    pub fn applied(&self) -> u64 {
+        self.applied
+    }
+
+    /// Remember how far the state machine got, once the data written up to
+    /// `index` is durable.
+    pub fn set_applied(&mut self, index: u64) -> ServerResult<()> {
+        self.write(vec![BatchOp::Put(
+            KEY_APPLIED.to_vec(),
+            index.to_be_bytes().to_vec(),
+        )])?;
+        self.applied = index;
+        Ok(())
+    }
+
+    pub fn snapshot_index(&self) -> u64 {
+        self.snapshot_index
+    }
+
+    pub fn snapshot_term(&self) -> u64 {
+        self.snapshot_term
+    }
+
+    pub fn snapshot_config(&self) -> &[u8] {
+        &self.snapshot_config
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.snapshot_index + self.entries.len() as u64
+    }
+
+    pub fn last_term(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(self.snapshot_term, |entry| entry.term)
+    }
+
+    /// The term of the entry at `index`, `None` when it is compacted away
+    /// or not in the log yet.
+    pub fn term_at(&self, index: u64) -> Option<u64> {
+        if index == self.snapshot_index {
+            return Some(self.snapshot_term);
+        }
+        self.entry(index).map(|entry| entry.term)
+    }
+
+    pub fn entry(&self, index: u64) -> Option<&RaftEntry> {
+        let pos = index.checked_sub(self.snapshot_index + 1)?;
+        self.entries.get(pos as usize)
+    }
+
+    /// Up to `max` entries from `index` on.
+    pub fn entries_from(&self, index: u64, max: usize) -> Vec<RaftEntry> {
+        let pos = index.saturating_sub(self.snapshot_index + 1) as usize;
+        let end = self.entries.len().min(pos.saturating_add(max));
+        self.entries.get(pos..end).unwrap_or_default().to_vec()
+    }
+
+    /// The entries from `index` on, used to find the latest membership.
+    pub fn entries_since(&self, index: u64) -> impl DoubleEndedIterator<Item = (u64, &RaftEntry)> {
+        let first = self.snapshot_index + 1;
+        self.entries
+            .iter()
+            .enumerate()
+            .map(move |(pos, entry)| (first + pos as u64, entry))
+            .filter(move |(at, _)| *at >= index)
+    }
+
+    pub fn append(&mut self, entries: Vec<RaftEntry>) -> ServerResult<()> {
+        let mut index = self.last_index();
+        let mut ops = vec![];
+        for entry in &entries {
+            index += 1;
+            let mut value = entry.term.to_be_bytes().to_vec();
+            value.extend_from_slice(&entry.data);
+            ops.push(BatchOp::Put(log_key(index), value));
+        }
+        self.write(ops)?;
+        self.entries.extend(entries);
+        Ok(())
+    }
+
+    /// Drop the entries from `index` on, which conflict with the leader.
+    pub fn truncate(&mut self, index: u64) -> ServerResult<()> {
+        let last = self.last_index();
+        let ops = (index..=last).map(|at| BatchOp::Delete(log_key(at)));
+        self.write(ops.collect())?;
+        self.entries
+            .truncate(index.saturating_sub(self.snapshot_index + 1) as usize);
+        Ok(())
+    }
+
+    /// Record a snapshot covering the log up to `index`, dropping the
+    /// entries it replaces. With `keep_later` unset, every entry goes.
+    pub fn compact(
+        &mut self,
+        index: u64,
+        term: u64,
+        config: &[u8],
+        keep_later: bool,
+    ) -> ServerResult<()> {
+        let last = if keep_later { index } else { self.last_index() };
+        let mut value = index.to_be_bytes().to_vec();
+        value.extend_from_slice(&term.to_be_bytes());
+        value.extend_from_slice(config);
+        let mut ops = vec![BatchOp::Put(KEY_SNAPSHOT.to_vec(), value)];
+        ops.extend((self.snapshot_index + 1..=last).map(|at| BatchOp::Delete(log_key(at))));
+        self.write(ops)?;
+
+        let dropped = last.saturating_sub(self.snapshot_index) as usize;
+        self.entries.drain(..dropped.min(self.entries.len()));
+        self.snapshot_index = index;
+        self.snapshot_term = term;
+        self.snapshot_config = config.to_vec();
+        Ok(())
+    }
+
+    fn write(&self, ops: Vec<BatchOp>) -> ServerResult<()> {
+        let durability = Durability {
+            sync: true,
+            disable_wal: false,
+        };
+        self.db.write_batch_with(ops, durability)?;
+        Ok(())
+    }
+}
+
+fn log_key(index: u64) -> Vec<u8> {
+    let mut key = LOG_PREFIX.to_vec();
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+fn decode_number(bytes: &[u8]) -> ServerResult<u64> {
+    let bytes: [u8; 8] = bytes.try_into().map_err(|_| ServerError::InvalidData)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(term: u64, data: u8) -> RaftEntry {
+        RaftEntry {
+            term,
+            data: vec![data],
+        }
+    }
+
+    fn open(dir: &tempfile::TempDir) -> RaftLog {
+        RaftLog::open(&dir.path().join("log").to_string_lossy()).unwrap()
+    }
+
+    #[test]
+    fn test_truncate_conflicting_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = open(&dir);
+        log.append(vec![entry(1, 1), entry(1, 2), entry(2, 3), entry(2, 4)])
+            .unwrap();
+        assert_eq!((log.last_index(), log.last_term()), (4, 2));
+
+        // a new leader of term 3 disagrees from index 3 on
+        log.truncate(3).unwrap();
+        assert_eq!((log.last_index(), log.last_term()), (2, 1));
+        assert_eq!(log.term_at(3), None);
+        log.append(vec![entry(3, 5)]).unwrap();
+        assert_eq!(log.entry(3), Some(&entry(3, 5)));
+
+        let log = open_again(log, &dir);
+        assert_eq!(log.last_index(), 3);
+        assert_eq!(
+            log.entries_from(1, 10),
+            vec![entry(1, 1), entry(1, 2), entry(3, 5)]
+        );
+    }
+
+    #[test]
+    fn test_compact_keeping_later_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = open(&dir);
+        log.append((1..=5).map(|i| entry(i, i as u8)).collect())
+            .unwrap();
+        log.compact(3, 3, b"config", true).unwrap();
+        assert_eq!((log.snapshot_index(), log.snapshot_term()), (3, 3));
+        assert_eq!(log.term_at(3), Some(3));
+        assert_eq!(log.term_at(2), None);
+        assert_eq!(log.entry(3), None);
+        assert_eq!(log.entry(4), Some(&entry(4, 4)));
+        assert_eq!(log.entries_from(1, 10), vec![entry(4, 4), entry(5, 5)]);
+        assert_eq!(log.last_index(), 5);
+
+        // truncating after a compaction counts from the snapshot
+        log.truncate(5).unwrap();
+        assert_eq!((log.last_index(), log.last_term()), (4, 4));
+
+        let log = open_again(log, &dir);
+        assert_eq!((log.snapshot_index(), log.snapshot_term()), (3, 3));
+        assert_eq!(log.snapshot_config(), b"config");
+        assert_eq!(log.entries_from(4, 10), vec![entry(4, 4)]);
+    }
+
+    #[test]
+    fn test_compact_dropping_conflicting_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = open(&dir);
+        log.append(vec![entry(1, 1), entry(1, 2), entry(1, 3)])
+            .unwrap();
+        // a snapshot of the leader reaching past the log, from another term
+        log.compact(6, 2, b"", false).unwrap();
+        assert_eq!((log.last_index(), log.last_term()), (6, 2));
+        assert_eq!(log.entries_from(1, 10), vec![]);
+        log.append(vec![entry(2, 7)]).unwrap();
+        assert_eq!(log.entry(7), Some(&entry(2, 7)));
+
+        let log = open_again(log, &dir);
+        assert_eq!(log.last_index(), 7);
+        assert_eq!(log.term_at(6), Some(2));
+    }
+
+    #[test]
+    fn test_term_vote_and_applied_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut log = open(&dir);
+        log.set_term(4, 2).unwrap();
+        log.set_applied(9).unwrap();
+        let log = open_again(log, &dir);
+        assert_eq!((log.term(), log.vote(), log.applied()), (4, 2, 9));
+    }
+
+    fn open_again(log: RaftLog, dir: &tempfile::TempDir) -> RaftLog {
+        drop(log);
+        open(dir)
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rsdbrs::{ChangeOp, Direction, IteratorMode, RsDBClient, RsDBError};
+use storage::{BatchOp, KvEngine, MultiDB, StorageError};
+
+use crate::errors::{ServerError, ServerResult};
+use crate::logic::DROP_WAIT;
+
+/// Pairs copied or changes applied per request to the primary.
+const PAGE_SIZE: u16 = 1000;
+
+/// How long to wait once every database has caught up.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait before connecting again after losing the primary.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Directory under the root with the state of the replication.
+const REPLICATION_DIR: &str = ".replication";
+
+/// File in `REPLICATION_DIR` with the primary followed, then a `name seq`
+/// line per database whose applied writes are durable.
+const PROGRESS_FILE: &str = "progress";
+
+#[derive(Default)]
+struct DbProgress {
+    /// Set once the initial copy is in, after which the WAL is tailed.
+    copied: bool,
+    /// Sequence number on the primary up to which writes are applied.
+    applied: u64,
+    /// Latest sequence number of the primary when last asked.
+    primary: u64,
+    synced_at: Option<Instant>,
+    /// Why the database is not replicated, until the next reconnect.
+    error: Option<String>,
+}
+
+/// Keeps the databases of this server in step with those of a primary
+/// server, asynchronously.
+///
+/// Each attached database of the primary is copied from a checkpoint, then
+/// followed through its change feed. Writes that skip the WAL on the
+/// primary are not replicated, and a database falling behind the WAL kept
+/// by the primary (see `wal_ttl_seconds`) is copied again from scratch.
+///
+/// The applied sequence number of each database is saved under the root
+/// once the writes up to it are synced, so a restarted replica resumes
+/// where it was, unless it now follows another primary. Databases without
+/// a WAL are copied again instead. Failover is manual: restart the replica
+/// without `--replica-of`.
+pub struct Replication {
+    primary: String,
+    dir: PathBuf,
+    connected: AtomicBool,
+    dbs: Mutex<HashMap<String, DbProgress>>,
+}
+
+impl Replication {
+    /// Start following `primary` into `mdb` on a thread of its own, from
+    /// the progress saved under `root` by a previous run.
+    pub fn start(primary: &str, root: &str, mdb: Arc<MultiDB>) -> Arc<Self> {
+        let dir = Path::new(root).join(REPLICATION_DIR);
+        let dbs = match load_progress(&dir, primary) {
+            Ok(dbs) => dbs,
+            Err(e) => {
+                eprintln!("    > Failed to load the replication progress: {}", e);
+                HashMap::new()
+            }
+        };
+        let replication = Arc::new(Self {
+            primary: primary.to_string(),
+            dir,
+            connected: AtomicBool::new(false),
+            dbs: Mutex::new(dbs),
+        });
+        let state = replication.clone();
+        thread::spawn(move || loop {
+            if let Err(e) = state.follow(&mdb) {
+                eprintln!("    > Replication from {} stopped: {}", state.primary, e);
+            }
+            state.connected.store(false, Ordering::Relaxed);
+            thread::sleep(RETRY_INTERVAL);
+        });
+        replication
+    }
+
+    pub fn primary(&self) -> &str {
+        &self.primary
+    }
+
+    /// Figures for `CmdServerStats`: the primary, and per database the last
+    /// applied sequence number and how far it is behind the primary, in
+    /// writes and in milliseconds since the last successful poll.
+    pub fn stats(&self) -> Vec<(String, String)> {
+        let mut stats = vec![
+            ("replica_of".to_string(), self.primary.clone()),
+            (
+                "replication_connected".to_string(),
+                self.connected.load(Ordering::Relaxed).to_string(),
+            ),
+        ];
+        let dbs = self.dbs();
+        let mut names: Vec<_> = dbs.keys().collect();
+        names.sort();
+        for name in names {
+            let progress = &dbs[name];
+            let mut figures = vec![
+                ("applied_seq", progress.applied.to_string()),
+                (
+                    "lag",
+                    progress
+                        .primary
+                        .saturating_sub(progress.applied)
+                        .to_string(),
+                ),
+            ];
+            if let Some(synced_at) = progress.synced_at {
+                let millis = synced_at.elapsed().as_millis();
+                figures.push(("last_sync_ms", millis.to_string()));
+            }
+            if let Some(error) = progress.error.as_ref() {
+                figures.push(("error", error.clone()));
+            }
+            for (figure, value) in figures {
+                stats.push((format!("replication.{}.{}", name, figure), value));
+            }
+        }
+        stats
+    }
+
+    fn dbs(&self) -> MutexGuard<'_, HashMap<String, DbProgress>> {
+        self.dbs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replicate until the connection to the primary fails.
+    fn follow(&self, mdb: &MultiDB) -> ServerResult<()> {
+        let mut client = RsDBClient::new();
+        client.connect(&self.primary)?;
+        self.connected.store(true, Ordering::Relaxed);
+        for progress in self.dbs().values_mut() {
+            progress.error = None;
+        }
+
+        loop {
+            let names = self.refresh_catalog(&mut client, mdb)?;
+            let mut idle = true;
+            for name in &names {
+                match self.sync_db(&mut client, mdb, name) {
+                    Ok(caught_up) => idle &= caught_up,
+                    Err(ServerError::PrimaryError(RsDBError::RespError(msg))) => {
+                        self.failed(name, msg)
+                    }
+                    Err(ServerError::StorageError(e)) => self.failed(name, e.to_string()),
+                    Err(e) => return Err(e),
+                }
+            }
+            if idle {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    /// The attached databases of the primary. Local copies of databases
+    /// gone from the primary are dropped.
+    fn refresh_catalog(&self, client: &mut RsDBClient, mdb: &MultiDB) -> ServerResult<Vec<String>> {
+        let catalog = client.catalog()?;
+        let gone: Vec<String> = self
+            .dbs()
+            .keys()
+            .filter(|name| !catalog.iter().any(|(entry, _)| entry == *name))
+            .cloned()
+            .collect();
+        for name in gone {
+            match mdb.drop_db(&name, DROP_WAIT) {
+                Ok(()) | Err(StorageError::DbNotFound(_)) => {
+                    self.dbs().remove(&name);
+                }
+                Err(e) => eprintln!("    > Failed to drop replicated database `{}`: {}", name, e),
+            }
+        }
+        let attached = catalog
+            .into_iter()
+            .filter(|(_, state)| state == "attached")
+            .map(|(name, _)| name);
+        Ok(attached.collect())
+    }
+
+    /// Bring the local copy of `name` closer to the primary, returning
+    /// whether it has caught up.
+    fn sync_db(&self, client: &mut RsDBClient, mdb: &MultiDB, name: &str) -> ServerResult<bool> {
+        let (copied, applied) = match self.dbs().entry(name.to_string()).or_default() {
+            progress if progress.error.is_some() => return Ok(true),
+            progress => (progress.copied, progress.applied),
+        };
+        // never recreate a database the primary has just dropped
+        client.use_existing_db(name)?;
+        let primary = client.latest_sequence()?;
+
+        let (applied, caught_up) = if copied {
+            // gone since the progress was saved, so copied again on failing
+            let local = mdb.attach_existing(name)?;
+            let changes = client.changes_since(applied, PAGE_SIZE)?;
+            let caught_up = changes.len() < PAGE_SIZE as usize;
+            let last = changes.last().map_or(applied, |change| change.seq);
+            if !changes.is_empty() {
+                apply_changes(local.as_ref(), changes)?;
+                local.sync_wal()?;
+            }
+            // nothing was left for this database up to `primary` at least
+            (if caught_up { last.max(primary) } else { last }, caught_up)
+        } else {
+            let applied = self.copy(client, mdb, name, primary)?;
+            eprintln!("    > Copied database `{}` from {}", name, self.primary);
+            (applied, false)
+        };
+
+        let changed = {
+            let mut dbs = self.dbs();
+            let progress = dbs.entry(name.to_string()).or_default();
+            let changed = !progress.copied || progress.applied != applied;
+            progress.copied = true;
+            progress.applied = applied;
+            progress.primary = primary;
+            progress.synced_at = Some(Instant::now());
+            changed
+        };
+        if changed {
+            self.save_progress(mdb)?;
+        }
+        Ok(caught_up)
+    }
+
+    /// Copy the current database of `client` over the local `name`,
+    /// returning the sequence number of the primary the copy has every
+    /// write up to. Primaries that cannot checkpoint, as when keeping their
+    /// databases as column families, are copied from a snapshot instead.
+    fn copy(
+        &self,
+        client: &mut RsDBClient,
+        mdb: &MultiDB,
+        name: &str,
+        primary: u64,
+    ) -> ServerResult<u64> {
+        let checkpoint = match client.fetch_checkpoint() {
+            Ok(checkpoint) => checkpoint,
+            Err(RsDBError::RespError(msg)) => {
+                eprintln!(
+                    "    > No checkpoint of database `{}` from {}: {}",
+                    name, self.primary, msg
+                );
+                let local = mdb.attach(name)?;
+                copy_db(client, local.as_ref())?;
+                return Ok(primary);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let incoming = self.dir.join(format!("incoming-{}", name));
+        let rs = download(client, &checkpoint, &incoming);
+        let released = client.release_checkpoint(checkpoint.handle);
+        let installed = rs.and_then(|()| {
+            mdb.install(name, &incoming.to_string_lossy(), DROP_WAIT)?;
+            Ok(())
+        });
+        if incoming.exists() {
+            fs::remove_dir_all(&incoming)?;
+        }
+        installed?;
+        released?;
+        Ok(checkpoint.seq)
+    }
+
+    /// Save the applied sequence number of the databases whose writes are
+    /// in their WAL, which `sync_db` has synced by now.
+    fn save_progress(&self, mdb: &MultiDB) -> ServerResult<()> {
+        let mut text = format!("{}\n", self.primary);
+        {
+            let dbs = self.dbs();
+            let mut names: Vec<_> = dbs.keys().collect();
+            names.sort();
+            for name in names {
+                let progress = &dbs[name];
+                if !progress.copied {
+                    continue;
+                }
+                let durable = mdb
+                    .attach_existing(name)
+                    .map(|sdb| !sdb.options().disable_wal.unwrap_or(false))
+                    .unwrap_or(false);
+                if durable {
+                    text.push_str(&format!("{} {}\n", name, progress.applied));
+                }
+            }
+        }
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(PROGRESS_FILE);
+        let tmp = self.dir.join(format!("{}.tmp", PROGRESS_FILE));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        File::open(&self.dir)?.sync_all()?;
+        Ok(())
+    }
+
+    /// Give up on `name` until the next reconnect, or copy it again if it
+    /// was being tailed, e.g. when the primary no longer has the WAL.
+    fn failed(&self, name: &str, msg: String) {
+        eprintln!("    > Replication of database `{}` failed: {}", name, msg);
+        let mut dbs = self.dbs();
+        let progress = dbs.entry(name.to_string()).or_default();
+        if progress.copied {
+            progress.copied = false;
+        } else {
+            progress.error = Some(msg);
+        }
+    }
+}
+
+/// Read the progress saved by a previous run following `primary`, as
+/// copied databases to tail from their applied sequence number.
+fn load_progress(dir: &Path, primary: &str) -> ServerResult<HashMap<String, DbProgress>> {
+    let path = dir.join(PROGRESS_FILE);
+    let mut dbs = HashMap::new();
+    if !path.exists() {
+        return Ok(dbs);
+    }
+    let text = fs::read_to_string(path)?;
+    let mut lines = text.lines();
+    if lines.next() != Some(primary) {
+        return Ok(dbs);
+    }
+    for line in lines {
+        let (name, seq) = line.rsplit_once(' ').ok_or(ServerError::InvalidData)?;
+        let applied = seq.parse().map_err(|_| ServerError::InvalidData)?;
+        let progress = DbProgress {
+            copied: true,
+            applied,
+            ..Default::default()
+        };
+        dbs.insert(name.to_string(), progress);
+    }
+    Ok(dbs)
+}
+
+/// Download the files of `checkpoint` into `dir`, synced.
+fn download(
+    client: &mut RsDBClient,
+    checkpoint: &rsdbrs::RemoteCheckpoint,
+    dir: &Path,
+) -> ServerResult<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    fs::create_dir_all(dir)?;
+    for name in &checkpoint.files {
+        let mut file = File::create(dir.join(name))?;
+        let mut offset = 0;
+        loop {
+            let data = client.read_checkpoint(checkpoint.handle, name, offset)?;
+            if data.is_empty() {
+                break;
+            }
+            file.write_all(&data)?;
+            offset += data.len() as u64;
+        }
+        file.sync_all()?;
+    }
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Replace the pairs of `local` with those of the current database of
+/// `client`, read from a snapshot. Writes between the sequence number taken
+/// before and the snapshot are applied again afterwards, which is harmless.
+fn copy_db(client: &mut RsDBClient, local: &dyn KvEngine) -> ServerResult<()> {
+    let handle = client.snapshot()?;
+    let rs = copy_snapshot(client, handle, local);
+    let released = client.release_snapshot(handle);
+    rs?;
+    released?;
+    Ok(())
+}
+
+fn copy_snapshot(client: &mut RsDBClient, handle: u64, local: &dyn KvEngine) -> ServerResult<()> {
+    local.delete_prefix(b"")?;
+    let mut last_key: Option<Vec<u8>> = None;
+    loop {
+        let pairs = match last_key.as_ref() {
+            Some(key) => {
+                let iter_mode = IteratorMode::From(key, Direction::Forward);
+                client.snapshot_range(handle, iter_mode, PAGE_SIZE, true)?
+            }
+            None => client.snapshot_range(handle, IteratorMode::Start, PAGE_SIZE, false)?,
+        };
+        last_key = match pairs.last() {
+            Some((key, _)) => Some(key.clone()),
+            None => return Ok(()),
+        };
+        let ops = pairs
+            .into_iter()
+            .map(|(key, value)| BatchOp::Put(key, value));
+        local.write_batch(ops.collect())?;
+    }
+}
+
+/// Apply changes in order, batching the puts and deletes between range
+/// deletions.
+fn apply_changes(local: &dyn KvEngine, changes: Vec<rsdbrs::Change>) -> ServerResult<()> {
+    let mut ops = vec![];
+    for change in changes {
+        match change.op {
+            ChangeOp::Put(key, value) => ops.push(BatchOp::Put(key, value)),
+            ChangeOp::Delete(key) => ops.push(BatchOp::Delete(key)),
+            ChangeOp::DeleteRange(start, end) => {
+                if !ops.is_empty() {
+                    local.write_batch(std::mem::take(&mut ops))?;
+                }
+                local.delete_range(&start, &end)?;
+            }
+        }
+    }
+    if !ops.is_empty() {
+        local.write_batch(ops)?;
+    }
+    Ok(())
+}
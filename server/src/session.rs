@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use storage::{KvEngine, KvSnapshot};
+
+use crate::errors::ServerResult;
+
+/// Directory under the root holding the checkpoints taken for replicas.
+pub const CHECKPOINTS_DIR: &str = ".checkpoints";
+
+/// Most bytes of a checkpoint file sent per request.
+const CHECKPOINT_CHUNK_SIZE: u64 = 1 << 20;
+
+/// Numbers the checkpoint directories of every connection.
+static NEXT_CHECKPOINT: AtomicU64 = AtomicU64::new(1);
+
+struct SessionSnapshot {
+    snapshot: Box<dyn KvSnapshot>,
+    last_used: Instant,
+}
+
+/// Snapshots created by one connection, addressed by a session-local handle.
+///
+/// A snapshot which has not been used for `timeout` is released on the next
+/// request, so an abandoned scan does not pin old data forever.
+pub struct Snapshots {
+    items: HashMap<u64, SessionSnapshot>,
+    next_handle: u64,
+    timeout: Duration,
+}
+
+impl Snapshots {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            items: HashMap::new(),
+            next_handle: 1,
+            timeout,
+        }
+    }
+
+    pub fn create(&mut self, sdb: &Arc<dyn KvEngine>) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.items.insert(
+            handle,
+            SessionSnapshot {
+                snapshot: sdb.clone().snapshot(),
+                last_used: Instant::now(),
+            },
+        );
+        handle
+    }
+
+    pub fn get(&mut self, handle: u64) -> Option<&dyn KvSnapshot> {
+        let item = self.items.get_mut(&handle)?;
+        item.last_used = Instant::now();
+        Some(item.snapshot.as_ref())
+    }
+
+    pub fn release(&mut self, handle: u64) -> bool {
+        self.items.remove(&handle).is_some()
+    }
+
+    pub fn expire(&mut self) {
+        let timeout = self.timeout;
+        self.items
+            .retain(|_, item| item.last_used.elapsed() < timeout);
+    }
+}
+
+struct SessionCheckpoint {
+    dir: PathBuf,
+    files: Vec<String>,
+    last_used: Instant,
+}
+
+/// Checkpoints taken by one connection for a replica to copy, addressed by
+/// a session-local handle like snapshots and expiring the same way.
+///
+/// Each is a directory under `<root>/.checkpoints`, removed once released,
+/// expired or the connection is gone.
+pub struct Checkpoints {
+    dir: PathBuf,
+    items: HashMap<u64, SessionCheckpoint>,
+    next_handle: u64,
+    timeout: Duration,
+}
+
+impl Checkpoints {
+    pub fn new(root: &str, timeout: Duration) -> Self {
+        Self {
+            dir: Path::new(root).join(CHECKPOINTS_DIR),
+            items: HashMap::new(),
+            next_handle: 1,
+            timeout,
+        }
+    }
+
+    /// Checkpoint `sdb` along with its options, and return the handle, a
+    /// sequence number the checkpoint has every write up to, and its files.
+    pub fn create(&mut self, sdb: &Arc<dyn KvEngine>) -> ServerResult<(u64, u64, Vec<String>)> {
+        let seq = sdb.latest_sequence()?;
+        fs::create_dir_all(&self.dir)?;
+        let dir = self
+            .dir
+            .join(NEXT_CHECKPOINT.fetch_add(1, Ordering::Relaxed).to_string());
+        if let Err(e) = checkpoint(sdb.as_ref(), &dir) {
+            if dir.exists() {
+                fs::remove_dir_all(&dir)?;
+            }
+            return Err(e);
+        }
+        let mut files = vec![];
+        for entry in fs::read_dir(&dir)? {
+            files.push(entry?.file_name().to_string_lossy().to_string());
+        }
+        files.sort();
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.items.insert(
+            handle,
+            SessionCheckpoint {
+                dir,
+                files: files.clone(),
+                last_used: Instant::now(),
+            },
+        );
+        Ok((handle, seq, files))
+    }
+
+    /// Up to a chunk of `file` from `offset` on, empty at the end. `None`
+    /// for an unknown handle or a file not in the checkpoint.
+    pub fn read(&mut self, handle: u64, file: &str, offset: u64) -> ServerResult<Option<Vec<u8>>> {
+        let item = match self.items.get_mut(&handle) {
+            Some(item) if item.files.iter().any(|known| known == file) => item,
+            _ => return Ok(None),
+        };
+        item.last_used = Instant::now();
+        let mut reader = File::open(item.dir.join(file))?;
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut data = vec![];
+        reader.take(CHECKPOINT_CHUNK_SIZE).read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    pub fn release(&mut self, handle: u64) -> bool {
+        match self.items.remove(&handle) {
+            Some(item) => {
+                remove_checkpoint(&item);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn expire(&mut self) {
+        let timeout = self.timeout;
+        self.items.retain(|_, item| {
+            let keep = item.last_used.elapsed() < timeout;
+            if !keep {
+                remove_checkpoint(item);
+            }
+            keep
+        });
+    }
+}
+
+impl Drop for Checkpoints {
+    fn drop(&mut self) {
+        for item in self.items.values() {
+            remove_checkpoint(item);
+        }
+    }
+}
+
+fn checkpoint(sdb: &dyn KvEngine, dir: &Path) -> ServerResult<()> {
+    let path = dir.to_string_lossy();
+    sdb.checkpoint(&path)?;
+    // opened with the same options once installed
+    sdb.options().save(&path)?;
+    Ok(())
+}
+
+fn remove_checkpoint(item: &SessionCheckpoint) {
+    if let Err(e) = fs::remove_dir_all(&item.dir) {
+        eprintln!("    > Failed to remove {}: {}", item.dir.display(), e);
+    }
+}
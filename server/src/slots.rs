@@ -0,0 +1,256 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+use packet::{key_slot, parse_slot_range, Packet, SLOT_COUNT};
+
+use crate::errors::{ServerError, ServerResult};
+
+/// File under the root holding the slot table.
+const SLOTS_FILE: &str = ".slots";
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Owner {
+    Local,
+    /// Moved away to the node at this address.
+    Moved(String),
+    Unknown,
+}
+
+/// The owner of each hash slot, as far as this node knows.
+pub struct Owners(Vec<Owner>);
+
+impl Owners {
+    /// The response redirecting a command on `keys` when any of them is in
+    /// a slot not served here.
+    pub fn moved(&self, keys: &[&[u8]]) -> Option<Packet> {
+        keys.iter().find_map(|key| {
+            let slot = key_slot(key);
+            match &self.0[slot as usize] {
+                Owner::Local => None,
+                Owner::Moved(addr) => Some(Packet::RespMoved(slot, addr.clone())),
+                Owner::Unknown => Some(Packet::RespMoved(slot, String::new())),
+            }
+        })
+    }
+
+    /// The first slot from `first` to `last` whose owner fails `allowed`.
+    pub fn find_not<F: Fn(&Owner) -> bool>(
+        &self,
+        first: u16,
+        last: u16,
+        allowed: F,
+    ) -> Option<u16> {
+        (first..=last).find(|&slot| !allowed(&self.0[slot as usize]))
+    }
+
+    pub fn assign(&mut self, first: u16, last: u16, owner: Owner) {
+        self.0[first as usize..=last as usize].fill(owner);
+    }
+
+    pub fn served(&self) -> usize {
+        self.0
+            .iter()
+            .filter(|owner| **owner == Owner::Local)
+            .count()
+    }
+
+    /// Runs of slots with the same known owner, in slot order.
+    pub fn ranges(&self) -> Vec<(u16, u16, &Owner)> {
+        let mut ranges: Vec<(u16, u16, &Owner)> = vec![];
+        for (slot, owner) in self.0.iter().enumerate() {
+            match ranges.last_mut() {
+                Some((_, last, prev)) if *prev == owner && *last as usize + 1 == slot => {
+                    *last = slot as u16
+                }
+                _ if *owner == Owner::Unknown => {}
+                _ => ranges.push((slot as u16, slot as u16, owner)),
+            }
+        }
+        ranges
+    }
+
+    /// A line per range, `first-last` for the slots served here and
+    /// `first-last=addr` for those moved away.
+    fn format(&self) -> String {
+        let mut text = String::new();
+        for (first, last, owner) in self.ranges() {
+            match owner {
+                Owner::Moved(addr) => text.push_str(&format!("{}-{}={}\n", first, last, addr)),
+                _ => text.push_str(&format!("{}-{}\n", first, last)),
+            }
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut owners = Owners(vec![Owner::Unknown; SLOT_COUNT as usize]);
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (range, owner) = match line.split_once('=') {
+                Some((range, addr)) => (range, Owner::Moved(addr.trim().to_string())),
+                None => (line, Owner::Local),
+            };
+            let (first, last) = parse_slot_range(range)?;
+            owners.assign(first, last, owner);
+        }
+        Ok(owners)
+    }
+}
+
+/// Which hash slots this node serves in a sharded deployment, and where
+/// those it no longer serves were moved to, saved under the root.
+///
+/// Commands on keys hold the table for reading while they run, so that
+/// taking it for writing to hand slots over waits for the writes already
+/// let through, and holds back the next ones.
+pub struct SlotTable {
+    path: PathBuf,
+    owners: RwLock<Owners>,
+    /// Held while slots are imported, one import at a time.
+    importing: Mutex<()>,
+}
+
+impl SlotTable {
+    /// Load the table saved under `root`, or start one serving `ranges`,
+    /// a comma separated list of `first-last` slots which may be empty.
+    pub fn open(root: &str, ranges: &str) -> ServerResult<Self> {
+        let path = Path::new(root).join(SLOTS_FILE);
+        let owners = match fs::read_to_string(&path) {
+            Ok(text) => Owners::parse(&text).map_err(ServerError::SlotError)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let lines: Vec<&str> = ranges.split(',').collect();
+                Owners::parse(&lines.join("\n")).map_err(ServerError::SlotError)?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let table = Self {
+            path,
+            owners: RwLock::new(owners),
+            importing: Mutex::new(()),
+        };
+        table.save(&table.read())?;
+        Ok(table)
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Owners> {
+        self.owners.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, Owners> {
+        self.owners.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Write `owners`, read or changed through a guard of this table, to
+    /// the file, replacing it whole.
+    pub fn save(&self, owners: &Owners) -> ServerResult<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, owners.format())?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// The lock to hold while importing, unless an import is running.
+    pub fn try_import(&self) -> Option<MutexGuard<'_, ()>> {
+        match self.importing.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(dir: &tempfile::TempDir, ranges: &str) -> SlotTable {
+        SlotTable::open(&dir.path().to_string_lossy(), ranges).unwrap()
+    }
+
+    #[test]
+    fn test_open_with_ranges() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = open(&dir, "0-99,200-299");
+        let owners = table.read();
+        assert_eq!(owners.served(), 200);
+        assert_eq!(
+            owners.ranges(),
+            vec![(0, 99, &Owner::Local), (200, 299, &Owner::Local)]
+        );
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(open(&empty, "").read().served(), 0);
+        let invalid = tempfile::tempdir().unwrap();
+        assert!(SlotTable::open(&invalid.path().to_string_lossy(), "5-1").is_err());
+    }
+
+    #[test]
+    fn test_saved_table_takes_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = open(&dir, "0-16383");
+        {
+            let mut owners = table.write();
+            owners.assign(100, 199, Owner::Moved("127.0.0.1:7002".to_string()));
+            table.save(&owners).unwrap();
+        }
+        drop(table);
+
+        let table = open(&dir, "0-9");
+        let owners = table.read();
+        let moved = Owner::Moved("127.0.0.1:7002".to_string());
+        assert_eq!(
+            owners.ranges(),
+            vec![
+                (0, 99, &Owner::Local),
+                (100, 199, &moved),
+                (200, 16383, &Owner::Local)
+            ]
+        );
+        assert_eq!(owners.served(), 16384 - 100);
+    }
+
+    #[test]
+    fn test_moved() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = open(&dir, "");
+        let mut owners = table.write();
+        let (key, other) = (b"{user1}a".as_slice(), b"{user2}b".as_slice());
+        let (slot, other_slot) = (key_slot(key), key_slot(other));
+        assert_ne!(slot, other_slot);
+        owners.assign(slot, slot, Owner::Local);
+        assert_eq!(owners.moved(&[key]), None);
+        // not known to be anywhere
+        assert_eq!(
+            owners.moved(&[key, other]),
+            Some(Packet::RespMoved(other_slot, String::new()))
+        );
+        owners.assign(other_slot, other_slot, Owner::Moved("b".to_string()));
+        assert_eq!(
+            owners.moved(&[other, key]),
+            Some(Packet::RespMoved(other_slot, "b".to_string()))
+        );
+        assert_eq!(
+            owners.find_not(0, 16383, |o| *o != Owner::Local),
+            Some(slot)
+        );
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(SLOTS_FILE), "0-99\nnot a range\n").unwrap();
+        let rs = SlotTable::open(&dir.path().to_string_lossy(), "0-16383");
+        assert!(matches!(rs, Err(ServerError::SlotError(_))));
+    }
+
+    #[test]
+    fn test_one_import_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let table = open(&dir, "");
+        let importing = table.try_import();
+        assert!(importing.is_some());
+        assert!(table.try_import().is_none());
+        drop(importing);
+        assert!(table.try_import().is_some());
+    }
+}
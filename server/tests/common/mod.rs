@@ -0,0 +1,105 @@
+//! Helpers to run `rsdb-server` processes on free localhost ports.
+
+#![allow(dead_code)]
+
+use std::fs::{self, File};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rsdbrs::RsDBClient;
+
+/// How long a server gets to come up, or a condition to hold.
+pub const WAIT: Duration = Duration::from_secs(30);
+
+pub struct TestServer {
+    child: Child,
+    pub addr: String,
+    pub root: PathBuf,
+    /// Where the output of the server goes, across restarts.
+    pub log: PathBuf,
+}
+
+impl TestServer {
+    /// Start a server on a free port with the databases under `root` and
+    /// `args` besides `--addr` and `--root`.
+    pub fn start(root: &Path, args: &[&str]) -> Self {
+        Self::start_at(&free_addr(), root, args)
+    }
+
+    pub fn start_at(addr: &str, root: &Path, args: &[&str]) -> Self {
+        fs::create_dir_all(root).unwrap();
+        let log = root.with_extension("log");
+        let out = File::options()
+            .create(true)
+            .append(true)
+            .open(&log)
+            .unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_rsdb-server"))
+            .arg("--addr")
+            .arg(addr)
+            .arg("--root")
+            .arg(root)
+            .args(args)
+            .stdout(out.try_clone().unwrap())
+            .stderr(out)
+            .stdin(Stdio::null())
+            .spawn()
+            .unwrap();
+        let server = Self {
+            child,
+            addr: addr.to_string(),
+            root: root.to_path_buf(),
+            log,
+        };
+        assert!(
+            wait_until(|| RsDBClient::new().connect(addr).is_ok()),
+            "server at {} did not start",
+            addr
+        );
+        server
+    }
+
+    /// A client connected to the server, using `db`.
+    pub fn client(&self, db: &str) -> RsDBClient {
+        let mut client = RsDBClient::new();
+        client.connect(&self.addr).unwrap();
+        client.use_db(db).unwrap();
+        client
+    }
+
+    pub fn log_text(&self) -> String {
+        fs::read_to_string(&self.log).unwrap_or_default()
+    }
+
+    pub fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// An address on localhost nothing listens on right now.
+pub fn free_addr() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+/// Poll `f` until it holds, for up to `WAIT`.
+pub fn wait_until<F: FnMut() -> bool>(mut f: F) -> bool {
+    let deadline = Instant::now() + WAIT;
+    while Instant::now() < deadline {
+        if f() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use common::TestServer;
+
+#[test]
+fn test_get_at_tells_missing_from_empty() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = TestServer::start(&dir.path().join("node"), &[]);
+    let mut client = rsdbrs::RsDBClient::new();
+    client.connect(&server.addr).unwrap();
+    client
+        .use_db_with_options("history", "history_ttl_seconds = 0")
+        .unwrap();
+
+    client.set(b"empty", b"").unwrap();
+    client.set(b"key", b"value").unwrap();
+    let seq = client.latest_sequence().unwrap();
+    client.delete(b"key").unwrap();
+
+    assert_eq!(client.get_at(b"empty", seq).unwrap(), Some(vec![]));
+    assert_eq!(client.get_at(b"key", seq).unwrap(), Some(b"value".to_vec()));
+    assert_eq!(client.get_at(b"key", seq + 1).unwrap(), None);
+    assert_eq!(client.get_at(b"missing", seq).unwrap(), None);
+    assert_eq!(
+        client.get_at_time(b"empty", u64::MAX).unwrap(),
+        Some(vec![])
+    );
+    assert_eq!(client.get_at_time(b"key", u64::MAX).unwrap(), None);
+    assert_eq!(client.get_at_time(b"key", 0).unwrap(), None);
+
+    // refused by databases keeping no history
+    let mut other = server.client("plain");
+    assert!(other.get_at(b"key", seq).is_err());
+}
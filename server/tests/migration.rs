@@ -0,0 +1,171 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use common::TestServer;
+use rsdbrs::{key_slot, RsDBClient, RsDBError};
+
+/// A client of a sharded pair of nodes, following `RespMoved` answers.
+/// Slots not known to a node are looked for on `home`.
+struct Router {
+    home: String,
+    clients: Vec<(String, RsDBClient)>,
+}
+
+impl Router {
+    fn new(home: &str) -> Self {
+        Self {
+            home: home.to_string(),
+            clients: vec![],
+        }
+    }
+
+    fn client(&mut self, addr: &str) -> &mut RsDBClient {
+        let pos = match self.clients.iter().position(|(known, _)| known == addr) {
+            Some(pos) => pos,
+            None => {
+                let mut client = RsDBClient::new();
+                client.connect(addr).unwrap();
+                client.use_db("users").unwrap();
+                self.clients.push((addr.to_string(), client));
+                self.clients.len() - 1
+            }
+        };
+        &mut self.clients[pos].1
+    }
+
+    fn route<T, F>(&mut self, mut f: F) -> T
+    where
+        F: FnMut(&mut RsDBClient) -> Result<T, RsDBError>,
+    {
+        let mut addr = self.home.clone();
+        for _ in 0..5 {
+            match f(self.client(&addr)) {
+                Ok(value) => return value,
+                Err(RsDBError::Moved(_, to)) if !to.is_empty() => addr = to,
+                Err(RsDBError::Moved(..)) => addr = self.home.clone(),
+                Err(e) => panic!("{}", e),
+            }
+        }
+        panic!("moved around too often");
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.route(|client| client.set(key, value))
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.route(|client| client.get(key))
+    }
+}
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key-{:06}", i).into_bytes()
+}
+
+fn sharded(dir: &tempfile::TempDir) -> (TestServer, TestServer) {
+    let source = TestServer::start(&dir.path().join("source"), &["--slots", "0-16383"]);
+    let target = TestServer::start(&dir.path().join("target"), &["--slots="]);
+    (source, target)
+}
+
+#[test]
+fn test_import_while_writing() {
+    let dir = tempfile::tempdir().unwrap();
+    let (source, target) = sharded(&dir);
+    let mut router = Router::new(&source.addr);
+    for i in 0..2000 {
+        router.set(&key(i), b"before");
+    }
+    // the target serves no slot yet, and knows of no owner
+    let mut client = target.client("users");
+    assert!(matches!(client.get(&key(0)), Err(RsDBError::Moved(_, addr)) if addr.is_empty()));
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let (stop, home) = (stop.clone(), source.addr.clone());
+        thread::spawn(move || {
+            let mut router = Router::new(&home);
+            let mut written = 2000;
+            while !stop.load(Ordering::Relaxed) || written < 4000 {
+                router.set(&key(written), b"during");
+                // changed under the move too
+                router.set(&key(written - 2000), b"updated");
+                written += 1;
+            }
+            written
+        })
+    };
+    let figures = client
+        .import_slots(0, 8191, &source.addr, &target.addr)
+        .unwrap();
+    stop.store(true, Ordering::Relaxed);
+    let written = writer.join().unwrap();
+
+    let figure = |name: &str| -> u64 {
+        let (_, value) = figures.iter().find(|(n, _)| n == name).unwrap();
+        value.parse().unwrap()
+    };
+    assert!(figure("copied") > 0);
+    assert!(figure("purged_on_source") > 0);
+
+    let mut router = Router::new(&source.addr);
+    for i in 0..written {
+        let expected: &[u8] = match i {
+            i if i < written - 2000 => b"updated",
+            i if i < 2000 => b"before",
+            _ => b"during",
+        };
+        assert_eq!(router.get(&key(i)), Some(expected.to_vec()), "key {}", i);
+    }
+
+    // the source redirects the slots it handed over, and keeps the others
+    let mut client = source.client("users");
+    for i in 0..100 {
+        let key = key(i);
+        match client.get(&key) {
+            Err(RsDBError::Moved(slot, addr)) => {
+                assert!(key_slot(&key) <= 8191);
+                assert_eq!((slot, addr), (key_slot(&key), target.addr.clone()));
+            }
+            Ok(value) => {
+                assert!(key_slot(&key) > 8191);
+                assert!(value.is_some());
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+#[test]
+fn test_import_refused_with_detached_dbs() {
+    let dir = tempfile::tempdir().unwrap();
+    let (source, target) = sharded(&dir);
+    let mut client = source.client("archive");
+    client.detach_db("archive").unwrap();
+
+    let mut client = target.client("users");
+    let rs = client.import_slots(0, 8191, &source.addr, &target.addr);
+    assert!(matches!(rs, Err(RsDBError::RespError(msg)) if msg.contains("detached")));
+    // the slots stay with the source
+    let mut client = source.client("users");
+    client.set(&key(0), b"value").unwrap();
+}
+
+#[test]
+fn test_import_refused_when_read_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let (source, target) = sharded(&dir);
+    let mut client = target.client("users");
+    client.set_read_only(true).unwrap();
+    let rs = client.import_slots(0, 8191, &source.addr, &target.addr);
+    assert!(matches!(rs, Err(RsDBError::RespError(msg)) if msg.contains("read-only")));
+    assert!(client.purge_slots(0, 8191).is_err());
+
+    client.set_read_only(false).unwrap();
+    client
+        .import_slots(0, 8191, &source.addr, &target.addr)
+        .unwrap();
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::{free_addr, wait_until, TestServer};
+use rsdbrs::RsDBClient;
+
+fn stat(addr: &str, name: &str) -> Option<String> {
+    let mut client = RsDBClient::new();
+    client.connect(addr).ok()?;
+    let stats = client.server_stats().ok()?;
+    stats.into_iter().find(|(n, _)| n == name).map(|(_, v)| v)
+}
+
+/// The only node among `nodes` that considers itself the leader.
+fn leader(nodes: &[Option<TestServer>]) -> Option<usize> {
+    let leaders: Vec<usize> = (0..nodes.len())
+        .filter(|i| {
+            nodes[*i]
+                .as_ref()
+                .is_some_and(|node| stat(&node.addr, "raft_role").as_deref() == Some("leader"))
+        })
+        .collect();
+    match leaders[..] {
+        [leader] => Some(leader),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_new_leader_keeps_committed_writes() {
+    let dir = tempfile::tempdir().unwrap();
+    let addrs: Vec<String> = (0..3).map(|_| free_addr()).collect();
+    let peers = (0..3)
+        .map(|i| format!("{}={}", i + 1, addrs[i]))
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut nodes: Vec<Option<TestServer>> = (0..3)
+        .map(|i| {
+            let root = dir.path().join(format!("node{}", i + 1));
+            let id = (i + 1).to_string();
+            let args = ["--raft-id", &id, "--raft-peers", &peers];
+            Some(TestServer::start_at(&addrs[i], &root, &args))
+        })
+        .collect();
+
+    let mut first = None;
+    assert!(wait_until(|| {
+        first = leader(&nodes);
+        first.is_some()
+    }));
+    let first = first.unwrap();
+    let term = stat(&addrs[first], "raft_term").unwrap();
+    let mut client = nodes[first].as_ref().unwrap().client("users");
+    for i in 0..100u32 {
+        client.set(&i.to_be_bytes(), b"committed").unwrap();
+    }
+    // a follower answers writes with the address of the leader
+    let follower = (first + 1) % 3;
+    let mut other = nodes[follower].as_ref().unwrap().client("users");
+    assert!(other.set(b"key", b"value").is_err());
+
+    nodes[first] = None;
+    let mut second = None;
+    assert!(wait_until(|| {
+        second = leader(&nodes);
+        second.is_some()
+    }));
+    let second = second.unwrap();
+    assert_ne!(second, first);
+    assert_ne!(stat(&addrs[second], "raft_term").unwrap(), term);
+
+    // applied by the new leader once it commits an entry of its own term
+    let mut client = nodes[second].as_ref().unwrap().client("users");
+    assert!(wait_until(|| (0..100u32).all(|i| {
+        client.get(&i.to_be_bytes()).unwrap() == Some(b"committed".to_vec())
+    })));
+    // the remaining two nodes are still a majority
+    client.set(b"after", b"failover").unwrap();
+    assert_eq!(client.get(b"after").unwrap(), Some(b"failover".to_vec()));
+}
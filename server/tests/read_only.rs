@@ -0,0 +1,48 @@
+mod common;
+
+use common::TestServer;
+use rsdbrs::RsDBClient;
+
+#[test]
+fn test_read_only_creates_no_databases() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("node");
+    let server = TestServer::start(&root, &[]);
+    let mut client = server.client("users");
+    client.set(b"key", b"value").unwrap();
+    client.set_read_only(true).unwrap();
+
+    let mut other = RsDBClient::new();
+    other.connect(&server.addr).unwrap();
+    assert!(other.use_db("new").is_err());
+    assert!(other
+        .use_db_with_options("new", "write_buffer_size = 1048576")
+        .is_err());
+    assert!(other.use_db_read_only("new").is_err());
+    assert!(!other
+        .catalog()
+        .unwrap()
+        .iter()
+        .any(|(name, _)| name == "new"));
+
+    // existing databases are still served for reading
+    other.use_db("users").unwrap();
+    assert_eq!(other.get(b"key").unwrap(), Some(b"value".to_vec()));
+    assert!(other.set(b"key", b"other").is_err());
+
+    // any client can switch it off again
+    other.set_read_only(false).unwrap();
+    other.use_db("new").unwrap();
+    other.set(b"key", b"value").unwrap();
+}
+
+#[test]
+fn test_started_read_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("node");
+    let server = TestServer::start(&root, &["--read-only"]);
+    let mut client = RsDBClient::new();
+    client.connect(&server.addr).unwrap();
+    assert!(client.use_db("new").is_err());
+    assert!(client.catalog().unwrap().is_empty());
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use common::{wait_until, TestServer};
+use rsdbrs::RsDBClient;
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key-{:05}", i).into_bytes()
+}
+
+fn has_keys(replica: &TestServer, keys: std::ops::Range<u32>) -> bool {
+    let mut client = RsDBClient::new();
+    if client.connect(&replica.addr).is_err() || client.use_existing_db("users").is_err() {
+        return false;
+    }
+    keys.into_iter()
+        .all(|i| matches!(client.get(&key(i)), Ok(Some(value)) if value == key(i)))
+}
+
+#[test]
+fn test_replica_copies_and_resumes() {
+    let dir = tempfile::tempdir().unwrap();
+    let primary = TestServer::start(&dir.path().join("primary"), &[]);
+    let mut client = primary.client("users");
+    for i in 0..500 {
+        client.set(&key(i), &key(i)).unwrap();
+    }
+
+    let replica_root = dir.path().join("replica");
+    let mut replica = TestServer::start(&replica_root, &["--replica-of", &primary.addr]);
+    assert!(wait_until(|| has_keys(&replica, 0..500)));
+    // written while the replica tails the change feed
+    for i in 500..600 {
+        client.set(&key(i), &key(i)).unwrap();
+    }
+    assert!(wait_until(|| has_keys(&replica, 0..600)));
+    let mut writer = replica.client("users");
+    assert!(writer.set(b"local", b"write").is_err());
+    assert_eq!(
+        replica
+            .log_text()
+            .matches("Copied database `users`")
+            .count(),
+        1
+    );
+    assert!(!replica.log_text().contains("No checkpoint"));
+    let checkpoints = primary.root.join(".checkpoints");
+    assert_eq!(std::fs::read_dir(checkpoints).unwrap().count(), 0);
+
+    replica.kill();
+    for i in 600..700 {
+        client.set(&key(i), &key(i)).unwrap();
+    }
+    let replica = TestServer::start(&replica_root, &["--replica-of", &primary.addr]);
+    assert!(wait_until(|| has_keys(&replica, 0..700)));
+    // tailed from the saved progress rather than copied again
+    assert_eq!(
+        replica
+            .log_text()
+            .matches("Copied database `users`")
+            .count(),
+        1
+    );
+}
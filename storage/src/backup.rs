@@ -0,0 +1,80 @@
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::Env;
+
+use crate::{Storage, StorageResult};
+
+pub struct BackupInfo {
+    pub backup_id: u32,
+    pub timestamp: i64,
+    pub size: u64,
+    pub num_files: u32,
+}
+
+fn open_engine(backup_dir: &str) -> StorageResult<BackupEngine> {
+    let opts = BackupEngineOptions::new(backup_dir)?;
+    let env = Env::new()?;
+    Ok(BackupEngine::open(&opts, &env)?)
+}
+
+impl Storage {
+    /// Create an openable copy of the db in `target_dir`, which must not exist.
+    ///
+    /// Sst files are hard-linked when the target is on the same filesystem.
+    /// In column family mode this covers every database of the instance.
+    pub(crate) fn create_checkpoint(&self, target_dir: &str) -> StorageResult<()> {
+        let checkpoint = Checkpoint::new(&*self.db)?;
+        checkpoint.create_checkpoint(target_dir)?;
+        Ok(())
+    }
+
+    /// Add an incremental backup of the db to `backup_dir` and return its id.
+    /// In column family mode this covers every database of the instance.
+    pub(crate) fn create_backup(&self, backup_dir: &str) -> StorageResult<u32> {
+        let mut engine = open_engine(backup_dir)?;
+        engine.create_new_backup_flush(&*self.db, true)?;
+        let backup_id = engine
+            .get_backup_info()
+            .iter()
+            .map(|info| info.backup_id)
+            .max()
+            .unwrap_or_default();
+        Ok(backup_id)
+    }
+}
+
+pub fn list_backups(backup_dir: &str) -> StorageResult<Vec<BackupInfo>> {
+    let engine = open_engine(backup_dir)?;
+    let backups = engine
+        .get_backup_info()
+        .into_iter()
+        .map(|info| BackupInfo {
+            backup_id: info.backup_id,
+            timestamp: info.timestamp,
+            size: info.size,
+            num_files: info.num_files,
+        })
+        .collect();
+    Ok(backups)
+}
+
+pub fn purge_backups(backup_dir: &str, keep: usize) -> StorageResult<()> {
+    let mut engine = open_engine(backup_dir)?;
+    engine.purge_old_backups(keep)?;
+    Ok(())
+}
+
+/// Restore the backup `backup_id` (or the latest one) into `db_path`.
+pub fn restore_backup(
+    backup_dir: &str,
+    backup_id: Option<u32>,
+    db_path: &str,
+) -> StorageResult<()> {
+    let mut engine = open_engine(backup_dir)?;
+    let opts = RestoreOptions::default();
+    match backup_id {
+        Some(backup_id) => engine.restore_from_backup(db_path, db_path, &opts, backup_id)?,
+        None => engine.restore_from_latest_backup(db_path, db_path, &opts)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::options::split_sections;
+use crate::{validate_name, StorageError, StorageOptions, StorageResult};
+
+/// File in the root directory listing the databases of a `MultiDB`.
+const CATALOG_FILE: &str = "RSDB-CATALOG";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DbState {
+    Attached,
+    Detached,
+}
+
+impl Display for DbState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DbState::Attached => write!(f, "attached"),
+            DbState::Detached => write!(f, "detached"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogEntry {
+    pub name: String,
+    pub state: DbState,
+    /// Attached with `MultiDB::attach_read_only`, reopened the same way.
+    pub read_only: bool,
+    pub options: StorageOptions,
+}
+
+/// The known databases, saved as a `[name]` section per database holding
+/// `state = attached|detached`, `read_only = true` when attached so, and
+/// the options it was last opened with.
+///
+/// Without a path nothing is persisted.
+#[derive(Default)]
+pub(crate) struct Catalog {
+    path: Option<PathBuf>,
+    entries: BTreeMap<String, CatalogEntry>,
+}
+
+impl Catalog {
+    /// Read the catalog under `root_path`, empty if there is none yet.
+    pub(crate) fn load(root_path: &str) -> StorageResult<Self> {
+        let path = Path::new(root_path).join(CATALOG_FILE);
+        let mut entries = BTreeMap::new();
+        if path.exists() {
+            let text = fs::read_to_string(&path)?;
+            for (name, section) in split_sections(&text, "") {
+                if name.is_empty() {
+                    if section.trim().is_empty() {
+                        continue;
+                    }
+                    return Err(invalid("entry without a database name".to_string()));
+                }
+                let entry = parse_entry(name, &section)?;
+                entries.insert(entry.name.clone(), entry);
+            }
+        }
+        Ok(Self {
+            path: Some(path),
+            entries,
+        })
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = &CatalogEntry> {
+        self.entries.values()
+    }
+
+    /// Record `name` as attached with `options`, read-only or not.
+    pub(crate) fn attach(
+        &mut self,
+        name: &str,
+        options: &StorageOptions,
+        read_only: bool,
+    ) -> StorageResult<()> {
+        let entry = CatalogEntry {
+            name: name.to_string(),
+            state: DbState::Attached,
+            read_only,
+            options: options.clone(),
+        };
+        if self.entries.get(name) == Some(&entry) {
+            return Ok(());
+        }
+        self.entries.insert(name.to_string(), entry);
+        self.save()
+    }
+
+    pub(crate) fn detach(&mut self, name: &str) -> StorageResult<()> {
+        match self.entries.get_mut(name) {
+            Some(entry) if entry.state != DbState::Detached => {
+                entry.state = DbState::Detached;
+                entry.read_only = false;
+                self.save()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, name: &str) -> StorageResult<()> {
+        match self.entries.remove(name) {
+            Some(_) => self.save(),
+            None => Ok(()),
+        }
+    }
+
+    /// Forget the entries missing from `found`, the databases on disk, and
+    /// add the unknown ones as detached.
+    pub(crate) fn sync(&mut self, found: Vec<(String, StorageOptions)>) -> StorageResult<()> {
+        let names: HashSet<&str> = found.iter().map(|(name, _)| name.as_str()).collect();
+        self.entries.retain(|name, _| names.contains(name.as_str()));
+        for (name, options) in found {
+            self.entries
+                .entry(name.clone())
+                .or_insert_with(|| CatalogEntry {
+                    name,
+                    state: DbState::Detached,
+                    read_only: false,
+                    options,
+                });
+        }
+        self.save()
+    }
+
+    /// Write the catalog to a temporary file first, so a crash leaves either
+    /// the old or the new one in place.
+    fn save(&self) -> StorageResult<()> {
+        let path = match self.path.as_ref() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut text = String::new();
+        for entry in self.entries.values() {
+            text.push_str(&format!("[{}]\nstate = {}\n", entry.name, entry.state));
+            if entry.read_only {
+                text.push_str("read_only = true\n");
+            }
+            text.push_str(&entry.options.to_string());
+            text.push('\n');
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+fn parse_entry(name: &str, section: &str) -> StorageResult<CatalogEntry> {
+    validate_name(name)?;
+    let mut state = None;
+    let mut read_only = false;
+    let mut options = String::new();
+    for line in section.lines() {
+        match line.split_once('=') {
+            Some((key, value)) if key.trim() == "state" => {
+                state = match value.trim() {
+                    "attached" => Some(DbState::Attached),
+                    "detached" => Some(DbState::Detached),
+                    value => return Err(invalid(format!("unknown state `{}`", value))),
+                };
+            }
+            Some((key, value)) if key.trim() == "read_only" => {
+                read_only = match value.trim() {
+                    "true" => true,
+                    "false" => false,
+                    value => return Err(invalid(format!("invalid read_only `{}`", value))),
+                };
+            }
+            _ => {
+                options.push_str(line);
+                options.push('\n');
+            }
+        }
+    }
+    Ok(CatalogEntry {
+        name: name.to_string(),
+        state: state.ok_or_else(|| invalid(format!("no state for `{}`", name)))?,
+        read_only,
+        options: StorageOptions::parse(&options)?,
+    })
+}
+
+fn invalid(msg: String) -> StorageError {
+    StorageError::InvalidCatalog(msg)
+}
@@ -0,0 +1,181 @@
+use std::iter::Peekable;
+
+use rocksdb::{AsColumnFamilyRef, DBWALIterator};
+
+use crate::{Storage, StorageError, StorageResult};
+
+/// A committed write, in the order of the WAL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Sequence number of the write, increasing across the db.
+    pub seq: u64,
+    pub op: ChangeOp,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+    /// Every key in `[start, end)`.
+    DeleteRange(Vec<u8>, Vec<u8>),
+}
+
+// record types of a serialized write batch, see `ValueType` in RocksDB
+const TYPE_DELETION: u8 = 0x0;
+const TYPE_VALUE: u8 = 0x1;
+const TYPE_MERGE: u8 = 0x2;
+const TYPE_LOG_DATA: u8 = 0x3;
+const TYPE_CF_DELETION: u8 = 0x4;
+const TYPE_CF_VALUE: u8 = 0x5;
+const TYPE_CF_MERGE: u8 = 0x6;
+const TYPE_SINGLE_DELETION: u8 = 0x7;
+const TYPE_CF_SINGLE_DELETION: u8 = 0x8;
+const TYPE_NOOP: u8 = 0xd;
+const TYPE_CF_RANGE_DELETION: u8 = 0xe;
+const TYPE_RANGE_DELETION: u8 = 0xf;
+
+/// Sequence number and count before the records of a batch.
+const BATCH_HEADER: usize = 12;
+
+impl Storage {
+    pub(crate) fn latest_sequence_number(&self) -> u64 {
+        self.db.latest_sequence_number()
+    }
+
+    /// Up to `limit` writes to this db with a sequence number above `seq`,
+    /// read back from the WAL. In column family mode the writes to other
+    /// databases are skipped.
+    pub(crate) fn read_changes(&self, seq: u64, limit: usize) -> StorageResult<Vec<Change>> {
+        let cf_id = match self.cf_handle()? {
+            // SAFETY: the handle is valid while `cf` is borrowed
+            Some(cf) => unsafe { librocksdb_sys::rocksdb_column_family_handle_get_id(cf.inner()) },
+            None => 0,
+        };
+        let mut changes = vec![];
+        if seq >= self.db.latest_sequence_number() {
+            return Ok(changes);
+        }
+        for item in self.wal_batches(seq)? {
+            let (batch_seq, batch) = item?;
+            parse_batch(batch.data(), batch_seq, cf_id, &mut |change| {
+                if change.seq > seq && changes.len() < limit {
+                    changes.push(change);
+                }
+            })?;
+            if changes.len() >= limit {
+                break;
+            }
+        }
+        Ok(changes)
+    }
+
+    /// The WAL from the batch holding sequence number `seq + 1` on.
+    ///
+    /// The iterator of `get_updates_since(n)` drops the batches starting at
+    /// or before `n`, so a batch that began earlier and still holds `seq + 1`
+    /// is found by starting further back, doubling the distance each time.
+    fn wal_batches(&self, seq: u64) -> StorageResult<Peekable<DBWALIterator>> {
+        let mut lookback = 0;
+        let mut found = None;
+        loop {
+            let start = seq.saturating_sub(lookback);
+            let mut batches = match self.db.get_updates_since(start) {
+                Ok(batches) => batches.peekable(),
+                // started before the oldest WAL file kept
+                Err(_) if found.is_some() => break,
+                Err(e) => return Err(e.into()),
+            };
+            let skipped = matches!(batches.peek(), Some(Ok((first, _))) if *first > seq + 1);
+            found = Some(batches);
+            if !skipped || start == 0 {
+                break;
+            }
+            lookback = (lookback * 2).max(1);
+        }
+        Ok(found.expect("set before leaving the loop"))
+    }
+}
+
+/// Call `f` with the writes to column family `cf_id` of a serialized write
+/// batch starting at sequence number `seq`.
+fn parse_batch<F: FnMut(Change)>(
+    data: &[u8],
+    seq: u64,
+    cf_id: u32,
+    f: &mut F,
+) -> StorageResult<()> {
+    let mut input = data
+        .get(BATCH_HEADER..)
+        .ok_or_else(|| corrupt("batch too short"))?;
+    let mut seq = seq;
+    while let Some((&tag, rest)) = input.split_first() {
+        input = rest;
+        let cf = match tag {
+            TYPE_CF_DELETION
+            | TYPE_CF_VALUE
+            | TYPE_CF_MERGE
+            | TYPE_CF_SINGLE_DELETION
+            | TYPE_CF_RANGE_DELETION => read_varint(&mut input)?,
+            _ => 0,
+        };
+        let op = match tag {
+            TYPE_VALUE | TYPE_CF_VALUE => {
+                let key = read_slice(&mut input)?;
+                Some(ChangeOp::Put(key, read_slice(&mut input)?))
+            }
+            TYPE_DELETION | TYPE_CF_DELETION | TYPE_SINGLE_DELETION | TYPE_CF_SINGLE_DELETION => {
+                Some(ChangeOp::Delete(read_slice(&mut input)?))
+            }
+            TYPE_RANGE_DELETION | TYPE_CF_RANGE_DELETION => {
+                let start = read_slice(&mut input)?;
+                Some(ChangeOp::DeleteRange(start, read_slice(&mut input)?))
+            }
+            TYPE_MERGE | TYPE_CF_MERGE => {
+                // never written by rsdb, but it still takes a sequence number
+                read_slice(&mut input)?;
+                read_slice(&mut input)?;
+                None
+            }
+            TYPE_LOG_DATA => {
+                read_slice(&mut input)?;
+                continue;
+            }
+            TYPE_NOOP => continue,
+            _ => return Err(corrupt(&format!("unsupported record type {:#x}", tag))),
+        };
+        if let Some(op) = op.filter(|_| cf == cf_id) {
+            f(Change { seq, op });
+        }
+        seq += 1;
+    }
+    Ok(())
+}
+
+fn read_varint(input: &mut &[u8]) -> StorageResult<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let (&byte, rest) = input
+            .split_first()
+            .ok_or_else(|| corrupt("truncated varint"))?;
+        *input = rest;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(corrupt("varint too long"))
+}
+
+fn read_slice(input: &mut &[u8]) -> StorageResult<Vec<u8>> {
+    let len = read_varint(input)? as usize;
+    if input.len() < len {
+        return Err(corrupt("truncated record"));
+    }
+    let (slice, rest) = input.split_at(len);
+    *input = rest;
+    Ok(slice.to_vec())
+}
+
+fn corrupt(msg: &str) -> StorageError {
+    StorageError::InvalidData(format!("write batch in the WAL: {}", msg))
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rocksdb::{Direction, IteratorMode};
+
+use crate::{Change, Durability, StorageError, StorageOptions, StorageResult, Version};
+
+pub type KvPair = (Box<[u8]>, Box<[u8]>);
+pub type KvIterator<'a> = Box<dyn Iterator<Item = StorageResult<KvPair>> + 'a>;
+
+pub enum BatchOp {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+}
+
+/// A key-value store backing one database.
+pub trait KvEngine: Send + Sync {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>>;
+
+    fn set(&self, key: &[u8], value: &[u8]) -> StorageResult<()>;
+
+    fn delete(&self, key: &[u8]) -> StorageResult<()>;
+
+    /// Look up several keys at once, returning the values in key order.
+    fn multi_get(&self, keys: &[Vec<u8>]) -> StorageResult<Vec<Option<Vec<u8>>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    fn exists(&self, key: &[u8]) -> StorageResult<bool> {
+        Ok(self.get(key)?.is_some())
+    }
+
+    /// Apply all operations atomically.
+    fn write_batch(&self, ops: Vec<BatchOp>) -> StorageResult<()> {
+        self.write_batch_with(ops, Durability::default())
+    }
+
+    /// Like `write_batch`, with `durability` added to the defaults from the
+    /// options of the db.
+    fn write_batch_with(&self, ops: Vec<BatchOp>, durability: Durability) -> StorageResult<()>;
+
+    /// Delete all keys in `[start, end)` and return the number of keys seen
+    /// in the range just before deletion, which is approximate if there are
+    /// concurrent writes.
+    fn delete_range(&self, start: &[u8], end: &[u8]) -> StorageResult<u64>;
+
+    fn iterator(&self, mode: IteratorMode) -> StorageResult<KvIterator<'_>>;
+
+    /// A point-in-time view, which keeps the engine alive until dropped.
+    fn snapshot(self: Arc<Self>) -> Box<dyn KvSnapshot>;
+
+    /// The location of the database, `None` when it is not on disk.
+    fn path(&self) -> Option<&str>;
+
+    fn options(&self) -> &StorageOptions;
+
+    /// Whether the db was opened for reading only, failing every write.
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// Catch up with the writes of the primary, for a secondary instance
+    /// following another process. Other engines have nothing to do.
+    fn try_catch_up(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    /// Drop the versions kept past `history_ttl_seconds`, also of keys not
+    /// written since, returning how many. Dbs without history have none.
+    fn prune_history(&self) -> StorageResult<u64> {
+        Ok(0)
+    }
+
+    /// Figures describing the db, such as its estimated number of keys, as
+    /// name and value pairs.
+    fn stats(&self) -> StorageResult<Vec<(String, String)>>;
+
+    /// Add an incremental backup of the db to `backup_dir` and return its id.
+    fn backup(&self, _backup_dir: &str) -> StorageResult<u32> {
+        Err(StorageError::Unsupported("backup".to_string()))
+    }
+
+    /// Create an openable copy of the db in `target_dir`, which must not exist.
+    fn checkpoint(&self, _target_dir: &str) -> StorageResult<()> {
+        Err(StorageError::Unsupported("checkpoint".to_string()))
+    }
+
+    /// The sequence number of the last write, where a change feed starting
+    /// now would resume from.
+    fn latest_sequence(&self) -> StorageResult<u64> {
+        Err(StorageError::Unsupported("change feed".to_string()))
+    }
+
+    /// Up to `limit` committed writes with a sequence number above `seq`,
+    /// oldest first. Writes skipping the WAL are missing, and an error is
+    /// returned once the WAL holding `seq` is gone, see `wal_ttl_seconds`.
+    fn changes_since(&self, _seq: u64, _limit: usize) -> StorageResult<Vec<Change>> {
+        Err(StorageError::Unsupported("change feed".to_string()))
+    }
+
+    /// The value of `key` as of sequence number `seq`, from the versions
+    /// kept with `history_ttl_seconds`. Before the oldest version kept the
+    /// key reads as missing.
+    fn get_at(&self, _key: &[u8], _seq: u64) -> StorageResult<Option<Vec<u8>>> {
+        Err(StorageError::Unsupported("history".to_string()))
+    }
+
+    /// The value of `key` as of `time_ms` milliseconds since the Unix epoch,
+    /// by the time each version was written, as `get_at` otherwise.
+    fn get_at_time(&self, _key: &[u8], _time_ms: u64) -> StorageResult<Option<Vec<u8>>> {
+        Err(StorageError::Unsupported("history".to_string()))
+    }
+
+    /// Up to `limit` of the versions of `key` kept, newest first.
+    fn history(&self, _key: &[u8], _limit: usize) -> StorageResult<Vec<Version>> {
+        Err(StorageError::Unsupported("history".to_string()))
+    }
+
+    /// Add the pairs of sst files from `write_sst_files` atomically, taking
+    /// over the files.
+    fn ingest(&self, _files: &[String]) -> StorageResult<()> {
+        Err(StorageError::Unsupported("ingest".to_string()))
+    }
+
+    /// Rewrite the keys in `[start, end]` down to the last level, dropping
+    /// deleted and overwritten data. `None` leaves that side of the range
+    /// open. Engines without sst files have nothing to do.
+    fn compact(&self, _start: Option<&[u8]>, _end: Option<&[u8]>) -> StorageResult<()> {
+        Ok(())
+    }
+
+    /// Write the memtables out to sst files.
+    fn flush(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    /// Make the writes so far durable, as if the last one had been synced.
+    /// Writes that skipped the WAL are not covered.
+    fn sync_wal(&self) -> StorageResult<()> {
+        Ok(())
+    }
+
+    /// Block until no flush or compaction is queued or running, failing once
+    /// `timeout` has passed, if any.
+    fn wait_for_compact(&self, _timeout: Option<Duration>) -> StorageResult<()> {
+        Ok(())
+    }
+
+    /// Iterate forward over the keys in `[start, end)`.
+    fn scan<'a>(&'a self, start: &[u8], end: &'a [u8]) -> StorageResult<KvIterator<'a>> {
+        let it = self.iterator(IteratorMode::From(start, Direction::Forward))?;
+        Ok(Box::new(it.take_while(move |item| match item {
+            Ok((key, _)) => key.as_ref() < end,
+            Err(_) => true,
+        })))
+    }
+
+    /// Delete all keys starting with `prefix`, see `delete_range`.
+    fn delete_prefix(&self, prefix: &[u8]) -> StorageResult<u64> {
+        if let Some(end) = prefix_end(prefix) {
+            return self.delete_range(prefix, &end);
+        }
+        // every key from `prefix` to the end of the db matches, so the
+        // range ends right after the last key
+        let last = match self.iterator(IteratorMode::End)?.next() {
+            Some(item) => item?.0,
+            None => return Ok(0),
+        };
+        if last.as_ref() < prefix {
+            return Ok(0);
+        }
+        let mut end = last.to_vec();
+        end.push(0);
+        self.delete_range(prefix, &end)
+    }
+}
+
+/// A point-in-time view of a `KvEngine`.
+pub trait KvSnapshot {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>>;
+
+    fn multi_get(&self, keys: &[Vec<u8>]) -> StorageResult<Vec<Option<Vec<u8>>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    fn iterator(&self, mode: IteratorMode) -> StorageResult<KvIterator<'_>>;
+}
+
+/// The smallest key greater than every key starting with `prefix`, or `None`
+/// when no such key exists (empty prefix or all bytes 0xff).
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
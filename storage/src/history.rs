@@ -0,0 +1,348 @@
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rocksdb::{BoundColumnFamily, WriteBatch, WriteOptions};
+
+use crate::{lock, BatchOp, Durability, Storage, StorageError, StorageResult};
+
+/// Column family holding the versions of the values of a db, keyed by the
+/// length of the key, the key and the sequence number of the write, so the
+/// versions of a key are together and oldest first. A version is the time
+/// of the write in milliseconds, whether it is a put, and the value.
+pub(crate) const HISTORY_CF: &str = "history";
+
+/// Sequence number of the version recording the value a key had before
+/// its history was kept.
+const BEFORE_HISTORY: u64 = 0;
+
+/// Versions dropped per batch by `prune_versions`.
+const PRUNE_BATCH: usize = 1000;
+
+const PUT: u8 = 1;
+const DELETE: u8 = 0;
+
+/// A value a key held, `None` once deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Version {
+    /// Sequence number of the write, 0 for the value the key had when its
+    /// history started to be kept.
+    pub seq: u64,
+    /// When the write was made, in milliseconds since the Unix epoch, 0 when
+    /// unknown.
+    pub time_ms: u64,
+    pub value: Option<Vec<u8>>,
+}
+
+/// How a db with `history_ttl_seconds` keeps the versions of its values.
+pub(crate) struct History {
+    ttl_ms: u64,
+    /// Held while writing, so that the sequence numbers the writes get are
+    /// known ahead, each key and range deletion taking the next one.
+    writes: Mutex<()>,
+}
+
+impl History {
+    pub(crate) fn new(ttl_seconds: u64) -> Self {
+        Self {
+            ttl_ms: ttl_seconds.saturating_mul(1000),
+            writes: Mutex::new(()),
+        }
+    }
+}
+
+impl Storage {
+    /// Write `ops` along with a version of each key, as a single batch.
+    pub(crate) fn write_with_history(
+        &self,
+        history: &History,
+        ops: Vec<BatchOp>,
+        opts: &WriteOptions,
+    ) -> StorageResult<()> {
+        let _writing = lock(&history.writes);
+        let first = self.db.latest_sequence_number() + 1;
+        let mut batch = WriteBatch::default();
+        for op in &ops {
+            match op {
+                BatchOp::Put(key, value) => batch.put(key, value),
+                BatchOp::Delete(key) => batch.delete(key),
+            }
+        }
+        for (seq, op) in (first..).zip(ops) {
+            let (key, value) = match op {
+                BatchOp::Put(key, value) => (key, Some(value)),
+                BatchOp::Delete(key) => (key, None),
+            };
+            self.add_version(&mut batch, history, &key, value, seq)?;
+        }
+        self.db.write_opt(batch, opts)?;
+        Ok(())
+    }
+
+    /// Delete the keys in `[start, end)` with a range tombstone, adding a
+    /// deleted version of each of them, and return how many there were.
+    pub(crate) fn delete_range_with_history(
+        &self,
+        history: &History,
+        start: &[u8],
+        end: &[u8],
+        opts: &WriteOptions,
+    ) -> StorageResult<u64> {
+        let _writing = lock(&history.writes);
+        let seq = self.db.latest_sequence_number() + 1;
+        let mut keys = vec![];
+        let mut iter = self.db.raw_iterator();
+        iter.seek(start);
+        while let Some(key) = iter.key() {
+            if key >= end {
+                break;
+            }
+            keys.push(key.to_vec());
+            iter.next();
+        }
+        iter.status()?;
+        drop(iter);
+
+        let mut batch = WriteBatch::default();
+        batch.delete_range(start, end);
+        for key in &keys {
+            self.add_version(&mut batch, history, key, None, seq)?;
+        }
+        self.db.write_opt(batch, opts)?;
+        Ok(keys.len() as u64)
+    }
+
+    /// Drop the versions of every key replaced longer than the ttl ago, but
+    /// for the one in place at that time, as writing the key would. Returns
+    /// how many were dropped.
+    pub(crate) fn prune_versions(&self, history: &History) -> StorageResult<u64> {
+        if history.ttl_ms == 0 {
+            return Ok(0);
+        }
+        let cf = self.history_cf()?;
+        let opts = self.options.write_options(Durability::default())?;
+        let cutoff = now_ms().saturating_sub(history.ttl_ms);
+        let mut pruned = 0;
+        let mut expired = vec![];
+        let mut previous: Option<Vec<u8>> = None;
+        let mut iter = self.db.raw_iterator_cf(&cf);
+        iter.seek_to_first();
+        while let (Some(found), Some(version)) = (iter.key(), iter.value()) {
+            // a version is replaced when the next one of its key is written
+            if let Some(prev) = previous.take() {
+                let prefix = &prev[..prev.len().saturating_sub(8)];
+                if found.starts_with(prefix) && decode(version)?.0 < cutoff {
+                    expired.push(prev);
+                }
+            }
+            if expired.len() >= PRUNE_BATCH {
+                pruned += self.delete_versions(history, &cf, &mut expired, &opts)?;
+            }
+            previous = Some(found.to_vec());
+            iter.next();
+        }
+        iter.status()?;
+        pruned += self.delete_versions(history, &cf, &mut expired, &opts)?;
+        Ok(pruned)
+    }
+
+    fn delete_versions(
+        &self,
+        history: &History,
+        cf: &Arc<BoundColumnFamily<'_>>,
+        keys: &mut Vec<Vec<u8>>,
+        opts: &WriteOptions,
+    ) -> StorageResult<u64> {
+        let count = keys.len() as u64;
+        if count > 0 {
+            let mut batch = WriteBatch::default();
+            for key in keys.drain(..) {
+                batch.delete_cf(cf, key);
+            }
+            // the deletions take sequence numbers, which writes count on
+            let _writing = lock(&history.writes);
+            self.db.write_opt(batch, opts)?;
+        }
+        Ok(count)
+    }
+
+    /// The value of `key` once the writes up to sequence number `seq` were
+    /// made. A key without versions has had its current value all along.
+    pub(crate) fn read_at(&self, key: &[u8], seq: u64) -> StorageResult<Option<Vec<u8>>> {
+        let cf = self.history_cf()?;
+        let snapshot = self.db.snapshot();
+        let prefix = version_prefix(key);
+        let mut iter = snapshot.raw_iterator_cf(&cf);
+        iter.seek_for_prev(version_key(&prefix, seq));
+        if let (Some(found), Some(version)) = (iter.key(), iter.value()) {
+            if found.starts_with(&prefix) {
+                return Ok(decode(version)?.1);
+            }
+        }
+        iter.seek(&prefix);
+        match iter.key() {
+            Some(found) if found.starts_with(&prefix) => Ok(None),
+            _ => {
+                iter.status()?;
+                Ok(snapshot.get(key)?)
+            }
+        }
+    }
+
+    /// The value of `key` once the writes made up to `time_ms` were made,
+    /// going by the times of its versions. A key without versions has had
+    /// its current value all along.
+    pub(crate) fn read_at_time(&self, key: &[u8], time_ms: u64) -> StorageResult<Option<Vec<u8>>> {
+        let cf = self.history_cf()?;
+        let snapshot = self.db.snapshot();
+        let prefix = version_prefix(key);
+        let mut iter = snapshot.raw_iterator_cf(&cf);
+        iter.seek_for_prev(version_key(&prefix, u64::MAX));
+        let mut versions = false;
+        while let (Some(found), Some(version)) = (iter.key(), iter.value()) {
+            if !found.starts_with(&prefix) {
+                break;
+            }
+            let (written_ms, value) = decode(version)?;
+            if written_ms <= time_ms {
+                return Ok(value);
+            }
+            versions = true;
+            iter.prev();
+        }
+        iter.status()?;
+        if versions {
+            Ok(None)
+        } else {
+            Ok(snapshot.get(key)?)
+        }
+    }
+
+    /// Up to `limit` versions of `key`, newest first.
+    pub(crate) fn read_history(&self, key: &[u8], limit: usize) -> StorageResult<Vec<Version>> {
+        let cf = self.history_cf()?;
+        let prefix = version_prefix(key);
+        let mut iter = self.db.raw_iterator_cf(&cf);
+        iter.seek_for_prev(version_key(&prefix, u64::MAX));
+        let mut versions = vec![];
+        while let (Some(found), Some(version)) = (iter.key(), iter.value()) {
+            if versions.len() >= limit || !found.starts_with(&prefix) {
+                break;
+            }
+            let seq = found[prefix.len()..].try_into().map_err(|_| corrupt())?;
+            let (time_ms, value) = decode(version)?;
+            versions.push(Version {
+                seq: u64::from_be_bytes(seq),
+                time_ms,
+                value,
+            });
+            iter.prev();
+        }
+        iter.status()?;
+        Ok(versions)
+    }
+
+    fn history_cf(&self) -> StorageResult<Arc<BoundColumnFamily<'_>>> {
+        self.db.cf_handle(HISTORY_CF).ok_or_else(|| {
+            StorageError::Unsupported("history without `history_ttl_seconds`".to_string())
+        })
+    }
+
+    /// Add the version of `key` written with `seq` to `batch`, after the
+    /// value it had if its history starts now. Versions replaced longer than
+    /// the ttl ago are dropped, but for the one in place at that time.
+    fn add_version(
+        &self,
+        batch: &mut WriteBatch,
+        history: &History,
+        key: &[u8],
+        value: Option<Vec<u8>>,
+        seq: u64,
+    ) -> StorageResult<()> {
+        let cf = self.history_cf()?;
+        let now = now_ms();
+        let prefix = version_prefix(key);
+        let mut versions = vec![];
+        let mut iter = self.db.raw_iterator_cf(&cf);
+        iter.seek(&prefix);
+        while let (Some(found), Some(version)) = (iter.key(), iter.value()) {
+            if !found.starts_with(&prefix) {
+                break;
+            }
+            versions.push((found.to_vec(), decode(version)?.0));
+            iter.next();
+        }
+        iter.status()?;
+
+        if versions.is_empty() {
+            let before = self.db.get(key)?;
+            if before.is_none() && value.is_none() {
+                return Ok(());
+            }
+            if let Some(before) = before {
+                batch.put_cf(
+                    &cf,
+                    version_key(&prefix, BEFORE_HISTORY),
+                    encode(0, Some(&before)),
+                );
+            }
+        } else if history.ttl_ms > 0 {
+            let cutoff = now.saturating_sub(history.ttl_ms);
+            if let Some(kept) = versions.iter().rposition(|(_, time)| *time < cutoff) {
+                for (expired, _) in &versions[..kept] {
+                    batch.delete_cf(&cf, expired);
+                }
+            }
+        }
+        batch.put_cf(
+            &cf,
+            version_key(&prefix, seq),
+            encode(now, value.as_deref()),
+        );
+        Ok(())
+    }
+}
+
+fn version_prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = (key.len() as u32).to_be_bytes().to_vec();
+    prefix.extend_from_slice(key);
+    prefix
+}
+
+fn version_key(prefix: &[u8], seq: u64) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    key.extend_from_slice(&seq.to_be_bytes());
+    key
+}
+
+fn encode(time_ms: u64, value: Option<&[u8]>) -> Vec<u8> {
+    let mut version = time_ms.to_be_bytes().to_vec();
+    match value {
+        Some(value) => {
+            version.push(PUT);
+            version.extend_from_slice(value);
+        }
+        None => version.push(DELETE),
+    }
+    version
+}
+
+/// The time and the value of a version.
+fn decode(version: &[u8]) -> StorageResult<(u64, Option<Vec<u8>>)> {
+    let (time_ms, rest) = version.split_first_chunk::<8>().ok_or_else(corrupt)?;
+    let value = match rest.split_first() {
+        Some((&PUT, value)) => Some(value.to_vec()),
+        Some((&DELETE, _)) => None,
+        _ => return Err(corrupt()),
+    };
+    Ok((u64::from_be_bytes(*time_ms), value))
+}
+
+fn corrupt() -> StorageError {
+    StorageError::InvalidData("corrupt value version".to_string())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::Path;
+
+use rocksdb::{IngestExternalFileOptions, SstFileWriter};
+
+use crate::{Storage, StorageError, StorageOptions, StorageResult};
+
+/// Write `pairs` into sst files for `KvEngine::ingest`, named `000001.sst`,
+/// `000002.sst`, ... in `dir`, starting a new file once one holds
+/// `file_size` bytes. Returns the paths of the files in key order.
+///
+/// Files left in `dir` by an earlier run are never overwritten.
+///
+/// Keys must be strictly ascending across all pairs, which also keeps the
+/// files from overlapping. `options` should match those of the target db so
+/// the files need no rewriting, e.g. for compression.
+pub fn write_sst_files<I>(
+    dir: &str,
+    options: &StorageOptions,
+    file_size: u64,
+    pairs: I,
+) -> StorageResult<Vec<String>>
+where
+    I: IntoIterator<Item = StorageResult<(Vec<u8>, Vec<u8>)>>,
+{
+    fs::create_dir_all(dir)?;
+    let opts = options.to_rocksdb();
+    let mut files = vec![];
+    let mut writer: Option<SstFileWriter> = None;
+    let mut last_key: Option<Vec<u8>> = None;
+    for pair in pairs {
+        let (key, value) = pair?;
+        if let Some(last_key) = last_key.as_ref() {
+            if key <= *last_key {
+                return Err(StorageError::InvalidData(format!(
+                    "key {:?} is not after {:?}",
+                    String::from_utf8_lossy(&key),
+                    String::from_utf8_lossy(last_key)
+                )));
+            }
+        }
+        let sst = match writer.as_mut() {
+            Some(sst) => sst,
+            None => {
+                let path = Path::new(dir).join(format!("{:06}.sst", files.len() + 1));
+                if path.exists() {
+                    let msg = format!("`{}` already exists", path.display());
+                    return Err(StorageError::InvalidData(msg));
+                }
+                let sst = SstFileWriter::create(&opts);
+                sst.open(&path)?;
+                files.push(path.to_string_lossy().into_owned());
+                writer.insert(sst)
+            }
+        };
+        sst.put(&key, value)?;
+        if sst.file_size() >= file_size {
+            sst.finish()?;
+            writer = None;
+        }
+        last_key = Some(key);
+    }
+    if let Some(mut sst) = writer {
+        sst.finish()?;
+    }
+    Ok(files)
+}
+
+impl Storage {
+    /// Ingest sst files built by `write_sst_files` in one atomic step. The
+    /// files are moved into the db, or copied when on another filesystem.
+    pub(crate) fn ingest_files(&self, files: &[String]) -> StorageResult<()> {
+        if files.is_empty() {
+            return Err(StorageError::InvalidData("no files to ingest".to_string()));
+        }
+        for file in files {
+            if !Path::new(file).is_file() {
+                return Err(StorageError::InvalidData(format!("no sst file `{}`", file)));
+            }
+        }
+        let mut opts = IngestExternalFileOptions::default();
+        opts.set_move_files(true);
+        match self.cf_handle()? {
+            Some(cf) => self
+                .db
+                .ingest_external_file_cf_opts(&cf, &opts, files.to_vec())?,
+            None => self.db.ingest_external_file_opts(&opts, files.to_vec())?,
+        }
+        Ok(())
+    }
+}
@@ -1,25 +1,113 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::Display;
+use std::fs;
 use std::io::Error as IOError;
-use std::mem::drop;
-use std::sync::Arc;
+use std::mem::{drop, transmute};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use std::time::{Duration, Instant};
 
 extern crate rocksdb;
 extern crate tempfile;
 
+use rocksdb::{
+    BottommostLevelCompaction, BoundColumnFamily, ColumnFamilyDescriptor, CompactOptions,
+    DBWithThreadMode, Error as DBError, MultiThreaded, Options, SnapshotWithThreadMode,
+    WaitForCompactOptions, WriteBatch,
+};
 pub use rocksdb::{Direction, IteratorMode};
-use rocksdb::{Error as DBError, Options, DB};
 
+type RocksDB = DBWithThreadMode<MultiThreaded>;
+
+/// Directory under the root holding the shared instance in column family mode.
+const SHARED_DB_DIR: &str = ".shared";
+
+/// Column family every RocksDB instance has, which holds no database.
+const DEFAULT_CF: &str = "default";
+
+/// Longest accepted database name, in bytes.
+pub const MAX_NAME_LEN: usize = 64;
+
+mod backup;
+pub use backup::{list_backups, purge_backups, restore_backup, BackupInfo};
+
+mod catalog;
+use catalog::Catalog;
+pub use catalog::{CatalogEntry, DbState};
+
+mod changes;
+pub use changes::{Change, ChangeOp};
+
+mod engine;
+pub use engine::{BatchOp, KvEngine, KvIterator, KvPair, KvSnapshot};
+
+mod history;
+pub use history::Version;
+use history::{History, HISTORY_CF};
+
+mod ingest;
+pub use ingest::write_sst_files;
+
+mod memory;
+pub use memory::MemoryStorage;
+
+mod pool;
+use pool::Pool;
+pub use pool::PoolStats;
+
+mod stats;
+
+mod options;
+pub use options::{load_profiles, Durability, StorageOptions, DEFAULT_PROFILE};
+
+/// How `MultiDB` stores its databases.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EngineKind {
+    /// A RocksDB instance per database under the root path.
+    RocksDB,
+    /// Column families of one RocksDB instance, sharing the WAL, memtable
+    /// budget and background threads.
+    ColumnFamilies,
+    /// In-memory maps, lost when detached.
+    Memory,
+}
+
+/// The databases under a root path, safe to share between threads.
+///
+/// Databases are opened outside of any lock shared by all names, so a slow
+/// open only holds up callers of the same name. Poisoned locks are taken
+/// over, as every update leaves the state consistent.
 pub struct MultiDB {
-    storage: HashMap<String, Arc<Storage>>,
+    storage: RwLock<HashMap<String, Arc<dyn KvEngine>>>,
+    opening: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    /// Databases evicted from the pool and not yet closed.
+    closing: Mutex<HashSet<String>>,
     root_path: String,
+    profiles: HashMap<String, StorageOptions>,
+    engine: EngineKind,
+    shared: Mutex<Option<Arc<RocksDB>>>,
+    catalog: Mutex<Catalog>,
+    pool: Mutex<Pool>,
+    read_only: AtomicBool,
+    /// Root of the databases followed as secondary instances, if any.
+    primary_root: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum StorageError {
     DbErr(DBError),
     IoErr(IOError),
+    DbExists(String),
+    InvalidOptions(String),
+    DbNotFound(String),
+    DbInUse(String),
+    Unsupported(String),
+    InvalidName(String),
+    InvalidCatalog(String),
+    InvalidData(String),
 }
 
 impl Error for StorageError {}
@@ -29,6 +117,14 @@ impl Display for StorageError {
         match self {
             StorageError::DbErr(e) => write!(f, "DBError: {}", e),
             StorageError::IoErr(e) => write!(f, "IOError: {}", e),
+            StorageError::DbExists(name) => write!(f, "database `{}` already exists", name),
+            StorageError::InvalidOptions(msg) => write!(f, "invalid options: {}", msg),
+            StorageError::DbNotFound(name) => write!(f, "database `{}` not found", name),
+            StorageError::DbInUse(name) => write!(f, "database `{}` is in use", name),
+            StorageError::Unsupported(what) => write!(f, "{} is not supported", what),
+            StorageError::InvalidName(msg) => write!(f, "invalid database name: {}", msg),
+            StorageError::InvalidCatalog(msg) => write!(f, "invalid catalog: {}", msg),
+            StorageError::InvalidData(msg) => write!(f, "invalid data: {}", msg),
         }
     }
 }
@@ -49,92 +145,1097 @@ pub type StorageResult<T> = Result<T, StorageError>;
 
 impl MultiDB {
     pub fn new(root_path: &str) -> Self {
+        Self::with_engine(root_path, EngineKind::RocksDB)
+    }
+
+    pub fn with_engine(root_path: &str, engine: EngineKind) -> Self {
         Self {
-            storage: HashMap::new(),
+            storage: RwLock::new(HashMap::new()),
+            opening: Mutex::new(HashMap::new()),
+            closing: Mutex::new(HashSet::new()),
             root_path: root_path.to_string(),
+            profiles: HashMap::new(),
+            engine,
+            shared: Mutex::new(None),
+            catalog: Mutex::new(Catalog::default()),
+            pool: Mutex::new(Pool::default()),
+            read_only: AtomicBool::new(false),
+            primary_root: None,
         }
     }
 
-    pub fn get_db(&self, name: &str) -> Option<Arc<Storage>> {
-        let s = self.storage.get(name);
-        if let Some(s) = s {
-            Some(s.clone())
-        } else {
-            None
+    /// Follow the databases under `primary_root`, written by another
+    /// process, as RocksDB secondary instances: each is opened read-only on
+    /// the files of the primary, and sees its writes once caught up with
+    /// `catch_up`. The root path of this `MultiDB` keeps their own logs.
+    pub fn follow(&mut self, primary_root: &str) -> StorageResult<()> {
+        if self.engine != EngineKind::RocksDB {
+            return Err(StorageError::Unsupported(format!(
+                "secondary instances with {:?} engine",
+                self.engine
+            )));
         }
+        self.primary_root = Some(primary_root.to_string());
+        Ok(())
+    }
+
+    pub fn root_path(&self) -> &str {
+        &self.root_path
     }
 
-    pub fn attach(&mut self, name: &str) -> StorageResult<()> {
-        let s_opt = self.get_db(name);
-        if let Some(_s) = s_opt {
-            return Ok(());
+    /// The root of the databases followed, see `follow`.
+    pub fn primary(&self) -> Option<&str> {
+        self.primary_root.as_deref()
+    }
+
+    /// Catch the open databases up with the primary, returning those that
+    /// failed to. Closed ones catch up when reopened.
+    pub fn catch_up(&self) -> Vec<(String, StorageError)> {
+        let open: Vec<(String, Arc<dyn KvEngine>)> = read(&self.storage)
+            .iter()
+            .map(|(name, s)| (name.clone(), s.clone()))
+            .collect();
+        let mut failed = vec![];
+        for (name, s) in open {
+            if let Err(e) = s.try_catch_up() {
+                failed.push((name, e));
+            }
         }
-        let db_path = format!("{}/{}", self.root_path, name);
-        let storage = Storage::new(&db_path)?;
-        self.storage.insert(name.to_string(), Arc::new(storage));
-        Ok(())
+        failed
+    }
+
+    /// Drop the expired versions of the open databases keeping history,
+    /// returning those which failed. Detached or evicted ones are pruned as
+    /// their keys are written.
+    pub fn prune_history(&self) -> Vec<(String, StorageError)> {
+        let open: Vec<(String, Arc<dyn KvEngine>)> = read(&self.storage)
+            .iter()
+            .map(|(name, s)| (name.clone(), s.clone()))
+            .collect();
+        let mut failed = vec![];
+        for (name, s) in open {
+            if let Err(e) = s.prune_history() {
+                failed.push((name, e));
+            }
+        }
+        failed
+    }
+
+    /// Turn the server-wide maintenance switch on or off. While on, the
+    /// server refuses the writes of clients and creates no databases for
+    /// them, though the databases stay open read-write for replication to
+    /// go on.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Limit the number of open databases, closing the least recently used
+    /// idle ones beyond it. They stay attached and are reopened when next
+    /// attached. Ignored by the memory engine, which would lose their data.
+    pub fn set_max_open(&mut self, max_open: Option<usize>) {
+        if self.engine != EngineKind::Memory {
+            lock(&self.pool).set_max_open(max_open);
+        }
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        let open = read(&self.storage).len();
+        lock(&self.pool).stats(open)
+    }
+
+    /// Load the catalog kept in the root path and track databases in it
+    /// from now on, then reattach the databases that were attached when the
+    /// catalog was last saved.
+    ///
+    /// Databases found on disk but missing from the catalog are added as
+    /// detached, and entries whose data is gone are dropped. Returns the
+    /// databases that failed to reattach. The memory engine has no catalog.
+    pub fn open_catalog(&self) -> StorageResult<Vec<(String, StorageError)>> {
+        if self.engine == EngineKind::Memory {
+            return Ok(vec![]);
+        }
+        let mut catalog = Catalog::load(&self.root_path)?;
+        catalog.sync(self.databases_on_disk()?)?;
+        let attached: Vec<String> = catalog
+            .entries()
+            .filter(|entry| entry.state == DbState::Attached)
+            .map(|entry| entry.name.clone())
+            .collect();
+        *lock(&self.catalog) = catalog;
+
+        let mut failed = vec![];
+        for name in attached {
+            if let Err(e) = self.attach(&name) {
+                failed.push((name, e));
+            }
+        }
+        Ok(failed)
+    }
+
+    /// All databases known to the catalog, attached or not.
+    pub fn catalog(&self) -> Vec<CatalogEntry> {
+        match self.engine {
+            EngineKind::Memory => read(&self.storage)
+                .iter()
+                .map(|(name, s)| CatalogEntry {
+                    name: name.clone(),
+                    state: DbState::Attached,
+                    read_only: false,
+                    options: s.options().clone(),
+                })
+                .collect(),
+            _ => lock(&self.catalog).entries().cloned().collect(),
+        }
+    }
+
+    /// Set the option profiles used when a database without persisted
+    /// options is opened, keyed by database name or `DEFAULT_PROFILE`.
+    pub fn set_profiles(&mut self, profiles: HashMap<String, StorageOptions>) {
+        self.profiles = profiles;
+    }
+
+    pub fn get_db(&self, name: &str) -> Option<Arc<dyn KvEngine>> {
+        read(&self.storage).get(name).cloned()
     }
 
-    pub fn detach(&mut self, name: &str) {
-        let s_opt = self.storage.remove(name);
-        if let Some(s) = s_opt {
+    /// Open the database `name`, creating it if missing, and return it.
+    pub fn attach(&self, name: &str) -> StorageResult<Arc<dyn KvEngine>> {
+        if let Some(s) = self.reuse(name) {
+            return Ok(s);
+        }
+        self.with_name_lock(name, || match self.reuse(name) {
+            Some(s) => Ok(s),
+            None => self.open_db(name),
+        })
+    }
+
+    /// Like `attach`, but fails with `DbNotFound` when the database is
+    /// neither open nor in the catalog, instead of creating it.
+    pub fn attach_existing(&self, name: &str) -> StorageResult<Arc<dyn KvEngine>> {
+        if let Some(s) = self.reuse(name) {
+            return Ok(s);
+        }
+        self.with_name_lock(name, || {
+            if let Some(s) = self.reuse(name) {
+                return Ok(s);
+            }
+            let known = self.engine != EngineKind::Memory
+                && lock(&self.catalog)
+                    .entries()
+                    .any(|entry| entry.name == name);
+            if !known {
+                return Err(StorageError::DbNotFound(name.to_string()));
+            }
+            self.open_db(name)
+        })
+    }
+
+    /// Open the existing database `name` for reading only, on data as of
+    /// now, and return it. It stays read-only when reopened, until detached.
+    /// Fails if the database is already open for writing.
+    ///
+    /// Only databases with an instance of their own can be read-only.
+    pub fn attach_read_only(&self, name: &str) -> StorageResult<Arc<dyn KvEngine>> {
+        if self.primary_root.is_some() {
+            return self.attach_existing(name);
+        }
+        if self.engine != EngineKind::RocksDB {
+            return Err(StorageError::Unsupported(format!(
+                "read-only databases with {:?} engine",
+                self.engine
+            )));
+        }
+        self.with_name_lock(name, || {
+            if let Some(s) = self.get_db(name) {
+                if !s.read_only() {
+                    return Err(StorageError::InvalidOptions(format!(
+                        "database `{}` is already open for writing",
+                        name
+                    )));
+                }
+                self.reuse(name);
+                return Ok(s);
+            }
+            let db_path = self.db_path(name)?;
+            if !Path::new(&db_path).join("CURRENT").exists() {
+                return Err(StorageError::DbNotFound(name.to_string()));
+            }
+            self.opened(name, Arc::new(Storage::open_read_only(&db_path)?))
+        })
+    }
+
+    /// Attach a database with explicit options, which replace the persisted
+    /// ones. Fails if the database is already open with other options.
+    ///
+    /// In column family mode the options of an existing database are fixed.
+    pub fn attach_with_options(
+        &self,
+        name: &str,
+        options: StorageOptions,
+    ) -> StorageResult<Arc<dyn KvEngine>> {
+        if self.primary_root.is_some() {
+            return Err(StorageError::Unsupported(
+                "options of a secondary instance".to_string(),
+            ));
+        }
+        self.with_name_lock(name, || {
+            if let Some(s) = self.get_db(name) {
+                if *s.options() != options {
+                    return Err(StorageError::InvalidOptions(format!(
+                        "database `{}` is already open with other options",
+                        name
+                    )));
+                }
+                self.reuse(name);
+                return Ok(s);
+            }
+            let db_path = self.db_path(name)?;
+            let storage: Arc<dyn KvEngine> = match self.engine {
+                EngineKind::RocksDB => Arc::new(Storage::open(&db_path, options)?),
+                EngineKind::ColumnFamilies => {
+                    let shared = self.shared_db()?;
+                    if shared.cf_handle(name).is_some() {
+                        let current = StorageOptions::load_cf(&self.shared_path(), name)?;
+                        if current.unwrap_or_default() != options {
+                            return Err(StorageError::InvalidOptions(format!(
+                                "column family `{}` already exists with other options",
+                                name
+                            )));
+                        }
+                    }
+                    Arc::new(Storage::open_cf(shared, &self.root_path, name, options)?)
+                }
+                EngineKind::Memory => Arc::new(MemoryStorage::new(options)),
+            };
+            self.opened(name, storage)
+        })
+    }
+
+    /// Restore a backup as the new database `name` and attach it.
+    pub fn restore(
+        &self,
+        backup_dir: &str,
+        backup_id: Option<u32>,
+        name: &str,
+    ) -> StorageResult<()> {
+        if self.engine != EngineKind::RocksDB {
+            return Err(StorageError::Unsupported(format!(
+                "restore with {:?} engine",
+                self.engine
+            )));
+        }
+        self.with_name_lock(name, || {
+            let db_path = self.db_path(name)?;
+            if self.get_db(name).is_some() || Path::new(&db_path).exists() {
+                return Err(StorageError::DbExists(name.to_string()));
+            }
+            restore_backup(backup_dir, backup_id, &db_path)?;
+            self.open_db(name)?;
+            Ok(())
+        })
+    }
+
+    pub fn detach(&self, name: &str) -> StorageResult<()> {
+        self.with_name_lock(name, || {
+            let s_opt = write(&self.storage).remove(name);
+            if let Some(s) = s_opt {
+                drop(s);
+            }
+            lock(&self.pool).closed(name);
+            lock(&self.catalog).detach(name)
+        })
+    }
+
+    /// Detach the database `name` and delete its data.
+    ///
+    /// Waits up to `timeout` for other users of the database to let go of it,
+    /// and leaves it attached if they don't.
+    pub fn drop_db(&self, name: &str, timeout: Duration) -> StorageResult<()> {
+        let db_path = self.db_path(name)?;
+        self.with_name_lock(name, || {
+            if self.close_unused(name, timeout)? && self.engine == EngineKind::Memory {
+                return Ok(());
+            }
+
+            match self.engine {
+                EngineKind::RocksDB => {
+                    if !Path::new(&db_path).exists() {
+                        return Err(StorageError::DbNotFound(name.to_string()));
+                    }
+                    RocksDB::destroy(&Options::default(), &db_path)?;
+                    fs::remove_dir_all(&db_path)?;
+                }
+                EngineKind::ColumnFamilies => {
+                    let shared = self.shared_db()?;
+                    if shared.cf_handle(name).is_none() {
+                        return Err(StorageError::DbNotFound(name.to_string()));
+                    }
+                    shared.drop_cf(name)?;
+                    StorageOptions::remove_cf(&self.shared_path(), name)?;
+                }
+                EngineKind::Memory => return Err(StorageError::DbNotFound(name.to_string())),
+            }
+            lock(&self.pool).closed(name);
+            lock(&self.catalog).remove(name)
+        })
+    }
+
+    /// Replace the database `name` with the checkpoint in `checkpoint_dir`,
+    /// which is moved into place, and attach it. Waits for other users of
+    /// the current database like `drop_db`.
+    pub fn install(
+        &self,
+        name: &str,
+        checkpoint_dir: &str,
+        timeout: Duration,
+    ) -> StorageResult<()> {
+        if self.engine != EngineKind::RocksDB {
+            return Err(StorageError::Unsupported(format!(
+                "install with {:?} engine",
+                self.engine
+            )));
+        }
+        let db_path = self.db_path(name)?;
+        self.with_name_lock(name, || {
+            self.close_unused(name, timeout)?;
+            if Path::new(&db_path).exists() {
+                RocksDB::destroy(&Options::default(), &db_path)?;
+                fs::remove_dir_all(&db_path)?;
+            }
+            fs::rename(checkpoint_dir, &db_path)?;
+            self.open_db(name)?;
+            Ok(())
+        })
+    }
+
+    pub fn list_db(&self) -> Vec<String> {
+        read(&self.storage).keys().cloned().collect()
+    }
+
+    /// Run `f` holding the lock for `name`, so that each database is opened
+    /// or closed by one caller at a time while other names go ahead.
+    fn with_name_lock<T, F: FnOnce() -> T>(&self, name: &str, f: F) -> T {
+        let slot = lock(&self.opening)
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+        let result = {
+            let _guard = lock(&slot);
+            f()
+        };
+        let mut opening = lock(&self.opening);
+        // the map and `slot` are the only holders when nobody is waiting
+        if Arc::strong_count(&slot) == 2 {
+            opening.remove(name);
+        }
+        result
+    }
+
+    /// Close the database `name` once nobody else holds it, waiting up to
+    /// `timeout`, and return whether it was open. Call with its name lock.
+    fn close_unused(&self, name: &str, timeout: Duration) -> StorageResult<bool> {
+        let s = match write(&self.storage).remove(name) {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+        let deadline = Instant::now() + timeout;
+        while Arc::strong_count(&s) > 1 {
+            if Instant::now() >= deadline {
+                write(&self.storage).insert(name.to_string(), s);
+                return Err(StorageError::DbInUse(name.to_string()));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        drop(s);
+        lock(&self.pool).closed(name);
+        Ok(true)
+    }
+
+    /// Mark the database `name` as used and return it, if it is open.
+    fn reuse(&self, name: &str) -> Option<Arc<dyn KvEngine>> {
+        let mut storage = write(&self.storage);
+        let s = storage.get(name)?.clone();
+        let mut pool = lock(&self.pool);
+        pool.touch(name);
+        let evicted = pool.evict(&mut storage, name);
+        self.close_evicted(evicted, (pool, storage));
+        Some(s)
+    }
+
+    /// Close the databases evicted from the pool once `guards` are
+    /// released, as closing one may flush its memtables and other databases
+    /// must stay usable meanwhile.
+    fn close_evicted<G>(&self, evicted: Vec<(String, Arc<dyn KvEngine>)>, guards: G) {
+        lock(&self.closing).extend(evicted.iter().map(|(name, _)| name.clone()));
+        drop(guards);
+        for (name, s) in evicted {
             drop(s);
+            lock(&self.closing).remove(&name);
+        }
+    }
+
+    /// Open `name` with its persisted options or profile, holding its lock.
+    fn open_db(&self, name: &str) -> StorageResult<Arc<dyn KvEngine>> {
+        // an evicted instance may still hold the files of the database
+        while lock(&self.closing).contains(name) {
+            thread::sleep(Duration::from_millis(10));
         }
+        let db_path = self.db_path(name)?;
+        let storage: Arc<dyn KvEngine> = match self.engine {
+            EngineKind::RocksDB if self.primary_root.is_some() => {
+                let primary_path = self.primary_path(name);
+                if !Path::new(&primary_path).join("CURRENT").exists() {
+                    return Err(StorageError::DbNotFound(name.to_string()));
+                }
+                Arc::new(Storage::open_secondary(&primary_path, &db_path)?)
+            }
+            EngineKind::RocksDB if self.attached_read_only(name) => {
+                Arc::new(Storage::open_read_only(&db_path)?)
+            }
+            EngineKind::RocksDB => {
+                let options = match StorageOptions::load(&db_path)? {
+                    Some(options) => options,
+                    None => self.profile(name),
+                };
+                Arc::new(Storage::open(&db_path, options)?)
+            }
+            EngineKind::ColumnFamilies => {
+                let shared = self.shared_db()?;
+                let options = match StorageOptions::load_cf(&self.shared_path(), name)? {
+                    Some(options) => options,
+                    None => self.profile(name),
+                };
+                Arc::new(Storage::open_cf(shared, &self.root_path, name, options)?)
+            }
+            EngineKind::Memory => Arc::new(MemoryStorage::new(self.profile(name))),
+        };
+        self.opened(name, storage)
+    }
+
+    fn primary_path(&self, name: &str) -> String {
+        format!(
+            "{}/{}",
+            self.primary_root.as_deref().unwrap_or_default(),
+            name
+        )
+    }
+
+    /// Whether `name` was last attached read-only, so it is reopened so
+    /// after being closed as idle or on restart.
+    fn attached_read_only(&self, name: &str) -> bool {
+        lock(&self.catalog)
+            .entries()
+            .any(|entry| entry.name == name && entry.state == DbState::Attached && entry.read_only)
     }
 
-    pub fn list_db(&self) -> Vec<&[u8]> {
-        self.storage.iter().map(|(k, _v)| k.as_bytes()).collect()
+    fn profile(&self, name: &str) -> StorageOptions {
+        self.profiles
+            .get(name)
+            .or_else(|| self.profiles.get(DEFAULT_PROFILE))
+            .cloned()
+            .unwrap_or_default()
     }
+
+    /// The directory of the database `name`, after checking that the name is
+    /// valid and that the directory does not lead outside the root, e.g.
+    /// through a symlink.
+    fn db_path(&self, name: &str) -> StorageResult<String> {
+        validate_name(name)?;
+        let db_path = format!("{}/{}", self.root_path, name);
+        let path = Path::new(&db_path);
+        if path.exists() {
+            let root = fs::canonicalize(&self.root_path)?;
+            if !fs::canonicalize(path)?.starts_with(&root) {
+                return Err(StorageError::InvalidName(format!(
+                    "`{}` resolves outside the storage root",
+                    name
+                )));
+            }
+        }
+        Ok(db_path)
+    }
+
+    fn opened(&self, name: &str, storage: Arc<dyn KvEngine>) -> StorageResult<Arc<dyn KvEngine>> {
+        // secondary instances are read-only whichever way they are attached
+        let read_only = storage.read_only() && self.primary_root.is_none();
+        lock(&self.catalog).attach(name, storage.options(), read_only)?;
+        let mut open = write(&self.storage);
+        open.insert(name.to_string(), storage.clone());
+        let mut pool = lock(&self.pool);
+        pool.opened(name);
+        let evicted = pool.evict(&mut open, name);
+        self.close_evicted(evicted, (pool, open));
+        Ok(storage)
+    }
+
+    /// The databases with data under the root path, or the primary root
+    /// when following one, and their persisted options.
+    fn databases_on_disk(&self) -> StorageResult<Vec<(String, StorageOptions)>> {
+        let mut found = vec![];
+        match self.engine {
+            EngineKind::RocksDB => {
+                let root = self.primary_root.as_ref().unwrap_or(&self.root_path);
+                if !Path::new(root).exists() {
+                    return Ok(found);
+                }
+                for entry in fs::read_dir(root)? {
+                    let path = entry?.path();
+                    let name = match path.file_name().and_then(|name| name.to_str()) {
+                        Some(name) if validate_name(name).is_ok() => name.to_string(),
+                        _ => continue,
+                    };
+                    if path.is_dir() && path.join("CURRENT").exists() {
+                        let db_path = match self.primary_root {
+                            Some(_) => self.primary_path(&name),
+                            None => self.db_path(&name)?,
+                        };
+                        let options = StorageOptions::load(&db_path)?;
+                        found.push((name, options.unwrap_or_default()));
+                    }
+                }
+            }
+            EngineKind::ColumnFamilies => {
+                let path = self.shared_path();
+                if !Path::new(&path).exists() {
+                    return Ok(found);
+                }
+                for name in RocksDB::list_cf(&Options::default(), &path)? {
+                    if name == DEFAULT_CF {
+                        continue;
+                    }
+                    let options = StorageOptions::load_cf(&path, &name)?;
+                    found.push((name, options.unwrap_or_default()));
+                }
+            }
+            EngineKind::Memory => {}
+        }
+        Ok(found)
+    }
+
+    fn shared_path(&self) -> String {
+        format!("{}/{}", self.root_path, SHARED_DB_DIR)
+    }
+
+    /// The instance holding the column families, opened on first use so the
+    /// profiles are in place.
+    fn shared_db(&self) -> StorageResult<Arc<RocksDB>> {
+        let mut guard = lock(&self.shared);
+        if let Some(shared) = guard.as_ref() {
+            return Ok(shared.clone());
+        }
+
+        let path = self.shared_path();
+        let mut opts = self.profile(DEFAULT_PROFILE).to_rocksdb();
+        opts.create_missing_column_families(true);
+        let names = if Path::new(&path).exists() {
+            RocksDB::list_cf(&opts, &path)?
+        } else {
+            vec![]
+        };
+        let mut cfs = vec![];
+        for name in names {
+            let options = StorageOptions::load_cf(&path, &name)?.unwrap_or_default();
+            cfs.push(ColumnFamilyDescriptor::new(name, options.to_rocksdb()));
+        }
+        let shared = Arc::new(RocksDB::open_cf_descriptors(&opts, &path, cfs)?);
+        *guard = Some(shared.clone());
+        Ok(shared)
+    }
+}
+
+/// The column families of the db at `path`, which must all be opened, and
+/// the one keeping its history if asked for.
+fn column_families(path: &str, history: bool) -> StorageResult<Vec<String>> {
+    let mut cfs = if Path::new(path).join("CURRENT").exists() {
+        RocksDB::list_cf(&Options::default(), path)?
+    } else {
+        vec![DEFAULT_CF.to_string()]
+    };
+    if history && !cfs.iter().any(|cf| cf == HISTORY_CF) {
+        cfs.push(HISTORY_CF.to_string());
+    }
+    Ok(cfs)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
 }
 
+fn read<T>(rwlock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    rwlock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(rwlock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    rwlock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Check that `name` can be used as a database name: 1 to `MAX_NAME_LEN`
+/// ASCII letters, digits, `_`, `-` or `.`, not starting with `-` or `.`.
+pub fn validate_name(name: &str) -> StorageResult<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(StorageError::InvalidName(format!(
+            "length must be 1 to {} bytes",
+            MAX_NAME_LEN
+        )));
+    }
+    if name.starts_with(['-', '.']) {
+        return Err(StorageError::InvalidName(format!(
+            "`{}` must not start with `-` or `.`",
+            name.escape_default()
+        )));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '_' | '-' | '.'))
+    {
+        return Err(StorageError::InvalidName(format!(
+            "`{}` contains `{}`",
+            name.escape_default(),
+            c.escape_default()
+        )));
+    }
+    Ok(())
+}
+
+/// The RocksDB `KvEngine`, owning an instance or one column family of a
+/// shared instance.
 pub struct Storage {
-    pub db: DB,
+    db: Arc<RocksDB>,
+    cf: Option<String>,
+    read_only: bool,
+    secondary: bool,
+    history: Option<History>,
     pub path: Option<String>,
     pub temp: bool,
+    pub options: StorageOptions,
 }
 
 impl Storage {
+    /// Open the db at `path` with its persisted options, if any.
     pub fn new(path: &str) -> StorageResult<Self> {
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        let db = DB::open(&opts, path)?;
+        let options = StorageOptions::load(path)?.unwrap_or_default();
+        Self::open(path, options)
+    }
+
+    /// Open the db at `path` with `options` and persist them next to the db.
+    pub fn open(path: &str, options: StorageOptions) -> StorageResult<Self> {
+        let history = options.history_ttl_seconds.map(History::new);
+        let mut opts = options.to_rocksdb();
+        opts.create_missing_column_families(true);
+        let cfs = column_families(path, history.is_some())?;
+        // the versions would go stale with the writes made without it
+        if history.is_none() && cfs.iter().any(|cf| cf == HISTORY_CF) {
+            return Err(StorageError::InvalidOptions(
+                "the db keeps history, open it with `history_ttl_seconds`".to_string(),
+            ));
+        }
+        let db = RocksDB::open_cf(&opts, path, cfs)?;
+        options.save(path)?;
         Ok(Self {
-            db,
+            db: Arc::new(db),
+            cf: None,
+            read_only: false,
+            secondary: false,
+            history,
+            path: Some(path.to_string()),
+            temp: false,
+            options,
+        })
+    }
+
+    /// Open the db at `path` for reading only, with its persisted options.
+    /// Writes made elsewhere after opening are not seen, and the db is left
+    /// as it is, files and options alike.
+    pub fn open_read_only(path: &str) -> StorageResult<Self> {
+        let options = StorageOptions::load(path)?.unwrap_or_default();
+        let cfs = column_families(path, false)?;
+        let db = RocksDB::open_cf_for_read_only(&options.to_rocksdb(), path, cfs, false)?;
+        Ok(Self {
+            db: Arc::new(db),
+            cf: None,
+            read_only: true,
+            secondary: false,
+            history: None,
+            path: Some(path.to_string()),
+            temp: false,
+            options,
+        })
+    }
+
+    /// Follow the db at `primary_path`, written by another process, as a
+    /// secondary instance keeping its own files in `path`. Reads see the
+    /// writes of the primary as of opening or the last `try_catch_up`.
+    pub fn open_secondary(primary_path: &str, path: &str) -> StorageResult<Self> {
+        let options = StorageOptions::load(primary_path)?.unwrap_or_default();
+        let mut opts = options.to_rocksdb();
+        // secondary instances must keep every table file open, as the
+        // primary may delete them at any time
+        opts.set_max_open_files(-1);
+        fs::create_dir_all(path)?;
+        let cfs = column_families(primary_path, false)?;
+        let db = RocksDB::open_cf_as_secondary(&opts, primary_path, path, cfs)?;
+        Ok(Self {
+            db: Arc::new(db),
+            cf: None,
+            read_only: true,
+            secondary: true,
+            history: None,
             path: Some(path.to_string()),
             temp: false,
+            options,
+        })
+    }
+
+    /// Use the column family `name` of the shared instance under `root_path`,
+    /// creating it with `options` if missing.
+    fn open_cf(
+        db: Arc<RocksDB>,
+        root_path: &str,
+        name: &str,
+        options: StorageOptions,
+    ) -> StorageResult<Self> {
+        if options.history_ttl_seconds.is_some() {
+            return Err(StorageError::Unsupported(
+                "history in column family mode".to_string(),
+            ));
+        }
+        if db.cf_handle(name).is_none() {
+            db.create_cf(name, &options.to_rocksdb())?;
+        }
+        options.save_cf(&format!("{}/{}", root_path, SHARED_DB_DIR), name)?;
+        Ok(Self {
+            db,
+            cf: Some(name.to_string()),
+            read_only: false,
+            secondary: false,
+            history: None,
+            path: Some(format!("{}/{}", root_path, name)),
+            temp: false,
+            options,
         })
     }
 
     pub fn new_with_temp_dir(prefix: &str) -> StorageResult<Self> {
         let dir = tempfile::Builder::new().prefix(prefix).tempdir()?;
-        let db = DB::open_default(dir.path())?;
+        let db = RocksDB::open_default(dir.path())?;
         Ok(Self {
-            db,
+            db: Arc::new(db),
+            cf: None,
+            read_only: false,
+            secondary: false,
+            history: None,
             path: None,
             temp: true,
+            options: StorageOptions::default(),
         })
     }
 
-    pub fn set(&self, key: &[u8], value: &[u8]) -> StorageResult<()> {
-        self.db.put(key, value)?;
+    /// The column family holding this db, when opened in column family mode.
+    pub fn column_family(&self) -> Option<&str> {
+        self.cf.as_deref()
+    }
+
+    fn cf_handle(&self) -> StorageResult<Option<Arc<BoundColumnFamily<'_>>>> {
+        match self.cf.as_ref() {
+            Some(name) => match self.db.cf_handle(name) {
+                Some(cf) => Ok(Some(cf)),
+                None => Err(StorageError::DbNotFound(name.clone())),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn count_range(&self, start: &[u8], end: &[u8]) -> StorageResult<u64> {
+        let mut count = 0;
+        let mut iter = match self.cf_handle()? {
+            Some(cf) => self.db.raw_iterator_cf(&cf),
+            None => self.db.raw_iterator(),
+        };
+        iter.seek(start);
+        while let Some(key) = iter.key() {
+            if key >= end {
+                break;
+            }
+            count += 1;
+            iter.next();
+        }
+        Ok(count)
+    }
+}
+
+impl KvEngine for Storage {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        match self.cf_handle()? {
+            Some(cf) => Ok(self.db.get_cf(&cf, key)?),
+            None => Ok(self.db.get(key)?),
+        }
+    }
+
+    fn multi_get(&self, keys: &[Vec<u8>]) -> StorageResult<Vec<Option<Vec<u8>>>> {
+        match self.cf_handle()? {
+            Some(cf) => self
+                .db
+                .batched_multi_get_cf(&cf, keys, false)
+                .into_iter()
+                .map(|value| Ok(value?.map(|value| value.to_vec())))
+                .collect(),
+            None => self
+                .db
+                .multi_get(keys)
+                .into_iter()
+                .map(|value| Ok(value?))
+                .collect(),
+        }
+    }
+
+    /// Skips the read when the memtables and bloom filters rule the key out.
+    fn exists(&self, key: &[u8]) -> StorageResult<bool> {
+        let found = match self.cf_handle()? {
+            Some(cf) => {
+                self.db.key_may_exist_cf(&cf, key) && self.db.get_pinned_cf(&cf, key)?.is_some()
+            }
+            None => self.db.key_may_exist(key) && self.db.get_pinned(key)?.is_some(),
+        };
+        Ok(found)
+    }
+
+    fn set(&self, key: &[u8], value: &[u8]) -> StorageResult<()> {
+        let opts = self.options.write_options(Durability::default())?;
+        if let Some(history) = self.history.as_ref() {
+            let ops = vec![BatchOp::Put(key.to_vec(), value.to_vec())];
+            return self.write_with_history(history, ops, &opts);
+        }
+        match self.cf_handle()? {
+            Some(cf) => self.db.put_cf_opt(&cf, key, value, &opts)?,
+            None => self.db.put_opt(key, value, &opts)?,
+        }
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> StorageResult<()> {
+        let opts = self.options.write_options(Durability::default())?;
+        if let Some(history) = self.history.as_ref() {
+            let ops = vec![BatchOp::Delete(key.to_vec())];
+            return self.write_with_history(history, ops, &opts);
+        }
+        match self.cf_handle()? {
+            Some(cf) => Ok(self.db.delete_cf_opt(&cf, key, &opts)?),
+            None => Ok(self.db.delete_opt(key, &opts)?),
+        }
+    }
+
+    fn write_batch_with(&self, ops: Vec<BatchOp>, durability: Durability) -> StorageResult<()> {
+        let opts = self.options.write_options(durability)?;
+        if let Some(history) = self.history.as_ref() {
+            return self.write_with_history(history, ops, &opts);
+        }
+        let cf = self.cf_handle()?;
+        let mut batch = WriteBatch::default();
+        for op in ops {
+            match (op, cf.as_ref()) {
+                (BatchOp::Put(key, value), Some(cf)) => batch.put_cf(cf, key, value),
+                (BatchOp::Put(key, value), None) => batch.put(key, value),
+                (BatchOp::Delete(key), Some(cf)) => batch.delete_cf(cf, key),
+                (BatchOp::Delete(key), None) => batch.delete(key),
+            }
+        }
+        self.db.write_opt(batch, &opts)?;
         Ok(())
     }
 
-    pub fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
-        Ok(self.db.get(key)?)
+    /// Deletes with a single range tombstone, after counting the keys with
+    /// a key-only scan.
+    fn delete_range(&self, start: &[u8], end: &[u8]) -> StorageResult<u64> {
+        if start >= end {
+            return Ok(0);
+        }
+        if let Some(history) = self.history.as_ref() {
+            let opts = self.options.write_options(Durability::default())?;
+            return self.delete_range_with_history(history, start, end, &opts);
+        }
+        let count = self.count_range(start, end)?;
+        let mut batch = WriteBatch::default();
+        match self.cf_handle()? {
+            Some(cf) => batch.delete_range_cf(&cf, start, end),
+            None => batch.delete_range(start, end),
+        }
+        let opts = self.options.write_options(Durability::default())?;
+        self.db.write_opt(batch, &opts)?;
+        Ok(count)
+    }
+
+    fn iterator(&self, mode: IteratorMode) -> StorageResult<KvIterator<'_>> {
+        let it = match self.cf_handle()? {
+            Some(cf) => self.db.iterator_cf(&cf, mode),
+            None => self.db.iterator(mode),
+        };
+        Ok(Box::new(it.map(|item| Ok(item?))))
+    }
+
+    fn snapshot(self: Arc<Self>) -> Box<dyn KvSnapshot> {
+        let snapshot = self.db.snapshot();
+        // SAFETY: the snapshot only borrows `self.db`, which lives on the heap
+        // behind the `Arc` kept by `StorageSnapshot`, so it never moves and
+        // outlives the snapshot (fields are dropped in declaration order).
+        let snapshot = unsafe {
+            transmute::<SnapshotWithThreadMode<'_, RocksDB>, SnapshotWithThreadMode<'static, RocksDB>>(
+                snapshot,
+            )
+        };
+        Box::new(StorageSnapshot {
+            snapshot,
+            storage: self,
+        })
+    }
+
+    fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    fn options(&self) -> &StorageOptions {
+        &self.options
     }
 
-    pub fn delete(&self, key: &[u8]) -> StorageResult<()> {
-        Ok(self.db.delete(key)?)
+    fn read_only(&self) -> bool {
+        self.read_only
     }
 
-    pub fn this_db(&self) -> &DB {
-        &self.db
+    fn try_catch_up(&self) -> StorageResult<()> {
+        if self.secondary {
+            self.db.try_catch_up_with_primary()?;
+        }
+        Ok(())
+    }
+
+    fn prune_history(&self) -> StorageResult<u64> {
+        match self.history.as_ref() {
+            Some(history) => self.prune_versions(history),
+            None => Ok(0),
+        }
+    }
+
+    fn stats(&self) -> StorageResult<Vec<(String, String)>> {
+        self.properties()
+    }
+
+    fn backup(&self, backup_dir: &str) -> StorageResult<u32> {
+        self.create_backup(backup_dir)
+    }
+
+    fn checkpoint(&self, target_dir: &str) -> StorageResult<()> {
+        self.create_checkpoint(target_dir)
+    }
+
+    /// Refused while history is kept, as the pairs would have no versions.
+    fn ingest(&self, files: &[String]) -> StorageResult<()> {
+        if self.history.is_some() {
+            return Err(StorageError::Unsupported(
+                "ingest into a db keeping history".to_string(),
+            ));
+        }
+        self.ingest_files(files)
+    }
+
+    fn latest_sequence(&self) -> StorageResult<u64> {
+        Ok(self.latest_sequence_number())
+    }
+
+    fn changes_since(&self, seq: u64, limit: usize) -> StorageResult<Vec<Change>> {
+        self.read_changes(seq, limit)
+    }
+
+    fn get_at(&self, key: &[u8], seq: u64) -> StorageResult<Option<Vec<u8>>> {
+        self.read_at(key, seq)
+    }
+
+    fn get_at_time(&self, key: &[u8], time_ms: u64) -> StorageResult<Option<Vec<u8>>> {
+        self.read_at_time(key, time_ms)
+    }
+
+    fn history(&self, key: &[u8], limit: usize) -> StorageResult<Vec<Version>> {
+        self.read_history(key, limit)
+    }
+
+    /// Forces the last level to be rewritten too, as that is where the range
+    /// tombstones of bulk deletions end up, while automatic compactions keep
+    /// running alongside.
+    fn compact(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> StorageResult<()> {
+        let mut opts = CompactOptions::default();
+        opts.set_bottommost_level_compaction(BottommostLevelCompaction::ForceOptimized);
+        match self.cf_handle()? {
+            Some(cf) => self.db.compact_range_cf_opt(&cf, start, end, &opts),
+            None => self.db.compact_range_opt(start, end, &opts),
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> StorageResult<()> {
+        match self.cf_handle()? {
+            Some(cf) => Ok(self.db.flush_cf(&cf)?),
+            None => Ok(self.db.flush()?),
+        }
+    }
+
+    fn sync_wal(&self) -> StorageResult<()> {
+        if !self.read_only {
+            self.db.flush_wal(true)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the whole instance, so for every column family when shared.
+    fn wait_for_compact(&self, timeout: Option<Duration>) -> StorageResult<()> {
+        let mut opts = WaitForCompactOptions::default();
+        if let Some(timeout) = timeout {
+            // zero would mean no timeout at all
+            opts.set_timeout((timeout.as_micros() as u64).max(1));
+        }
+        Ok(self.db.wait_for_compact(&opts)?)
+    }
+}
+
+/// A point-in-time view of a `Storage`, which keeps the storage alive
+/// until the snapshot is dropped.
+struct StorageSnapshot {
+    snapshot: SnapshotWithThreadMode<'static, RocksDB>,
+    storage: Arc<Storage>,
+}
+
+impl KvSnapshot for StorageSnapshot {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        match self.storage.cf_handle()? {
+            Some(cf) => Ok(self.snapshot.get_cf(&cf, key)?),
+            None => Ok(self.snapshot.get(key)?),
+        }
+    }
+
+    fn multi_get(&self, keys: &[Vec<u8>]) -> StorageResult<Vec<Option<Vec<u8>>>> {
+        let values = match self.storage.cf_handle()? {
+            Some(cf) => self
+                .snapshot
+                .multi_get_cf(keys.iter().map(|key| (&cf, key))),
+            None => self.snapshot.multi_get(keys),
+        };
+        values.into_iter().map(|value| Ok(value?)).collect()
+    }
+
+    fn iterator(&self, mode: IteratorMode) -> StorageResult<KvIterator<'_>> {
+        let it = match self.storage.cf_handle()? {
+            Some(cf) => self.snapshot.iterator_cf(&cf, mode),
+            None => self.snapshot.iterator(mode),
+        };
+        Ok(Box::new(it.map(|item| Ok(item?))))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
     use super::*;
 
     #[test]
@@ -144,5 +1245,836 @@ mod tests {
         assert_eq!(storage.get(b"key1").unwrap().unwrap(), b"value1");
         storage.delete(b"key1").unwrap();
         assert_eq!(storage.get(b"key1").unwrap(), None);
+        check_write_batch(&storage);
+    }
+
+    #[test]
+    fn test_memory_engine() {
+        let mdb = MultiDB::with_engine("", EngineKind::Memory);
+        mdb.attach("db1").unwrap();
+        let db1 = mdb.get_db("db1").unwrap();
+        db1.set(b"key1", b"value1").unwrap();
+        assert_eq!(db1.get(b"key1").unwrap().unwrap(), b"value1");
+        assert_eq!(db1.path(), None);
+        assert!(db1.backup("/tmp").is_err());
+        assert!(mdb.restore("/tmp", None, "db2").is_err());
+
+        let storage = MemoryStorage::default();
+        for key in [&b"a"[..], b"b", b"c"] {
+            storage.set(key, b"value").unwrap();
+        }
+        let keys = |mode| -> Vec<Box<[u8]>> {
+            storage
+                .iterator(mode)
+                .unwrap()
+                .map(|item| item.unwrap().0)
+                .collect()
+        };
+        assert_eq!(keys(IteratorMode::End).concat(), b"cba");
+        assert_eq!(
+            keys(IteratorMode::From(b"bb", Direction::Forward)).concat(),
+            b"c"
+        );
+        assert_eq!(
+            keys(IteratorMode::From(b"bb", Direction::Reverse)).concat(),
+            b"ba"
+        );
+        assert_eq!(storage.scan(b"a", b"c").unwrap().count(), 2);
+
+        check_write_batch(&MemoryStorage::default());
+        check_delete_range(&MemoryStorage::default());
+        check_snapshot(Arc::new(MemoryStorage::default()));
+    }
+
+    fn check_write_batch(storage: &dyn KvEngine) {
+        storage.set(b"key1", b"value1").unwrap();
+        storage
+            .write_batch(vec![
+                BatchOp::Put(b"key2".to_vec(), b"value2".to_vec()),
+                BatchOp::Delete(b"key1".to_vec()),
+            ])
+            .unwrap();
+        assert_eq!(storage.get(b"key1").unwrap(), None);
+        assert_eq!(storage.get(b"key2").unwrap().unwrap(), b"value2");
+
+        let keys = [b"key1".to_vec(), b"key2".to_vec()];
+        assert_eq!(
+            storage.multi_get(&keys).unwrap(),
+            [None, Some(b"value2".to_vec())]
+        );
+        assert!(!storage.exists(b"key1").unwrap());
+        assert!(storage.exists(b"key2").unwrap());
+    }
+
+    #[test]
+    fn test_options() {
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().to_str().unwrap();
+        let config_path = format!("{}/profiles.conf", root_path);
+        std::fs::write(
+            &config_path,
+            "max_open_files = 100\n[logs]\ncompression = none\nwrite_buffer_size = 1048576\n",
+        )
+        .unwrap();
+        let profiles = load_profiles(&config_path).unwrap();
+        assert_eq!(profiles[DEFAULT_PROFILE].max_open_files, Some(100));
+        assert_eq!(profiles["logs"].write_buffer_size, Some(1048576));
+        assert!(StorageOptions::parse("compression = gzip").is_err());
+        assert!(StorageOptions::parse("block_size = 1").is_err());
+
+        let mut mdb = MultiDB::new(root_path);
+        mdb.set_profiles(profiles.clone());
+        mdb.attach("logs").unwrap();
+        mdb.attach("other").unwrap();
+        assert_eq!(*mdb.get_db("logs").unwrap().options(), profiles["logs"]);
+        assert_eq!(
+            *mdb.get_db("other").unwrap().options(),
+            profiles[DEFAULT_PROFILE]
+        );
+
+        let tuned =
+            StorageOptions::parse("bloom_filter_bits = 10; compaction_style = universal").unwrap();
+        assert!(mdb.attach_with_options("logs", tuned.clone()).is_err());
+        mdb.detach("logs").unwrap();
+        mdb.attach_with_options("logs", tuned.clone()).unwrap();
+        mdb.detach("logs").unwrap();
+
+        // reopening ignores the profiles in favour of the persisted options
+        let mut mdb = MultiDB::new(root_path);
+        mdb.set_profiles(profiles);
+        mdb.attach("logs").unwrap();
+        assert_eq!(*mdb.get_db("logs").unwrap().options(), tuned);
+    }
+
+    #[test]
+    fn test_durability() {
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().to_str().unwrap();
+        assert!(StorageOptions::parse("sync_writes = true; disable_wal = true").is_err());
+
+        let mdb = MultiDB::new(root_path);
+        let cache = StorageOptions::parse("disable_wal = true").unwrap();
+        let db = mdb.attach_with_options("cache", cache.clone()).unwrap();
+        db.set(b"key1", b"value1").unwrap();
+        let sync = Durability {
+            sync: true,
+            ..Durability::default()
+        };
+        let put = || vec![BatchOp::Put(b"key2".to_vec(), b"value2".to_vec())];
+        assert!(db.write_batch_with(put(), sync).is_err());
+        assert_eq!(db.get(b"key2").unwrap(), None);
+        assert_eq!(db.delete_range(b"key1", b"key2").unwrap(), 1);
+        // neither write went to the WAL
+        assert!(db.changes_since(0, 10).unwrap().is_empty());
+        drop(db);
+
+        let db = mdb.attach("ledger").unwrap();
+        db.write_batch_with(put(), sync).unwrap();
+        let no_wal = Durability {
+            disable_wal: true,
+            ..Durability::default()
+        };
+        db.write_batch_with(put(), no_wal).unwrap();
+        assert_eq!(db.get(b"key2").unwrap(), Some(b"value2".to_vec()));
+        mdb.detach("cache").unwrap();
+        assert_eq!(*mdb.attach("cache").unwrap().options(), cache);
+    }
+
+    #[test]
+    fn test_stats() {
+        let root = tempfile::tempdir().unwrap();
+        let mdb = MultiDB::new(root.path().to_str().unwrap());
+        let options = StorageOptions::parse("statistics = true").unwrap();
+        let db1 = mdb.attach_with_options("db1", options).unwrap();
+        db1.set(b"key1", b"value1").unwrap();
+        let stats: HashMap<_, _> = db1.stats().unwrap().into_iter().collect();
+        assert_eq!(stats["estimated_keys"], "1");
+        assert_eq!(stats["files_at_level0"], "0");
+        assert!(stats.contains_key("rocksdb.bytes.written"));
+
+        let db2 = mdb.attach("db2").unwrap();
+        let stats: HashMap<_, _> = db2.stats().unwrap().into_iter().collect();
+        assert!(stats.contains_key("memtable_size"));
+        assert!(!stats.contains_key("rocksdb.bytes.written"));
+
+        let storage = MemoryStorage::default();
+        storage.set(b"key1", b"value1").unwrap();
+        assert_eq!(
+            storage.stats().unwrap(),
+            [("estimated_keys".to_string(), "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_column_families() {
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().to_str().unwrap();
+        let tuned = StorageOptions::parse("write_buffer_size = 1048576").unwrap();
+        {
+            let mdb = MultiDB::with_engine(root_path, EngineKind::ColumnFamilies);
+            mdb.attach("db1").unwrap();
+            mdb.attach_with_options("db2", tuned.clone()).unwrap();
+            let db1 = mdb.get_db("db1").unwrap();
+            let db2 = mdb.get_db("db2").unwrap();
+
+            db1.set(b"key1", b"value1").unwrap();
+            db2.set(b"key1", b"value2").unwrap();
+            db2.set(b"key2", b"value2").unwrap();
+            assert_eq!(db1.get(b"key1").unwrap().unwrap(), b"value1");
+            assert_eq!(db1.get(b"key2").unwrap(), None);
+            assert_eq!(
+                db1.multi_get(&[b"key1".to_vec(), b"key2".to_vec()])
+                    .unwrap(),
+                [Some(b"value1".to_vec()), None]
+            );
+            assert!(!db1.exists(b"key2").unwrap());
+            assert_eq!(db2.iterator(IteratorMode::Start).unwrap().count(), 2);
+
+            let snapshot = db2.clone().snapshot();
+            assert_eq!(db2.delete_prefix(b"key").unwrap(), 2);
+            assert_eq!(db1.iterator(IteratorMode::Start).unwrap().count(), 1);
+            assert_eq!(snapshot.get(b"key2").unwrap().unwrap(), b"value2");
+            assert_eq!(
+                snapshot.multi_get(&[b"key2".to_vec()]).unwrap(),
+                [Some(b"value2".to_vec())]
+            );
+            assert_eq!(snapshot.iterator(IteratorMode::Start).unwrap().count(), 2);
+            assert!(mdb.restore(root_path, None, "db3").is_err());
+        }
+
+        let mdb = MultiDB::with_engine(root_path, EngineKind::ColumnFamilies);
+        mdb.detach("db2").unwrap();
+        assert!(mdb.attach_with_options("db1", tuned.clone()).is_err());
+        mdb.attach("db1").unwrap();
+        mdb.attach("db2").unwrap();
+        assert_eq!(
+            mdb.get_db("db1").unwrap().get(b"key1").unwrap().unwrap(),
+            b"value1"
+        );
+        assert_eq!(*mdb.get_db("db2").unwrap().options(), tuned);
+        assert!(!root.path().join("db1").exists());
+    }
+
+    #[test]
+    fn test_drop_db() {
+        let timeout = Duration::from_millis(50);
+        for engine in [
+            EngineKind::RocksDB,
+            EngineKind::ColumnFamilies,
+            EngineKind::Memory,
+        ] {
+            let root = tempfile::tempdir().unwrap();
+            let root_path = root.path().to_str().unwrap();
+            let mdb = MultiDB::with_engine(root_path, engine);
+            mdb.attach("db1").unwrap();
+            let db1 = mdb.get_db("db1").unwrap();
+            db1.set(b"key1", b"value1").unwrap();
+
+            assert!(mdb.drop_db("db1", timeout).is_err());
+            assert!(mdb.get_db("db1").is_some());
+            drop(db1);
+            mdb.drop_db("db1", timeout).unwrap();
+            assert!(mdb.get_db("db1").is_none());
+            assert!(!root.path().join("db1").exists());
+            assert!(mdb.drop_db("db1", timeout).is_err());
+            assert!(matches!(
+                mdb.attach_existing("db1"),
+                Err(StorageError::DbNotFound(_))
+            ));
+
+            mdb.attach("db1").unwrap();
+            assert_eq!(mdb.get_db("db1").unwrap().get(b"key1").unwrap(), None);
+            assert!(mdb.attach_existing("db1").is_ok());
+        }
+    }
+
+    #[test]
+    fn test_catalog() {
+        for engine in [EngineKind::RocksDB, EngineKind::ColumnFamilies] {
+            let root = tempfile::tempdir().unwrap();
+            let root_path = root.path().to_str().unwrap();
+            let options = StorageOptions::parse("write_buffer_size = 1048576").unwrap();
+            {
+                let mdb = MultiDB::with_engine(root_path, engine);
+                assert!(mdb.open_catalog().unwrap().is_empty());
+                mdb.attach_with_options("db1", options.clone()).unwrap();
+                mdb.get_db("db1").unwrap().set(b"key1", b"value1").unwrap();
+                mdb.attach("db2").unwrap();
+                mdb.attach("db3").unwrap();
+                mdb.detach("db2").unwrap();
+                mdb.drop_db("db3", Duration::ZERO).unwrap();
+            }
+            if engine == EngineKind::RocksDB {
+                // created without a catalog
+                Storage::open(&format!("{}/db4", root_path), options.clone()).unwrap();
+            }
+
+            let mdb = MultiDB::with_engine(root_path, engine);
+            assert!(mdb.open_catalog().unwrap().is_empty());
+            let db1 = mdb.get_db("db1").unwrap();
+            assert_eq!(db1.get(b"key1").unwrap().unwrap(), b"value1");
+            assert_eq!(*db1.options(), options);
+            assert!(mdb.get_db("db2").is_none());
+
+            let catalog = mdb.catalog();
+            let states: Vec<_> = catalog.iter().map(|e| (e.name.as_str(), e.state)).collect();
+            let mut expected = vec![("db1", DbState::Attached), ("db2", DbState::Detached)];
+            if engine == EngineKind::RocksDB {
+                expected.push(("db4", DbState::Detached));
+            }
+            assert_eq!(states, expected);
+        }
+    }
+
+    #[test]
+    fn test_max_open() {
+        let root = tempfile::tempdir().unwrap();
+        let mut mdb = MultiDB::new(root.path().to_str().unwrap());
+        mdb.set_max_open(Some(2));
+        mdb.attach("db1").unwrap();
+        mdb.get_db("db1").unwrap().set(b"key1", b"value1").unwrap();
+        mdb.attach("db2").unwrap();
+        mdb.attach("db3").unwrap();
+        assert!(mdb.get_db("db1").is_none());
+
+        // db2 is in use, so db3 goes
+        let db2 = mdb.get_db("db2").unwrap();
+        mdb.attach("db1").unwrap();
+        let db1 = mdb.get_db("db1").unwrap();
+        assert_eq!(db1.get(b"key1").unwrap().unwrap(), b"value1");
+        assert!(mdb.get_db("db3").is_none());
+
+        // nothing idle to close
+        mdb.attach("db3").unwrap();
+        assert_eq!(mdb.list_db().len(), 3);
+        drop(db2);
+        drop(db1);
+        mdb.attach("db3").unwrap();
+        assert_eq!(
+            mdb.pool_stats(),
+            PoolStats {
+                open: 2,
+                max_open: Some(2),
+                evictions: 3,
+                reopens: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_only() {
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().to_str().unwrap();
+        {
+            let mdb = MultiDB::new(root_path);
+            mdb.open_catalog().unwrap();
+            assert!(matches!(
+                mdb.attach_read_only("db1"),
+                Err(StorageError::DbNotFound(_))
+            ));
+            mdb.attach("db1").unwrap().set(b"key1", b"value1").unwrap();
+            assert!(mdb.attach_read_only("db1").is_err());
+            mdb.detach("db1").unwrap();
+
+            let db1 = mdb.attach_read_only("db1").unwrap();
+            assert!(db1.read_only());
+            assert_eq!(db1.get(b"key1").unwrap().unwrap(), b"value1");
+            assert!(db1.set(b"key2", b"value2").is_err());
+            // reused as it is by a plain attach
+            assert!(mdb.attach("db1").unwrap().read_only());
+        }
+
+        // reopened read-only with the catalog
+        let mdb = MultiDB::new(root_path);
+        assert!(mdb.open_catalog().unwrap().is_empty());
+        assert!(mdb.get_db("db1").unwrap().read_only());
+        mdb.detach("db1").unwrap();
+        assert!(!mdb.attach("db1").unwrap().read_only());
+
+        let mdb = MultiDB::with_engine(root_path, EngineKind::Memory);
+        assert!(mdb.attach_read_only("db1").is_err());
+    }
+
+    #[test]
+    fn test_secondary() {
+        let root = tempfile::tempdir().unwrap();
+        let primary_root = root.path().join("primary");
+        let secondary_root = root.path().join("secondary");
+        fs::create_dir_all(&primary_root).unwrap();
+        fs::create_dir_all(&secondary_root).unwrap();
+        let primary_root = primary_root.to_str().unwrap();
+        let primary = MultiDB::new(primary_root);
+        primary.open_catalog().unwrap();
+        let db1 = primary.attach("db1").unwrap();
+        db1.set(b"key1", b"value1").unwrap();
+
+        let mut secondary = MultiDB::new(secondary_root.to_str().unwrap());
+        secondary.follow(primary_root).unwrap();
+        assert!(secondary.open_catalog().unwrap().is_empty());
+        assert_eq!(secondary.catalog()[0].state, DbState::Detached);
+        let follower = secondary.attach("db1").unwrap();
+        assert!(follower.read_only());
+        assert_eq!(follower.get(b"key1").unwrap().unwrap(), b"value1");
+        assert!(follower.set(b"key2", b"value2").is_err());
+        assert!(matches!(
+            secondary.attach("db2"),
+            Err(StorageError::DbNotFound(_))
+        ));
+
+        db1.set(b"key2", b"value2").unwrap();
+        db1.flush().unwrap();
+        db1.set(b"key3", b"value3").unwrap();
+        assert_eq!(follower.get(b"key2").unwrap(), None);
+        assert!(secondary.catch_up().is_empty());
+        assert_eq!(follower.get(b"key2").unwrap().unwrap(), b"value2");
+        assert_eq!(follower.get(b"key3").unwrap().unwrap(), b"value3");
+
+        let mut memory = MultiDB::with_engine(primary_root, EngineKind::Memory);
+        assert!(memory.follow(primary_root).is_err());
+    }
+
+    #[test]
+    fn test_history() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("db1");
+        let path = path.to_str().unwrap();
+        {
+            let storage = Storage::open(path, StorageOptions::default()).unwrap();
+            storage.set(b"key1", b"value0").unwrap();
+            assert!(storage.get_at(b"key1", 1).is_err());
+            assert!(storage.history(b"key1", 10).is_err());
+        }
+
+        let options = StorageOptions {
+            history_ttl_seconds: Some(0),
+            ..Default::default()
+        };
+        let storage = Storage::open(path, options).unwrap();
+        let start = storage.latest_sequence().unwrap();
+        storage.set(b"key1", b"value1").unwrap();
+        storage
+            .write_batch(vec![
+                BatchOp::Put(b"key1".to_vec(), b"value2".to_vec()),
+                BatchOp::Put(b"key2".to_vec(), b"value2".to_vec()),
+            ])
+            .unwrap();
+        storage.delete(b"key1").unwrap();
+        assert_eq!(storage.delete_range(b"key2", b"key3").unwrap(), 1);
+        // the versions do not show in the changes
+        let seqs: Vec<_> = storage
+            .changes_since(start, 10)
+            .unwrap()
+            .iter()
+            .map(|change| change.seq)
+            .collect();
+        assert_eq!(seqs.len(), 5);
+
+        // the value held before the history was kept
+        assert_eq!(storage.get_at(b"key1", start).unwrap().unwrap(), b"value0");
+        assert_eq!(
+            storage.get_at(b"key1", seqs[0]).unwrap().unwrap(),
+            b"value1"
+        );
+        assert_eq!(
+            storage.get_at(b"key1", seqs[2]).unwrap().unwrap(),
+            b"value2"
+        );
+        assert_eq!(storage.get_at(b"key1", seqs[3]).unwrap(), None);
+        assert_eq!(storage.get_at(b"key2", seqs[1]).unwrap(), None);
+        assert_eq!(
+            storage.get_at(b"key2", seqs[3]).unwrap().unwrap(),
+            b"value2"
+        );
+        assert_eq!(storage.get_at(b"key2", seqs[4]).unwrap(), None);
+        assert_eq!(storage.get_at(b"key3", seqs[4]).unwrap(), None);
+
+        let history = storage.history(b"key1", 10).unwrap();
+        let values: Vec<_> = history.iter().map(|v| (v.seq, v.value.clone())).collect();
+        assert_eq!(
+            values,
+            [
+                (seqs[3], None),
+                (seqs[1], Some(b"value2".to_vec())),
+                (seqs[0], Some(b"value1".to_vec())),
+                (0, Some(b"value0".to_vec())),
+            ]
+        );
+        assert!(history[0].time_ms > 0);
+        assert_eq!(storage.history(b"key1", 1).unwrap(), history[..1]);
+        assert!(storage.history(b"key3", 10).unwrap().is_empty());
+        assert!(storage.ingest(&[]).is_err());
+        drop(storage);
+
+        // kept across reopening with the persisted options
+        let storage = Storage::open_read_only(path).unwrap();
+        assert_eq!(
+            storage.get_at(b"key1", seqs[0]).unwrap().unwrap(),
+            b"value1"
+        );
+        drop(storage);
+
+        // refused without the option, as the versions would go stale
+        let rs = Storage::open(path, StorageOptions::default());
+        assert!(matches!(rs, Err(StorageError::InvalidOptions(_))));
+        let storage = Storage::new(path).unwrap();
+        assert_eq!(
+            storage.get_at(b"key1", seqs[0]).unwrap().unwrap(),
+            b"value1"
+        );
+    }
+
+    #[test]
+    fn test_get_at_time() {
+        let now_ms = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as u64
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        {
+            let storage = Storage::open(path, StorageOptions::default()).unwrap();
+            storage.set(b"old", b"value0").unwrap();
+        }
+        let options = StorageOptions {
+            history_ttl_seconds: Some(0),
+            ..Default::default()
+        };
+        let storage = Storage::open(path, options).unwrap();
+        let start = now_ms();
+        thread::sleep(Duration::from_millis(5));
+        storage.set(b"key", b"value1").unwrap();
+        storage.set(b"old", b"value1").unwrap();
+        thread::sleep(Duration::from_millis(5));
+        let between = now_ms();
+        thread::sleep(Duration::from_millis(5));
+        storage.set(b"key", b"value2").unwrap();
+        storage.delete(b"old").unwrap();
+
+        assert_eq!(storage.get_at_time(b"key", start).unwrap(), None);
+        assert_eq!(
+            storage.get_at_time(b"key", between).unwrap().unwrap(),
+            b"value1"
+        );
+        assert_eq!(
+            storage.get_at_time(b"key", now_ms()).unwrap().unwrap(),
+            b"value2"
+        );
+        // held before the history was kept, whenever that was
+        assert_eq!(storage.get_at_time(b"old", 0).unwrap().unwrap(), b"value0");
+        assert_eq!(storage.get_at_time(b"old", now_ms()).unwrap(), None);
+        assert!(MemoryStorage::default().get_at_time(b"key", 0).is_err());
+    }
+
+    #[test]
+    fn test_prune_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = StorageOptions {
+            history_ttl_seconds: Some(1),
+            ..Default::default()
+        };
+        let storage = Storage::open(dir.path().to_str().unwrap(), options).unwrap();
+        for value in [b"value1", b"value2", b"value3"] {
+            storage.set(b"cold", value).unwrap();
+        }
+        storage.set(b"once", b"value1").unwrap();
+        assert_eq!(storage.prune_history().unwrap(), 0);
+
+        thread::sleep(Duration::from_millis(1100));
+        storage.set(b"hot", b"value1").unwrap();
+        storage.set(b"hot", b"value2").unwrap();
+        // but for the value in place a ttl ago
+        assert_eq!(storage.prune_history().unwrap(), 2);
+        let cold = storage.history(b"cold", 10).unwrap();
+        assert_eq!(cold.len(), 1);
+        assert_eq!(cold[0].value.as_deref(), Some(b"value3".as_slice()));
+        assert_eq!(storage.history(b"once", 10).unwrap().len(), 1);
+        assert_eq!(storage.history(b"hot", 10).unwrap().len(), 2);
+        assert_eq!(storage.get(b"cold").unwrap().unwrap(), b"value3");
+
+        assert_eq!(MemoryStorage::default().prune_history().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_concurrent_attach() {
+        let root = tempfile::tempdir().unwrap();
+        let mdb = Arc::new(MultiDB::new(root.path().to_str().unwrap()));
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let mdb = mdb.clone();
+                thread::spawn(move || {
+                    let name = format!("db{}", i % 2);
+                    mdb.attach(&name).unwrap();
+                    mdb.get_db(&name).unwrap().set(b"key1", b"value1").unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let mut names = mdb.list_db();
+        names.sort();
+        assert_eq!(names, ["db0", "db1"]);
+
+        // a caller panicking with the lock held does not lock out the others
+        let poisoner = mdb.clone();
+        thread::spawn(move || {
+            let _guard = poisoner.storage.write().unwrap();
+            panic!("poison the lock");
+        })
+        .join()
+        .unwrap_err();
+        mdb.attach("db2").unwrap();
+        assert!(mdb.get_db("db0").is_some());
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().join("root");
+        fs::create_dir(&root_path).unwrap();
+        let mdb = MultiDB::new(root_path.to_str().unwrap());
+
+        let long = "a".repeat(MAX_NAME_LEN + 1);
+        for name in [
+            "", "..", "../db1", "/tmp/db1", "a/b", ".shared", "-db", "db 1", &long,
+        ] {
+            assert!(matches!(
+                mdb.attach(name),
+                Err(StorageError::InvalidName(_))
+            ));
+        }
+        assert!(!root.path().join("db1").exists());
+        mdb.attach("db-1.v2_x").unwrap();
+
+        std::os::unix::fs::symlink(root.path(), root_path.join("link")).unwrap();
+        assert!(matches!(
+            mdb.attach("link"),
+            Err(StorageError::InvalidName(_))
+        ));
+        assert!(matches!(
+            mdb.drop_db("link", Duration::ZERO),
+            Err(StorageError::InvalidName(_))
+        ));
+        assert!(root.path().exists());
+    }
+
+    #[test]
+    fn test_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path().to_str().unwrap()).unwrap();
+        let files = |storage: &Storage| {
+            let stats: HashMap<_, _> = storage.stats().unwrap().into_iter().collect();
+            (
+                stats["files_at_level0"].clone(),
+                stats["live_sst_size"].clone(),
+            )
+        };
+        for idx in 0..100u32 {
+            storage.set(&idx.to_be_bytes(), b"value").unwrap();
+        }
+        storage.flush().unwrap();
+        assert_eq!(files(&storage).0, "1");
+
+        storage.delete_prefix(b"").unwrap();
+        storage.flush().unwrap();
+        storage.compact(None, None).unwrap();
+        storage
+            .wait_for_compact(Some(Duration::from_secs(10)))
+            .unwrap();
+        assert_eq!(files(&storage), ("0".to_string(), "0".to_string()));
+        assert_eq!(storage.iterator(IteratorMode::Start).unwrap().count(), 0);
+
+        let storage = MemoryStorage::default();
+        storage.compact(Some(b"a"), None).unwrap();
+        storage.flush().unwrap();
+    }
+
+    #[test]
+    fn test_ingest() {
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().to_str().unwrap();
+        let sst_dir = format!("{}/sst", root_path);
+        let pairs = (0..1000u32).map(|idx| Ok((idx.to_be_bytes().to_vec(), b"value".to_vec())));
+        let files = write_sst_files(&sst_dir, &StorageOptions::default(), 4096, pairs).unwrap();
+        assert!(files.len() > 1);
+
+        let unsorted = [b"b", b"a"].map(|key| Ok((key.to_vec(), b"value".to_vec())));
+        let err = write_sst_files(
+            &format!("{}/bad", root_path),
+            &StorageOptions::default(),
+            4096,
+            unsorted,
+        );
+        assert!(matches!(err, Err(StorageError::InvalidData(_))));
+        let pairs = [Ok((b"a".to_vec(), b"value".to_vec()))];
+        assert!(write_sst_files(&sst_dir, &StorageOptions::default(), 4096, pairs).is_err());
+
+        let mdb = MultiDB::new(root_path);
+        let db = mdb.attach("db1").unwrap();
+        db.set(&5u32.to_be_bytes(), b"old").unwrap();
+        db.ingest(&files).unwrap();
+        assert_eq!(
+            db.get(&5u32.to_be_bytes()).unwrap(),
+            Some(b"value".to_vec())
+        );
+        assert_eq!(db.iterator(IteratorMode::Start).unwrap().count(), 1000);
+        assert!(db.ingest(&files).is_err());
+        assert!(MemoryStorage::default().ingest(&files).is_err());
+    }
+
+    #[test]
+    fn test_delete_range() {
+        check_delete_range(&Storage::new_with_temp_dir("test_delete_range").unwrap());
+    }
+
+    fn check_delete_range(storage: &dyn KvEngine) {
+        for key in [&b"a1"[..], b"b1", b"b2", b"b\xff", b"c1", b"\xff\xff"] {
+            storage.set(key, b"value").unwrap();
+        }
+
+        assert_eq!(storage.delete_range(b"b1", b"b2").unwrap(), 1);
+        assert_eq!(storage.get(b"b1").unwrap(), None);
+        assert!(storage.get(b"b2").unwrap().is_some());
+
+        assert_eq!(storage.delete_prefix(b"b").unwrap(), 2);
+        assert_eq!(storage.get(b"b\xff").unwrap(), None);
+        assert!(storage.get(b"c1").unwrap().is_some());
+
+        assert_eq!(storage.delete_prefix(b"\xff").unwrap(), 1);
+        assert_eq!(storage.delete_prefix(b"").unwrap(), 2);
+        assert_eq!(storage.iterator(IteratorMode::Start).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_changes() {
+        for engine in [EngineKind::RocksDB, EngineKind::ColumnFamilies] {
+            let root = tempfile::tempdir().unwrap();
+            let mdb = MultiDB::with_engine(root.path().to_str().unwrap(), engine);
+            let db1 = mdb.attach("db1").unwrap();
+            let db2 = mdb.attach("db2").unwrap();
+            let start = db1.latest_sequence().unwrap();
+
+            db1.set(b"key1", b"value1").unwrap();
+            db2.set(b"key2", b"value2").unwrap();
+            db1.write_batch(vec![
+                BatchOp::Put(b"key3".to_vec(), b"value3".to_vec()),
+                BatchOp::Delete(b"key1".to_vec()),
+            ])
+            .unwrap();
+            db1.delete_range(b"a", b"z").unwrap();
+
+            let changes = db1.changes_since(start, 10).unwrap();
+            let ops: Vec<_> = changes.iter().map(|change| change.op.clone()).collect();
+            assert_eq!(
+                ops,
+                [
+                    ChangeOp::Put(b"key1".to_vec(), b"value1".to_vec()),
+                    ChangeOp::Put(b"key3".to_vec(), b"value3".to_vec()),
+                    ChangeOp::Delete(b"key1".to_vec()),
+                    ChangeOp::DeleteRange(b"a".to_vec(), b"z".to_vec()),
+                ]
+            );
+            assert!(changes.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+
+            // resuming after the second change
+            let rest = db1.changes_since(changes[1].seq, 1).unwrap();
+            assert_eq!(rest, changes[2..3]);
+            let latest = db1.latest_sequence().unwrap();
+            assert!(db1.changes_since(latest, 10).unwrap().is_empty());
+        }
+        assert!(MemoryStorage::default().changes_since(0, 10).is_err());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        check_snapshot(Arc::new(
+            Storage::new_with_temp_dir("test_snapshot").unwrap(),
+        ));
+    }
+
+    fn check_snapshot(storage: Arc<dyn KvEngine>) {
+        storage.set(b"key1", b"value1").unwrap();
+        let snapshot = storage.clone().snapshot();
+        storage.set(b"key1", b"value2").unwrap();
+        storage.set(b"key2", b"value2").unwrap();
+
+        assert_eq!(snapshot.get(b"key1").unwrap().unwrap(), b"value1");
+        assert_eq!(snapshot.get(b"key2").unwrap(), None);
+        assert_eq!(
+            snapshot
+                .multi_get(&[b"key1".to_vec(), b"key2".to_vec()])
+                .unwrap(),
+            [Some(b"value1".to_vec()), None]
+        );
+        assert_eq!(snapshot.iterator(IteratorMode::Start).unwrap().count(), 1);
+        assert_eq!(storage.iterator(IteratorMode::Start).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_backup_restore() {
+        let root = tempfile::tempdir().unwrap();
+        let root_path = root.path().to_str().unwrap();
+        let backup_dir = format!("{}/backups", root_path);
+        let mdb = MultiDB::new(root_path);
+        mdb.attach("origin").unwrap();
+        let origin = mdb.get_db("origin").unwrap();
+
+        origin.set(b"key1", b"value1").unwrap();
+        assert_eq!(origin.backup(&backup_dir).unwrap(), 1);
+        origin.set(b"key1", b"value2").unwrap();
+        assert_eq!(origin.backup(&backup_dir).unwrap(), 2);
+        assert_eq!(list_backups(&backup_dir).unwrap().len(), 2);
+
+        mdb.restore(&backup_dir, Some(1), "first").unwrap();
+        let first = mdb.get_db("first").unwrap();
+        assert_eq!(first.get(b"key1").unwrap().unwrap(), b"value1");
+
+        purge_backups(&backup_dir, 1).unwrap();
+        let backups = list_backups(&backup_dir).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].backup_id, 2);
+
+        mdb.restore(&backup_dir, None, "latest").unwrap();
+        let latest = mdb.get_db("latest").unwrap();
+        assert_eq!(latest.get(b"key1").unwrap().unwrap(), b"value2");
+        assert!(mdb.restore(&backup_dir, None, "latest").is_err());
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let root = tempfile::tempdir().unwrap();
+        let origin_path = format!("{}/origin", root.path().to_str().unwrap());
+        let checkpoint_path = format!("{}/checkpoint", root.path().to_str().unwrap());
+        let origin = Storage::new(&origin_path).unwrap();
+        origin.set(b"key1", b"value1").unwrap();
+        origin.checkpoint(&checkpoint_path).unwrap();
+        origin.set(b"key1", b"value2").unwrap();
+
+        let checkpoint = Storage::new(&checkpoint_path).unwrap();
+        assert_eq!(checkpoint.get(b"key1").unwrap().unwrap(), b"value1");
+    }
+
+    #[test]
+    fn test_install() {
+        let timeout = Duration::from_millis(50);
+        let root = tempfile::tempdir().unwrap();
+        let mdb = MultiDB::new(root.path().join("root").to_str().unwrap());
+        let checkpoint_path = format!("{}/checkpoint", root.path().to_str().unwrap());
+        let db1 = mdb.attach("db1").unwrap();
+        db1.set(b"key1", b"value1").unwrap();
+        db1.checkpoint(&checkpoint_path).unwrap();
+        db1.set(b"key1", b"value2").unwrap();
+
+        assert!(mdb.install("db1", &checkpoint_path, timeout).is_err());
+        drop(db1);
+        mdb.install("db1", &checkpoint_path, timeout).unwrap();
+        let db1 = mdb.get_db("db1").unwrap();
+        assert_eq!(db1.get(b"key1").unwrap().unwrap(), b"value1");
+        assert!(!Path::new(&checkpoint_path).exists());
+
+        let memory = MultiDB::with_engine("", EngineKind::Memory);
+        assert!(memory.install("db1", &checkpoint_path, timeout).is_err());
     }
 }
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+
+use rocksdb::{Direction, IteratorMode};
+
+use crate::{
+    read, write, BatchOp, Durability, KvEngine, KvIterator, KvPair, KvSnapshot, StorageOptions,
+    StorageResult,
+};
+
+type Map = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// A `KvEngine` keeping everything in a `BTreeMap`, for tests.
+///
+/// Snapshots and iterators share the map, which is copied on the next write
+/// while any of them is alive.
+#[derive(Default)]
+pub struct MemoryStorage {
+    map: RwLock<Arc<Map>>,
+    options: StorageOptions,
+}
+
+impl MemoryStorage {
+    pub fn new(options: StorageOptions) -> Self {
+        Self {
+            map: RwLock::new(Arc::new(Map::new())),
+            options,
+        }
+    }
+
+    fn current(&self) -> Arc<Map> {
+        read(&self.map).clone()
+    }
+
+    fn update<F: FnOnce(&mut Map) -> u64>(&self, f: F) -> u64 {
+        let mut map = write(&self.map);
+        f(Arc::make_mut(&mut map))
+    }
+}
+
+impl KvEngine for MemoryStorage {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        Ok(read(&self.map).get(key).cloned())
+    }
+
+    fn multi_get(&self, keys: &[Vec<u8>]) -> StorageResult<Vec<Option<Vec<u8>>>> {
+        let map = self.current();
+        Ok(keys.iter().map(|key| map.get(key).cloned()).collect())
+    }
+
+    fn exists(&self, key: &[u8]) -> StorageResult<bool> {
+        Ok(read(&self.map).contains_key(key))
+    }
+
+    fn set(&self, key: &[u8], value: &[u8]) -> StorageResult<()> {
+        self.update(|map| {
+            map.insert(key.to_vec(), value.to_vec());
+            0
+        });
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> StorageResult<()> {
+        self.update(|map| {
+            map.remove(key);
+            0
+        });
+        Ok(())
+    }
+
+    /// Nothing is ever on disk, so `durability` makes no difference.
+    fn write_batch_with(&self, ops: Vec<BatchOp>, _durability: Durability) -> StorageResult<()> {
+        self.update(|map| {
+            for op in ops {
+                match op {
+                    BatchOp::Put(key, value) => map.insert(key, value),
+                    BatchOp::Delete(key) => map.remove(&key),
+                };
+            }
+            0
+        });
+        Ok(())
+    }
+
+    fn delete_range(&self, start: &[u8], end: &[u8]) -> StorageResult<u64> {
+        if start >= end {
+            return Ok(0);
+        }
+        let count = self.update(|map| {
+            let mut tail = map.split_off(start);
+            let mut rest = tail.split_off(end);
+            map.append(&mut rest);
+            tail.len() as u64
+        });
+        Ok(count)
+    }
+
+    fn iterator(&self, mode: IteratorMode) -> StorageResult<KvIterator<'_>> {
+        Ok(Box::new(MemoryIterator::new(self.current(), mode)))
+    }
+
+    fn snapshot(self: Arc<Self>) -> Box<dyn KvSnapshot> {
+        Box::new(MemorySnapshot {
+            map: self.current(),
+        })
+    }
+
+    fn path(&self) -> Option<&str> {
+        None
+    }
+
+    fn options(&self) -> &StorageOptions {
+        &self.options
+    }
+
+    fn stats(&self) -> StorageResult<Vec<(String, String)>> {
+        let keys = read(&self.map).len();
+        Ok(vec![("estimated_keys".to_string(), keys.to_string())])
+    }
+}
+
+struct MemorySnapshot {
+    map: Arc<Map>,
+}
+
+impl KvSnapshot for MemorySnapshot {
+    fn get(&self, key: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+        Ok(self.map.get(key).cloned())
+    }
+
+    fn multi_get(&self, keys: &[Vec<u8>]) -> StorageResult<Vec<Option<Vec<u8>>>> {
+        Ok(keys.iter().map(|key| self.map.get(key).cloned()).collect())
+    }
+
+    fn iterator(&self, mode: IteratorMode) -> StorageResult<KvIterator<'_>> {
+        Ok(Box::new(MemoryIterator::new(self.map.clone(), mode)))
+    }
+}
+
+/// Walks a map version from a bound, looking up each next key so that it
+/// does not borrow the map.
+struct MemoryIterator {
+    map: Arc<Map>,
+    bound: Bound<Vec<u8>>,
+    direction: Direction,
+}
+
+impl MemoryIterator {
+    fn new(map: Arc<Map>, mode: IteratorMode) -> Self {
+        let (bound, direction) = match mode {
+            IteratorMode::Start => (Bound::Unbounded, Direction::Forward),
+            IteratorMode::End => (Bound::Unbounded, Direction::Reverse),
+            IteratorMode::From(key, direction) => (Bound::Included(key.to_vec()), direction),
+        };
+        Self {
+            map,
+            bound,
+            direction,
+        }
+    }
+}
+
+impl Iterator for MemoryIterator {
+    type Item = StorageResult<KvPair>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let from = match &self.bound {
+            Bound::Included(key) => Bound::Included(key.as_slice()),
+            Bound::Excluded(key) => Bound::Excluded(key.as_slice()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let (key, value) = match self.direction {
+            Direction::Forward => self.map.range::<[u8], _>((from, Bound::Unbounded)).next()?,
+            Direction::Reverse => self
+                .map
+                .range::<[u8], _>((Bound::Unbounded, from))
+                .next_back()?,
+        };
+        self.bound = Bound::Excluded(key.clone());
+        Some(Ok((key.clone().into(), value.clone().into())))
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rocksdb::{
+    BlockBasedOptions, Cache, DBCompactionStyle, DBCompressionType, Options, WriteOptions,
+};
+
+use crate::{StorageError, StorageResult};
+
+/// File in the db directory holding the options the db was opened with.
+const OPTIONS_FILE: &str = "RSDB-OPTIONS";
+
+/// Profile section applied to databases without a section of their own.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Tuning knobs for one database, unset ones keep the RocksDB defaults.
+///
+/// The text form is one `key = value` per line (or separated by `;`), e.g.
+/// `write_buffer_size = 67108864; compression = lz4; bloom_filter_bits = 10`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StorageOptions {
+    pub block_cache_size: Option<usize>,
+    pub bloom_filter_bits: Option<f64>,
+    pub compression: Option<String>,
+    pub compaction_style: Option<String>,
+    pub write_buffer_size: Option<usize>,
+    pub max_open_files: Option<i32>,
+    /// Collect RocksDB statistics, at some cost to every operation.
+    pub statistics: Option<bool>,
+    /// fsync the WAL on every write, unless the write asks otherwise.
+    pub sync_writes: Option<bool>,
+    /// Write nothing to the WAL, so recent writes are lost on a crash.
+    pub disable_wal: Option<bool>,
+    /// Keep obsolete WAL files this long, for change feeds to catch up.
+    pub wal_ttl_seconds: Option<u64>,
+    /// Keep a version of each value written, for reads as of a sequence
+    /// number, until replaced this long ago, 0 for ever. Versions past it
+    /// are dropped when the key is next written or the db pruned. A db
+    /// keeping history is refused without it. Not with column families.
+    pub history_ttl_seconds: Option<u64>,
+}
+
+/// Durability asked for by a single write, on top of the defaults of the db.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Durability {
+    pub sync: bool,
+    pub disable_wal: bool,
+}
+
+impl StorageOptions {
+    pub fn parse(text: &str) -> StorageResult<Self> {
+        let mut options = Self::default();
+        for item in text.split(['\n', ';']) {
+            let item = item.trim();
+            if item.is_empty() || item.starts_with('#') {
+                continue;
+            }
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("expected `key = value`, got `{}`", item)))?;
+            options.set(key.trim(), value.trim())?;
+        }
+        options.write_options(Durability::default())?;
+        Ok(options)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> StorageResult<()> {
+        match key {
+            "block_cache_size" => self.block_cache_size = Some(parse_value(key, value)?),
+            "bloom_filter_bits" => self.bloom_filter_bits = Some(parse_value(key, value)?),
+            "compression" => {
+                compression_type(value)?;
+                self.compression = Some(value.to_string());
+            }
+            "compaction_style" => {
+                compaction_style(value)?;
+                self.compaction_style = Some(value.to_string());
+            }
+            "write_buffer_size" => self.write_buffer_size = Some(parse_value(key, value)?),
+            "max_open_files" => self.max_open_files = Some(parse_value(key, value)?),
+            "statistics" => self.statistics = Some(parse_value(key, value)?),
+            "sync_writes" => self.sync_writes = Some(parse_value(key, value)?),
+            "disable_wal" => self.disable_wal = Some(parse_value(key, value)?),
+            "wal_ttl_seconds" => self.wal_ttl_seconds = Some(parse_value(key, value)?),
+            "history_ttl_seconds" => self.history_ttl_seconds = Some(parse_value(key, value)?),
+            _ => return Err(invalid(format!("unknown option `{}`", key))),
+        }
+        Ok(())
+    }
+
+    /// Build the RocksDB options for opening a db with this profile.
+    pub fn to_rocksdb(&self) -> Options {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+
+        let mut block_opts = BlockBasedOptions::default();
+        if let Some(size) = self.block_cache_size {
+            block_opts.set_block_cache(&Cache::new_lru_cache(size));
+        }
+        if let Some(bits) = self.bloom_filter_bits {
+            block_opts.set_bloom_filter(bits, false);
+        }
+        opts.set_block_based_table_factory(&block_opts);
+
+        if let Some(name) = self.compression.as_deref() {
+            // validated when parsed
+            if let Ok(compression) = compression_type(name) {
+                opts.set_compression_type(compression);
+            }
+        }
+        if let Some(name) = self.compaction_style.as_deref() {
+            if let Ok(style) = compaction_style(name) {
+                opts.set_compaction_style(style);
+            }
+        }
+        if let Some(size) = self.write_buffer_size {
+            opts.set_write_buffer_size(size);
+        }
+        if let Some(nfiles) = self.max_open_files {
+            opts.set_max_open_files(nfiles);
+        }
+        if self.statistics == Some(true) {
+            opts.enable_statistics();
+        }
+        if let Some(secs) = self.wal_ttl_seconds {
+            opts.set_wal_ttl_seconds(secs);
+        }
+        opts
+    }
+
+    /// The RocksDB options for a write asking for `durability`.
+    pub(crate) fn write_options(&self, durability: Durability) -> StorageResult<WriteOptions> {
+        let sync = durability.sync || self.sync_writes == Some(true);
+        let disable_wal = durability.disable_wal || self.disable_wal == Some(true);
+        if sync && disable_wal {
+            return Err(invalid("sync writes need the WAL".to_string()));
+        }
+        let mut opts = WriteOptions::default();
+        opts.set_sync(sync);
+        opts.disable_wal(disable_wal);
+        Ok(opts)
+    }
+
+    /// Read the options persisted in `db_path`, if any.
+    pub fn load(db_path: &str) -> StorageResult<Option<Self>> {
+        Self::load_file(&Path::new(db_path).join(OPTIONS_FILE))
+    }
+
+    pub fn save(&self, db_path: &str) -> StorageResult<()> {
+        fs::write(Path::new(db_path).join(OPTIONS_FILE), self.to_string())?;
+        Ok(())
+    }
+
+    /// Read the options persisted for column family `cf` of the db in `db_path`.
+    pub(crate) fn load_cf(db_path: &str, cf: &str) -> StorageResult<Option<Self>> {
+        Self::load_file(&cf_options_path(db_path, cf))
+    }
+
+    pub(crate) fn save_cf(&self, db_path: &str, cf: &str) -> StorageResult<()> {
+        fs::write(cf_options_path(db_path, cf), self.to_string())?;
+        Ok(())
+    }
+
+    pub(crate) fn remove_cf(db_path: &str, cf: &str) -> StorageResult<()> {
+        let path = cf_options_path(db_path, cf);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn load_file(path: &Path) -> StorageResult<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(path)?;
+        Ok(Some(Self::parse(&text)?))
+    }
+}
+
+impl Display for StorageOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(v) = self.block_cache_size {
+            writeln!(f, "block_cache_size = {}", v)?;
+        }
+        if let Some(v) = self.bloom_filter_bits {
+            writeln!(f, "bloom_filter_bits = {}", v)?;
+        }
+        if let Some(v) = &self.compression {
+            writeln!(f, "compression = {}", v)?;
+        }
+        if let Some(v) = &self.compaction_style {
+            writeln!(f, "compaction_style = {}", v)?;
+        }
+        if let Some(v) = self.write_buffer_size {
+            writeln!(f, "write_buffer_size = {}", v)?;
+        }
+        if let Some(v) = self.max_open_files {
+            writeln!(f, "max_open_files = {}", v)?;
+        }
+        if let Some(v) = self.statistics {
+            writeln!(f, "statistics = {}", v)?;
+        }
+        if let Some(v) = self.sync_writes {
+            writeln!(f, "sync_writes = {}", v)?;
+        }
+        if let Some(v) = self.disable_wal {
+            writeln!(f, "disable_wal = {}", v)?;
+        }
+        if let Some(v) = self.wal_ttl_seconds {
+            writeln!(f, "wal_ttl_seconds = {}", v)?;
+        }
+        if let Some(v) = self.history_ttl_seconds {
+            writeln!(f, "history_ttl_seconds = {}", v)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a profiles config file: `[name]` sections of options, where the
+/// section name is a database name or `default`.
+pub fn load_profiles(path: &str) -> StorageResult<HashMap<String, StorageOptions>> {
+    let text = fs::read_to_string(path)?;
+    let mut profiles = HashMap::new();
+    for (name, section) in split_sections(&text, DEFAULT_PROFILE) {
+        profiles.insert(name.to_string(), StorageOptions::parse(&section)?);
+    }
+    Ok(profiles)
+}
+
+/// Split INI-like text into `[name]` sections, the lines before the first
+/// header going to a section named `first`.
+pub(crate) fn split_sections<'a>(text: &'a str, first: &'a str) -> Vec<(&'a str, String)> {
+    let mut sections = vec![];
+    let mut name = first;
+    let mut section = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name, section));
+            name = header.trim();
+            section = String::new();
+        } else {
+            section.push_str(line);
+            section.push('\n');
+        }
+    }
+    sections.push((name, section));
+    sections
+}
+
+fn cf_options_path(db_path: &str, cf: &str) -> PathBuf {
+    Path::new(db_path).join(format!("{}.{}", OPTIONS_FILE, cf))
+}
+
+fn invalid(msg: String) -> StorageError {
+    StorageError::InvalidOptions(msg)
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str) -> StorageResult<T> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("invalid value `{}` for `{}`", value, key)))
+}
+
+fn compression_type(name: &str) -> StorageResult<DBCompressionType> {
+    match name {
+        "none" => Ok(DBCompressionType::None),
+        "snappy" => Ok(DBCompressionType::Snappy),
+        "zlib" => Ok(DBCompressionType::Zlib),
+        "bz2" => Ok(DBCompressionType::Bz2),
+        "lz4" => Ok(DBCompressionType::Lz4),
+        "lz4hc" => Ok(DBCompressionType::Lz4hc),
+        "zstd" => Ok(DBCompressionType::Zstd),
+        _ => Err(invalid(format!("unknown compression `{}`", name))),
+    }
+}
+
+fn compaction_style(name: &str) -> StorageResult<DBCompactionStyle> {
+    match name {
+        "level" => Ok(DBCompactionStyle::Level),
+        "universal" => Ok(DBCompactionStyle::Universal),
+        "fifo" => Ok(DBCompactionStyle::Fifo),
+        _ => Err(invalid(format!("unknown compaction style `{}`", name))),
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::KvEngine;
+
+/// Open databases and eviction counters of a `MultiDB`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolStats {
+    pub open: usize,
+    pub max_open: Option<usize>,
+    /// Databases closed to make room for others.
+    pub evictions: u64,
+    /// Evicted databases opened again.
+    pub reopens: u64,
+}
+
+/// Tracks when each open database was last used, so that the least recently
+/// used idle ones can be closed once more than `max_open` are open.
+#[derive(Default)]
+pub(crate) struct Pool {
+    max_open: Option<usize>,
+    clock: u64,
+    used: HashMap<String, u64>,
+    evicted: HashSet<String>,
+    evictions: u64,
+    reopens: u64,
+}
+
+impl Pool {
+    pub(crate) fn set_max_open(&mut self, max_open: Option<usize>) {
+        self.max_open = max_open;
+    }
+
+    pub(crate) fn touch(&mut self, name: &str) {
+        self.clock += 1;
+        self.used.insert(name.to_string(), self.clock);
+    }
+
+    pub(crate) fn opened(&mut self, name: &str) {
+        if self.evicted.remove(name) {
+            self.reopens += 1;
+        }
+        self.touch(name);
+    }
+
+    pub(crate) fn closed(&mut self, name: &str) {
+        self.used.remove(name);
+        self.evicted.remove(name);
+    }
+
+    /// Close databases from `open` until the limit is met, least recently
+    /// used first, skipping `keep` and any database still referenced outside
+    /// the map. The limit is exceeded while everything else is in use.
+    ///
+    /// The evicted databases are returned to be dropped by the caller once
+    /// its locks are released, as closing one may flush its memtables.
+    #[must_use]
+    pub(crate) fn evict(
+        &mut self,
+        open: &mut HashMap<String, Arc<dyn KvEngine>>,
+        keep: &str,
+    ) -> Vec<(String, Arc<dyn KvEngine>)> {
+        let mut evicted = vec![];
+        let max_open = match self.max_open {
+            Some(max_open) => max_open,
+            None => return evicted,
+        };
+        while open.len() > max_open {
+            let victim = open
+                .iter()
+                .filter(|(name, s)| name.as_str() != keep && Arc::strong_count(s) == 1)
+                .min_by_key(|(name, _)| self.used.get(name.as_str()))
+                .map(|(name, _)| name.clone());
+            let name = match victim {
+                Some(name) => name,
+                None => break,
+            };
+            if let Some(s) = open.remove(&name) {
+                evicted.push((name.clone(), s));
+            }
+            self.used.remove(&name);
+            self.evicted.insert(name);
+            self.evictions += 1;
+        }
+        evicted
+    }
+
+    pub(crate) fn stats(&self, open: usize) -> PoolStats {
+        PoolStats {
+            open,
+            max_open: self.max_open,
+            evictions: self.evictions,
+            reopens: self.reopens,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use rocksdb::properties::{self, PropName};
+
+use crate::{Storage, StorageResult};
+
+/// Levels reported in the layout, the RocksDB default.
+const NUM_LEVELS: usize = 7;
+
+const INT_PROPERTIES: [(&str, &PropName); 5] = [
+    ("estimated_keys", properties::ESTIMATE_NUM_KEYS),
+    ("live_sst_size", properties::LIVE_SST_FILES_SIZE),
+    ("memtable_size", properties::CUR_SIZE_ALL_MEM_TABLES),
+    (
+        "pending_compaction_bytes",
+        properties::ESTIMATE_PENDING_COMPACTION_BYTES,
+    ),
+    ("block_cache_usage", properties::BLOCK_CACHE_USAGE),
+];
+
+impl Storage {
+    /// Size figures and the number of sst files per level from the RocksDB
+    /// properties, followed by the tickers and histograms of the instance
+    /// when it collects statistics.
+    pub(crate) fn properties(&self) -> StorageResult<Vec<(String, String)>> {
+        let cf = self.cf_handle()?;
+        let int_value = |name: &PropName| -> StorageResult<Option<u64>> {
+            match cf.as_ref() {
+                Some(cf) => Ok(self.db.property_int_value_cf(cf, name)?),
+                None => Ok(self.db.property_int_value(name)?),
+            }
+        };
+
+        let mut stats = vec![];
+        for (label, name) in INT_PROPERTIES {
+            if let Some(value) = int_value(name)? {
+                stats.push((label.to_string(), value.to_string()));
+            }
+        }
+        for level in 0..NUM_LEVELS {
+            if let Some(files) = int_value(&properties::num_files_at_level(level))? {
+                stats.push((format!("files_at_level{}", level), files.to_string()));
+            }
+        }
+
+        // one `name COUNT : n` or `name P50 : ...` line per ticker or histogram
+        if let Some(text) = self.db.property_value(properties::OPTIONS_STATISTICS)? {
+            for line in text.lines() {
+                if let Some((name, value)) = line.split_once(' ') {
+                    let value = value.strip_prefix("COUNT : ").unwrap_or(value);
+                    stats.push((name.to_string(), value.trim().to_string()));
+                }
+            }
+        }
+        Ok(stats)
+    }
+}